@@ -0,0 +1,75 @@
+//! Progressive rendering for the interactive window. Each pass traces every
+//! pixel with its samples laid out afresh, and passes are summed per pixel
+//! into a float buffer whose running average is what's shown, so a still
+//! view converges on a clean image. Whatever changes the view empties the
+//! buffer, all of it or only the part an edit damaged, and the average
+//! starts over there.
+
+use cgmath::Vector3;
+use im::Rgb;
+
+use render::{Color, Frame, Rect};
+
+pub struct Accumulator {
+    width: u32,
+    // Radiance summed over each pixel's passes, and how many there were
+    sums: Vec<Color>,
+    passes: Vec<u32>,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        let count = (width * height) as usize;
+        Accumulator {
+            width,
+            sums: vec![Vector3::new(0.0, 0.0, 0.0); count],
+            passes: vec![0; count],
+        }
+    }
+
+    fn index(&self, px_x: u32, px_y: u32) -> usize {
+        (px_y * self.width + px_x) as usize
+    }
+
+    /// Adds the pixels of `rect` in `pass`, a frame of radiance.
+    pub fn add(&mut self, pass: &Frame<Rgb<f32>>, rect: &Rect) {
+        for px_y in rect.y..(rect.y + rect.height) {
+            for px_x in rect.x..(rect.x + rect.width) {
+                let [r, g, b] = pass.get_pixel(px_x, px_y).data;
+                let i = self.index(px_x, px_y);
+                self.sums[i] += Vector3::new(r, g, b);
+                self.passes[i] += 1;
+            }
+        }
+    }
+
+    /// Forgets the passes summed in `rect`, or in the whole frame without
+    /// one.
+    pub fn reset(&mut self, rect: Option<Rect>) {
+        let rect = match rect {
+            Some(rect) => rect,
+            None => {
+                self.sums.iter_mut().for_each(|sum| *sum = Vector3::new(0.0, 0.0, 0.0));
+                self.passes.iter_mut().for_each(|passes| *passes = 0);
+                return;
+            }
+        };
+        for px_y in rect.y..(rect.y + rect.height) {
+            for px_x in rect.x..(rect.x + rect.width) {
+                let i = self.index(px_x, px_y);
+                self.sums[i] = Vector3::new(0.0, 0.0, 0.0);
+                self.passes[i] = 0;
+            }
+        }
+    }
+
+    /// Mean radiance over the passes at pixel (`px_x`, `px_y`), if it has
+    /// had any since it was last reset.
+    pub fn average(&self, px_x: u32, px_y: u32) -> Option<Color> {
+        let i = self.index(px_x, px_y);
+        match self.passes[i] {
+            0 => None,
+            passes => Some(self.sums[i] / passes as f32),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Embedded HTTP API for watching and steering an interactive session from
+//! scripts or other machines:
+//!
+//! - `GET /status` reports frames, frame rate, scene time, pause state,
+//!   exposure and camera as JSON
+//! - `POST /pause` and `POST /resume` stop and restart the scene clock
+//! - `PUT /<parameter>` sets any parameter OSC can, such as `/exposure` or
+//!   `/camera/position`, to the numbers in the body, e.g. `1.5` or
+//!   `[0, 1, 5]`
+//! - `GET /scenes` lists the loaded scenes and which is active
+//! - `POST /scenes` loads the scene file whose path is the body
+//! - `POST /scenes/<n>/activate` switches to scene `n`, counting from 0,
+//!   from the next frame
+//! - `DELETE /scenes/<n>` unloads inactive scene `n`
+//!
+//! Successful requests answer with the status, or for `/scenes` the scene
+//! list; failures with a JSON `error`. Requests are served between frames,
+//! one connection each.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+
+use camera::Camera;
+use edit::Damage;
+use osc;
+use render::RenderOptions;
+use report::{self, json_string};
+use scene::Scene;
+use scenes::SceneSet;
+
+// Longest a slow client can hold up the render loop
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_BODY: usize = 4096;
+
+pub struct ApiServer {
+    listener: TcpListener,
+}
+
+/// The session requests see and change.
+pub struct Session<'a> {
+    pub scene: &'a mut Scene,
+    pub camera: &'a mut Camera,
+    pub render_options: &'a mut RenderOptions,
+    pub paused: &'a mut bool,
+    // What PUT requests have changed, gathered across requests
+    pub damage: &'a mut Damage,
+    pub scenes: &'a mut SceneSet,
+    // Scene to switch to once the frame being traced is finished
+    pub switch: &'a mut Option<usize>,
+    // Frames completed so far
+    pub frames: u64,
+    pub fps: f64,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+struct Response {
+    status: &'static str,
+    body: String,
+}
+
+fn error(status: &'static str, message: &str) -> Response {
+    Response {
+        status,
+        body: format!("{{\"error\": {}}}\n", json_string(message)),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let bad = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(bad("bad request line")),
+    };
+    // The query string isn't used
+    let path = target.split('?').next().unwrap_or("").to_string();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(bad("headers cut short"));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| bad("bad Content-Length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(bad("body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| bad("body is not UTF-8"))?;
+    Ok(Request { method, path, body })
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )
+}
+
+fn status(session: &Session) -> String {
+    let p = session.camera.position;
+    format!(
+        "{{\"frames\": {}, \"fps\": {:.2}, \"rays\": {}, \"time\": {:.3}, \"paused\": {}, \
+         \"width\": {}, \"height\": {}, \"exposure\": {}, \
+         \"camera\": {{\"position\": [{}, {}, {}], \"fov\": {}}}}}\n",
+        session.frames,
+        session.fps,
+        report::rays_traced(),
+        session.scene.time,
+        *session.paused,
+        session.render_options.width,
+        session.render_options.height,
+        session.render_options.exposure,
+        p.x,
+        p.y,
+        p.z,
+        session.camera.fov
+    )
+}
+
+fn scene_list(session: &Session) -> String {
+    let names: Vec<String> = session.scenes.names().map(json_string).collect();
+    format!(
+        "{{\"active\": {}, \"scenes\": [{}]}}\n",
+        session.scenes.active(),
+        names.join(", ")
+    )
+}
+
+// Requests to `/scenes` and the scenes under it
+fn handle_scenes(request: &Request, session: &mut Session) -> Response {
+    let parts: Vec<&str> = request.path.split('/').filter(|p| !p.is_empty()).skip(1).collect();
+    let index = |s: &str| -> Result<usize, String> {
+        match s.parse() {
+            Ok(index) if index < session.scenes.names().count() => Ok(index),
+            _ => Err(format!("no scene {}", s)),
+        }
+    };
+    let result = match (request.method.as_str(), parts.as_slice()) {
+        ("GET", []) => Ok(()),
+        ("POST", []) => {
+            let path = request.body.trim();
+            session.scenes.load(Path::new(path), session.render_options).map(|warnings| {
+                for warning in warnings {
+                    eprintln!("\n{}: {}", path, warning);
+                }
+            })
+        }
+        ("POST", [n, "activate"]) => index(n).map(|index| *session.switch = Some(index)),
+        ("DELETE", [n]) => index(n).and_then(|index| session.scenes.unload(index)),
+        (_, []) | (_, [_, "activate"]) | (_, [_]) => {
+            return error("405 Method Not Allowed", "method not allowed")
+        }
+        _ => return error("404 Not Found", "no such endpoint"),
+    };
+    match result {
+        Ok(()) => Response {
+            status: "200 OK",
+            body: scene_list(session),
+        },
+        Err(e) => error("400 Bad Request", &e),
+    }
+}
+
+// Numbers in a request body, separated by commas or spaces and optionally
+// bracketed as a JSON array
+fn numbers(body: &str) -> Option<Vec<f32>> {
+    body.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().ok())
+        .collect()
+}
+
+fn handle(request: &Request, session: &mut Session) -> Response {
+    if request.path == "/scenes" || request.path.starts_with("/scenes/") {
+        return handle_scenes(request, session);
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => {}
+        ("POST", "/pause") => *session.paused = true,
+        ("POST", "/resume") => *session.paused = false,
+        ("PUT", path) => {
+            let args = match numbers(&request.body) {
+                Some(args) => args,
+                None => return error("400 Bad Request", "body must be numbers"),
+            };
+            let message = osc::Message {
+                address: path.to_string(),
+                args,
+            };
+            let result = osc::apply(
+                &message,
+                session.scene,
+                session.camera,
+                session.render_options,
+            );
+            match result {
+                Ok(damage) => *session.damage = session.damage.union(damage),
+                Err(e) => return error("400 Bad Request", &e),
+            }
+        }
+        (_, "/status") | (_, "/pause") | (_, "/resume") => {
+            return error("405 Method Not Allowed", "method not allowed")
+        }
+        _ => return error("404 Not Found", "no such endpoint"),
+    }
+    Response {
+        status: "200 OK",
+        body: status(session),
+    }
+}
+
+impl ApiServer {
+    pub fn bind(address: &str) -> io::Result<ApiServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(ApiServer { listener })
+    }
+
+    /// Answers every request waiting, without waiting for more. Clients
+    /// that break off or send nonsense are dropped.
+    pub fn serve(&self, session: &mut Session) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            let ready = stream
+                .set_nonblocking(false)
+                .and_then(|_| stream.set_read_timeout(Some(CLIENT_TIMEOUT)))
+                .and_then(|_| stream.set_write_timeout(Some(CLIENT_TIMEOUT)));
+            if ready.is_err() {
+                continue;
+            }
+            let response = match read_request(&stream) {
+                Ok(request) => handle(&request, session),
+                Err(e) => error("400 Bad Request", &e.to_string()),
+            };
+            // Nothing to do if the client has gone
+            let _ = write_response(&stream, &response);
+        }
+    }
+}
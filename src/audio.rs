@@ -0,0 +1,245 @@
+//! Audio-reactive animation. The level of a frequency band of a WAV file,
+//! read at the scene clock, scales scene parameters such as light intensity
+//! or sphere size, for music-visualiser style renders.
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use light::Light;
+use scene::Scene;
+
+// Samples analysed around each point in time, about 23 ms at 44.1 kHz
+const WINDOW: usize = 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Band {
+    Bass,
+    Mid,
+    Treble,
+}
+
+impl Band {
+    pub fn from_name(name: &str) -> Option<Band> {
+        match name {
+            "bass" => Some(Band::Bass),
+            "mid" => Some(Band::Mid),
+            "treble" => Some(Band::Treble),
+            _ => None,
+        }
+    }
+
+    // Frequency range in Hz
+    fn range(self) -> (f32, f32) {
+        match self {
+            Band::Bass => (20.0, 250.0),
+            Band::Mid => (250.0, 2000.0),
+            Band::Treble => (2000.0, 8000.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Target {
+    // Intensity of every light
+    LightIntensity,
+    // Radius of every sphere not gathered into a LOD cluster
+    SphereScale,
+}
+
+impl Target {
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "light-intensity" => Some(Target::LightIntensity),
+            "sphere-scale" => Some(Target::SphereScale),
+            _ => None,
+        }
+    }
+}
+
+/// Scales `target` by 1 + `amount` x the level of `band`.
+#[derive(Clone, Copy, Debug)]
+pub struct Mapping {
+    pub band: Band,
+    pub target: Target,
+    pub amount: f32,
+}
+
+/// Mono audio, with samples in [-1, 1].
+pub struct Track {
+    rate: u32,
+    samples: Vec<f32>,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Reads an uncompressed WAV file of 8, 16, 24 or 32-bit integer or 32-bit
+/// float samples, mixing its channels down to mono.
+pub fn load_wav(path: &Path) -> Result<Track, String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|e| e.to_string())?;
+    if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+        return Err("not a WAV file".to_string());
+    }
+
+    // (format tag, channels, sample rate, bits per sample)
+    let mut format = None;
+    let mut data = None;
+    let mut at = 12;
+    while let (Some(id), Some(size)) = (bytes.get(at..at + 4), u32_at(&bytes, at + 4)) {
+        let body = at + 8;
+        let end = body.saturating_add(size as usize).min(bytes.len());
+        match id {
+            b"fmt " => {
+                let mut tag = u16_at(&bytes, body).ok_or("truncated fmt chunk")?;
+                // WAVE_FORMAT_EXTENSIBLE keeps the real tag in its sub-format
+                if tag == 0xfffe {
+                    tag = u16_at(&bytes, body + 24).ok_or("truncated fmt chunk")?;
+                }
+                let channels = u16_at(&bytes, body + 2).ok_or("truncated fmt chunk")?;
+                let rate = u32_at(&bytes, body + 4).ok_or("truncated fmt chunk")?;
+                let bits = u16_at(&bytes, body + 14).ok_or("truncated fmt chunk")?;
+                format = Some((tag, channels, rate, bits));
+            }
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at = end + (size as usize & 1);
+    }
+    let (tag, channels, rate, bits) = format.ok_or("no fmt chunk")?;
+    let data = data.ok_or("no data chunk")?;
+    if channels == 0 || rate == 0 {
+        return Err("no channels".to_string());
+    }
+
+    let width = bits as usize / 8;
+    let sample = |b: &[u8]| -> Option<f32> {
+        match (tag, bits) {
+            (1, 8) => Some((f32::from(b[0]) - 128.0) / 128.0),
+            (1, 16) => Some(f32::from(i16::from_le_bytes([b[0], b[1]])) / 32768.0),
+            // Shifted up into an i32 so the sign bit lands in place
+            (1, 24) => Some(i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0),
+            (1, 32) => Some(i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0),
+            (3, 32) => Some(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            _ => None,
+        }
+    };
+    if !(1..=4).contains(&width) || sample(&[0; 4][..width]).is_none() {
+        return Err(format!("unsupported format {} with {} bits per sample", tag, bits));
+    }
+
+    let frame = width * channels as usize;
+    let samples = data
+        .chunks(frame)
+        .filter(|f| f.len() == frame)
+        .map(|f| {
+            let sum: f32 = f.chunks(width).filter_map(&sample).sum();
+            sum / f32::from(channels)
+        })
+        .collect();
+    Ok(Track { rate, samples })
+}
+
+impl Track {
+    /// Amplitude of the part of the signal in `band` around `time` seconds
+    /// in, about 1 for a full-scale tone; 0 outside the track.
+    pub fn level(&self, band: Band, time: f32) -> f32 {
+        let center = (time * self.rate as f32) as isize;
+        let start = center - WINDOW as isize / 2;
+        if start < 0 || start as usize + WINDOW > self.samples.len() {
+            return 0.0;
+        }
+        // A Hann window keeps a loud tone in one band from leaking into the
+        // others
+        let window: Vec<f32> = self.samples[start as usize..start as usize + WINDOW]
+            .iter()
+            .enumerate()
+            .map(|(i, x)| x * (1.0 - (2.0 * PI * i as f32 / WINDOW as f32).cos()) / 2.0)
+            .collect();
+
+        // Goertzel filters for each DFT bin in the band, summing their power
+        let bin_width = self.rate as f32 / WINDOW as f32;
+        let (low, high) = band.range();
+        let first = (low / bin_width).ceil().max(1.0) as usize;
+        let last = ((high / bin_width).floor() as usize).min(WINDOW / 2 - 1);
+        let mut power = 0.0;
+        for bin in first..=last {
+            let coefficient = 2.0 * (2.0 * PI * bin as f32 / WINDOW as f32).cos();
+            let (mut s1, mut s2) = (0.0, 0.0);
+            for &x in &window {
+                let s = x + coefficient * s1 - s2;
+                s2 = s1;
+                s1 = s;
+            }
+            power += s1 * s1 + s2 * s2 - coefficient * s1 * s2;
+        }
+        // The window halves the amplitude it passes and spreads a tone's
+        // power over 1.5 bins' worth
+        (4.0 * (power / 1.5).sqrt() / WINDOW as f32).min(1.0)
+    }
+}
+
+/// Applies `mappings` to a scene as its clock runs, scaling the values the
+/// scene started with.
+pub struct AudioDriver {
+    track: Track,
+    mappings: Vec<Mapping>,
+    lights: Vec<Light>,
+    radii: Vec<f32>,
+}
+
+impl AudioDriver {
+    pub fn new(track: Track, mappings: Vec<Mapping>, scene: &Scene) -> AudioDriver {
+        AudioDriver {
+            track,
+            mappings,
+            lights: scene.lights.iter().cloned().collect(),
+            radii: scene.spheres().map(|s| s.radius).collect(),
+        }
+    }
+
+    /// Sets the mapped parameters of `scene` for its current time.
+    pub fn apply(&self, scene: &mut Scene) {
+        let (mut intensity, mut scale) = (1.0, 1.0);
+        for mapping in &self.mappings {
+            let factor = 1.0 + mapping.amount * self.track.level(mapping.band, scene.time);
+            match mapping.target {
+                Target::LightIntensity => intensity *= factor,
+                Target::SphereScale => scale *= factor,
+            }
+        }
+        for (light, base) in scene.lights.iter_mut().zip(&self.lights) {
+            *light = base.scaled(intensity);
+        }
+        for (sphere, base) in scene.spheres_mut().zip(&self.radii) {
+            sphere.radius = base * scale;
+        }
+    }
+
+    /// Puts back the values `scene` had before the driver scaled them.
+    pub fn restore(&self, scene: &mut Scene) {
+        for (light, base) in scene.lights.iter_mut().zip(&self.lights) {
+            *light = base.clone();
+        }
+        for (sphere, base) in scene.spheres_mut().zip(&self.radii) {
+            sphere.radius = *base;
+        }
+    }
+
+    /// Takes the values `scene` has now as the ones to scale, for driving a
+    /// different scene.
+    pub fn rebase(&mut self, scene: &Scene) {
+        self.lights = scene.lights.iter().cloned().collect();
+        self.radii = scene.spheres().map(|s| s.radius).collect();
+    }
+}
@@ -0,0 +1,190 @@
+//! Lightmap baking: the light reaching a model's surface, or how open to
+//! the sky each part of it is, rendered into the model's UV layout rather
+//! than through a camera, for texturing it with in a game engine.
+//!
+//! Each texel is shaded at the point of the model its centre maps to,
+//! facing the way the triangle there winds anticlockwise. Irradiance is
+//! the light every light in the scene gives a white matte surface there,
+//! shadowed as in a render, so multiplying it by a surface's albedo shades
+//! the surface as the direct integrator would. Ambient occlusion is the
+//! fraction of rays over the hemisphere there that travel a metre without
+//! hitting anything.
+//!
+//! Texels no triangle covers are filled from their neighbours for a few
+//! texels around the layout's islands, so a texture filtered across an
+//! island's edge doesn't bleed black into it.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use im::Rgb;
+use rayon::prelude::*;
+
+use geometry::Ray;
+use material::Material;
+use mesh::UvTriangle;
+use render::{direct_light, Color, Frame, RenderOptions};
+use sampling;
+use scene::{occluded, Scene};
+
+// Rays each texel's ambient occlusion is estimated with, for each of its
+// pixel samples
+const OCCLUSION_RAYS: u32 = 64;
+// Texels filled in around each island of the layout
+const DILATION: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bake {
+    Irradiance,
+    Occlusion,
+}
+
+impl Bake {
+    pub fn from_name(name: &str) -> Option<Bake> {
+        match name {
+            "irradiance" => Some(Bake::Irradiance),
+            "occlusion" => Some(Bake::Occlusion),
+            _ => None,
+        }
+    }
+}
+
+/// Bakes `bake` over `layout`, a model lying in `scene`, into a texture of
+/// `RenderOptions::width` by `height` texels.
+pub fn bake(
+    scene: &Scene,
+    layout: &[UvTriangle],
+    bake: Bake,
+    render_options: &RenderOptions,
+) -> Frame<Rgb<f32>> {
+    let (width, height) = (render_options.width, render_options.height);
+    let surface = rasterize(layout, width, height);
+    let colors: Vec<Option<Color>> = surface
+        .par_iter()
+        .map(|texel| {
+            texel.map(|(point, normal)| match bake {
+                Bake::Irradiance => irradiance(scene, point, normal, render_options),
+                Bake::Occlusion => {
+                    let open = openness(scene, point, normal, render_options);
+                    Vector3::new(open, open, open)
+                }
+            })
+        })
+        .collect();
+    let colors = dilate(colors, width, height);
+    Frame::from_fn(width, height, |x, y| {
+        let color = colors[(y * width + x) as usize].unwrap_or(Vector3::new(0.0, 0.0, 0.0));
+        Rgb([color.x, color.y, color.z])
+    })
+}
+
+// The point on the model under each texel's centre, in rows from the top,
+// and the unit normal there, or None where no triangle covers the texel.
+// The texture's v runs up the image.
+fn rasterize(
+    layout: &[UvTriangle],
+    width: u32,
+    height: u32,
+) -> Vec<Option<(Point3<f32>, Vector3<f32>)>> {
+    let mut surface = vec![None; (width * height) as usize];
+    for uv_triangle in layout {
+        let normal = uv_triangle.triangle.normal();
+        if normal.magnitude2() <= 0.0 {
+            continue;
+        }
+        let normal = normal.normalize();
+        // Corners in texel coordinates
+        let [a, b, c] = uv_triangle.uvs;
+        let texel = |uv: [f32; 2]| (uv[0] * width as f32, (1.0 - uv[1]) * height as f32);
+        let (a, b, c) = (texel(a), texel(b), texel(c));
+        let area = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+        if area == 0.0 {
+            continue;
+        }
+        let range = |lo: f32, hi: f32, size: u32| {
+            let first = lo.floor().max(0.0) as u32;
+            let last = (hi.ceil().max(0.0) as u32).min(size);
+            first..last
+        };
+        let xs = range(a.0.min(b.0).min(c.0), a.0.max(b.0).max(c.0), width);
+        let ys = range(a.1.min(b.1).min(c.1), a.1.max(b.1).max(c.1), height);
+        for y in ys {
+            for x in xs.clone() {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                // Weights of the corners at the texel's centre
+                let wb = ((px - a.0) * (c.1 - a.1) - (c.0 - a.0) * (py - a.1)) / area;
+                let wc = ((b.0 - a.0) * (py - a.1) - (px - a.0) * (b.1 - a.1)) / area;
+                let wa = 1.0 - wb - wc;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let t = &uv_triangle.triangle;
+                let point = t.a.to_vec() * wa + t.b.to_vec() * wb + t.c.to_vec() * wc;
+                surface[(y * width + x) as usize] = Some((Point3::from_vec(point), normal));
+            }
+        }
+    }
+    surface
+}
+
+// Light reaching a white matte surface at `point` facing `normal`
+fn irradiance(
+    scene: &Scene,
+    point: Point3<f32>,
+    normal: Vector3<f32>,
+    render_options: &RenderOptions,
+) -> Color {
+    let white = Material::default();
+    direct_light(scene, point, normal, normal, &white, render_options).0
+}
+
+// Fraction of cosine-weighted rays from `point` over the hemisphere about
+// `normal` that go a metre unblocked
+fn openness(
+    scene: &Scene,
+    point: Point3<f32>,
+    normal: Vector3<f32>,
+    render_options: &RenderOptions,
+) -> f32 {
+    let reach = 1.0 / scene.units.meters_per_unit;
+    let rays = OCCLUSION_RAYS * render_options.samples_per_pixel;
+    let rotation = sampling::rotation(point);
+    let open = (0..rays)
+        .filter(|&i| {
+            let sample = sampling::hammersley(i, rays, rotation);
+            let direction = sampling::cosine_hemisphere(normal, sample);
+            let ray = Ray::from_surface(point, normal, direction);
+            !occluded(scene, &ray, reach, render_options)
+        })
+        .count();
+    open as f32 / rays as f32
+}
+
+// `colors` with each texel that has none given the average of its
+// neighbours that do, `DILATION` times over
+fn dilate(mut colors: Vec<Option<Color>>, width: u32, height: u32) -> Vec<Option<Color>> {
+    for _ in 0..DILATION {
+        let before = colors.clone();
+        for y in 0..height {
+            for x in 0..width {
+                if before[(y * width + x) as usize].is_some() {
+                    continue;
+                }
+                let mut sum = Vector3::new(0.0, 0.0, 0.0);
+                let mut count = 0;
+                for (dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                        continue;
+                    }
+                    if let Some(color) = before[(ny as u32 * width + nx as u32) as usize] {
+                        sum += color;
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    colors[(y * width + x) as usize] = Some(sum / count as f32);
+                }
+            }
+        }
+    }
+    colors
+}
@@ -0,0 +1,338 @@
+//! Batch rendering of a list of scene, camera and output triples.
+//!
+//! Each non-blank line of a batch file reads `<scene> <camera> <out>`, where
+//! the scene is a `.usda`, `.json` or `.pbrt` file and the camera is a camera's
+//! path or name in it, or `-` for its first camera. Lines starting with `#` are
+//! comments and relative paths are taken from the batch file's directory. A
+//! scene named by several jobs is loaded once and shared between them. Jobs are
+//! numbered from 1 in list order, which is the frame number burn-in shows.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use camera::Camera;
+use checkpoint::Checkpoint;
+use embed;
+use hud::Hud;
+use im::{Rgb, Rgba};
+use interrupt;
+use lod;
+use motion;
+use output::{self, Metadata};
+use render::{render_tiles, Frame, RenderOptions};
+use report::Report;
+use resolve::AssetResolver;
+use scene::{load_stage, Scene, Units};
+use stereo;
+use stream;
+use tiles;
+use usd::Stage;
+
+pub struct Job {
+    scene: PathBuf,
+    camera: Option<String>,
+    output: PathBuf,
+    // Frame number burn-in shows
+    frame: u32,
+}
+
+impl Job {
+    /// Job rendering `scene` from its first camera.
+    pub fn new(scene: PathBuf, output: PathBuf) -> Job {
+        Job {
+            scene,
+            camera: None,
+            output,
+            frame: 1,
+        }
+    }
+}
+
+pub fn read_jobs(path: &Path) -> Result<Vec<Job>, String> {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut jobs = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            &[scene, camera, output] => jobs.push(Job {
+                scene: dir.join(scene),
+                camera: if camera == "-" { None } else { Some(camera.to_string()) },
+                output: dir.join(output),
+                frame: jobs.len() as u32 + 1,
+            }),
+            _ => {
+                return Err(format!(
+                    "{}:{}: expected <scene> <camera> <output>",
+                    path.display(),
+                    i + 1
+                ))
+            }
+        }
+    }
+    Ok(jobs)
+}
+
+/// Renders every job in turn, adding its output or error to `report` and
+/// carrying on with the rest. Scenes without cameras are seen from
+/// `default_camera`. An interrupted job saves a checkpoint, which the next
+/// run of it resumes, and no further jobs are started. 8-bit PNG frames
+/// larger than `stream::STREAM_PIXELS` are streamed to disk instead; they
+/// and 16-bit frames aren't resumable. Returns the number of jobs that
+/// failed.
+pub fn run(
+    jobs: &[Job],
+    units: &Units,
+    resolver: &AssetResolver,
+    render_options: &RenderOptions,
+    default_camera: &Camera,
+    report: &mut Report,
+) -> usize {
+    let mut stages: HashMap<&Path, Stage> = HashMap::new();
+    let mut failed = 0;
+    for job in jobs {
+        let result = run_job(job, &mut stages, units, resolver, render_options, default_camera, report);
+        match result {
+            Ok(()) => report.output(&job.output),
+            Err(e) => {
+                report.error(format!("{}: {}", job.output.display(), e));
+                failed += 1;
+            }
+        }
+        if interrupt::interrupted() {
+            break;
+        }
+    }
+    failed
+}
+
+fn run_job<'a>(
+    job: &'a Job,
+    stages: &mut HashMap<&'a Path, Stage>,
+    units: &Units,
+    resolver: &AssetResolver,
+    render_options: &RenderOptions,
+    default_camera: &Camera,
+    report: &mut Report,
+) -> Result<(), String> {
+    if !stages.contains_key(job.scene.as_path()) {
+        let mut stage = load_stage(&job.scene, units, resolver)?;
+        for warning in stage.warnings.drain(..) {
+            report.warning(format!("{}: {}", job.scene.display(), warning));
+        }
+        if render_options.lod_pixels > 0.0 {
+            lod::build_clusters(&mut stage.scene);
+        }
+        stages.insert(&job.scene, stage);
+    }
+    let stage = stages.get_mut(job.scene.as_path()).unwrap();
+
+    let camera = match job.camera {
+        Some(ref path) => stage
+            .camera(Some(path))
+            .ok_or_else(|| format!("no camera at {}", path))?,
+        None => stage.camera(None).unwrap_or(default_camera),
+    }
+    .at_time(stage.scene.time)
+    .with_overrides(render_options);
+    if render_options.lod_pixels > 0.0 {
+        lod::select_lod(&mut stage.scene, &camera, render_options);
+    }
+
+    let hud = if render_options.burn_in {
+        let camera_name = match job.camera {
+            Some(ref path) => path.clone(),
+            None => stage
+                .cameras
+                .first()
+                .map_or_else(|| "default camera".to_string(), |c| c.0.clone()),
+        };
+        Some(Hud {
+            frame: job.frame,
+            scene: job.scene.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            camera: camera_name,
+        })
+    } else {
+        None
+    };
+
+    let scene_file = Some(job.scene.as_path());
+    let metadata = metadata(scene_file, job.camera.as_deref(), &stage.scene, render_options)?;
+    render(&mut stage.scene, &camera, render_options, &job.output, &metadata, hud.as_ref())
+}
+
+/// Renders `scene` from `camera` into the image at `output`, recording
+/// `metadata` in it and burning in `hud` if given. An interrupted 8-bit
+/// render saves a checkpoint, which the next render to `output` with the
+/// same scene and settings in its `metadata` resumes; large PNG frames
+/// are streamed, and 16-bit, `.hdr` and `.exr` frames aren't resumable, as
+/// for batch jobs. `.hdr` and `.exr` frames hold linear radiance, with no
+/// burn-in. Frames blurred by an open shutter or made as anaglyphs aren't
+/// resumable either, and leave `scene` as they found it.
+pub fn render(
+    scene: &mut Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    output: &Path,
+    metadata: &Metadata,
+    hud: Option<&Hud>,
+) -> Result<(), String> {
+    let (width, height) = (render_options.width, render_options.height);
+    if let Some(separation) = render_options.anaglyph {
+        let radiance = stereo::render_anaglyph(scene, camera, render_options, separation)
+            .ok_or("interrupted")?;
+        return save_radiance(&radiance, render_options, output, metadata, hud);
+    }
+    if motion::is_blurred(scene, render_options) {
+        let radiance = motion::render(scene, camera, render_options).ok_or("interrupted")?;
+        return save_radiance(&radiance, render_options, output, metadata, hud);
+    }
+    if output::is_hdr(output) {
+        let stride = width as usize * 4;
+        let mut pixels = vec![0.0; stride * height as usize];
+        embed::render_rgba32f(scene, camera, render_options, 0..height, &mut pixels, stride)?;
+        return output::save_hdr(&pixels, (width, height), output);
+    }
+    if render_options.bit_depth == 16 {
+        let mut img: Frame<Rgba<u16>> = Frame::new(width, height);
+        let mut done = vec![false; tiles::tiles(width, height, render_options.tile_size).len()];
+        if !render_tiles(scene, camera, render_options, &mut img, &mut done) {
+            return Err("interrupted".to_string());
+        }
+        if let Some(hud) = hud {
+            hud.draw(&mut img, 0);
+        }
+        return output::save16(&img, output, metadata);
+    }
+    let is_png = output.extension().is_some_and(|e| e == "png");
+    if is_png && width as u64 * height as u64 > stream::STREAM_PIXELS {
+        return match stream::render_png(scene, camera, render_options, output, metadata, hud)? {
+            true => Ok(()),
+            false => Err("interrupted, rows not reached were left black".to_string()),
+        };
+    }
+    let tile_size = render_options.tile_size;
+    let mut checkpoint = Checkpoint::resume(output, width, height, tile_size, metadata);
+    if render_tiles(scene, camera, render_options, &mut checkpoint.image, &mut checkpoint.done) {
+        if let Some(hud) = hud {
+            hud.draw(&mut checkpoint.image, 0);
+        }
+        output::save(&checkpoint.image, output, metadata)?;
+        Checkpoint::clear(output);
+        Ok(())
+    } else {
+        checkpoint.save(output, metadata).map_err(|e| e.to_string())?;
+        Err(format!(
+            "interrupted, partial image saved to {}",
+            Checkpoint::partial_path(output).display()
+        ))
+    }
+}
+
+/// Writes a frame of `radiance` to `output` as `render` would have written
+/// it traced straight into the output's format.
+pub fn save_radiance(
+    radiance: &Frame<Rgb<f32>>,
+    render_options: &RenderOptions,
+    output: &Path,
+    metadata: &Metadata,
+    hud: Option<&Hud>,
+) -> Result<(), String> {
+    if output::is_hdr(output) {
+        let scale = render_options.exposure.exp2();
+        let pixels: Vec<f32> = radiance
+            .pixels()
+            .flat_map(|p| [p.data[0] * scale, p.data[1] * scale, p.data[2] * scale, 1.0])
+            .collect();
+        return output::save_hdr(&pixels, radiance.dimensions(), output);
+    }
+    if render_options.bit_depth == 16 {
+        let mut img: Frame<Rgba<u16>> = motion::resolve(radiance, render_options);
+        if let Some(hud) = hud {
+            hud.draw(&mut img, 0);
+        }
+        return output::save16(&img, output, metadata);
+    }
+    let mut img: Frame<Rgba<u8>> = motion::resolve(radiance, render_options);
+    if let Some(hud) = hud {
+        hud.draw(&mut img, 0);
+    }
+    output::save(&img, output, metadata)
+}
+
+/// What a render of `scene`, loaded from `scene_file` if it wasn't the
+/// built-in demo, through the camera at path `camera` or else its first,
+/// records about how it was made.
+pub fn metadata(
+    scene_file: Option<&Path>,
+    camera: Option<&str>,
+    scene: &Scene,
+    render_options: &RenderOptions,
+) -> Result<Metadata, String> {
+    let mut metadata = Metadata::new();
+    match scene_file {
+        Some(path) => {
+            let scene_hash =
+                output::file_hash(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            metadata.add("scene", path.display().to_string());
+            metadata.add("scene-hash", format!("fnv1a64:{}", scene_hash));
+        }
+        None => metadata.add("scene", "built-in".to_string()),
+    }
+    metadata.add("camera", camera.unwrap_or("-").to_string());
+    metadata.add("integrator", render_options.integrator.name().to_string());
+    // Scenes without lights are shaded by facing ratio instead, whatever
+    // the integrator
+    if scene.lights.is_empty() {
+        metadata.add("lighting", "facing-ratio".to_string());
+    }
+    metadata.add("samples-per-pixel", render_options.samples_per_pixel.to_string());
+    // Every random choice is hashed from the pixel, ray or point and the
+    // progressive pass, so the pass seeds the whole render
+    metadata.add("seed", render_options.pass.to_string());
+    // Settings given every camera in place of its own, or the camera's
+    let or_camera = |value: Option<f32>| value.map_or("camera".to_string(), |v| v.to_string());
+    let shake = render_options
+        .shake
+        .map_or("none".to_string(), |s| format!("{},{}", s.amplitude, s.frequency));
+    let anaglyph = render_options.anaglyph.map_or("none".to_string(), |a| a.to_string());
+    metadata.add(
+        "options",
+        format!(
+            "size={}x{} bit-depth={} exposure={} output-space={} dither={:?} \
+             robust-intersections={} lod-pixels={} pixel-samples={} light-samples={} \
+             max-depth={} caustic-spread={} integrator={} traversal={} fov={} aperture={} \
+             focal-distance={} shutter={} anaglyph={} shake={}",
+            render_options.width,
+            render_options.height,
+            render_options.bit_depth,
+            render_options.exposure,
+            render_options.output_space.name(),
+            render_options.dither,
+            render_options.robust_intersections,
+            render_options.lod_pixels,
+            render_options.samples_per_pixel,
+            render_options.light_samples,
+            render_options.max_depth,
+            render_options.caustic_spread,
+            render_options.integrator.name(),
+            render_options.traversal.name(),
+            or_camera(render_options.fov),
+            or_camera(render_options.aperture),
+            or_camera(render_options.focal_distance),
+            render_options.shutter,
+            anaglyph,
+            shake
+        ),
+    );
+    Ok(metadata)
+}
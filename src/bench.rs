@@ -0,0 +1,201 @@
+//! Benchmarks: a fixed set of scenes rendered at fixed settings, so work
+//! meant to speed the renderer up is measured against the same yardstick
+//! every time. Each case is rendered a few times and timed at its fastest,
+//! and reported as rays traced a second, overall and per render thread.
+//!
+//! Baselines keep the rate per thread, so one taken on a machine with a
+//! different number of cores still compares, if roughly. A baseline is a
+//! text file with a line for each case: its name, then millions of rays a
+//! second per thread.
+
+use cgmath::{Point3, Vector3};
+use rayon;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use builtin;
+use camera::Camera;
+use geometry::Sphere;
+use light::Light;
+use material::Material;
+use path;
+use primitive::Primitive;
+use procedural::{self, Patch};
+use render::{render_frame, RenderOptions};
+use report;
+use scene::Scene;
+
+// Times each case is rendered, the fastest of which counts
+const RUNS: u32 = 3;
+// Seed the sphere field is scattered with, so it's the same every run
+const SPHERE_FIELD_SEED: u32 = 7;
+
+pub struct Case {
+    pub name: &'static str,
+    scene: Scene,
+    camera: Camera,
+    render_options: RenderOptions,
+}
+
+pub struct Measurement {
+    pub rays: usize,
+    pub seconds: f64,
+}
+
+impl Measurement {
+    /// Millions of rays traced a second.
+    pub fn mrays(&self) -> f64 {
+        self.rays as f64 / self.seconds / 1e6
+    }
+
+    /// Millions of rays traced a second by each render thread.
+    pub fn mrays_per_thread(&self) -> f64 {
+        self.mrays() / rayon::current_num_threads() as f64
+    }
+}
+
+/// The scenes benchmarked, each with the settings it's rendered at: the
+/// demo under direct lighting, the Cornell box under the path integrator,
+/// and a field of many small spheres, with shadows.
+pub fn cases() -> Vec<Case> {
+    let options = |samples_per_pixel, integrator| RenderOptions {
+        width: 480,
+        height: 360,
+        samples_per_pixel,
+        integrator,
+        ..RenderOptions::default()
+    };
+    let mut cases = Vec::new();
+    for &(name, samples, integrator) in &[
+        ("demo", 4, path::Integrator::Direct),
+        ("cornell-box", 4, path::Integrator::Path),
+    ] {
+        let (scene, camera) = builtin::scene(name).expect("benchmarks name built-in scenes");
+        cases.push(Case {
+            name,
+            scene,
+            camera,
+            render_options: options(samples, integrator),
+        });
+    }
+    let (scene, camera) = sphere_field();
+    cases.push(Case {
+        name: "sphere-field",
+        scene,
+        camera,
+        render_options: options(1, path::Integrator::Direct),
+    });
+    cases
+}
+
+// Some hundreds of small spheres scattered over a floor, lit from above
+// by a point light, and seen at a low angle so rays pass many of them
+fn sphere_field() -> (Scene, Camera) {
+    let mut scene = Scene::empty();
+    let floor = Patch {
+        origin: Point3::new(-5.0, 0.0, -12.0),
+        u: Vector3::new(10.0, 0.0, 0.0),
+        v: Vector3::new(0.0, 0.0, 10.0),
+    };
+    let mut rng = procedural::rng(SPHERE_FIELD_SEED);
+    for (i, center) in floor.poisson_disk(&mut rng, 0.4).into_iter().enumerate() {
+        let albedo = [Vector3::new(0.8, 0.3, 0.2), Vector3::new(0.3, 0.5, 0.8)][i % 2];
+        scene.primitives.push(Primitive::Sphere(Sphere {
+            center: center + Vector3::new(0.0, 0.15, 0.0),
+            radius: 0.15,
+            material: Material::diffuse(albedo),
+        }));
+    }
+    scene.primitives.push(Primitive::Sphere(Sphere {
+        center: Point3::new(0.0, -1000.0, -7.0),
+        radius: 1000.0,
+        material: Material::diffuse(Vector3::new(0.7, 0.7, 0.7)),
+    }));
+    scene.lights.push(Light::Point {
+        position: Point3::new(1.0, 6.0, -5.0),
+        color: Vector3::new(1.0, 1.0, 1.0),
+        intensity: 60.0,
+        shaping: None,
+    });
+    let camera = Camera {
+        position: Point3::new(0.0, 1.2, 0.0),
+        at: Vector3::new(0.0, -0.2, -1.0),
+        ..Camera::default()
+    };
+    (scene, camera)
+}
+
+/// Renders `case` `RUNS` times, measuring the fastest.
+pub fn measure(case: &Case) -> Measurement {
+    let mut best: Option<Measurement> = None;
+    for _ in 0..RUNS {
+        let rays_at_start = report::rays_traced();
+        let started = Instant::now();
+        render_frame(&case.scene, &case.camera, &case.render_options);
+        let measurement = Measurement {
+            rays: report::rays_traced() - rays_at_start,
+            seconds: started.elapsed().as_secs_f64(),
+        };
+        if best.as_ref().is_none_or(|b| measurement.seconds < b.seconds) {
+            best = Some(measurement);
+        }
+    }
+    best.expect("there's at least one run")
+}
+
+/// Reads the rate per thread of each case in the baseline at `path`.
+pub fn read_baseline(path: &Path) -> Result<HashMap<String, f64>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut baseline = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [] => {}
+            &[name, rate] => {
+                let rate = rate.parse().map_err(|_| format!("line {}: bad rate {}", i + 1, rate))?;
+                baseline.insert(name.to_string(), rate);
+            }
+            _ => return Err(format!("line {}: expected a case and a rate", i + 1)),
+        }
+    }
+    Ok(baseline)
+}
+
+/// Writes the rate per thread of each of `results` as a baseline to
+/// `path`.
+pub fn write_baseline(path: &Path, results: &[(&str, f64)]) -> Result<(), String> {
+    let lines: String = results
+        .iter()
+        .map(|&(name, rate)| format!("{} {:.4}\n", name, rate))
+        .collect();
+    fs::write(path, lines).map_err(|e| e.to_string())
+}
+
+/// Measures every case, printing a line for each with its change on the
+/// rate `baseline` has for it, and returns each case's name and rate per
+/// thread.
+pub fn run(baseline: &HashMap<String, f64>) -> Vec<(&'static str, f64)> {
+    let threads = rayon::current_num_threads();
+    println!("{} render threads, fastest of {} runs", threads, RUNS);
+    let mut results = Vec::new();
+    for case in cases() {
+        let measurement = measure(&case);
+        let rate = measurement.mrays_per_thread();
+        let change = match baseline.get(case.name) {
+            Some(&base) => format!("{:+.1}% on baseline", (rate / base - 1.0) * 100.0),
+            None => "no baseline".to_string(),
+        };
+        println!(
+            "{:<14} {:>8.3}s {:>8.2} Mray/s {:>7.3} Mray/s per thread  {}",
+            case.name,
+            measurement.seconds,
+            measurement.mrays(),
+            rate,
+            change
+        );
+        results.push((case.name, rate));
+    }
+    results
+}
@@ -0,0 +1,157 @@
+//! Scenes built into the tracer, chosen by name with `--builtin`: the demo
+//! seen with no scene file, scenes well known enough to check shading
+//! against, and a studio for previewing materials on.
+
+use cgmath::{Point3, Vector3};
+
+use camera::Camera;
+use cuboid::Cuboid;
+use geometry::Sphere;
+use light::Light;
+use material::Material;
+use mesh::Mesh;
+use primitive::Primitive;
+use scene::Scene;
+use texture::{Checker, Pattern};
+
+/// The built-in scene called `name`, and the camera it's meant to be seen
+/// through.
+pub fn scene(name: &str) -> Option<(Scene, Camera)> {
+    match name {
+        "demo" => Some((Scene::demo(), Camera::default())),
+        "cornell-box" => Some(cornell_box()),
+        "material-preview" => Some(material_preview(Material::default())),
+        _ => None,
+    }
+}
+
+// A block `size` across, standing on the floor at `center` and turned
+// `degrees` about the vertical
+fn block(center: Point3<f32>, size: Vector3<f32>, degrees: f32, material: Material) -> Primitive {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let unit = Cuboid {
+        min: Point3::new(-0.5, 0.0, -0.5),
+        max: Point3::new(0.5, 1.0, 0.5),
+        material,
+    };
+    let corners = unit
+        .corners()
+        .iter()
+        .map(|c| {
+            let (x, z) = (c.x * size.x, c.z * size.z);
+            Point3::new(center.x + x * cos + z * sin, c.y * size.y, center.z - x * sin + z * cos)
+        })
+        .collect();
+    let mesh = Mesh::new(corners, Cuboid::triangles()).expect("triangles index the corners");
+    Primitive::Mesh(mesh.with_material(material))
+}
+
+// The Cornell box, as measured at the Cornell Program of Computer Graphics,
+// in metres with its open side facing +Z: a white room with a red wall on
+// the left and a green one on the right, two white blocks, and a panel in
+// the ceiling lighting it. The panel is emissive rather than a light, so
+// the box is lit under the path integrator, and by the headlight under the
+// direct one.
+fn cornell_box() -> (Scene, Camera) {
+    let mut scene = Scene::empty();
+
+    let white = Material::diffuse(Vector3::new(0.725, 0.71, 0.68));
+    let red = Material::diffuse(Vector3::new(0.63, 0.065, 0.05));
+    let green = Material::diffuse(Vector3::new(0.14, 0.45, 0.091));
+    let light = Material {
+        emissive: Vector3::new(17.0, 12.0, 4.0),
+        ..Material::diffuse(Vector3::new(0.78, 0.78, 0.78))
+    };
+    let (width, height, depth) = (0.556, 0.549, 0.559);
+    // Walls are slabs a centimetre thick outside the room
+    let wall = 0.01;
+    let slab = |min: [f32; 3], max: [f32; 3], material| {
+        Primitive::Cuboid(Cuboid {
+            min: Point3::new(min[0], min[1], min[2]),
+            max: Point3::new(max[0], max[1], max[2]),
+            material,
+        })
+    };
+    let shapes = vec![
+        slab([0.0, -wall, -depth], [width, 0.0, 0.0], white),
+        slab([0.0, height, -depth], [width, height + wall, 0.0], white),
+        slab([0.0, 0.0, -depth - wall], [width, height, -depth], white),
+        slab([-wall, 0.0, -depth], [0.0, height, 0.0], red),
+        slab([width, 0.0, -depth], [width + wall, height, 0.0], green),
+        slab([0.213, height - 0.001, -0.332], [0.343, height, -0.227], light),
+        block(Point3::new(0.371, 0.0, -0.169), Vector3::new(0.165, 0.165, 0.165), -17.0, white),
+        block(Point3::new(0.188, 0.0, -0.351), Vector3::new(0.165, 0.33, 0.165), 17.0, white),
+    ];
+    for shape in shapes {
+        scene.primitives.push(shape);
+    }
+
+    let camera = Camera {
+        position: Point3::new(0.278, 0.273, 0.8),
+        up: Vector3::new(0.0, 1.0, 0.0),
+        at: Vector3::new(0.0, 0.0, -1.0),
+        fov: 39.3,
+        aperture: 0.0,
+        focal_distance: 1.0,
+        keys: Vec::new(),
+    };
+    (scene, camera)
+}
+
+/// A ball of `material` in a photographer's studio, for thumbnails of
+/// materials: a neutral grey backdrop, a floor checkered in two greys so
+/// reflection and refraction show what's around the ball, and a key light,
+/// a dimmer fill and a rim light behind, all at the height of a person.
+pub fn material_preview(material: Material) -> (Scene, Camera) {
+    let mut scene = Scene::empty();
+
+    let grey = Vector3::new(0.5, 0.5, 0.5);
+    let floor = Material {
+        texture: Some(Pattern::Checker(Checker {
+            even: grey,
+            odd: Vector3::new(0.3, 0.3, 0.3),
+            scale: 0.5,
+        })),
+        ..Material::diffuse(grey)
+    };
+    scene.primitives.push(Primitive::Cuboid(Cuboid {
+        min: Point3::new(-6.0, -0.1, -3.0),
+        max: Point3::new(6.0, 0.0, 6.0),
+        material: floor,
+    }));
+    scene.primitives.push(Primitive::Cuboid(Cuboid {
+        min: Point3::new(-6.0, 0.0, -3.1),
+        max: Point3::new(6.0, 6.0, -3.0),
+        material: Material::diffuse(grey),
+    }));
+    scene.primitives.push(Primitive::Sphere(Sphere {
+        center: Point3::new(0.0, 1.0, 0.0),
+        radius: 1.0,
+        material,
+    }));
+
+    let white = Vector3::new(1.0, 1.0, 1.0);
+    for &(position, intensity) in &[
+        // Key, above and to the left of the camera
+        ([-3.0, 4.0, 3.0], 30.0),
+        // Fill, low on the right, softening the key's shadows
+        ([4.0, 2.0, 3.0], 8.0),
+        // Rim, behind the ball, picking out its edge against the backdrop
+        ([2.0, 3.0, -0.5], 6.0),
+    ] {
+        scene.lights.push(Light::Point {
+            position: Point3::new(position[0], position[1], position[2]),
+            color: white,
+            intensity,
+            shaping: None,
+        });
+    }
+
+    let camera = Camera {
+        position: Point3::new(0.0, 1.6, 6.0),
+        at: Vector3::new(0.0, -0.6, -6.0),
+        fov: 30.0,
+        ..Camera::default()
+    };
+    (scene, camera)
+}
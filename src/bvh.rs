@@ -0,0 +1,333 @@
+//! A wide bounding volume hierarchy over the triangles of a mesh, with its
+//! child bounds quantized, as an alternative to testing a ray against every
+//! triangle.
+//!
+//! Each node holds up to eight children and stores their boxes as 8-bit
+//! steps from a corner of the node's own box, so a node takes 104 bytes
+//! where eight boxes of floats would take 192 on their own. Tracing large
+//! meshes on the CPU is bound by memory bandwidth more than arithmetic, and
+//! the smaller nodes spend a little arithmetic decoding boxes to fetch far
+//! less. Steps are rounded outwards, so a decoded box only ever grows and
+//! no hit is lost to quantization.
+//!
+//! Nodes are split at the median of the triangles' centres along the
+//! longest axis, up to `LEAF_SIZE` triangles to a leaf. The hierarchy is
+//! built the first time a ray traverses a mesh with it, and moves with the
+//! mesh as it is translated rather than being rebuilt.
+
+use cgmath::{EuclideanSpace, Point3, Vector3};
+use std::cmp::Ordering;
+use std::f32;
+
+use geometry::Ray;
+
+// Children per node
+const WIDTH: usize = 8;
+// Most triangles a leaf holds
+const LEAF_SIZE: usize = 4;
+// Nodes waiting to be visited can't outnumber this, as every level of a
+// tree of at most 2^27 triangles adds fewer than `WIDTH` of them
+const STACK_SIZE: usize = 256;
+
+// Children are node indices, leaves or empty slots. A leaf packs its count
+// of triangles, less one, above the position of its first in `order`.
+const EMPTY: u32 = u32::MAX;
+const LEAF: u32 = 1 << 31;
+const COUNT_SHIFT: u32 = 27;
+const START_MASK: u32 = (1 << COUNT_SHIFT) - 1;
+
+/// How rays are tested against the triangles of meshes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Traversal {
+    // Against every triangle, once the ray meets the mesh's bounds
+    Linear,
+    // Through a quantized 8-wide hierarchy
+    WideBvh,
+}
+
+impl Traversal {
+    pub fn from_name(name: &str) -> Option<Traversal> {
+        match name {
+            "linear" => Some(Traversal::Linear),
+            "wide-bvh" => Some(Traversal::WideBvh),
+            _ => None,
+        }
+    }
+
+    /// The name `from_name` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Traversal::Linear => "linear",
+            Traversal::WideBvh => "wide-bvh",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    // Corner the children's boxes are measured from, and the size of a step
+    // along each axis
+    origin: [f32; 3],
+    step: [f32; 3],
+    // Each child's box, in steps from `origin`
+    lower: [[u8; WIDTH]; 3],
+    upper: [[u8; WIDTH]; 3],
+    children: [u32; WIDTH],
+}
+
+#[derive(Clone, Copy)]
+struct Item {
+    index: u32,
+    min: Point3<f32>,
+    max: Point3<f32>,
+    center: Point3<f32>,
+}
+
+pub struct WideBvh {
+    nodes: Vec<Node>,
+    root: u32,
+    // Triangle indices, in the order leaves refer to them
+    order: Vec<u32>,
+    // How far the mesh had been translated when this was built
+    moved: Vector3<f32>,
+}
+
+fn axis(p: Point3<f32>, axis: usize) -> f32 {
+    match axis {
+        0 => p.x,
+        1 => p.y,
+        _ => p.z,
+    }
+}
+
+// Box around every box of `boxes`
+fn enclose<I>(boxes: I) -> (Point3<f32>, Point3<f32>)
+where
+    I: Iterator<Item = (Point3<f32>, Point3<f32>)>,
+{
+    let inf = f32::INFINITY;
+    let start = (Point3::new(inf, inf, inf), Point3::new(-inf, -inf, -inf));
+    boxes.fold(start, |(min, max), (lo, hi)| {
+        (
+            Point3::new(min.x.min(lo.x), min.y.min(lo.y), min.z.min(lo.z)),
+            Point3::new(max.x.max(hi.x), max.y.max(hi.y), max.z.max(hi.z)),
+        )
+    })
+}
+
+fn union(items: &[Item]) -> (Point3<f32>, Point3<f32>) {
+    enclose(items.iter().map(|item| (item.min, item.max)))
+}
+
+impl WideBvh {
+    /// A hierarchy over triangles with boxes `bounds`, in order, of a mesh
+    /// translated `moved` in all, unless there are too many triangles for
+    /// leaves to refer to.
+    pub fn build(bounds: &[(Point3<f32>, Point3<f32>)], moved: Vector3<f32>) -> Option<WideBvh> {
+        if bounds.len() > START_MASK as usize + 1 {
+            return None;
+        }
+        let mut items: Vec<Item> = bounds
+            .iter()
+            .enumerate()
+            .map(|(i, &(min, max))| Item {
+                index: i as u32,
+                min,
+                max,
+                center: min.midpoint(max),
+            })
+            .collect();
+        let mut bvh = WideBvh {
+            nodes: Vec::new(),
+            root: EMPTY,
+            order: Vec::with_capacity(items.len()),
+            moved,
+        };
+        if !items.is_empty() {
+            bvh.root = bvh.subtree(&mut items);
+        }
+        Some(bvh)
+    }
+
+    // Builds the subtree over `items`, returning the child naming it
+    fn subtree(&mut self, items: &mut [Item]) -> u32 {
+        if items.len() <= LEAF_SIZE {
+            let start = self.order.len() as u32;
+            self.order.extend(items.iter().map(|item| item.index));
+            return LEAF | ((items.len() as u32 - 1) << COUNT_SHIFT) | start;
+        }
+
+        // Split the largest group in two until there is a group per child
+        let mut groups = vec![(0, items.len())];
+        while groups.len() < WIDTH {
+            let largest = (0..groups.len())
+                .max_by_key(|&g| groups[g].1 - groups[g].0)
+                .filter(|&g| groups[g].1 - groups[g].0 > LEAF_SIZE);
+            let g = match largest {
+                Some(g) => g,
+                None => break,
+            };
+            let (start, end) = groups[g];
+            let group = &mut items[start..end];
+            let (min, max) = enclose(group.iter().map(|item| (item.center, item.center)));
+            let extent = max - min;
+            let longest = if extent.x >= extent.y && extent.x >= extent.z {
+                0
+            } else if extent.y >= extent.z {
+                1
+            } else {
+                2
+            };
+            let middle = group.len() / 2;
+            group.select_nth_unstable_by(middle, |a, b| {
+                let (a, b) = (axis(a.center, longest), axis(b.center, longest));
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            });
+            groups[g] = (start, start + middle);
+            groups.push((start + middle, end));
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            origin: [0.0; 3],
+            step: [0.0; 3],
+            lower: [[0; WIDTH]; 3],
+            upper: [[0; WIDTH]; 3],
+            children: [EMPTY; WIDTH],
+        });
+        let (min, max) = union(items);
+        let origin = [min.x, min.y, min.z];
+        // A hair over a 255th of the extent, so the top step clears the box
+        let step = [
+            ((max.x - min.x) / 255.0 * 1.0001).max(f32::MIN_POSITIVE),
+            ((max.y - min.y) / 255.0 * 1.0001).max(f32::MIN_POSITIVE),
+            ((max.z - min.z) / 255.0 * 1.0001).max(f32::MIN_POSITIVE),
+        ];
+        let mut node = self.nodes[index];
+        node.origin = origin;
+        node.step = step;
+        for (slot, &(start, end)) in groups.iter().enumerate() {
+            let (child_min, child_max) = union(&items[start..end]);
+            for a in 0..3 {
+                let (lo, hi) = (axis(child_min, a), axis(child_max, a));
+                let mut lower = ((lo - origin[a]) / step[a]).floor().clamp(0.0, 255.0) as u8;
+                while lower > 0 && origin[a] + lower as f32 * step[a] > lo {
+                    lower -= 1;
+                }
+                let mut upper = ((hi - origin[a]) / step[a]).ceil().clamp(0.0, 255.0) as u8;
+                while upper < 255 && origin[a] + upper as f32 * step[a] < hi {
+                    upper += 1;
+                }
+                node.lower[a][slot] = lower;
+                node.upper[a][slot] = upper;
+            }
+            node.children[slot] = self.subtree(&mut items[start..end]);
+        }
+        self.nodes[index] = node;
+        index as u32
+    }
+
+    // Calls `visit` with each triangle in the leaves `ray` might meet nearer
+    // than `limit`, nearest boxes first, for a mesh translated `moved` in
+    // all. `visit` returns the new limit, and stops the walk by returning a
+    // negative one.
+    fn walk<F>(&self, ray: &Ray, moved: Vector3<f32>, mut limit: f32, mut visit: F)
+    where
+        F: FnMut(usize, f32) -> f32,
+    {
+        if self.root == EMPTY {
+            return;
+        }
+        // The hierarchy lies where the mesh was when it was built
+        let origin = ray.origin + (self.moved - moved);
+        let o = [origin.x, origin.y, origin.z];
+        let d = ray.direction;
+        let inverse = [1.0 / d.x, 1.0 / d.y, 1.0 / d.z];
+        let mut stack = [0u32; STACK_SIZE];
+        stack[0] = self.root;
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let child = stack[top];
+            if child & LEAF != 0 {
+                let start = (child & START_MASK) as usize;
+                let count = ((child & !LEAF) >> COUNT_SHIFT) as usize + 1;
+                for &index in &self.order[start..start + count] {
+                    limit = visit(index as usize, limit);
+                    if limit < 0.0 {
+                        return;
+                    }
+                }
+                continue;
+            }
+            let node = &self.nodes[child as usize];
+            let mut near = [(0u32, 0f32); WIDTH];
+            let mut hits = 0;
+            for slot in 0..WIDTH {
+                if node.children[slot] == EMPTY {
+                    break;
+                }
+                let (mut entry, mut exit) = (0f32, limit);
+                for a in 0..3 {
+                    let lo = node.origin[a] + node.lower[a][slot] as f32 * node.step[a];
+                    let hi = node.origin[a] + node.upper[a][slot] as f32 * node.step[a];
+                    let t0 = (lo - o[a]) * inverse[a];
+                    let t1 = (hi - o[a]) * inverse[a];
+                    // Comparisons with NaN, from a ray in the plane of a
+                    // face, leave the interval as it was
+                    entry = entry.max(t0.min(t1));
+                    // Widened against rounding ("Robust BVH Ray
+                    // Traversal", Ize)
+                    exit = exit.min(t0.max(t1) * 1.000_000_4);
+                }
+                if entry <= exit {
+                    near[hits] = (node.children[slot], entry);
+                    hits += 1;
+                }
+            }
+            // Farthest first onto the stack, so the nearest comes off first
+            let near = &mut near[..hits];
+            near.sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+            for &(child, _) in near.iter() {
+                stack[top] = child;
+                top += 1;
+            }
+        }
+    }
+
+    /// Nearest of the triangles `ray` meets, and the distance to it, where
+    /// `test` gives the distance to triangle `index` or None if the ray
+    /// misses it. `moved` is how far the mesh has been translated in all.
+    pub fn closest<F>(&self, ray: &Ray, moved: Vector3<f32>, mut test: F) -> Option<(usize, f32)>
+    where
+        F: FnMut(usize) -> Option<f32>,
+    {
+        let mut nearest = None;
+        self.walk(ray, moved, f32::INFINITY, |index, limit| match test(index) {
+            Some(t) if t < limit => {
+                nearest = Some((index, t));
+                t
+            }
+            _ => limit,
+        });
+        nearest
+    }
+
+    /// Whether any triangle nearer than `max_distance` blocks `ray`, where
+    /// `blocks` says whether triangle `index` does.
+    pub fn any<F>(&self, ray: &Ray, moved: Vector3<f32>, max_distance: f32, mut blocks: F) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut blocked = false;
+        self.walk(ray, moved, max_distance, |index, limit| {
+            if blocks(index) {
+                blocked = true;
+                -1.0
+            } else {
+                limit
+            }
+        });
+        blocked
+    }
+}
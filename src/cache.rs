@@ -0,0 +1,1154 @@
+//! Binary caches of loaded scenes, so a large scene is parsed once and
+//! read back in a fraction of the time on later runs.
+//!
+//! The cache of `<scene>` is kept next to it as `<scene>.cache`. It holds
+//! the loaded stage, meshes already triangulated and transformed into
+//! place, along with a hash of the scene file's contents and the units it
+//! was loaded in, and every file the scene read through the resolver:
+//! included layers, models, IES profiles, environment maps and fonts, each
+//! as the scene named it, where it was found and a hash of its contents. A
+//! cache is only used while all of those still match, with each asset
+//! resolved again to check it'd still be found in the same place, so
+//! editing the scene or any of its assets, loading it in other units, or
+//! changing the `${VAR}`s or search paths its assets are found through,
+//! parses it afresh and rewrites the cache. Hashes are FNV-1a, which is
+//! quick and stable from one build to the next.
+//!
+//! Meshes are stored as they lie in memory, so on Unix a cache is mapped
+//! rather than read and its meshes used where they lie, shared between
+//! every process rendering the scene; see `mapped`. A cache is therefore
+//! only ever replaced whole, by renaming a new one over it.
+//!
+//! IES profiles are small and quick to parse, and environment maps are
+//! images already, so the cache keeps only their paths and reads them again
+//! through the resolver. Labels keep their text as it was rasterized, so
+//! fonts aren't read at all. Scenes whose handles have gaps, from objects
+//! removed while loading, aren't cached, since handles are stored as
+//! positions.
+
+use cgmath::{Matrix3, Matrix4, Point3, Vector3};
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use camera::{Camera, CameraKey};
+use components::{Animation, Column, Keyframes, MeshSource};
+use cuboid::Cuboid;
+use environment::Environment;
+use geometry::Sphere;
+use handle::{Handle, Pool};
+use label::{Coverage, Label};
+use light::{Light, Portal, Shaping};
+use mapped::{Buffer, Mapping, Plain};
+use material::Material;
+use mesh::Mesh;
+use output::{fnv1a, FNV1A_OFFSET};
+use particles::{Emitter, EmitterSettings};
+use primitive::{Intersectable, Primitive};
+use resolve::{self, AssetResolver, Resolution};
+use scene::{parse_stage, Scene, Units, UpAxis};
+use texture::{Checker, Marble, Noise, Pattern};
+use usd::Stage;
+
+const MAGIC: &[u8] = b"rs-tracer scene cache\n";
+// Bumped whenever the layout below changes
+const VERSION: u32 = 10;
+
+// Hash of the contents of the file at `path`, if it can be read
+fn file_hash(path: &Path) -> Option<u64> {
+    fs::read(path).ok().map(|bytes| fnv1a(&bytes, FNV1A_OFFSET))
+}
+
+// Hash of what the stage loaded from `path` in `units` depends on besides
+// the files it names
+fn scene_hash(path: &Path, units: &Units) -> Option<u64> {
+    let mut key = Vec::new();
+    units.write(&mut key);
+    Some(fnv1a(&key, file_hash(path)?))
+}
+
+fn cache_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".cache");
+    PathBuf::from(name)
+}
+
+/// Loads the scene file at `path` from its cache if that is up to date, or
+/// else parses it and writes the cache for next time. A cache that can't
+/// be written is reported among the stage's warnings.
+pub fn load(path: &Path, units: &Units, resolver: &AssetResolver) -> Result<Stage, String> {
+    let cache = cache_path(path);
+    let key = scene_hash(path, units);
+    if let Some(key) = key {
+        // Safe as long as nothing writes caches in place: this module only
+        // ever renames a whole new cache over an old one, which leaves the
+        // old one mapped as it was
+        let cached = match unsafe { Mapping::open(&cache) } {
+            Ok(mapping) => {
+                let mapping = Arc::new(mapping);
+                read_cache(&mut Reader::new(&mapping, Some(&mapping), resolver), key)
+            }
+            Err(_) => match fs::read(&cache) {
+                Ok(bytes) => read_cache(&mut Reader::new(&bytes, None, resolver), key),
+                Err(_) => Ok(None),
+            },
+        };
+        if let Ok(Some(stage)) = cached {
+            return Ok(stage);
+        }
+    }
+
+    // Only the files this load resolves are its dependencies
+    resolver.take_resolved();
+    let mut stage = parse_stage(path, units, resolver)?;
+    let mut dependencies = resolver.take_resolved();
+    dependencies.sort();
+    dependencies.dedup();
+    let key = match key {
+        Some(key) => key,
+        None => return Ok(stage),
+    };
+    if let Some(bytes) = write_cache(&stage, key, &dependencies) {
+        // Written aside and renamed into place, so processes with the old
+        // cache mapped keep it whole
+        let mut partial = cache.clone().into_os_string();
+        partial.push(format!(".{}.partial", process::id()));
+        if let Err(e) = fs::write(&partial, bytes).and_then(|_| fs::rename(&partial, &cache)) {
+            stage
+                .warnings
+                .push(format!("can't write scene cache {}: {}", cache.display(), e));
+        }
+    }
+    Ok(stage)
+}
+
+// The stage in a cache, or None if the cache is out of date
+fn read_cache(input: &mut Reader, key: u64) -> Result<Option<Stage>, String> {
+    if input.take(MAGIC.len())? != MAGIC || u32::read(input)? != VERSION {
+        return Ok(None);
+    }
+    if u64::read(input)? != key {
+        return Ok(None);
+    }
+    for _ in 0..u32::read(input)? {
+        let asset = String::read(input)?;
+        let (dir, path) = (PathBuf::read(input)?, PathBuf::read(input)?);
+        let hash = u64::read(input)?;
+        // Found elsewhere now, through other variables or search paths, or
+        // not at all
+        if input.resolver.resolve(&asset, &dir).ok() != Some(path.clone()) {
+            return Ok(None);
+        }
+        if file_hash(&path) != Some(hash) {
+            return Ok(None);
+        }
+    }
+    Stage::read(input).map(Some)
+}
+
+// The cache of `stage`, unless its handles can't be stored
+fn write_cache(stage: &Stage, key: u64, dependencies: &[Resolution]) -> Option<Vec<u8>> {
+    let scene = &stage.scene;
+    if !(scene.primitives.is_packed() && scene.lights.is_packed() && scene.materials.is_packed())
+    {
+        return None;
+    }
+    let mut out = MAGIC.to_vec();
+    VERSION.write(&mut out);
+    key.write(&mut out);
+    let hashed: Vec<(&Resolution, u64)> = dependencies
+        .iter()
+        .filter_map(|r| file_hash(&r.path).map(|hash| (r, hash)))
+        .collect();
+    (hashed.len() as u32).write(&mut out);
+    for &(resolution, hash) in &hashed {
+        resolution.asset.write(&mut out);
+        resolution.dir.write(&mut out);
+        resolution.path.write(&mut out);
+        hash.write(&mut out);
+    }
+    stage.write(&mut out);
+    Some(out)
+}
+
+struct Reader<'a> {
+    // The whole cache, and what is left to read of it
+    whole: &'a [u8],
+    bytes: &'a [u8],
+    // The cache's mapping, if mapped, for meshes to be read in place
+    mapping: Option<&'a Arc<Mapping>>,
+    // Profiles aren't stored, but read again through the resolver
+    resolver: &'a AssetResolver,
+}
+
+impl<'a> Reader<'a> {
+    fn new(
+        whole: &'a [u8],
+        mapping: Option<&'a Arc<Mapping>>,
+        resolver: &'a AssetResolver,
+    ) -> Reader<'a> {
+        Reader {
+            whole,
+            bytes: whole,
+            mapping,
+            resolver,
+        }
+    }
+
+    // Offset of the next byte to read from the start of the cache
+    fn offset(&self) -> usize {
+        self.whole.len() - self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if count > self.bytes.len() {
+            return Err("truncated scene cache".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
+
+// A value as stored in a cache
+trait Binary: Sized {
+    fn write(&self, out: &mut Vec<u8>);
+    fn read(input: &mut Reader) -> Result<Self, String>;
+}
+
+impl Binary for u32 {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(input: &mut Reader) -> Result<u32, String> {
+        let b = input.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+impl Binary for u64 {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read(input: &mut Reader) -> Result<u64, String> {
+        let b = input.take(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+}
+
+impl Binary for f32 {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.to_bits().write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<f32, String> {
+        u32::read(input).map(f32::from_bits)
+    }
+}
+
+impl Binary for String {
+    fn write(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).write(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn read(input: &mut Reader) -> Result<String, String> {
+        let len = u32::read(input)? as usize;
+        String::from_utf8(input.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+impl Binary for PathBuf {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.to_string_lossy().into_owned().write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<PathBuf, String> {
+        String::read(input).map(PathBuf::from)
+    }
+}
+
+impl<T: Binary> Binary for Vec<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).write(out);
+        for value in self {
+            value.write(out);
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Vec<T>, String> {
+        let len = u32::read(input)?;
+        // Not reserved up front, so a corrupt length can't ask for more
+        // memory than the cache holds
+        let mut values = Vec::new();
+        for _ in 0..len {
+            values.push(T::read(input)?);
+        }
+        Ok(values)
+    }
+}
+
+impl<T: Binary> Binary for Option<T> {
+    fn write(&self, out: &mut Vec<u8>) {
+        match *self {
+            Some(ref value) => {
+                1u32.write(out);
+                value.write(out);
+            }
+            None => 0u32.write(out),
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Option<T>, String> {
+        match u32::read(input)? {
+            0 => Ok(None),
+            _ => T::read(input).map(Some),
+        }
+    }
+}
+
+impl<A: Binary, B: Binary> Binary for (A, B) {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.0.write(out);
+        self.1.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<(A, B), String> {
+        Ok((A::read(input)?, B::read(input)?))
+    }
+}
+
+impl Binary for [u32; 3] {
+    fn write(&self, out: &mut Vec<u8>) {
+        for value in self {
+            value.write(out);
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<[u32; 3], String> {
+        Ok([u32::read(input)?, u32::read(input)?, u32::read(input)?])
+    }
+}
+
+impl Binary for Point3<f32> {
+    fn write(&self, out: &mut Vec<u8>) {
+        for &value in &[self.x, self.y, self.z] {
+            value.write(out);
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Point3<f32>, String> {
+        Ok(Point3::new(f32::read(input)?, f32::read(input)?, f32::read(input)?))
+    }
+}
+
+impl Binary for Vector3<f32> {
+    fn write(&self, out: &mut Vec<u8>) {
+        for &value in &[self.x, self.y, self.z] {
+            value.write(out);
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Vector3<f32>, String> {
+        Ok(Vector3::new(f32::read(input)?, f32::read(input)?, f32::read(input)?))
+    }
+}
+
+impl Binary for Matrix3<f32> {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.x.write(out);
+        self.y.write(out);
+        self.z.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Matrix3<f32>, String> {
+        Ok(Matrix3::from_cols(
+            Vector3::read(input)?,
+            Vector3::read(input)?,
+            Vector3::read(input)?,
+        ))
+    }
+}
+
+impl Binary for Matrix4<f32> {
+    fn write(&self, out: &mut Vec<u8>) {
+        let columns: &[[f32; 4]; 4] = self.as_ref();
+        for column in columns {
+            for value in column {
+                value.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Matrix4<f32>, String> {
+        let mut columns = [[0.0; 4]; 4];
+        for column in &mut columns {
+            for value in column.iter_mut() {
+                *value = f32::read(input)?;
+            }
+        }
+        Ok(Matrix4::from(columns))
+    }
+}
+
+impl Binary for Units {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.meters_per_unit.write(out);
+        let up: u32 = match self.up_axis {
+            UpAxis::Y => 0,
+            UpAxis::Z => 1,
+        };
+        up.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Units, String> {
+        let meters_per_unit = f32::read(input)?;
+        let up_axis = match u32::read(input)? {
+            0 => UpAxis::Y,
+            _ => UpAxis::Z,
+        };
+        Ok(Units {
+            meters_per_unit,
+            up_axis,
+        })
+    }
+}
+
+impl Binary for Material {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.albedo.write(out);
+        self.specular.write(out);
+        self.shininess.write(out);
+        self.reflectivity.write(out);
+        self.roughness.write(out);
+        self.emissive.write(out);
+        self.transparency.write(out);
+        self.ior.write(out);
+        self.texture.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Material, String> {
+        Ok(Material {
+            albedo: Vector3::read(input)?,
+            specular: f32::read(input)?,
+            shininess: f32::read(input)?,
+            reflectivity: f32::read(input)?,
+            roughness: f32::read(input)?,
+            emissive: Vector3::read(input)?,
+            transparency: f32::read(input)?,
+            ior: f32::read(input)?,
+            texture: Option::read(input)?,
+        })
+    }
+}
+
+impl Binary for Pattern {
+    fn write(&self, out: &mut Vec<u8>) {
+        match *self {
+            Pattern::Checker(ref checker) => {
+                0u32.write(out);
+                checker.even.write(out);
+                checker.odd.write(out);
+                checker.scale.write(out);
+            }
+            Pattern::Noise(ref noise) => {
+                1u32.write(out);
+                noise.low.write(out);
+                noise.high.write(out);
+                noise.scale.write(out);
+            }
+            Pattern::Marble(ref marble) => {
+                2u32.write(out);
+                marble.base.write(out);
+                marble.vein.write(out);
+                marble.scale.write(out);
+                marble.turbulence.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Pattern, String> {
+        match u32::read(input)? {
+            0 => Ok(Pattern::Checker(Checker {
+                even: Vector3::read(input)?,
+                odd: Vector3::read(input)?,
+                scale: f32::read(input)?,
+            })),
+            1 => Ok(Pattern::Noise(Noise {
+                low: Vector3::read(input)?,
+                high: Vector3::read(input)?,
+                scale: f32::read(input)?,
+            })),
+            2 => Ok(Pattern::Marble(Marble {
+                base: Vector3::read(input)?,
+                vein: Vector3::read(input)?,
+                scale: f32::read(input)?,
+                turbulence: f32::read(input)?,
+            })),
+            other => Err(format!("unknown texture {} in scene cache", other)),
+        }
+    }
+}
+
+impl Binary for Camera {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.position.write(out);
+        self.up.write(out);
+        self.at.write(out);
+        self.fov.write(out);
+        self.aperture.write(out);
+        self.focal_distance.write(out);
+        self.keys.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Camera, String> {
+        Ok(Camera {
+            position: Point3::read(input)?,
+            up: Vector3::read(input)?,
+            at: Vector3::read(input)?,
+            fov: f32::read(input)?,
+            aperture: f32::read(input)?,
+            focal_distance: f32::read(input)?,
+            keys: Vec::read(input)?,
+        })
+    }
+}
+
+impl Binary for CameraKey {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.time.write(out);
+        self.position.write(out);
+        self.at.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<CameraKey, String> {
+        Ok(CameraKey {
+            time: f32::read(input)?,
+            position: Point3::read(input)?,
+            at: Vector3::read(input)?,
+        })
+    }
+}
+
+impl Binary for Sphere {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.center.write(out);
+        self.radius.write(out);
+        self.material.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Sphere, String> {
+        Ok(Sphere {
+            center: Point3::read(input)?,
+            radius: f32::read(input)?,
+            material: Material::read(input)?,
+        })
+    }
+}
+
+impl Binary for Cuboid {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.min.write(out);
+        self.max.write(out);
+        self.material.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Cuboid, String> {
+        Ok(Cuboid {
+            min: Point3::read(input)?,
+            max: Point3::read(input)?,
+            material: Material::read(input)?,
+        })
+    }
+}
+
+impl Binary for Label {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.text.write(out);
+        self.font.write(out);
+        self.center.write(out);
+        self.height.write(out);
+        self.up.write(out);
+        self.material.write(out);
+        (self.coverage.width as u32).write(out);
+        (self.coverage.height as u32).write(out);
+        self.coverage.values.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Label, String> {
+        let text = String::read(input)?;
+        let font = Option::read(input)?;
+        let (center, height, up) = (Point3::read(input)?, f32::read(input)?, Vector3::read(input)?);
+        let material = Material::read(input)?;
+        let (width, rows) = (u32::read(input)? as usize, u32::read(input)? as usize);
+        let values = Vec::read(input)?;
+        if width == 0 || rows == 0 || values.len() != width * rows {
+            return Err(format!("label \"{}\" in scene cache is the wrong size", text));
+        }
+        Ok(Label {
+            text,
+            font,
+            center,
+            height,
+            up,
+            material,
+            coverage: Arc::new(Coverage {
+                width,
+                height: rows,
+                values,
+            }),
+        })
+    }
+}
+
+// Buffers of plain values are stored as they lie in memory on little-endian
+// machines, starting on a word boundary of the cache, so that a mapped
+// cache can be used in place
+fn write_buffer<T: Plain + Binary>(values: &[T], out: &mut Vec<u8>) {
+    (values.len() as u32).write(out);
+    let padding = (4 - out.len() % 4) % 4;
+    out.resize(out.len() + padding, 0);
+    for value in values {
+        value.write(out);
+    }
+}
+
+fn read_buffer<T: Plain + Binary>(input: &mut Reader) -> Result<Buffer<T>, String> {
+    let len = u32::read(input)? as usize;
+    input.take((4 - input.offset() % 4) % 4)?;
+    let offset = input.offset();
+    let mapped = input.mapping.and_then(|mapping| Buffer::mapped(mapping, offset, len));
+    match mapped {
+        Some(buffer) => {
+            input.take(len * mem::size_of::<T>())?;
+            Ok(buffer)
+        }
+        None => {
+            let mut values = Vec::new();
+            for _ in 0..len {
+                values.push(T::read(input)?);
+            }
+            Ok(values.into())
+        }
+    }
+}
+
+// Meshes are stored as they were built, and their bounds found again
+impl Binary for Mesh {
+    fn write(&self, out: &mut Vec<u8>) {
+        write_buffer(self.positions(), out);
+        write_buffer(self.indices(), out);
+        write_buffer(self.normals(), out);
+        write_buffer(self.normal_indices(), out);
+        let attributes: Vec<_> = self.attributes().collect();
+        (attributes.len() as u32).write(out);
+        for (name, values) in attributes {
+            name.to_string().write(out);
+            write_buffer(values, out);
+        }
+        self.material().write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Mesh, String> {
+        let mut mesh = Mesh::from_buffers(read_buffer(input)?, read_buffer(input)?)?;
+        let normals = read_buffer(input)?;
+        let normal_indices: Buffer<[u32; 3]> = read_buffer(input)?;
+        if !normal_indices.is_empty() {
+            mesh = mesh.with_normal_buffers(normals, normal_indices)?;
+        }
+        for _ in 0..u32::read(input)? {
+            let name = String::read(input)?;
+            mesh.set_attribute(&name, read_buffer(input)?)?;
+        }
+        Ok(mesh.with_material(Material::read(input)?))
+    }
+}
+
+impl Binary for Primitive {
+    fn write(&self, out: &mut Vec<u8>) {
+        match *self {
+            Primitive::Sphere(ref sphere) => {
+                0u32.write(out);
+                sphere.write(out);
+            }
+            Primitive::Mesh(ref mesh) => {
+                1u32.write(out);
+                mesh.write(out);
+            }
+            Primitive::Cuboid(ref cuboid) => {
+                2u32.write(out);
+                cuboid.write(out);
+            }
+            Primitive::Label(ref label) => {
+                3u32.write(out);
+                label.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Primitive, String> {
+        match u32::read(input)? {
+            0 => Sphere::read(input).map(Primitive::Sphere),
+            1 => Mesh::read(input).map(Primitive::Mesh),
+            2 => Cuboid::read(input).map(Primitive::Cuboid),
+            3 => Label::read(input).map(Primitive::Label),
+            other => Err(format!("unknown primitive {} in scene cache", other)),
+        }
+    }
+}
+
+impl Binary for Portal {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.corner.write(out);
+        self.u.write(out);
+        self.v.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Portal, String> {
+        Ok(Portal {
+            corner: Point3::read(input)?,
+            u: Vector3::read(input)?,
+            v: Vector3::read(input)?,
+        })
+    }
+}
+
+impl Binary for Shaping {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.path.write(out);
+        self.down.write(out);
+        self.across.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Shaping, String> {
+        let path = PathBuf::read(input)?;
+        Ok(Shaping {
+            modified: resolve::modified(&path),
+            profile: input.resolver.profile(&path)?,
+            path,
+            down: Vector3::read(input)?,
+            across: Vector3::read(input)?,
+        })
+    }
+}
+
+impl Binary for Environment {
+    fn write(&self, out: &mut Vec<u8>) {
+        match *self {
+            Environment::Gradient {
+                up,
+                zenith,
+                horizon,
+                ground,
+            } => {
+                0u32.write(out);
+                up.write(out);
+                zenith.write(out);
+                horizon.write(out);
+                ground.write(out);
+            }
+            Environment::Map {
+                ref path, to_map, ..
+            } => {
+                1u32.write(out);
+                path.write(out);
+                to_map.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Environment, String> {
+        match u32::read(input)? {
+            0 => Ok(Environment::Gradient {
+                up: Vector3::read(input)?,
+                zenith: Vector3::read(input)?,
+                horizon: Vector3::read(input)?,
+                ground: Vector3::read(input)?,
+            }),
+            1 => {
+                let path = PathBuf::read(input)?;
+                let to_map = Matrix3::read(input)?;
+                Environment::map(path, to_map, input.resolver)
+            }
+            other => Err(format!("unknown environment {} in scene cache", other)),
+        }
+    }
+}
+
+impl Binary for Light {
+    fn write(&self, out: &mut Vec<u8>) {
+        match *self {
+            Light::Point {
+                position,
+                color,
+                intensity,
+                ref shaping,
+            } => {
+                0u32.write(out);
+                position.write(out);
+                color.write(out);
+                intensity.write(out);
+                shaping.write(out);
+            }
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => {
+                1u32.write(out);
+                direction.write(out);
+                color.write(out);
+                intensity.write(out);
+            }
+            Light::Dome {
+                color,
+                intensity,
+                ref portals,
+                ref environment,
+            } => {
+                2u32.write(out);
+                color.write(out);
+                intensity.write(out);
+                portals.write(out);
+                environment.write(out);
+            }
+        }
+    }
+
+    fn read(input: &mut Reader) -> Result<Light, String> {
+        match u32::read(input)? {
+            0 => Ok(Light::Point {
+                position: Point3::read(input)?,
+                color: Vector3::read(input)?,
+                intensity: f32::read(input)?,
+                shaping: Option::read(input)?,
+            }),
+            1 => Ok(Light::Directional {
+                direction: Vector3::read(input)?,
+                color: Vector3::read(input)?,
+                intensity: f32::read(input)?,
+            }),
+            2 => Ok(Light::Dome {
+                color: Vector3::read(input)?,
+                intensity: f32::read(input)?,
+                portals: Vec::read(input)?,
+                environment: Option::read(input)?,
+            }),
+            other => Err(format!("unknown light {} in scene cache", other)),
+        }
+    }
+}
+
+// Emitters are stored as they start out, without particles
+impl Binary for EmitterSettings {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.position.write(out);
+        self.rate.write(out);
+        self.velocity.write(out);
+        self.spread.write(out);
+        self.acceleration.write(out);
+        self.lifetime.write(out);
+        self.start_radius.write(out);
+        self.end_radius.write(out);
+        self.seed.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<EmitterSettings, String> {
+        Ok(EmitterSettings {
+            position: Point3::read(input)?,
+            rate: f32::read(input)?,
+            velocity: Vector3::read(input)?,
+            spread: f32::read(input)?,
+            acceleration: Vector3::read(input)?,
+            lifetime: f32::read(input)?,
+            start_radius: f32::read(input)?,
+            end_radius: f32::read(input)?,
+            seed: u32::read(input)?,
+        })
+    }
+}
+
+impl Binary for Animation {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.velocity.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Animation, String> {
+        Ok(Animation {
+            velocity: Vector3::read(input)?,
+        })
+    }
+}
+
+impl Binary for Keyframes {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.keys.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Keyframes, String> {
+        Ok(Keyframes {
+            keys: Vec::read(input)?,
+        })
+    }
+}
+
+impl Binary for MeshSource {
+    fn write(&self, out: &mut Vec<u8>) {
+        self.path.write(out);
+        self.transform.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<MeshSource, String> {
+        let path = PathBuf::read(input)?;
+        Ok(MeshSource {
+            modified: resolve::modified(&path),
+            path,
+            transform: Matrix4::read(input)?,
+        })
+    }
+}
+
+// Positions of the values of a packed pool, by handle
+fn positions<T>(pool: &Pool<T>) -> HashMap<Handle<T>, u32> {
+    pool.entries()
+        .enumerate()
+        .map(|(i, (handle, _))| (handle, i as u32))
+        .collect()
+}
+
+// Written as their values in order, so reading them back into a new pool
+// gives each the handle it had
+fn write_pool<T: Binary>(pool: &Pool<T>, out: &mut Vec<u8>) {
+    (pool.len() as u32).write(out);
+    for value in pool {
+        value.write(out);
+    }
+}
+
+fn read_pool<T: Binary>(input: &mut Reader) -> Result<(Pool<T>, Vec<Handle<T>>), String> {
+    let mut pool = Pool::new();
+    let handles = Vec::<T>::read(input)?.into_iter().map(|value| pool.push(value)).collect();
+    Ok((pool, handles))
+}
+
+// Written as pairs of the object's position in its pool and its value
+fn write_column<T: Binary>(
+    column: &Column<T>,
+    objects: &HashMap<Handle<Primitive>, u32>,
+    out: &mut Vec<u8>,
+) {
+    let entries: Vec<(u32, &T)> = column
+        .iter()
+        .map(|(object, value)| (objects[&object], value))
+        .collect();
+    (entries.len() as u32).write(out);
+    for (object, value) in entries {
+        object.write(out);
+        value.write(out);
+    }
+}
+
+// The handle at position `i` of `handles`
+fn handle<T>(handles: &[Handle<T>], i: u32) -> Result<Handle<T>, String> {
+    handles
+        .get(i as usize)
+        .cloned()
+        .ok_or_else(|| format!("bad handle {} in scene cache", i))
+}
+
+fn read_column<T: Binary>(
+    input: &mut Reader,
+    objects: &[Handle<Primitive>],
+) -> Result<Column<T>, String> {
+    let mut column = Column::new();
+    for _ in 0..u32::read(input)? {
+        let object = handle(objects, u32::read(input)?)?;
+        column.insert(object, T::read(input)?);
+    }
+    Ok(column)
+}
+
+impl Binary for Stage {
+    fn write(&self, out: &mut Vec<u8>) {
+        let scene = &self.scene;
+        let objects = positions(&scene.primitives);
+        let materials = positions(&scene.materials);
+
+        write_pool(&scene.primitives, out);
+        (scene.emitters.len() as u32).write(out);
+        for emitter in &scene.emitters {
+            emitter.settings().write(out);
+        }
+        write_pool(&scene.lights, out);
+        write_pool(&scene.materials, out);
+        let bindings: Vec<(u32, u32)> = scene
+            .material_bindings
+            .iter()
+            .map(|(object, &material)| (objects[&object], materials[&material]))
+            .collect();
+        bindings.write(out);
+        write_column(&scene.animations, &objects, out);
+        write_column(&scene.keyframes, &objects, out);
+        write_column(&scene.mesh_sources, &objects, out);
+        scene.units.write(out);
+        scene.time.write(out);
+
+        self.cameras.write(out);
+        let named: Vec<(String, u32)> = self
+            .materials
+            .iter()
+            .map(|&(ref name, material)| (name.clone(), materials[&material]))
+            .collect();
+        named.write(out);
+        self.warnings.write(out);
+    }
+
+    fn read(input: &mut Reader) -> Result<Stage, String> {
+        let (primitives, objects) = read_pool(input)?;
+        let emitters = Vec::<EmitterSettings>::read(input)?
+            .into_iter()
+            .map(Emitter::new)
+            .collect();
+        let (lights, _) = read_pool(input)?;
+        let (materials, palette) = read_pool(input)?;
+        let mut material_bindings = Column::new();
+        for (object, material) in Vec::<(u32, u32)>::read(input)? {
+            material_bindings.insert(handle(&objects, object)?, handle(&palette, material)?);
+        }
+        let scene = Scene {
+            primitives,
+            emitters,
+            lights,
+            materials,
+            material_bindings,
+            animations: read_column(input, &objects)?,
+            keyframes: read_column(input, &objects)?,
+            mesh_sources: read_column(input, &objects)?,
+            units: Units::read(input)?,
+            time: f32::read(input)?,
+            ..Scene::empty()
+        };
+        let cameras = Vec::read(input)?;
+        let mut named = Vec::new();
+        for (name, material) in Vec::<(String, u32)>::read(input)? {
+            named.push((name, handle(&palette, material)?));
+        }
+        Ok(Stage {
+            scene,
+            cameras,
+            materials: named,
+            warnings: Vec::read(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use resolve::scratch_dir;
+
+    const SCENE: &str = r#"{
+      "version": 1,
+      "materials": { "red": { "albedo": [0.9, 0.1, 0.1] } },
+      "cameras": [ { "name": "main", "position": [0, 1, 5] } ],
+      "spheres": [
+        { "center": [0, 1, 0], "radius": 2, "material": "red", "velocity": [1, 0, 0] }
+      ],
+      "models": [ { "file": "triangle.obj" } ],
+      "lights": [ { "type": "point", "position": [0, 4, 0], "intensity": 5 } ]
+    }"#;
+
+    // A scene naming a model beside it, in a directory of its own
+    fn scene(name: &str) -> PathBuf {
+        let dir = scratch_dir(name);
+        fs::write(dir.join("triangle.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        fs::write(dir.join("scene.json"), SCENE).unwrap();
+        dir.join("scene.json")
+    }
+
+    // What a stage holds, as far as telling a cached one from a parsed one
+    fn summary(stage: &Stage) -> Vec<String> {
+        let scene = &stage.scene;
+        let mut summary = vec![format!("{} lights", scene.lights.len())];
+        for primitive in scene.primitives.iter() {
+            summary.push(match *primitive {
+                Primitive::Sphere(ref s) => {
+                    format!("sphere {:?} {} {:?}", s.center, s.radius, s.material.albedo)
+                }
+                Primitive::Mesh(ref mesh) => format!(
+                    "mesh {:?} {:?} {:?}",
+                    mesh.positions(),
+                    mesh.indices(),
+                    mesh.attributes().collect::<Vec<_>>()
+                ),
+                _ => "other".to_string(),
+            });
+        }
+        for (_, animation) in scene.animations.iter() {
+            summary.push(format!("velocity {:?}", animation.velocity));
+        }
+        for (name, camera) in &stage.cameras {
+            summary.push(format!("camera {} {:?}", name, camera.position));
+        }
+        for (name, _) in &stage.materials {
+            summary.push(format!("material {}", name));
+        }
+        summary.extend(stage.warnings.iter().cloned());
+        summary
+    }
+
+    #[test]
+    fn cached_stages_match_parsed_ones() {
+        let path = scene("cache-round-trip");
+        let (units, resolver) = (Units::default(), AssetResolver::new(Vec::new()));
+        let parsed = load(&path, &units, &resolver).unwrap();
+        let bytes = fs::read(cache_path(&path)).unwrap();
+        let key = scene_hash(&path, &units).unwrap();
+        let cached = read_cache(&mut Reader::new(&bytes, None, &resolver), key);
+        assert_eq!(summary(&cached.unwrap().unwrap()), summary(&parsed));
+        // And read in place from a mapping, as `load` reads it
+        let mapping = Arc::new(unsafe { Mapping::open(&cache_path(&path)) }.unwrap());
+        let cached = read_cache(&mut Reader::new(&mapping, Some(&mapping), &resolver), key);
+        assert_eq!(summary(&cached.unwrap().unwrap()), summary(&parsed));
+        assert_eq!(summary(&load(&path, &units, &resolver).unwrap()), summary(&parsed));
+    }
+
+    #[test]
+    fn stale_caches_are_passed_over() {
+        let path = scene("cache-stale");
+        let (units, resolver) = (Units::default(), AssetResolver::new(Vec::new()));
+        load(&path, &units, &resolver).unwrap();
+        let bytes = fs::read(cache_path(&path)).unwrap();
+        let key = scene_hash(&path, &units).unwrap();
+        let read = |bytes: &[u8], key| read_cache(&mut Reader::new(bytes, None, &resolver), key);
+        assert!(read(&bytes, key).unwrap().is_some());
+        assert!(read(&bytes[..bytes.len() / 2], key).is_err());
+
+        let centimetres = Units {
+            meters_per_unit: 0.01,
+            ..units
+        };
+        assert_ne!(scene_hash(&path, &centimetres), Some(key));
+        fs::write(path.with_file_name("triangle.obj"), "v 0 0 0\nv 2 0 0\nv 0 2 0\nf 1 2 3\n")
+            .unwrap();
+        assert!(read(&bytes, key).unwrap().is_none());
+        let stage = load(&path, &units, &resolver).unwrap();
+        assert!(summary(&stage).iter().any(|s| s.contains("Point3 [2.0, 0.0, 0.0]")));
+    }
+
+    #[test]
+    fn corrupt_caches_are_parsed_around() {
+        let path = scene("cache-corrupt");
+        let (units, resolver) = (Units::default(), AssetResolver::new(Vec::new()));
+        let parsed = load(&path, &units, &resolver).unwrap();
+        let bytes = fs::read(cache_path(&path)).unwrap();
+        fs::write(cache_path(&path), &bytes[..bytes.len() - 8]).unwrap();
+        assert_eq!(summary(&load(&path, &units, &resolver).unwrap()), summary(&parsed));
+    }
+}
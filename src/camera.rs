@@ -0,0 +1,179 @@
+//! Where a scene is seen from, and how pixels map to the rays through them.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use geometry::Ray;
+use render::RenderOptions;
+use sampling;
+use timeline;
+
+/// A thin-lens camera, or a pinhole camera while its aperture is 0.
+#[derive(Clone)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    // Roughly which way is up in the image; only the part of it square to
+    // `at` counts
+    pub up: Vector3<f32>,
+    // Direction the camera looks in
+    pub at: Vector3<f32>,
+    // Vertical field of view, in degrees
+    pub fov: f32,
+    // Diameter of the lens, in scene units; 0 keeps everything in focus
+    pub aperture: f32,
+    // Distance in front of the camera of the plane in focus, along `at`
+    pub focal_distance: f32,
+    // Where the camera stands and looks at times on the scene clock, in
+    // time order; without any it stays put
+    pub keys: Vec<CameraKey>,
+}
+
+/// Where a keyframed camera stands and looks at a time on the scene clock.
+#[derive(Clone, Debug)]
+pub struct CameraKey {
+    pub time: f32,
+    pub position: Point3<f32>,
+    pub at: Vector3<f32>,
+}
+
+impl Camera {
+    /// Unit vectors pointing right, up and forward in the image, square to
+    /// each other. An `up` along the view direction gives way to the world
+    /// axis least in line with it.
+    pub fn basis(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let forward = self.at.normalize();
+        let mut right = forward.cross(self.up);
+        if right.magnitude2() < 1e-12 {
+            let axis = if forward.y.abs() < 0.9 {
+                Vector3::unit_y()
+            } else {
+                Vector3::unit_z()
+            };
+            right = forward.cross(axis);
+        }
+        let right = right.normalize();
+        (right, right.cross(forward), forward)
+    }
+
+    /// The camera as its keys place it at `time`, or as it is if it has
+    /// none.
+    pub fn at_time(&self, time: f32) -> Camera {
+        if self.keys.is_empty() {
+            return self.clone();
+        }
+        let position = timeline::sample(&self.keys, time, |k| (k.time, k.position.to_vec()));
+        Camera {
+            position: Point3::from_vec(position),
+            at: timeline::sample(&self.keys, time, |k| (k.time, k.at)),
+            ..self.clone()
+        }
+    }
+
+    /// The camera with the field of view, aperture and focal distance that
+    /// `render_options` give every camera in place of its own.
+    pub fn with_overrides(&self, render_options: &RenderOptions) -> Camera {
+        Camera {
+            fov: render_options.fov.unwrap_or(self.fov),
+            aperture: render_options.aperture.unwrap_or(self.aperture),
+            focal_distance: render_options.focal_distance.unwrap_or(self.focal_distance),
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for Camera {
+    /// A pinhole camera at the origin looking down -Z, with +Y up.
+    fn default() -> Camera {
+        Camera {
+            position: Point3::new(0.0, 0.0, 0.0),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            at: Vector3::new(0.0, 0.0, -1.0),
+            fov: 90.0,
+            aperture: 0.0,
+            focal_distance: 1.0,
+            keys: Vec::new(),
+        }
+    }
+}
+
+/// Primary ray through the continuous pixel coordinate (`x`, `y`); pixel
+/// centres sit at half-integer coordinates.
+pub fn primary_ray(camera: &Camera, render_options: &RenderOptions, x: f32, y: f32) -> Ray {
+    let theta = camera.fov.to_radians() / 2.0;
+    let fov_scalar = theta.tan();
+    let w = render_options.width as f32;
+    let h = render_options.height as f32;
+    let aspect_ratio = w / h;
+
+    // Calculate pixel NDC (normalized device coordinates)
+    let px_ndc_x = x / w;
+    let px_ndc_y = y / h;
+
+    // Calculate pixel screen space coordinates
+    let mut px_screen_x = 2.0 * px_ndc_x - 1.0;
+    let mut px_screen_y = 1.0 - (2.0 * px_ndc_y);
+
+    // Account for aspect ratio
+    px_screen_x *= aspect_ratio;
+
+    // Account for camera FoV (Field of View)
+    px_screen_x *= fov_scalar;
+    px_screen_y *= fov_scalar;
+
+    let (right, up, forward) = camera.basis();
+    let ray_vector = (right * px_screen_x + up * px_screen_y + forward).normalize();
+    Ray {
+        origin: camera.position,
+        direction: ray_vector,
+    }
+}
+
+/// `primary_ray` leaving the point of the lens `lens` picks, as it covers
+/// the unit square, bent to meet the pinhole ray on the plane in focus.
+/// Without an aperture it is the pinhole ray.
+pub fn lens_ray(
+    camera: &Camera,
+    render_options: &RenderOptions,
+    x: f32,
+    y: f32,
+    lens: (f32, f32),
+) -> Ray {
+    let ray = primary_ray(camera, render_options, x, y);
+    if camera.aperture <= 0.0 {
+        return ray;
+    }
+    let (right, up, forward) = camera.basis();
+    let focus = ray.origin + ray.direction * (camera.focal_distance / ray.direction.dot(forward));
+    let (u, v) = sampling::concentric_disk(lens);
+    let radius = camera.aperture / 2.0;
+    let origin = camera.position + right * (u * radius) + up * (v * radius);
+    Ray {
+        origin,
+        direction: (focus - origin).normalize(),
+    }
+}
+
+/// Pixel coordinates `point` is seen at, the inverse of `primary_ray`, or
+/// None if it is behind the camera. Points outside the frame give
+/// coordinates outside it.
+pub fn project(
+    camera: &Camera,
+    render_options: &RenderOptions,
+    point: Point3<f32>,
+) -> Option<(f32, f32)> {
+    let fov_scalar = (camera.fov.to_radians() / 2.0).tan();
+    let w = render_options.width as f32;
+    let h = render_options.height as f32;
+    let aspect_ratio = w / h;
+
+    // Where the ray to `point` crosses the plane one unit in front of the
+    // camera that primary rays are aimed through
+    let (right, up, forward) = camera.basis();
+    let direction = point - camera.position;
+    let depth = direction.dot(forward);
+    if depth <= 0.0 || !depth.is_finite() {
+        return None;
+    }
+    let px_screen_x = direction.dot(right) / depth / (aspect_ratio * fov_scalar);
+    let px_screen_y = direction.dot(up) / depth / fov_scalar;
+    Some(((px_screen_x + 1.0) / 2.0 * w, (1.0 - px_screen_y) / 2.0 * h))
+}
@@ -0,0 +1,151 @@
+//! Caustics: light that reaches a diffuse surface by way of mirrors or
+//! glass.
+//!
+//! Shadow rays run straight from a surface to each light, so they never
+//! find light that arrives after a specular bounce. A point light seen in a
+//! mirror or through a lens lies in exactly one direction, which no amount
+//! of sampling hits, and the surfaces it should light stay dark, like the
+//! floor under a glass ball. Following bounded roughening ("Path Space
+//! Regularization for Holistic and Robust Light Transport", Kaplanyan and
+//! Dachsbacher), each specular surface is treated as slightly rough when it
+//! is joined to a light: it passes the light on towards a surface whenever
+//! the light lies within `RenderOptions::caustic_spread` degrees of its
+//! mirror or refracted direction. That blurs caustics by about the spread
+//! but lets them converge. Caustics are off unless a spread is given, since
+//! the narrower it is the sharper they come out and the more light samples
+//! they need to lose their speckle.
+//!
+//! Directions are sampled towards the bounds of each specular primitive,
+//! and chains of reflections and refractions are followed up to the
+//! render's depth. Only point and directional lights are found this way;
+//! dome lights are left to the rays the camera follows.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use std::ptr;
+
+use geometry::{reflect, Ray};
+use primitive::{Hit, Intersectable};
+use render::{Color, RenderOptions};
+use sampling;
+use scene::{closest_intersection, occluded, Scene};
+
+/// Light reaching a white surface at `point` with unit `normal` by way of
+/// one or more specular surfaces, from `RenderOptions::light_samples`
+/// samples towards each specular primitive.
+pub fn caustic_light(
+    scene: &Scene,
+    point: Point3<f32>,
+    normal: Vector3<f32>,
+    render_options: &RenderOptions,
+) -> Color {
+    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+    let mirrors = render_options.max_depth > 0 && render_options.reflections;
+    if render_options.caustic_spread <= 0.0 || !mirrors {
+        return sum;
+    }
+    let rotation = sampling::rotation(point);
+    let count = render_options.light_samples;
+    for primitive in &scene.primitives {
+        let shape = primitive.shape();
+        if !shape.material().is_specular() {
+            continue;
+        }
+        // Sample the cone the primitive's bounding sphere fills, or every
+        // direction from inside it
+        let (min, max) = shape.bounds();
+        let center = min.midpoint(max);
+        let radius_squared = (max - min).magnitude2() / 4.0;
+        let to_center = center - point;
+        let distance_squared = to_center.magnitude2();
+        let (axis, cos_max) = if distance_squared > radius_squared {
+            let cos_max = (1.0 - radius_squared / distance_squared).sqrt();
+            (to_center / distance_squared.sqrt(), cos_max)
+        } else {
+            (normal, 0.0)
+        };
+        let solid_angle = sampling::cone_solid_angle(cos_max);
+
+        let mut shape_sum = Vector3::new(0.0, 0.0, 0.0);
+        for i in 0..count {
+            let sample = sampling::hammersley(i, count, rotation);
+            let direction = sampling::uniform_cone(axis, cos_max, sample);
+            let cosine = normal.dot(direction);
+            if cosine <= 0.0 {
+                continue;
+            }
+            let ray = Ray::from_surface(point, normal, direction);
+            // Only paths that first meet this primitive count here, so each
+            // is counted once however many primitives' cones it lies in
+            if let Some((hit_shape, hit)) = closest_intersection(scene, &ray, render_options) {
+                if ptr::addr_eq(hit_shape, shape) {
+                    let radiance = specular(scene, &ray, hit_shape, &hit, render_options, 1);
+                    shape_sum += radiance * (cosine * solid_angle);
+                }
+            }
+        }
+        sum += shape_sum / count as f32;
+    }
+    sum
+}
+
+// Radiance `ray`, which meets `shape` with `hit`, brings back from lights
+// seen in the mirror or through the glass there, directly or by way of
+// further specular surfaces. `depth` counts the specular surfaces met so
+// far, including this one.
+fn specular(
+    scene: &Scene,
+    ray: &Ray,
+    shape: &dyn Intersectable,
+    hit: &Hit,
+    render_options: &RenderOptions,
+    depth: u32,
+) -> Color {
+    let material = shape.material();
+    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+    if !material.is_specular() || hit.cap.is_some() {
+        return sum;
+    }
+    let point = ray.origin + ray.direction * hit.distance;
+    let outward = shape.normal(point, hit.part).normalize();
+    let split = material.split(ray.direction, outward);
+    let normal = if outward.dot(ray.direction) > 0.0 { -outward } else { outward };
+    // Each direction light continues in, the side of the surface it leaves
+    // from and the share it takes
+    let lobes = [
+        (reflect(ray.direction, normal), normal, split.reflected),
+        (split.refraction, -normal, split.refracted),
+    ];
+
+    // A light within the spread of a lobe's direction has its light spread
+    // evenly over the cone
+    let cos_spread = render_options.caustic_spread.to_radians().cos();
+    let cone = sampling::cone_solid_angle(cos_spread);
+    for &(direction, side, share) in &lobes {
+        if share <= 0.0 {
+            continue;
+        }
+        let mut lobe = Vector3::new(0.0, 0.0, 0.0);
+        for light in &scene.lights {
+            if !light.is_singular() {
+                continue;
+            }
+            let incident = light.incident(point, side, (0.5, 0.5));
+            let in_spread = incident.direction.dot(direction) >= cos_spread;
+            if !in_spread || incident.direction.dot(side) <= 0.0 {
+                continue;
+            }
+            let shadow_ray = Ray::from_surface(point, side, incident.direction);
+            if !occluded(scene, &shadow_ray, incident.distance, render_options) {
+                lobe += incident.color / cone;
+            }
+        }
+        if depth < render_options.max_depth {
+            let next_ray = Ray::from_surface(point, side, direction);
+            if let Some((next, hit)) = closest_intersection(scene, &next_ray, render_options) {
+                lobe += specular(scene, &next_ray, next, &hit, render_options, depth + 1);
+            }
+        }
+        sum += lobe * share;
+    }
+    sum
+}
@@ -0,0 +1,145 @@
+//! Checkpoints that let an interrupted offline render carry on where it
+//! stopped. Next to `<name>.png` the partial image is kept as
+//! `<name>.partial.png` and the finished tiles are listed in
+//! `<name>.checkpoint`.
+//!
+//! A checkpoint records the hash of the scene file, the camera and the
+//! render options from the output's metadata, and is only resumed by a
+//! render of the same scene with the same settings. Any other render to
+//! the same output discards it, saying so, rather than mixing in tiles of
+//! a different image.
+
+use im::{self, RgbaImage};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use output::Metadata;
+use tiles;
+
+const HEADER: &str = "rs-tracer checkpoint";
+
+// Metadata a checkpoint is only resumed under if it's unchanged
+const SETTINGS: &[&str] = &["scene-hash", "camera", "options"];
+
+pub struct Checkpoint {
+    pub image: RgbaImage,
+    // Whether each tile, in `tiles::tiles` order, has been rendered
+    pub done: Vec<bool>,
+    tile_size: u32,
+}
+
+fn paths(output: &Path) -> (PathBuf, PathBuf) {
+    (
+        output.with_extension("partial.png"),
+        output.with_extension("checkpoint"),
+    )
+}
+
+// The settings in `metadata` a checkpoint is kept under, a line each
+fn settings(metadata: &Metadata) -> Vec<String> {
+    SETTINGS
+        .iter()
+        .map(|key| format!("{} {}", key, metadata.get(key).unwrap_or("-")))
+        .collect()
+}
+
+impl Checkpoint {
+    /// Resumes the checkpoint an interrupted render of the same size,
+    /// tiling and `metadata` settings left for `output`, or starts a fresh
+    /// one, discarding any left by a different render.
+    pub fn resume(
+        output: &Path,
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        metadata: &Metadata,
+    ) -> Checkpoint {
+        match Checkpoint::load(output, width, height, tile_size, metadata) {
+            Some(Ok(checkpoint)) => return checkpoint,
+            Some(Err(change)) => eprintln!(
+                "{}: discarded checkpoint of a render with a different {}",
+                output.display(),
+                change
+            ),
+            None => {}
+        }
+        Checkpoint {
+            image: RgbaImage::new(width, height),
+            done: vec![false; tiles::tiles(width, height, tile_size).len()],
+            tile_size,
+        }
+    }
+
+    // The checkpoint left for `output`, None if there's none to be read,
+    // or what differs if it was left by a different render
+    fn load(
+        output: &Path,
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        metadata: &Metadata,
+    ) -> Option<Result<Checkpoint, &'static str>> {
+        let (image_path, list_path) = paths(output);
+        let mut src = String::new();
+        File::open(list_path)
+            .and_then(|mut f| f.read_to_string(&mut src))
+            .ok()?;
+        let mut lines = src.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let size = format!("{} {} {}", width, height, tile_size);
+        if lines.next()? != size {
+            return Some(Err("size or tiling"));
+        }
+        for (key, setting) in SETTINGS.iter().zip(settings(metadata)) {
+            if lines.next()? != setting {
+                return Some(Err(key));
+            }
+        }
+        let mut done = vec![false; tiles::tiles(width, height, tile_size).len()];
+        for index in lines.next().unwrap_or("").split_whitespace() {
+            *done.get_mut(index.parse::<usize>().ok()?)? = true;
+        }
+        let image = im::open(image_path).ok()?.to_rgba();
+        if image.dimensions() != (width, height) {
+            return None;
+        }
+        Some(Ok(Checkpoint {
+            image,
+            done,
+            tile_size,
+        }))
+    }
+
+    /// Saves the checkpoint for `output`, to be resumed by renders with the
+    /// settings in `metadata`.
+    pub fn save(&self, output: &Path, metadata: &Metadata) -> io::Result<()> {
+        let (image_path, list_path) = paths(output);
+        self.image.save(&image_path)?;
+        let done: Vec<String> = (0..self.done.len())
+            .filter(|&i| self.done[i])
+            .map(|i| i.to_string())
+            .collect();
+        let mut file = File::create(list_path)?;
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "{} {} {}", self.image.width(), self.image.height(), self.tile_size)?;
+        for setting in settings(metadata) {
+            writeln!(file, "{}", setting)?;
+        }
+        writeln!(file, "{}", done.join(" "))
+    }
+
+    /// Partial image written for `output` by `save`.
+    pub fn partial_path(output: &Path) -> PathBuf {
+        paths(output).0
+    }
+
+    /// Removes anything `save` left for `output`.
+    pub fn clear(output: &Path) {
+        let (image_path, list_path) = paths(output);
+        let _ = fs::remove_file(image_path); // Fine if there was none
+        let _ = fs::remove_file(list_path);
+    }
+}
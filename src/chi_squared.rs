@@ -0,0 +1,333 @@
+//! Chi-squared tests of the direction samplers, which check that each picks
+//! directions with the density it claims.
+//!
+//! Estimates divide by the density of the directions they sample, so a
+//! sampler that favours some directions more or less than it says biases
+//! every render that uses it, by an amount too small to see but too large
+//! to trust. Each test draws many random directions, counts them in bins of
+//! equal solid angle over the sphere, and compares the counts with those
+//! the claimed density predicts, integrated over each bin. Bins expected to
+//! get only a few samples are pooled so the statistic stays valid, and a
+//! sample in a direction the density says can't be picked fails the test
+//! outright.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::f64::consts::PI;
+
+use light::{Light, Portal};
+use sampling;
+
+// Bins are equal steps of the polar angle's cosine and of the azimuth
+const COSINE_BINS: usize = 20;
+const AZIMUTH_BINS: usize = 40;
+// Each bin's expected count is integrated over this many cells a side
+const CELLS: usize = 32;
+const SAMPLES: usize = 200_000;
+// Bins expected to get fewer samples are pooled
+const MIN_EXPECTED: f64 = 5.0;
+// Chance of failing a correct sampler, shared across every test
+const SIGNIFICANCE: f64 = 0.01;
+
+/// A sampler under test: `sample` maps a point in the unit square to a
+/// direction, and `pdf` gives the density it claims for a direction.
+pub struct Case {
+    pub name: String,
+    pub sample: Box<dyn Fn((f32, f32)) -> Vector3<f32>>,
+    pub pdf: Box<dyn Fn(Vector3<f32>) -> f32>,
+}
+
+pub struct Outcome {
+    pub statistic: f64,
+    pub degrees_of_freedom: usize,
+    // Chance of a statistic this large from a correct sampler
+    pub p_value: f64,
+    // Samples landing where the density is 0
+    pub stray: usize,
+}
+
+impl Outcome {
+    /// Whether the sampler passed, at a p-value `threshold`.
+    pub fn passes(&self, threshold: f64) -> bool {
+        self.stray == 0 && self.p_value >= threshold
+    }
+}
+
+/// P-value below which one of `tests` samplers fails. Sidak's correction
+/// keeps the chance of any false failure at `SIGNIFICANCE` however many
+/// are tested.
+pub fn threshold(tests: usize) -> f64 {
+    1.0 - (1.0 - SIGNIFICANCE).powf(1.0 / tests as f64)
+}
+
+/// Every sampler the renderer estimates light with, in a few settings each.
+pub fn cases() -> Vec<Case> {
+    let mut cases = Vec::new();
+    let axes = [
+        ("up", Vector3::new(0.0, 0.0, 1.0)),
+        ("tilted", Vector3::new(0.48, -0.6, 0.64)),
+    ];
+    for &(label, axis) in &axes {
+        cases.push(Case {
+            name: format!("hemisphere {}", label),
+            sample: Box::new(move |s| sampling::uniform_hemisphere(axis, s)),
+            pdf: Box::new(move |d| if d.dot(axis) > 0.0 { 1.0 / (2.0 * PI as f32) } else { 0.0 }),
+        });
+        for &cos_max in &[0.5, 0.9] {
+            let solid_angle = sampling::cone_solid_angle(cos_max);
+            cases.push(Case {
+                name: format!("cone {} {}", cos_max, label),
+                sample: Box::new(move |s| sampling::uniform_cone(axis, cos_max, s)),
+                pdf: Box::new(move |d| if d.dot(axis) >= cos_max { 1.0 / solid_angle } else { 0.0 }),
+            });
+        }
+    }
+
+    // Both let light in to the origin, which is behind them
+    let window = Portal {
+        corner: Point3::new(-0.5, 1.0, 0.4),
+        u: Vector3::new(0.0, 0.3, 1.0),
+        v: Vector3::new(1.2, 0.0, 0.0),
+    };
+    let skylight = Portal {
+        corner: Point3::new(-2.0, -1.5, 2.0),
+        u: Vector3::new(1.0, 0.0, 0.2),
+        v: Vector3::new(0.0, 1.0, 0.0),
+    };
+    let domes = [
+        ("dome", Vec::new()),
+        ("dome through a window", vec![window.clone()]),
+        ("dome through two portals", vec![window, skylight]),
+    ];
+    for (name, portals) in domes.iter().cloned() {
+        let light = Light::Dome {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            portals,
+            environment: None,
+        };
+        let (point, normal) = (Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let sampled = light.clone();
+        cases.push(Case {
+            name: name.to_string(),
+            sample: Box::new(move |s| sampled.incident(point, normal, s).direction),
+            pdf: Box::new(move |d| light.pdf(point, normal, d)),
+        });
+    }
+    cases
+}
+
+// Unit direction at polar cosine `z` and azimuth `phi`
+fn direction(z: f64, phi: f64) -> Vector3<f32> {
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vector3::new((r * phi.cos()) as f32, (r * phi.sin()) as f32, z as f32)
+}
+
+// Index of the bin `d` falls in
+fn bin(d: Vector3<f32>) -> usize {
+    let z = (1.0 - d.z as f64) / 2.0;
+    let phi = (d.y as f64).atan2(d.x as f64).rem_euclid(2.0 * PI) / (2.0 * PI);
+    let i = ((z * COSINE_BINS as f64) as usize).min(COSINE_BINS - 1);
+    let j = ((phi * AZIMUTH_BINS as f64) as usize).min(AZIMUTH_BINS - 1);
+    i * AZIMUTH_BINS + j
+}
+
+// Samples each bin is expected to get, from `pdf` integrated over it with
+// the midpoint rule. Each cell has the same solid angle.
+fn expected_counts(pdf: &dyn Fn(Vector3<f32>) -> f32) -> Vec<f64> {
+    let bin_solid_angle = 4.0 * PI / (COSINE_BINS * AZIMUTH_BINS) as f64;
+    let cell_solid_angle = bin_solid_angle / (CELLS * CELLS) as f64;
+    let mut counts = vec![0.0; COSINE_BINS * AZIMUTH_BINS];
+    for i in 0..COSINE_BINS * CELLS {
+        let z = 1.0 - 2.0 * (i as f64 + 0.5) / (COSINE_BINS * CELLS) as f64;
+        for j in 0..AZIMUTH_BINS * CELLS {
+            let phi = 2.0 * PI * (j as f64 + 0.5) / (AZIMUTH_BINS * CELLS) as f64;
+            let index = (i / CELLS) * AZIMUTH_BINS + j / CELLS;
+            counts[index] += pdf(direction(z, phi)) as f64 * cell_solid_angle;
+        }
+    }
+    for count in &mut counts {
+        *count *= SAMPLES as f64;
+    }
+    counts
+}
+
+/// Draws `SAMPLES` directions from `case` with a generator seeded by `seed`
+/// and tests their spread against its density.
+pub fn test(case: &Case, seed: u32) -> Outcome {
+    let mut rng = XorShiftRng::from_seed([seed, 0x2545_f491, 0x9e37_79b9, 1]);
+    let mut observed = vec![0usize; COSINE_BINS * AZIMUTH_BINS];
+    let mut stray = 0;
+    for _ in 0..SAMPLES {
+        let d = (case.sample)((rng.gen(), rng.gen()));
+        if (case.pdf)(d) <= 0.0 {
+            stray += 1;
+        }
+        observed[bin(d)] += 1;
+    }
+    let expected = expected_counts(&*case.pdf);
+
+    // Bins from least to most expected, the least pooled until the pool
+    // is big enough to stand as a bin of its own
+    let mut order: Vec<usize> = (0..expected.len()).collect();
+    order.sort_by(|&a, &b| expected[a].partial_cmp(&expected[b]).unwrap());
+    let mut outcome = Outcome {
+        statistic: 0.0,
+        degrees_of_freedom: 0,
+        p_value: 0.0,
+        stray,
+    };
+    let (mut pooled_expected, mut pooled_observed) = (0.0, 0.0);
+    let mut bins = 0;
+    for &i in &order {
+        let pool_open = pooled_expected > 0.0 && pooled_expected < MIN_EXPECTED;
+        if expected[i] < MIN_EXPECTED || pool_open {
+            pooled_expected += expected[i];
+            pooled_observed += observed[i] as f64;
+            continue;
+        }
+        let difference = observed[i] as f64 - expected[i];
+        outcome.statistic += difference * difference / expected[i];
+        bins += 1;
+    }
+    if pooled_expected > 0.0 {
+        let difference = pooled_observed - pooled_expected;
+        outcome.statistic += difference * difference / pooled_expected;
+        bins += 1;
+    }
+    outcome.degrees_of_freedom = bins.max(2) - 1;
+    outcome.p_value = upper_gamma(outcome.degrees_of_freedom as f64 / 2.0, outcome.statistic / 2.0);
+    outcome
+}
+
+/// Tests every case, printing a line for each, and returns the names of
+/// those that fail.
+pub fn run() -> Vec<String> {
+    let cases = cases();
+    let threshold = threshold(cases.len());
+    let mut failed = Vec::new();
+    for (i, case) in cases.iter().enumerate() {
+        let outcome = test(case, i as u32 + 1);
+        let passes = outcome.passes(threshold);
+        println!(
+            "{:<26} chi2 {:>9.2}  dof {:>3}  p {:.4}  stray {}  {}",
+            case.name,
+            outcome.statistic,
+            outcome.degrees_of_freedom,
+            outcome.p_value,
+            outcome.stray,
+            if passes { "ok" } else { "FAILED" }
+        );
+        if !passes {
+            failed.push(case.name.clone());
+        }
+    }
+    failed
+}
+
+// Natural log of the gamma function, by Lanczos' approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000_000_000_190_015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+// Regularized upper incomplete gamma function Q(a, x), the chance of a
+// chi-squared statistic of at least 2x with 2a degrees of freedom. A series
+// converges quickly below a + 1 and a continued fraction above.
+fn upper_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        return (1.0 - sum * prefactor).max(0.0);
+    }
+    // Lentz's method
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    prefactor * h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_sampler_matches_its_density() {
+        let cases = cases();
+        let threshold = threshold(cases.len());
+        // Seeded by position, as the command seeds them, so a failure
+        // shows up the same way there
+        let failed: Vec<_> = cases
+            .iter()
+            .enumerate()
+            .filter(|&(i, case)| !test(case, i as u32 + 1).passes(threshold))
+            .map(|(_, case)| case.name.clone())
+            .collect();
+        assert!(failed.is_empty(), "failed the chi-squared test: {:?}", failed);
+    }
+
+    #[test]
+    fn a_sampler_off_its_density_fails() {
+        // Cosine-weighted directions claimed to be uniform
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        let case = Case {
+            name: "mislabelled".to_string(),
+            sample: Box::new(move |s| sampling::cosine_hemisphere(normal, s)),
+            pdf: Box::new(move |d| if d.dot(normal) > 0.0 { 1.0 / (2.0 * PI as f32) } else { 0.0 }),
+        };
+        assert!(!test(&case, 1).passes(threshold(1)));
+    }
+
+    #[test]
+    fn p_values_match_closed_forms() {
+        // With two degrees of freedom the chance of at least x is e^(-x/2)
+        for &x in &[0.5, 2.0, 9.0, 30.0] {
+            let p: f64 = upper_gamma(1.0, x / 2.0);
+            assert!((p - (-x / 2.0).exp()).abs() < 1e-9, "p {} at {}", p, x);
+        }
+        assert!((upper_gamma(0.5, 0.0) - 1.0).abs() < 1e-12);
+    }
+}
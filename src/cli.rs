@@ -0,0 +1,1058 @@
+//! The `rs-tracer` command line: options, scene loading and the commands
+//! run on the scene, falling back to the interactive window.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use rayon;
+use std::collections::HashMap;
+use std::env;
+use std::f32::consts::FRAC_1_PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use api;
+use audio;
+use bake;
+use batch;
+use bench;
+use builtin;
+use bvh;
+use camera::Camera;
+use chi_squared;
+use clip;
+use color;
+use components::MeshSource;
+use convert;
+use display;
+use dither;
+use environment::{self, Environment};
+use furnace;
+use heatmap;
+use hud;
+use interrupt;
+use json;
+use light::{self, Light};
+use lod;
+use obj;
+use osc;
+use panorama;
+use path;
+use primitive::Primitive;
+use probe;
+use reference;
+use render::{self, RenderOptions};
+use report;
+use resolve;
+use scene::{load_stage, Scene, UpAxis};
+use scenes;
+use schema;
+use shading_cache::ShadingCache;
+use shake;
+use sheet;
+use snapshot;
+use timeline;
+use watch;
+
+const PROBE_SIZE: u32 = 256;
+const SHEET_PANEL_SIZE: u32 = 320;
+const DEFAULT_FRAME_BUDGET_MS: u64 = 16;
+const PANORAMA_HEIGHT: u32 = 1024;
+const THUMBNAIL_SIZE: u32 = 256;
+
+fn parse_point(s: &str) -> Option<Point3<f32>> {
+    let coords: Vec<f32> = s.split(',').filter_map(|c| c.trim().parse().ok()).collect();
+    match coords.as_slice() {
+        &[x, y, z] => Some(Point3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+fn usage() -> ! {
+    println!(
+        "usage: rs-tracer [--size <width>x<height>] [--bit-depth <8|16>] \
+         [--output-space <linear|srgb|rec709|display-p3|name>] [--color-config <file>] \
+         [--dither <none|ordered|noise>] [--robust-intersections] [--lod-pixels <px>] [--frame-budget <ms>] \
+         [--target-fps <fps>] [--shading-cache <cell m>] [--progressive] \
+         [--threads <n>] [--tile-size <px>] [--fov <degrees>] [--pixel-samples <n>] [--light-samples <n>] \
+         [--aperture <diameter>] [--focal-distance <distance>] [--shutter <seconds>] \
+         [--anaglyph <eye separation>] \
+         [--max-depth <0-64>] [--caustic-spread <degrees>] [--integrator <direct|path|wavefront>] \
+         [--traversal <linear|wide-bvh>] [--builtin <demo|cornell-box|material-preview>] \
+         [--meters-per-unit <m>] [--up-axis <y|z>] [--search-path <dir>]... [--obj <file.obj>]... \
+         [--no-scene-cache] [--sky <zenith r,g,b> <horizon r,g,b> | --environment <map.hdr>] \
+         [--report <file.json>] [--output <image>] \
+         [--clip-plane <x,y,z> <nx,ny,nz>]... [--clip-cap <r,g,b>] \
+         [--heatmap <height|distance:x,y,z|attribute:name> <min,max>] \
+         [--colormap <viridis|inferno|grey>] \
+         [--shake <amplitude> <frequency>] [--burn-in] [--exposure <stops> | --ev100 <ev>] [--osc <host:port>] \
+         [--api <host:port>] \
+         [--audio <file.wav>] [--audio-map <bass|mid|treble>:<light-intensity|sphere-scale>:<amount>]... \
+         [--batch <list.txt> | --watch <in_dir> <out_dir> | --validate <scene> \
+         | --scene <scene> [--scene <scene>]...] \
+         [command]"
+    );
+    println!("commands:");
+    println!("    probes <out_dir> <x,y,z>...");
+    println!("    panorama <out.exr|out.hdr> <x,y,z>");
+    println!("    sheet <out.png>");
+    println!("    preview <material.json> <out.png>");
+    println!("    bake <irradiance|occlusion> <model.obj> <out.png>");
+    println!("    convert <scene.gltf|scene.glb|scene> <out.json>");
+    println!("    schema <out.json>");
+    println!("    diff <frame> <frame> [<dump_dir>]");
+    println!("    sequence <first frame> <last frame> <out_####.png>");
+    println!("    bench [<baseline> [save]]");
+    println!("    furnace");
+    println!("    chi-squared");
+    println!("    reference <samples>");
+    process::exit(report::EXIT_USAGE);
+}
+
+// Steps `scene` through `frames` frames at `snapshot::FRAME_RATE`, as the
+// interactive window would
+fn step_frames(scene: &mut Scene, frames: u32, audio: Option<&audio::AudioDriver>) {
+    for _ in 0..frames {
+        scene.advance(1.0 / snapshot::FRAME_RATE);
+        if let Some(audio) = audio {
+            audio.apply(scene);
+        }
+    }
+}
+
+// Burn-in for frame `frame` of the scene loaded from `scene_file`, seen
+// through the camera called `camera_name`, if `render_options` ask for one
+fn burn_in(
+    render_options: &RenderOptions,
+    scene_file: Option<&Path>,
+    camera_name: Option<&str>,
+    frame: u32,
+) -> Option<hud::Hud> {
+    if !render_options.burn_in {
+        return None;
+    }
+    let scene_name = scene_file.and_then(|f| f.file_name());
+    Some(hud::Hud {
+        frame,
+        scene: scene_name.map_or("built-in".into(), |n| n.to_string_lossy().into_owned()),
+        camera: camera_name.unwrap_or("default camera").to_string(),
+    })
+}
+
+// Writes the run's report, if one was asked for, and exits with `code`
+fn finish(report: &report::Report, path: Option<&Path>, code: i32) -> ! {
+    if let Some(path) = path {
+        if let Err(e) = report.write(path, code) {
+            println!("Failed to write report {}: {}", path.display(), e);
+            process::exit(report::EXIT_OUTPUT);
+        }
+    }
+    process::exit(code);
+}
+
+/// Runs the `rs-tracer` command line on the process's arguments.
+pub fn main() {
+    let mut scene = Scene::demo();
+    let mut camera = Camera::default();
+    let mut render_options = RenderOptions::default();
+
+    let mut frame_budget = Some(Duration::from_millis(DEFAULT_FRAME_BUDGET_MS));
+    let mut target_fps = None;
+    let mut shading_cache = None;
+    let mut progressive = false;
+    let mut scene_cache = true;
+    let mut search_paths = Vec::new();
+    let mut colormap = heatmap::Colormap::Viridis;
+    let mut color_spaces = Vec::new();
+    let mut output_space = None;
+    let mut audio_path: Option<PathBuf> = None;
+    let mut audio_maps = Vec::new();
+    let mut osc_address: Option<String> = None;
+    let mut api_address: Option<String> = None;
+    let mut models: Vec<String> = Vec::new();
+    let mut sky = None;
+    let mut environment_path: Option<PathBuf> = None;
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut report = report::Report::new(&args.join(" "));
+    let mut report_path: Option<PathBuf> = None;
+    let mut output_path: Option<PathBuf> = None;
+    // Where the scene came from, if not built in, and its camera's path
+    let mut scene_file: Option<PathBuf> = None;
+    let mut camera_name: Option<String> = None;
+    let mut builtin = None;
+    loop {
+        let consumed = match (args.first().map(String::as_str), args.get(1)) {
+            (Some("--robust-intersections"), _) => {
+                render_options.robust_intersections = true;
+                1
+            }
+            (Some("--builtin"), Some(value)) => {
+                builtin = Some(builtin::scene(value).unwrap_or_else(|| usage()));
+                2
+            }
+            (Some("--meters-per-unit"), Some(value)) => {
+                match value.parse() {
+                    Ok(m) if m > 0.0 => scene.units.meters_per_unit = m,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--up-axis"), Some(value)) => {
+                match value.to_lowercase().as_str() {
+                    "y" => scene.units.up_axis = UpAxis::Y,
+                    "z" => scene.units.up_axis = UpAxis::Z,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--size"), Some(value)) => {
+                let size: Vec<u32> = value.split('x').filter_map(|v| v.parse().ok()).collect();
+                match size.as_slice() {
+                    &[width, height] if width > 0 && height > 0 => {
+                        render_options.width = width;
+                        render_options.height = height;
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--bit-depth"), Some(value)) => {
+                match value.as_str() {
+                    "8" => render_options.bit_depth = 8,
+                    "16" => render_options.bit_depth = 16,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--color-config"), Some(value)) => {
+                match color::load_config(Path::new(value)) {
+                    Ok(spaces) => color_spaces.extend(spaces),
+                    Err(e) => {
+                        println!("Failed to load colour config: {}", e);
+                        process::exit(report::EXIT_USAGE);
+                    }
+                }
+                2
+            }
+            (Some("--output-space"), Some(value)) => {
+                output_space = Some(value.clone());
+                2
+            }
+            (Some("--dither"), Some(value)) => {
+                render_options.dither = dither::Dither::from_name(value).unwrap_or_else(|| usage());
+                2
+            }
+            (Some("--integrator"), Some(value)) => {
+                render_options.integrator =
+                    path::Integrator::from_name(value).unwrap_or_else(|| usage());
+                2
+            }
+            (Some("--traversal"), Some(value)) => {
+                render_options.traversal =
+                    bvh::Traversal::from_name(value).unwrap_or_else(|| usage());
+                2
+            }
+            (Some("--lod-pixels"), Some(value)) => {
+                match value.parse() {
+                    Ok(pixels) if pixels >= 0.0 => render_options.lod_pixels = pixels,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--threads"), Some(value)) => {
+                render_options.threads = value.parse().unwrap_or_else(|_| usage());
+                2
+            }
+            (Some("--tile-size"), Some(value)) => {
+                match value.parse() {
+                    Ok(size) if size > 0 => render_options.tile_size = size,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--max-depth"), Some(value)) => {
+                match value.parse() {
+                    Ok(depth) if depth <= render::MAX_DEPTH_LIMIT => {
+                        render_options.max_depth = depth
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--caustic-spread"), Some(value)) => {
+                match value.parse() {
+                    Ok(degrees) if (0.0..=90.0).contains(&degrees) => {
+                        render_options.caustic_spread = degrees
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--light-samples"), Some(value)) => {
+                match value.parse() {
+                    Ok(samples) if samples > 0 => render_options.light_samples = samples,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--pixel-samples"), Some(value)) => {
+                match value.parse() {
+                    Ok(samples) if samples > 0 => render_options.samples_per_pixel = samples,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--fov"), Some(value)) => {
+                match value.parse() {
+                    Ok(degrees) if degrees > 0.0 && degrees < 180.0 => {
+                        render_options.fov = Some(degrees)
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--aperture"), Some(value)) => {
+                match value.parse() {
+                    Ok(diameter) if diameter >= 0.0 => render_options.aperture = Some(diameter),
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--focal-distance"), Some(value)) => {
+                match value.parse() {
+                    Ok(distance) if distance > 0.0 => {
+                        render_options.focal_distance = Some(distance)
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--shutter"), Some(value)) => {
+                match value.parse() {
+                    Ok(seconds) if seconds >= 0.0 => render_options.shutter = seconds,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--anaglyph"), Some(value)) => {
+                match value.parse() {
+                    Ok(separation) if separation > 0.0 => {
+                        render_options.anaglyph = Some(separation)
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--frame-budget"), Some(value)) => {
+                frame_budget = match value.parse() {
+                    Ok(0) => None,
+                    Ok(ms) => Some(Duration::from_millis(ms)),
+                    Err(_) => usage(),
+                };
+                2
+            }
+            (Some("--target-fps"), Some(value)) => {
+                match value.parse() {
+                    Ok(fps) if fps > 0.0 => target_fps = Some(fps),
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--shading-cache"), Some(value)) => {
+                match value.parse() {
+                    Ok(cell) if cell > 0.0 => shading_cache = Some(cell),
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--progressive"), _) => {
+                progressive = true;
+                1
+            }
+            (Some("--no-scene-cache"), _) => {
+                scene_cache = false;
+                1
+            }
+            (Some("--search-path"), Some(value)) => {
+                search_paths.push(PathBuf::from(value));
+                2
+            }
+            (Some("--obj"), Some(model)) => {
+                models.push(model.clone());
+                2
+            }
+            (Some("--sky"), Some(zenith)) => {
+                let horizon = args.get(2).and_then(|h| parse_point(h));
+                match (parse_point(zenith), horizon) {
+                    (Some(zenith), Some(horizon)) => {
+                        sky = Some((zenith.to_vec(), horizon.to_vec()))
+                    }
+                    _ => usage(),
+                }
+                3
+            }
+            (Some("--environment"), Some(path)) => {
+                environment_path = Some(PathBuf::from(path));
+                2
+            }
+            (Some("--report"), Some(value)) => {
+                report_path = Some(PathBuf::from(value));
+                2
+            }
+            (Some("--output"), Some(value)) => {
+                output_path = Some(PathBuf::from(value));
+                2
+            }
+            (Some("--clip-plane"), Some(point)) => {
+                let normal = args.get(2).and_then(|n| parse_point(n));
+                match (parse_point(point), normal) {
+                    (Some(point), Some(normal)) if normal.to_vec().magnitude2() > 0.0 => {
+                        let plane = clip::ClipPlane::new(point, normal.to_vec());
+                        render_options.clip_planes.push(plane);
+                        render_options.clipping = true;
+                    }
+                    _ => usage(),
+                }
+                3
+            }
+            (Some("--exposure"), Some(value)) => {
+                match value.parse::<f32>() {
+                    Ok(stops) if stops.is_finite() => render_options.exposure = stops,
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--ev100"), Some(value)) => {
+                match value.parse::<f32>() {
+                    Ok(ev100) if ev100.is_finite() => {
+                        render_options.exposure = light::ev100_exposure(ev100)
+                    }
+                    _ => usage(),
+                }
+                2
+            }
+            (Some("--osc"), Some(address)) => {
+                osc_address = Some(address.clone());
+                2
+            }
+            (Some("--api"), Some(address)) => {
+                api_address = Some(address.clone());
+                2
+            }
+            (Some("--audio"), Some(path)) => {
+                audio_path = Some(PathBuf::from(path));
+                2
+            }
+            (Some("--audio-map"), Some(value)) => {
+                let parts: Vec<&str> = value.split(':').collect();
+                let mapping = match parts.as_slice() {
+                    &[band, target, amount] => match (
+                        audio::Band::from_name(band),
+                        audio::Target::from_name(target),
+                        amount.parse(),
+                    ) {
+                        (Some(band), Some(target), Ok(amount)) => Some(audio::Mapping {
+                            band,
+                            target,
+                            amount,
+                        }),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                audio_maps.push(mapping.unwrap_or_else(|| usage()));
+                2
+            }
+            (Some("--burn-in"), _) => {
+                render_options.burn_in = true;
+                1
+            }
+            (Some("--shake"), Some(amplitude)) => {
+                match (amplitude.parse(), args.get(2).map(|f| f.parse())) {
+                    (Ok(amplitude), Some(Ok(frequency)))
+                        if amplitude >= 0.0 && frequency >= 0.0 =>
+                    {
+                        render_options.shake = Some(shake::Shake {
+                            amplitude,
+                            frequency,
+                        });
+                    }
+                    _ => usage(),
+                }
+                3
+            }
+            (Some("--heatmap"), Some(scalar)) => {
+                let scalar = match scalar.as_str() {
+                    "height" => Some(heatmap::Scalar::Height),
+                    s if s.starts_with("distance:") => {
+                        parse_point(&s["distance:".len()..]).map(heatmap::Scalar::Distance)
+                    }
+                    s if s.starts_with("attribute:") && s.len() > "attribute:".len() => {
+                        Some(heatmap::Scalar::Attribute(s["attribute:".len()..].to_string()))
+                    }
+                    _ => None,
+                };
+                let range: Option<Vec<f32>> = args
+                    .get(2)
+                    .and_then(|r| r.split(',').map(|v| v.trim().parse().ok()).collect());
+                match (scalar, range.as_deref()) {
+                    (Some(scalar), Some(&[min, max])) if min < max => {
+                        render_options.heatmap = Some(heatmap::Heatmap {
+                            scalar,
+                            colormap,
+                            min,
+                            max,
+                        });
+                    }
+                    _ => usage(),
+                }
+                3
+            }
+            (Some("--colormap"), Some(value)) => {
+                colormap = heatmap::Colormap::from_name(value).unwrap_or_else(|| usage());
+                2
+            }
+            (Some("--clip-cap"), Some(value)) => {
+                match parse_point(value) {
+                    Some(color) => render_options.clip_cap = Some(color.to_vec()),
+                    None => usage(),
+                }
+                2
+            }
+            _ => break,
+        };
+        args.drain(..consumed);
+    }
+    // Built-in scenes are in whatever units were given for the scene
+    if let Some((built, view)) = builtin {
+        scene = Scene {
+            units: scene.units,
+            ..built
+        };
+        camera = view;
+    }
+    camera.up = scene.units.up();
+    camera = camera.with_overrides(&render_options);
+    report.set_samples_per_pixel(render_options.samples_per_pixel);
+    // Only fails if the pool was already built, which nothing else does
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(render_options.threads)
+        .build_global();
+    if let Some(name) = output_space {
+        render_options.output_space =
+            color::ColorSpace::named(&name, &color_spaces).unwrap_or_else(|| usage());
+    }
+    if let Some(ref mut heatmap) = render_options.heatmap {
+        heatmap.colormap = colormap;
+    }
+    // Shared by every scene the run loads, so they share its assets too
+    let mut resolver = resolve::AssetResolver::new(search_paths);
+    if !scene_cache {
+        resolver.disable_scene_cache();
+    }
+
+    if args.len() == 2 && args[0] == "--batch" {
+        interrupt::install();
+        let jobs = match batch::read_jobs(Path::new(&args[1])) {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                report.error(format!("Failed to read batch: {}", e));
+                finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+            }
+        };
+        let failed =
+            batch::run(&jobs, &scene.units, &resolver, &render_options, &camera, &mut report);
+        let code = if interrupt::interrupted() {
+            report::EXIT_INTERRUPTED
+        } else if failed > 0 {
+            report::EXIT_PARTIAL
+        } else {
+            report::EXIT_OK
+        };
+        finish(&report, report_path.as_deref(), code);
+    }
+
+    if args.len() == 3 && args[0] == "--watch" {
+        interrupt::install();
+        let out_dir = Path::new(&args[2]);
+        if let Err(e) = fs::create_dir_all(out_dir) {
+            println!("Failed to create {}: {}", out_dir.display(), e);
+            process::exit(report::EXIT_OUTPUT);
+        }
+        watch::watch(
+            Path::new(&args[1]),
+            out_dir,
+            &scene.units,
+            &resolver,
+            &render_options,
+            &camera,
+        );
+        process::exit(report::EXIT_INTERRUPTED);
+    }
+
+    if args.len() == 2 && args[0] == "--validate" {
+        match load_stage(Path::new(&args[1]), &scene.units, &resolver) {
+            Ok(stage) => {
+                for warning in stage.warnings {
+                    report.warning(format!("{}: {}", args[1], warning));
+                }
+                println!(
+                    "{}: {} spheres, {} meshes, {} emitters, {} cameras",
+                    args[1],
+                    stage.scene.spheres().count(),
+                    stage.scene.primitives.len() - stage.scene.spheres().count(),
+                    stage.scene.emitters.len(),
+                    stage.cameras.len()
+                );
+                for &(ref name, handle) in &stage.materials {
+                    println!("  material {}: {}", handle, name);
+                }
+                finish(&report, report_path.as_deref(), report::EXIT_OK);
+            }
+            Err(e) => {
+                report.error(format!("Failed to load scene: {}", e));
+                finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+            }
+        }
+    }
+
+    if args.len() >= 2 && args[0] == "--scene" {
+        match load_stage(Path::new(&args[1]), &scene.units, &resolver) {
+            Ok(stage) => {
+                if let Some(c) = stage.camera(None) {
+                    camera = c.with_overrides(&render_options);
+                }
+                scene_file = Some(PathBuf::from(&args[1]));
+                camera_name = stage.cameras.first().map(|c| c.0.clone());
+                for warning in stage.warnings {
+                    report.warning(format!("{}: {}", args[1], warning));
+                }
+                scene = stage.scene;
+            }
+            Err(e) => {
+                report.error(format!("Failed to load scene: {}", e));
+                finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+            }
+        }
+        args.drain(..2);
+    }
+    // Further scenes to switch to in the interactive window
+    let mut more_scenes: Vec<PathBuf> = Vec::new();
+    while args.len() >= 2 && args[0] == "--scene" {
+        more_scenes.push(PathBuf::from(&args[1]));
+        args.drain(..2);
+    }
+    // Models are found as scenes' models are, from the working directory,
+    // and remember their files to be reloaded as those change
+    for model in &models {
+        let loaded = resolver.resolve(model, Path::new(".")).and_then(|path| {
+            let source = MeshSource {
+                modified: resolve::modified(&path),
+                path,
+                transform: Matrix4::identity(),
+            };
+            Ok((resolver.mesh(&source.path)?, source))
+        });
+        match loaded {
+            Ok((mesh, source)) => {
+                let object = scene.primitives.push(Primitive::Mesh(mesh));
+                scene.mesh_sources.insert(object, source);
+            }
+            Err(e) => {
+                report.error(format!("Failed to load model: {}", e));
+                finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+            }
+        }
+    }
+    // A sky over the scene in place of any it has, at an intensity that
+    // rays escaping the scene see it at as given
+    let environment = match (sky, environment_path) {
+        (Some(_), Some(_)) => usage(),
+        (Some((zenith, horizon)), None) => Some(Environment::Gradient {
+            up: scene.units.up(),
+            zenith,
+            horizon,
+            ground: horizon,
+        }),
+        (None, Some(path)) => {
+            let to_map = environment::up_to_map(scene.units.up());
+            match Environment::map(path, to_map, &resolver) {
+                Ok(environment) => Some(environment),
+                Err(e) => {
+                    report.error(format!("Failed to load environment map: {}", e));
+                    finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+                }
+            }
+        }
+        (None, None) => None,
+    };
+    if environment.is_some() {
+        scene.lights.retain(|light| match *light {
+            Light::Dome { .. } => false,
+            Light::Point { .. } | Light::Directional { .. } => true,
+        });
+        scene.lights.push(Light::Dome {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: FRAC_1_PI,
+            portals: Vec::new(),
+            environment,
+        });
+    }
+    if render_options.lod_pixels > 0.0 {
+        lod::build_clusters(&mut scene);
+    }
+
+    let audio = audio_path.map(|path| match audio::load_wav(&path) {
+        Ok(track) => audio::AudioDriver::new(track, audio_maps, &scene),
+        Err(e) => {
+            report.error(format!("Failed to load audio {}: {}", path.display(), e));
+            finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+        }
+    });
+
+    // A single frame rendered without opening a window
+    if let Some(ref path) = output_path {
+        if !args.is_empty() {
+            usage();
+        }
+        interrupt::install();
+        if render_options.lod_pixels > 0.0 {
+            lod::select_lod(&mut scene, &camera, &render_options);
+        }
+        let hud = burn_in(&render_options, scene_file.as_deref(), camera_name.as_deref(), 1);
+        let view = camera.at_time(scene.time);
+        let result =
+            batch::metadata(scene_file.as_deref(), None, &scene, &render_options).and_then(|m| {
+                batch::render(&mut scene, &view, &render_options, path, &m, hud.as_ref())
+            });
+        let code = match result {
+            Ok(()) => {
+                report.output(path);
+                report::EXIT_OK
+            }
+            Err(e) => {
+                report.error(format!("{}: {}", path.display(), e));
+                if interrupt::interrupted() {
+                    report::EXIT_INTERRUPTED
+                } else {
+                    report::EXIT_OUTPUT
+                }
+            }
+        };
+        finish(&report, report_path.as_deref(), code);
+    }
+
+    if !args.is_empty() {
+        match args[0].as_str() {
+            "probes" if args.len() > 2 => {
+                let probes: Option<Vec<Point3<f32>>> =
+                    args[2..].iter().map(|a| parse_point(a)).collect();
+                let probes = probes.unwrap_or_else(|| usage());
+                let out_dir = Path::new(&args[1]);
+                match probe::bake_probes(&scene, &render_options, &probes, PROBE_SIZE, out_dir) {
+                    Ok(()) => report.output(out_dir),
+                    Err(e) => report.error(format!("Failed to bake probes: {}", e)),
+                }
+            }
+            "panorama" if args.len() == 3 => {
+                let position = parse_point(&args[2]).unwrap_or_else(|| usage());
+                let path = Path::new(&args[1]);
+                let result =
+                    panorama::export_hdri(&scene, &render_options, position, PANORAMA_HEIGHT, path);
+                match result {
+                    Ok(()) => report.output(path),
+                    Err(e) => report.error(format!("Failed to export panorama: {}", e)),
+                }
+            }
+            "sheet" if args.len() == 2 => {
+                let path = Path::new(&args[1]);
+                let result = sheet::export_contact_sheet(
+                    &scene,
+                    &camera,
+                    &render_options,
+                    SHEET_PANEL_SIZE,
+                    path,
+                );
+                match result {
+                    Ok(()) => report.output(path),
+                    Err(e) => report.error(format!("Failed to write contact sheet: {}", e)),
+                }
+            }
+            "preview" if args.len() == 3 => {
+                let (material_path, path) = (Path::new(&args[1]), Path::new(&args[2]));
+                let material = match json::load_material(material_path) {
+                    Ok((material, warnings)) => {
+                        for warning in warnings {
+                            report.warning(format!("{}: {}", material_path.display(), warning));
+                        }
+                        material
+                    }
+                    Err(e) => {
+                        report.error(format!("Failed to load material: {}", e));
+                        finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+                    }
+                };
+                interrupt::install();
+                let (mut preview, view) = builtin::material_preview(material);
+                let options = RenderOptions {
+                    width: THUMBNAIL_SIZE,
+                    height: THUMBNAIL_SIZE,
+                    ..render_options.clone()
+                };
+                let result = batch::metadata(Some(material_path), None, &preview, &options)
+                    .and_then(|m| batch::render(&mut preview, &view, &options, path, &m, None));
+                match result {
+                    Ok(()) => report.output(path),
+                    Err(e) => report.error(format!("Failed to render preview: {}", e)),
+                }
+            }
+            "bake" if args.len() == 4 => {
+                let kind = bake::Bake::from_name(&args[1]).unwrap_or_else(|| usage());
+                let path = Path::new(&args[3]);
+                // The model lies in the scene while it's baked, so it
+                // shadows itself
+                let model = resolver.resolve(&args[2], Path::new(".")).and_then(|model| {
+                    let layout = obj::load_uv_layout(&model)?;
+                    Ok((resolver.mesh(&model)?, layout))
+                });
+                let layout = match model {
+                    Ok((mesh, layout)) => {
+                        scene.primitives.push(Primitive::Mesh(mesh));
+                        layout
+                    }
+                    Err(e) => {
+                        report.error(format!("Failed to load model: {}", e));
+                        finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+                    }
+                };
+                if layout.is_empty() {
+                    report.warning(format!("{} has no texture coordinates", args[2]));
+                }
+                let texture = bake::bake(&scene, &layout, kind, &render_options);
+                let result = batch::metadata(scene_file.as_deref(), None, &scene, &render_options)
+                    .and_then(|m| batch::save_radiance(&texture, &render_options, path, &m, None));
+                match result {
+                    Ok(()) => report.output(path),
+                    Err(e) => report.error(format!("Failed to write lightmap: {}", e)),
+                }
+            }
+            "convert" if args.len() == 3 => {
+                let (input, path) = (Path::new(&args[1]), Path::new(&args[2]));
+                // Loaded into the units asked for, which the JSON file is
+                // then written in
+                let stage = match load_stage(input, &scene.units, &resolver) {
+                    Ok(stage) => stage,
+                    Err(e) => {
+                        report.error(format!("Failed to load scene: {}", e));
+                        finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+                    }
+                };
+                for warning in &stage.warnings {
+                    report.warning(format!("{}: {}", input.display(), warning));
+                }
+                match convert::save(&stage, path) {
+                    Ok(warnings) => {
+                        for warning in warnings {
+                            report.warning(format!("{}: {}", path.display(), warning));
+                        }
+                        report.output(path);
+                    }
+                    Err(e) => report.error(format!("Failed to write scene: {}", e)),
+                }
+            }
+            "schema" if args.len() == 2 => {
+                let path = Path::new(&args[1]);
+                match schema::save(path) {
+                    Ok(()) => report.output(path),
+                    Err(e) => report.error(format!("Failed to write schema: {}", e)),
+                }
+            }
+            "diff" if args.len() == 3 || args.len() == 4 => {
+                let (a, b) = match (args[1].parse::<u32>(), args[2].parse::<u32>()) {
+                    (Ok(a), Ok(b)) if a <= b => (a, b),
+                    _ => usage(),
+                };
+                let snapshot = |scene: &Scene| {
+                    let view = match render_options.shake {
+                        Some(shake) => shake.apply(&camera, scene.time),
+                        None => camera.clone(),
+                    };
+                    snapshot::Snapshot::capture(scene, &view)
+                };
+                step_frames(&mut scene, a, audio.as_ref());
+                let before = snapshot(&scene);
+                step_frames(&mut scene, b - a, audio.as_ref());
+                let after = snapshot(&scene);
+
+                println!("frame {} -> frame {}", a, b);
+                for change in snapshot::diff(&before, &after) {
+                    println!("{}", change);
+                }
+                if let Some(dir) = args.get(3) {
+                    let dir = Path::new(dir);
+                    let written = fs::create_dir_all(dir)
+                        .and_then(|_| before.write(&dir.join(format!("frame_{:04}.txt", a))))
+                        .and_then(|_| after.write(&dir.join(format!("frame_{:04}.txt", b))));
+                    match written {
+                        Ok(()) => report.output(dir),
+                        Err(e) => report.error(format!("Failed to write snapshots: {}", e)),
+                    }
+                }
+            }
+            "sequence" if args.len() == 4 => {
+                let (first, last) = match (args[1].parse::<u32>(), args[2].parse::<u32>()) {
+                    (Ok(first), Ok(last)) if first <= last => (first, last),
+                    _ => usage(),
+                };
+                let pattern = &args[3];
+                if timeline::frame_path(pattern, first).is_none() {
+                    usage();
+                }
+                interrupt::install();
+                step_frames(&mut scene, first, audio.as_ref());
+                for frame in first..=last {
+                    let path = timeline::frame_path(pattern, frame).expect("the pattern has a #");
+                    let view = camera.at_time(scene.time);
+                    let view = match render_options.shake {
+                        Some(shake) => shake.apply(&view, scene.time),
+                        None => view,
+                    };
+                    if render_options.lod_pixels > 0.0 {
+                        lod::select_lod(&mut scene, &view, &render_options);
+                    }
+                    let scene_file = scene_file.as_deref();
+                    let hud = burn_in(&render_options, scene_file, camera_name.as_deref(), frame);
+                    let metadata = batch::metadata(scene_file, None, &scene, &render_options);
+                    let result = metadata.and_then(|m| {
+                        batch::render(&mut scene, &view, &render_options, &path, &m, hud.as_ref())
+                    });
+                    match result {
+                        Ok(()) => report.output(&path),
+                        Err(e) => report.error(format!("{}: {}", path.display(), e)),
+                    }
+                    if interrupt::interrupted() {
+                        finish(&report, report_path.as_deref(), report::EXIT_INTERRUPTED);
+                    }
+                    step_frames(&mut scene, 1, audio.as_ref());
+                }
+            }
+            "bench" if args.len() <= 3 => {
+                let baseline_path = args.get(1).map(Path::new);
+                let save = match args.get(2).map(String::as_str) {
+                    None => false,
+                    Some("save") => true,
+                    Some(_) => usage(),
+                };
+                let baseline = match baseline_path {
+                    Some(path) if !save => match bench::read_baseline(path) {
+                        Ok(baseline) => baseline,
+                        Err(e) => {
+                            let path = path.display();
+                            report.error(format!("Failed to read baseline {}: {}", path, e));
+                            finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+                        }
+                    },
+                    _ => HashMap::new(),
+                };
+                let results = bench::run(&baseline);
+                if let (Some(path), true) = (baseline_path, save) {
+                    match bench::write_baseline(path, &results) {
+                        Ok(()) => report.output(path),
+                        Err(e) => report.error(format!("Failed to write baseline: {}", e)),
+                    }
+                }
+            }
+            "furnace" if args.len() == 1 => {
+                let failed = furnace::run(&render_options);
+                for name in &failed {
+                    report.error(format!("{} failed the furnace test", name));
+                }
+                let code = if failed.is_empty() { report::EXIT_OK } else { report::EXIT_CHECK };
+                finish(&report, report_path.as_deref(), code);
+            }
+            "chi-squared" if args.len() == 1 => {
+                let failed = chi_squared::run();
+                for name in &failed {
+                    report.error(format!("{} failed the chi-squared test", name));
+                }
+                let code = if failed.is_empty() { report::EXIT_OK } else { report::EXIT_CHECK };
+                finish(&report, report_path.as_deref(), code);
+            }
+            "reference" if args.len() == 2 => {
+                let samples = match args[1].parse() {
+                    Ok(samples) if samples > 0 => samples,
+                    _ => usage(),
+                };
+                let code = match reference::compare(scene, &camera, &render_options, samples) {
+                    Ok(comparison) => {
+                        println!(
+                            "mean {:.5}  reference {:.5}  bias {:+.2}%  ({:+.1} standard errors)",
+                            comparison.mean,
+                            comparison.reference_mean,
+                            comparison.bias() * 100.0,
+                            comparison.deviations()
+                        );
+                        if comparison.passes() {
+                            report::EXIT_OK
+                        } else {
+                            report.error("render differs from the reference".to_string());
+                            report::EXIT_CHECK
+                        }
+                    }
+                    Err(e) => {
+                        report.error(format!("Failed to compare with the reference: {}", e));
+                        report::EXIT_SCENE
+                    }
+                };
+                finish(&report, report_path.as_deref(), code);
+            }
+            _ => usage(),
+        }
+        let code = if report.has_errors() { report::EXIT_OUTPUT } else { report::EXIT_OK };
+        finish(&report, report_path.as_deref(), code);
+    }
+
+    let osc = osc_address.map(|address| match osc::OscListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            report.error(format!("Failed to listen for OSC on {}: {}", address, e));
+            finish(&report, report_path.as_deref(), report::EXIT_USAGE);
+        }
+    });
+
+    let api = api_address.map(|address| match api::ApiServer::bind(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            report.error(format!("Failed to serve the API on {}: {}", address, e));
+            finish(&report, report_path.as_deref(), report::EXIT_USAGE);
+        }
+    });
+
+    // Cells are given in metres, whatever units the scene is in
+    render_options.shading_cache = shading_cache.map(|cell: f32| {
+        Arc::new(ShadingCache::new(cell / scene.units.meters_per_unit))
+    });
+
+    let name = scene_file.as_ref().and_then(|f| f.file_name());
+    let name = name.map_or("built-in".into(), |n| n.to_string_lossy().into_owned());
+    let mut scenes = scenes::SceneSet::new(resolver, scene.units, name, scene, camera);
+    for path in &more_scenes {
+        match scenes.load(path, &render_options) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    report.warning(format!("{}: {}", path.display(), warning));
+                }
+            }
+            Err(e) => {
+                report.error(format!("Failed to load scene: {}", e));
+                finish(&report, report_path.as_deref(), report::EXIT_SCENE);
+            }
+        }
+    }
+    let pacing = display::Pacing {
+        frame_budget,
+        target_fps,
+    };
+    display::run(scenes, render_options, pacing, progressive, audio, osc, api);
+}
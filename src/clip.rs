@@ -0,0 +1,99 @@
+//! Section planes that cut away part of the scene to show what is inside.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use geometry::{Ray, Sphere};
+
+#[derive(Clone)]
+pub struct ClipPlane {
+    point: Point3<f32>,
+    // Unit normal, pointing into the half-space that is cut away
+    normal: Vector3<f32>,
+}
+
+impl ClipPlane {
+    pub fn new(point: Point3<f32>, normal: Vector3<f32>) -> ClipPlane {
+        ClipPlane {
+            point,
+            normal: normal.normalize(),
+        }
+    }
+
+    fn keeps(&self, point: Point3<f32>) -> bool {
+        (point - self.point).dot(self.normal) <= 0.0
+    }
+}
+
+/// Whether `point` survives every one of `planes`.
+pub fn keeps(planes: &[ClipPlane], point: Point3<f32>) -> bool {
+    planes.iter().all(|p| p.keeps(point))
+}
+
+// Both distances at which `ray` crosses the sphere's surface, nearest first
+fn roots(sphere: &Sphere, ray: &Ray) -> Option<(f32, f32)> {
+    let l = sphere.center - ray.origin;
+    let tca = l.dot(ray.direction);
+    let d2 = l.dot(l) - tca * tca;
+    let radius_squared = sphere.radius * sphere.radius;
+    if d2 > radius_squared {
+        return None;
+    }
+    let thc = (radius_squared - d2).sqrt();
+    Some((tca - thc, tca + thc))
+}
+
+/// Nearest visible hit on `sphere` once `planes` have cut it, and the cap
+/// normal if the ray lands on the cut face rather than the sphere.
+///
+/// Uncapped, the sphere is an open shell and a ray through the cut carries
+/// on to its inside surface. Capped, it is solid and the cut is filled in.
+pub fn intersect(
+    sphere: &Sphere,
+    ray: &Ray,
+    planes: &[ClipPlane],
+    capped: bool,
+) -> Option<(f32, Option<Vector3<f32>>)> {
+    let (t0, t1) = roots(sphere, ray)?;
+    span(t0, t1, ray, planes, capped)
+}
+
+/// As `intersect`, for any convex shape `ray` is inside of from distance
+/// `t0` to `t1`.
+pub fn span(
+    t0: f32,
+    t1: f32,
+    ray: &Ray,
+    planes: &[ClipPlane],
+    capped: bool,
+) -> Option<(f32, Option<Vector3<f32>>)> {
+    if !capped {
+        return [t0, t1]
+            .iter()
+            .cloned()
+            .find(|&t| t >= 0.0 && keeps(planes, ray.origin + ray.direction * t))
+            .map(|t| (t, None));
+    }
+
+    // Clip the span the ray spends inside the shape against each plane
+    let (mut start, mut end) = ((t0, None), t1);
+    for plane in planes {
+        let offset = (ray.origin - plane.point).dot(plane.normal);
+        let rate = ray.direction.dot(plane.normal);
+        if rate == 0.0 {
+            if offset > 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = -offset / rate;
+        if rate > 0.0 {
+            end = end.min(t);
+        } else if t > start.0 {
+            start = (t, Some(plane.normal));
+        }
+    }
+    if start.0 < 0.0 || start.0 > end {
+        return None;
+    }
+    Some(start)
+}
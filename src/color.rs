@@ -0,0 +1,154 @@
+//! Colour management. Rendering works in linear light with Rec.709
+//! primaries; an output colour space converts that to the primaries and
+//! transfer function an output or display expects.
+//!
+//! Besides the built-in spaces, more can be read from a config file with
+//! one space per line: `<name> <transfer> [<9 matrix entries>]`, where the
+//! transfer is `linear`, `srgb`, `rec709` or `gamma:<g>` and the optional
+//! matrix, in rows, converts from the working space's primaries. Blank
+//! lines and lines starting with `#` are ignored.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use render::Color;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Transfer {
+    Linear,
+    Srgb,
+    Rec709,
+    Gamma(f32),
+}
+
+impl Transfer {
+    fn parse(s: &str) -> Option<Transfer> {
+        match s {
+            "linear" => Some(Transfer::Linear),
+            "srgb" => Some(Transfer::Srgb),
+            "rec709" => Some(Transfer::Rec709),
+            _ => match s.strip_prefix("gamma:").map(str::parse) {
+                Some(Ok(g)) if g > 0.0 => Some(Transfer::Gamma(g)),
+                _ => None,
+            },
+        }
+    }
+
+    fn encode(self, v: f32) -> f32 {
+        match self {
+            Transfer::Linear => v,
+            Transfer::Srgb if v <= 0.003_130_8 => 12.92 * v,
+            Transfer::Srgb => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+            Transfer::Rec709 if v < 0.018 => 4.5 * v,
+            Transfer::Rec709 => 1.099 * v.powf(0.45) - 0.099,
+            Transfer::Gamma(g) => v.powf(1.0 / g),
+        }
+    }
+}
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+// Linear Rec.709 to linear Display P3 (D65 white in both)
+const REC709_TO_P3: [[f32; 3]; 3] = [
+    [0.8225, 0.1774, 0.0000],
+    [0.0332, 0.9669, 0.0000],
+    [0.0171, 0.0724, 0.9108],
+];
+
+#[derive(Clone, Debug)]
+pub struct ColorSpace {
+    name: String,
+    matrix: [[f32; 3]; 3],
+    transfer: Transfer,
+}
+
+impl ColorSpace {
+    /// The working space itself: values are written out unchanged.
+    pub fn linear() -> ColorSpace {
+        ColorSpace {
+            name: "linear".to_string(),
+            matrix: IDENTITY,
+            transfer: Transfer::Linear,
+        }
+    }
+
+    fn builtin(name: &str) -> Option<ColorSpace> {
+        let (matrix, transfer) = match name {
+            "linear" => (IDENTITY, Transfer::Linear),
+            "srgb" => (IDENTITY, Transfer::Srgb),
+            "rec709" => (IDENTITY, Transfer::Rec709),
+            "display-p3" => (REC709_TO_P3, Transfer::Srgb),
+            _ => return None,
+        };
+        Some(ColorSpace {
+            name: name.to_string(),
+            matrix,
+            transfer,
+        })
+    }
+
+    /// Looks `name` up among `configured` spaces, then the built-in ones.
+    pub fn named(name: &str, configured: &[ColorSpace]) -> Option<ColorSpace> {
+        configured
+            .iter()
+            .find(|space| space.name == name)
+            .cloned()
+            .or_else(|| ColorSpace::builtin(name))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Converts working-space radiance to this space's encoded values.
+    /// Colours outside its gamut are clipped at zero.
+    pub fn encode(&self, color: Color) -> Color {
+        let m = &self.matrix;
+        let c = [color.x, color.y, color.z];
+        let row = |r: [f32; 3]| r[0] * c[0] + r[1] * c[1] + r[2] * c[2];
+        let encode = |v: f32| self.transfer.encode(v.max(0.0));
+        Color::new(encode(row(m[0])), encode(row(m[1])), encode(row(m[2])))
+    }
+}
+
+pub fn load_config(path: &Path) -> Result<Vec<ColorSpace>, String> {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut spaces = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = || {
+            format!(
+                "{}:{}: expected <name> <transfer> [<9 matrix entries>]",
+                path.display(),
+                i + 1
+            )
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 {
+            return Err(error());
+        }
+        let transfer = Transfer::parse(fields[1]).ok_or_else(error)?;
+        let numbers: Vec<f32> = fields[2..].iter().filter_map(|f| f.parse().ok()).collect();
+        let matrix = match (fields.len() - 2, numbers.len()) {
+            (0, _) => IDENTITY,
+            (9, 9) => [
+                [numbers[0], numbers[1], numbers[2]],
+                [numbers[3], numbers[4], numbers[5]],
+                [numbers[6], numbers[7], numbers[8]],
+            ],
+            _ => return Err(error()),
+        };
+        spaces.push(ColorSpace {
+            name: fields[0].to_string(),
+            matrix,
+            transfer,
+        });
+    }
+    Ok(spaces)
+}
@@ -0,0 +1,115 @@
+//! Data about a scene's objects kept apart from the shapes the renderer
+//! traces, one column per kind of data, so the systems using it step
+//! through just that column: animation through the objects' motions and
+//! keyframes, material edits through which named material each object was
+//! given, and asset reloading through the files meshes were read from.
+//!
+//! Columns are packed, their values side by side in no particular order,
+//! and name their objects by handle. An object without a value in a column
+//! simply has none of that kind of data.
+
+use cgmath::{Matrix4, Vector3};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use handle::Handle;
+use primitive::Primitive;
+use timeline;
+
+/// Steady movement of an object.
+#[derive(Clone, Copy, Debug)]
+pub struct Animation {
+    // Scene units per second
+    pub velocity: Vector3<f32>,
+}
+
+/// Keyed movement of an object: how far it's moved from where it was
+/// placed, at times on the scene clock.
+#[derive(Clone, Debug)]
+pub struct Keyframes {
+    // Seconds, in order, each with the offset in scene units
+    pub keys: Vec<(f32, Vector3<f32>)>,
+}
+
+impl Keyframes {
+    /// How far the object is moved from where it was placed at `time`.
+    pub fn offset(&self, time: f32) -> Vector3<f32> {
+        timeline::sample(&self.keys, time, |&key| key)
+    }
+}
+
+/// The model file an object's mesh was read from, and how it was placed in
+/// the scene, so the mesh can be read again when the file changes.
+#[derive(Clone, Debug)]
+pub struct MeshSource {
+    pub path: PathBuf,
+    pub transform: Matrix4<f32>,
+    // When the file was modified as it was read
+    pub modified: Option<SystemTime>,
+}
+
+pub struct Column<T> {
+    objects: Vec<Handle<Primitive>>,
+    values: Vec<T>,
+    // Where each object's value is
+    index: HashMap<Handle<Primitive>, usize>,
+}
+
+impl<T> Column<T> {
+    pub fn new() -> Column<T> {
+        Column {
+            objects: Vec::new(),
+            values: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Gives `object` `value`, replacing any it had.
+    pub fn insert(&mut self, object: Handle<Primitive>, value: T) {
+        match self.index.get(&object) {
+            Some(&i) => self.values[i] = value,
+            None => {
+                self.index.insert(object, self.values.len());
+                self.objects.push(object);
+                self.values.push(value);
+            }
+        }
+    }
+
+    /// Takes out the value of `object`, moving the last value into its
+    /// place.
+    pub fn remove(&mut self, object: Handle<Primitive>) -> Option<T> {
+        let i = self.index.remove(&object)?;
+        self.objects.swap_remove(i);
+        let value = self.values.swap_remove(i);
+        if let Some(&moved) = self.objects.get(i) {
+            self.index.insert(moved, i);
+        }
+        Some(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get(&self, object: Handle<Primitive>) -> Option<&T> {
+        self.index.get(&object).map(|&i| &self.values[i])
+    }
+
+    pub fn get_mut(&mut self, object: Handle<Primitive>) -> Option<&mut T> {
+        let i = *self.index.get(&object)?;
+        Some(&mut self.values[i])
+    }
+
+    /// Every object with a value, and the value.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Handle<Primitive>, &'a T)> + 'a {
+        self.objects.iter().cloned().zip(&self.values)
+    }
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Column<T> {
+        Column::new()
+    }
+}
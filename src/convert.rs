@@ -0,0 +1,266 @@
+//! Conversion of loaded scenes into the tracer's own JSON scene files, so a
+//! scene brought in through an intermediate format such as glTF can be
+//! edited by hand and loaded again without converting it every time.
+//!
+//! The scene is written as it was loaded, in the units it was loaded into,
+//! so the command line's `--meters-per-unit` and `--up-axis` choose the
+//! units of the JSON file. Spheres, boxes, labels, named materials, cameras
+//! and lights go into the file itself, and each mesh into an `.obj` file beside
+//! it, named after it and numbered, already in place. Animation, particle
+//! emitters, IES profiles and mesh attributes have no place in a JSON scene
+//! and are left out with a warning.
+
+use cgmath::{EuclideanSpace, Point3, Vector3};
+use std::fs;
+use std::path::Path;
+
+use camera::Camera;
+use environment::Environment;
+use handle::Handle;
+use json::{self, Value};
+use light::{Light, Portal};
+use material::Material;
+use obj;
+use primitive::Primitive;
+use texture::Pattern;
+use usd::Stage;
+
+// Levels of the file laid out a member or item to a line: the scene, its
+// lists, and the entries of its palette
+const LAYOUT_DEPTH: usize = 2;
+
+fn number(n: f32) -> Value {
+    // Through its shortest decimal form, so 0.1 is written as 0.1 rather
+    // than as the f64 nearest the f32
+    Value::Number(n.to_string().parse().unwrap_or(f64::NAN))
+}
+
+fn vector(v: Vector3<f32>) -> Value {
+    Value::Array(vec![number(v.x), number(v.y), number(v.z)])
+}
+
+fn point(p: Point3<f32>) -> Value {
+    vector(p.to_vec())
+}
+
+fn object_of(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(members.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn texture(pattern: &Pattern) -> Value {
+    let (kind, colors, scale) = match *pattern {
+        Pattern::Checker(ref c) => ("checker", (c.even, c.odd), c.scale),
+        Pattern::Noise(ref n) => ("noise", (n.low, n.high), n.scale),
+        Pattern::Marble(ref m) => ("marble", (m.base, m.vein), m.scale),
+    };
+    let mut members = vec![
+        ("type", Value::String(kind.to_string())),
+        ("colors", Value::Array(vec![vector(colors.0), vector(colors.1)])),
+        ("scale", number(scale)),
+    ];
+    if let Pattern::Marble(ref m) = *pattern {
+        members.push(("turbulence", number(m.turbulence)));
+    }
+    object_of(members)
+}
+
+fn material(m: &Material) -> Value {
+    let mut members = vec![
+        ("albedo", vector(m.albedo)),
+        ("specular", number(m.specular)),
+        ("shininess", number(m.shininess)),
+        ("reflectivity", number(m.reflectivity)),
+        ("roughness", number(m.roughness)),
+        ("emissive", vector(m.emissive)),
+        ("transparency", number(m.transparency)),
+        ("ior", number(m.ior)),
+    ];
+    if let Some(ref pattern) = m.texture {
+        members.push(("texture", texture(pattern)));
+    }
+    object_of(members)
+}
+
+fn camera(name: &str, c: &Camera) -> Value {
+    object_of(vec![
+        ("name", Value::String(name.to_string())),
+        ("position", point(c.position)),
+        ("direction", vector(c.at)),
+        ("up", vector(c.up)),
+        ("fov", number(c.fov)),
+        ("aperture", number(c.aperture)),
+        ("focus", number(c.focal_distance)),
+    ])
+}
+
+// A light as a JSON scene gives it in a scene whose up axis is `up`, and
+// what of it was left out
+fn light(l: &Light, scene_up: Vector3<f32>) -> (Value, Option<&'static str>) {
+    match *l {
+        Light::Point {
+            position,
+            color,
+            intensity,
+            ref shaping,
+        } => {
+            let light = object_of(vec![
+                ("type", Value::String("point".to_string())),
+                ("position", point(position)),
+                ("color", vector(color)),
+                ("intensity", number(intensity)),
+            ]);
+            (light, shaping.as_ref().map(|_| "IES profile"))
+        }
+        Light::Directional {
+            direction,
+            color,
+            intensity,
+        } => {
+            let light = object_of(vec![
+                ("type", Value::String("directional".to_string())),
+                ("direction", vector(direction)),
+                ("color", vector(color)),
+                ("intensity", number(intensity)),
+            ]);
+            (light, None)
+        }
+        Light::Dome {
+            color,
+            intensity,
+            ref portals,
+            ref environment,
+        } => {
+            let portal = |p: &Portal| {
+                object_of(vec![
+                    ("corner", point(p.corner)),
+                    ("u", vector(p.u)),
+                    ("v", vector(p.v)),
+                ])
+            };
+            let mut members = vec![
+                ("type", Value::String("dome".to_string())),
+                ("color", vector(color)),
+                ("intensity", number(intensity)),
+                ("portals", Value::Array(portals.iter().map(portal).collect())),
+            ];
+            let mut skipped = None;
+            match *environment {
+                Some(Environment::Gradient {
+                    up,
+                    zenith,
+                    horizon,
+                    ground,
+                }) => {
+                    // The JSON loader turns gradients up the scene's up axis
+                    members.push(("zenith", vector(zenith)));
+                    members.push(("horizon", vector(horizon)));
+                    members.push(("ground", vector(ground)));
+                    if up != scene_up {
+                        skipped = Some("gradient's tilt");
+                    }
+                }
+                Some(Environment::Map { ref path, .. }) => {
+                    // Found from anywhere the JSON file is moved to
+                    let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                    let path = path.to_string_lossy().into_owned();
+                    members.push(("texture", Value::String(path)));
+                }
+                None => {}
+            }
+            (object_of(members), skipped)
+        }
+    }
+}
+
+/// Writes the scene of `stage` to the JSON scene file at `path`, and each
+/// of its meshes to an `.obj` file beside it, giving warnings about what
+/// was left out.
+pub fn save(stage: &Stage, path: &Path) -> Result<Vec<String>, String> {
+    let scene = &stage.scene;
+    let mut warnings = Vec::new();
+    let name_of = |handle: Handle<Material>| {
+        stage.materials.iter().find(|m| m.1 == handle).map(|m| m.0.clone())
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let stem = path
+        .file_stem()
+        .map_or("scene".into(), |s| s.to_string_lossy().into_owned());
+
+    let mut materials = Vec::new();
+    for &(ref name, handle) in &stage.materials {
+        if let Some(m) = scene.materials.get(handle) {
+            materials.push((name.clone(), material(m)));
+        }
+    }
+    let (mut spheres, mut boxes, mut models) = (Vec::new(), Vec::new(), Vec::new());
+    let mut labels = Vec::new();
+    for (object, primitive) in scene.primitives.entries() {
+        let bound = scene.material_bindings.get(object).and_then(|&m| name_of(m));
+        let made_of = bound.map_or_else(|| material(primitive.shape().material()), Value::String);
+        match *primitive {
+            Primitive::Sphere(ref sphere) => spheres.push(object_of(vec![
+                ("center", point(sphere.center)),
+                ("radius", number(sphere.radius)),
+                ("material", made_of),
+            ])),
+            Primitive::Cuboid(ref cuboid) => boxes.push(object_of(vec![
+                ("min", point(cuboid.min)),
+                ("max", point(cuboid.max)),
+                ("material", made_of),
+            ])),
+            Primitive::Mesh(ref mesh) => {
+                let file = format!("{}_{}.obj", stem, models.len() + 1);
+                obj::save(mesh, &dir.join(&file))?;
+                models.push(object_of(vec![
+                    ("file", Value::String(file)),
+                    ("material", made_of),
+                ]));
+                if mesh.attributes().next().is_some() {
+                    warnings.push(format!("attributes of mesh {} left out", models.len()));
+                }
+            }
+            Primitive::Label(ref label) => {
+                let mut members = vec![
+                    ("text", Value::String(label.text.clone())),
+                    ("position", point(label.center)),
+                    ("height", number(label.height)),
+                    ("color", vector(label.material.emissive)),
+                ];
+                if let Some(ref font) = label.font {
+                    let font = font.canonicalize().unwrap_or_else(|_| font.clone());
+                    members.push(("font", Value::String(font.to_string_lossy().into_owned())));
+                }
+                labels.push(object_of(members));
+            }
+        }
+        if scene.animations.get(object).is_some() || scene.keyframes.get(object).is_some() {
+            warnings.push(format!("animation of object {} left out", object));
+        }
+    }
+    if !scene.emitters.is_empty() {
+        warnings.push(format!("{} particle emitters left out", scene.emitters.len()));
+    }
+    let cameras = stage.cameras.iter().map(|c| camera(&c.0, &c.1)).collect();
+    let mut lights = Vec::new();
+    for (i, l) in scene.lights.iter().enumerate() {
+        let (value, skipped) = light(l, scene.units.up());
+        if let Some(skipped) = skipped {
+            warnings.push(format!("light {}: {} left out", i + 1, skipped));
+        }
+        lights.push(value);
+    }
+
+    let root = object_of(vec![
+        ("version", Value::Number(f64::from(json::VERSION))),
+        ("materials", Value::Object(materials)),
+        ("cameras", Value::Array(cameras)),
+        ("spheres", Value::Array(spheres)),
+        ("boxes", Value::Array(boxes)),
+        ("models", Value::Array(models)),
+        ("labels", Value::Array(labels)),
+        ("lights", Value::Array(lights)),
+    ]);
+    let text = root.to_json(LAYOUT_DEPTH) + "\n";
+    fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(warnings)
+}
@@ -0,0 +1,136 @@
+//! Axis-aligned boxes, for the walls, floors and blocks of simple
+//! architectural scenes, which would otherwise have to be built from
+//! triangles.
+//!
+//! A ray is tested against a box by clipping it to the slab between each
+//! pair of opposite faces ("An Efficient and Robust Ray-Box Intersection
+//! Algorithm", Williams et al.); the last slab entered is the face hit. Boxes
+//! are shaded from both sides, so one box with the camera inside it makes a
+//! room.
+
+use cgmath::{Point3, Vector3};
+use std::f32;
+
+use clip;
+use geometry::Ray;
+use material::Material;
+use primitive::{Hit, Intersectable};
+use render::RenderOptions;
+
+pub struct Cuboid {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    pub material: Material,
+}
+
+impl Cuboid {
+    // Distances along `ray` to where it enters and leaves the box, each
+    // with the face it crosses there, or None if it misses the box or the
+    // box is entirely behind it
+    fn slabs(&self, ray: &Ray) -> Option<((f32, usize), (f32, usize))> {
+        let (mut near, mut far) = ((f32::NEG_INFINITY, 0), (f32::INFINITY, 0));
+        for axis in 0..3 {
+            let (o, d) = (ray.origin[axis], ray.direction[axis]);
+            let (lo, hi) = (self.min[axis], self.max[axis]);
+            // A ray parallel to a slab never crosses its faces, so it is
+            // either always between them or never
+            if d == 0.0 {
+                if o < lo || o > hi {
+                    return None;
+                }
+                continue;
+            }
+            let inverse = 1.0 / d;
+            let low = ((lo - o) * inverse, 2 * axis);
+            let high = ((hi - o) * inverse, 2 * axis + 1);
+            let (enter, leave) = if inverse > 0.0 { (low, high) } else { (high, low) };
+            if enter.0 > near.0 {
+                near = enter;
+            }
+            if leave.0 < far.0 {
+                far = leave;
+            }
+        }
+        if near.0 > far.0 || far.0 < 0.0 {
+            return None;
+        }
+        Some((near, far))
+    }
+
+    // Outward unit normal of face `part`. Faces are numbered by axis, the low
+    // face of each before the high, which is how hits name them.
+    fn face_normal(part: usize) -> Vector3<f32> {
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[part / 2] = [-1.0, 1.0][part % 2];
+        normal
+    }
+
+    /// The box's eight corners, with bit 0 of the index picking the high x,
+    /// bit 1 the high y and bit 2 the high z.
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let mut corners = [self.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    corner[axis] = self.max[axis];
+                }
+            }
+        }
+        corners
+    }
+
+    /// The twelve triangles of a box with corners numbered as `corners`
+    /// numbers them, wound to face outwards.
+    pub fn triangles() -> Vec<[u32; 3]> {
+        vec![
+            [0, 4, 6], [0, 6, 2], // -x
+            [1, 3, 7], [1, 7, 5], // +x
+            [0, 1, 5], [0, 5, 4], // -y
+            [2, 6, 7], [2, 7, 3], // +y
+            [0, 2, 3], [0, 3, 1], // -z
+            [4, 5, 7], [4, 7, 6], // +z
+        ]
+    }
+}
+
+impl Intersectable for Cuboid {
+    fn intersect(&self, ray: &Ray, render_options: &RenderOptions) -> Option<Hit> {
+        let ((t0, entry), (t1, exit)) = self.slabs(ray)?;
+        let (distance, cap) = if render_options.clipping {
+            let capped = render_options.clip_cap.is_some();
+            clip::span(t0, t1, ray, &render_options.clip_planes, capped)?
+        } else if t0 >= 0.0 {
+            (t0, None)
+        } else {
+            (t1, None)
+        };
+        // From inside the box the ray meets the face it leaves through
+        let part = if distance == t0 { entry } else { exit };
+        if distance.is_finite() {
+            Some(Hit {
+                distance,
+                part,
+                cap,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn normal(&self, _point: Point3<f32>, part: usize) -> Vector3<f32> {
+        Cuboid::face_normal(part)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // Seen from inside, a box is a room
+    fn two_sided(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        (self.min, self.max)
+    }
+}
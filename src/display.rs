@@ -0,0 +1,584 @@
+//! The interactive window: frames traced progressively within a time budget
+//! and shown as they fill in, with a camera to fly around, a wipe to
+//! compare render options, measurements, a tutorial mode turning stages of
+//! the renderer on and off, and edits arriving over OSC and the API. With a
+//! target frame rate, frames are traced at a lower resolution while the
+//! scene is too slow to hold it.
+
+use cgmath::Point3;
+use im::{Rgb, Rgba, RgbaImage};
+use piston_window::*;
+use rayon;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use accumulate::Accumulator;
+use api;
+use audio;
+use camera::{primary_ray, Camera};
+use edit;
+use fly;
+use hud;
+use lod;
+use measure;
+use osc;
+use path::Integrator;
+use render::{render_parallel, render_rect, Frame, FramePixel, Rect, RenderOptions, TileBuffer};
+use resolution::{self, DynamicResolution};
+use scene::{closest_intersection, Scene};
+use scenes::SceneSet;
+use stats;
+use tiles;
+use tutorial::{self, Tutorial};
+
+// How often the active scene's asset files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+// Split-screen comparison: pixels left of the divider are rendered with the
+// main options, side A, pixels right of it with `right`, side B. B starts
+// as a copy of A and keeps its options while keys change A's, until B is
+// chosen to be edited in A's place.
+struct Wipe {
+    divider: u32,
+    dragging: bool,
+    right: RenderOptions,
+    editing_right: bool,
+}
+
+impl Wipe {
+    fn new(render_options: &RenderOptions) -> Wipe {
+        Wipe {
+            divider: render_options.width / 2,
+            dragging: false,
+            right: render_options.clone(),
+            editing_right: false,
+        }
+    }
+
+    fn near_divider(&self, x: f64) -> bool {
+        (x - self.divider as f64).abs() <= 4.0
+    }
+
+    fn drag_to(&mut self, x: f64, width: u32) {
+        self.divider = x.max(0.0).min(width as f64) as u32;
+    }
+
+    // The wipe for a frame `scale` times the window's size
+    fn scaled(&self, scale: f32) -> Wipe {
+        Wipe {
+            divider: (self.divider as f32 * scale).round() as u32,
+            dragging: self.dragging,
+            right: resolution::scaled(&self.right, scale),
+            editing_right: self.editing_right,
+        }
+    }
+
+    // A line for each side naming the options it's rendered with, marking
+    // the side keys edit
+    fn labels(&self, left: &RenderOptions) -> Vec<String> {
+        let label = |side: &str, render_options: &RenderOptions, editing: bool| {
+            format!(
+                "{} {:?} {} spp exposure {:.1}{}",
+                side,
+                render_options.integrator,
+                render_options.samples_per_pixel,
+                render_options.exposure,
+                if editing { " - editing" } else { "" }
+            )
+        };
+        vec![
+            label("A left", left, !self.editing_right),
+            label("B right", &self.right, self.editing_right),
+        ]
+    }
+}
+
+// The options keys edit: side B's while the wipe is editing it, otherwise
+// the main options
+fn edited<'a>(
+    render_options: &'a mut RenderOptions,
+    wipe: &'a mut Option<Wipe>,
+) -> &'a mut RenderOptions {
+    match wipe {
+        Some(ref mut wipe) if wipe.editing_right => &mut wipe.right,
+        _ => render_options,
+    }
+}
+
+// The integrator after `integrator` in the order the I key cycles through
+fn next_integrator(integrator: Integrator) -> Integrator {
+    match integrator {
+        Integrator::Direct => Integrator::Path,
+        Integrator::Path => Integrator::Wavefront,
+        Integrator::Wavefront => Integrator::Direct,
+    }
+}
+
+// Renders `rect`, using the wipe's right-hand options for any part of it
+// right of the divider.
+fn render_split<P: FramePixel>(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    wipe: Option<&Wipe>,
+    rect: &Rect,
+    tile: &mut TileBuffer<P>,
+) {
+    let wipe = match wipe {
+        Some(wipe) => wipe,
+        None => {
+            render_rect(scene, camera, render_options, rect, tile);
+            return;
+        }
+    };
+    let split = wipe.divider.max(rect.x).min(rect.x + rect.width);
+    let left = Rect {
+        x: rect.x,
+        y: rect.y,
+        width: split - rect.x,
+        height: rect.height,
+    };
+    let right = Rect {
+        x: split,
+        y: rect.y,
+        width: rect.x + rect.width - split,
+        height: rect.height,
+    };
+    render_rect(scene, camera, render_options, &left, tile);
+    render_rect(scene, camera, &wipe.right, &right, tile);
+}
+
+// Shows the running average of `rect` in `frame`, leaving pixels that have
+// had no passes since they were last reset as they were.
+fn show_average(
+    accumulator: &Accumulator,
+    render_options: &RenderOptions,
+    wipe: Option<&Wipe>,
+    rect: &Rect,
+    frame: &mut RgbaImage,
+) {
+    for px_y in rect.y..(rect.y + rect.height) {
+        for px_x in rect.x..(rect.x + rect.width) {
+            let render_options = match wipe {
+                Some(wipe) if px_x >= wipe.divider => &wipe.right,
+                _ => render_options,
+            };
+            if let Some(color) = accumulator.average(px_x, px_y) {
+                let pixel = Rgba::from_radiance(color, render_options, px_x, px_y);
+                frame.put_pixel(px_x, px_y, pixel);
+            }
+        }
+    }
+}
+
+/// World-space point on the surface under window position `cursor`, if any.
+fn pick(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    cursor: [f64; 2],
+) -> Option<Point3<f32>> {
+    let ray = primary_ray(camera, render_options, cursor[0] as f32, cursor[1] as f32);
+    closest_intersection(scene, &ray, render_options)
+        .map(|(_, hit)| ray.origin + ray.direction * hit.distance)
+}
+
+// Index of the scene a number key switches to: 1 for the first, up to 9
+fn scene_key(key: Key) -> Option<usize> {
+    let keys = [
+        Key::D1,
+        Key::D2,
+        Key::D3,
+        Key::D4,
+        Key::D5,
+        Key::D6,
+        Key::D7,
+        Key::D8,
+        Key::D9,
+    ];
+    keys.iter().position(|&k| k == key)
+}
+
+// Index into `tutorial::FEATURES` of the feature a function key toggles
+fn feature_key(key: Key) -> Option<usize> {
+    let keys = [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5];
+    keys.iter().position(|&k| k == key)
+}
+
+// Stops the exposure is raised by a key, or lowered by if negative
+fn exposure_key(key: Key) -> Option<f32> {
+    match key {
+        Key::Equals => Some(0.5),
+        Key::Minus => Some(-0.5),
+        _ => None,
+    }
+}
+
+/// How the window spends its time on frames.
+pub struct Pacing {
+    // Time spent tracing before each frame is shown; without one, whole
+    // frames are traced
+    pub frame_budget: Option<Duration>,
+    // Whole frames a second to hold by lowering the resolution; without
+    // one, frames are always traced at the window's size
+    pub target_fps: Option<f64>,
+}
+
+/// Opens the window on the active scene of `scenes` and renders it until
+/// the window is closed, paced by `pacing`. A `progressive` session shows
+/// the average of every pass traced since the view last changed.
+pub fn run(
+    mut scenes: SceneSet,
+    mut render_options: RenderOptions,
+    pacing: Pacing,
+    mut progressive: bool,
+    mut audio: Option<audio::AudioDriver>,
+    osc: Option<osc::OscListener>,
+    api: Option<api::ApiServer>,
+) {
+    let (mut scene, mut camera) = scenes.take_active();
+    let opengl = OpenGL::V3_2;
+    let mut window: PistonWindow =
+        WindowSettings::new("rs-tracer", (render_options.width, render_options.height))
+            .exit_on_esc(true)
+            .opengl(opengl)
+            .build()
+            .expect("Failed to create application window");
+
+    window.set_bench_mode(true);
+    let mut frame_stats = stats::FrameStats::new();
+
+    let mut scheduler = tiles::TileScheduler::new(
+        render_options.width,
+        render_options.height,
+        render_options.tile_size,
+        rayon::current_num_threads(),
+        pacing.frame_budget,
+    );
+    let mut resolution = pacing.target_fps.map(DynamicResolution::new);
+    // Fraction of the window's size frames are traced at
+    let mut scale = 1.0;
+    let mut wipe: Option<Wipe> = None;
+    let mut measurement: Option<measure::Measurement> = None;
+    let mut tutorial: Option<Tutorial> = None;
+    let mut fly = fly::FlyController::new(scene.units.meters_per_unit);
+    let mut cursor: Option<[f64; 2]> = None;
+    let mut frame = RgbaImage::new(render_options.width, render_options.height);
+    // The frame scaled up to the window, while it's traced smaller
+    let mut shown = RgbaImage::new(render_options.width, render_options.height);
+    let mut last_step = Instant::now();
+    // A paused session keeps rendering but stops the scene clock
+    let mut paused = false;
+    let mut frames: u64 = 0;
+    // Scene to switch to once the frame being traced is finished
+    let mut switch: Option<usize> = None;
+    // Progressive passes so far, and the radiance of the one being traced
+    let mut accumulator = Accumulator::new(render_options.width, render_options.height);
+    let mut radiance: Frame<Rgb<f32>> =
+        Frame::new(render_options.width, render_options.height);
+    let mut pass: u32 = 0;
+    let mut last_reload = Instant::now();
+    if let Some(ref cache) = render_options.shading_cache {
+        cache.set_lights(&scene.lights);
+    }
+    while let Some(e) = window.next() {
+        // Whether what's seen has changed so any passes so far are stale
+        let mut moved = false;
+        if let Some(Button::Keyboard(Key::C)) = e.press_args() {
+            wipe = match wipe {
+                Some(_) => None,
+                None => Some(Wipe::new(&render_options)),
+            };
+            moved = true;
+        }
+
+        if let (Some(Button::Keyboard(Key::B)), Some(ref mut wipe)) = (e.press_args(), &mut wipe) {
+            wipe.editing_right = !wipe.editing_right;
+        }
+
+        if let Some(Button::Keyboard(Key::X)) = e.press_args() {
+            let edited = edited(&mut render_options, &mut wipe);
+            if !edited.clip_planes.is_empty() {
+                edited.clipping = !edited.clipping;
+                moved = true;
+            }
+        }
+
+        if let Some(Button::Keyboard(Key::I)) = e.press_args() {
+            let edited = edited(&mut render_options, &mut wipe);
+            edited.integrator = next_integrator(edited.integrator);
+            moved = true;
+        }
+
+        if let Some(Button::Keyboard(Key::P)) = e.press_args() {
+            progressive = !progressive;
+            moved = true;
+        }
+
+        if let Some(Button::Keyboard(Key::M)) = e.press_args() {
+            measurement = match measurement {
+                Some(_) => None,
+                None => Some(measure::Measurement::new()),
+            };
+        }
+
+        if let Some(Button::Keyboard(Key::T)) = e.press_args() {
+            tutorial = match tutorial {
+                Some(_) => None,
+                None => Some(Tutorial::new(&render_options)),
+            };
+        }
+
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            if let Some(index) = scene_key(key) {
+                switch = Some(index);
+            }
+            if let (Some(ref tutorial), Some(index)) = (&tutorial, feature_key(key)) {
+                let edited = edited(&mut render_options, &mut wipe);
+                tutorial.toggle(tutorial::FEATURES[index], edited);
+                moved = true;
+            }
+            if let Some(stops) = exposure_key(key) {
+                edited(&mut render_options, &mut wipe).exposure += stops;
+                moved = true;
+            }
+        }
+
+        if let Some(position) = e.mouse_cursor_args() {
+            cursor = Some(position);
+        }
+        if let Some(false) = e.cursor_args() {
+            cursor = None;
+        }
+        scheduler.set_focus(cursor.map(|[x, y]| [x * scale as f64, y * scale as f64]));
+
+        moved |= fly.event(&e, &mut camera);
+        moved |= fly.step(&mut camera);
+
+        // Camera the frame is seen through, with any shake for the current
+        // scene time; the clock only moves between whole frames
+        let view = match render_options.shake {
+            Some(shake) => shake.apply(&camera, scene.time),
+            None => camera.clone(),
+        };
+
+        if let Some(ref mut wipe) = wipe {
+            if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+                wipe.dragging = false;
+            }
+            if let (true, Some([x, _])) = (wipe.dragging, e.mouse_cursor_args()) {
+                wipe.drag_to(x, render_options.width);
+                moved = true;
+            }
+            if let (Some(Button::Mouse(MouseButton::Left)), Some([x, _])) = (e.press_args(), cursor) {
+                wipe.dragging = wipe.near_divider(x);
+            }
+        }
+
+        // A click that grabs the wipe divider is not a measurement pick
+        let dragging_wipe = wipe.as_ref().is_some_and(|w| w.dragging);
+        if let (Some(measurement), false) = (measurement.as_mut(), dragging_wipe) {
+            if let (Some(Button::Mouse(MouseButton::Left)), Some(position)) =
+                (e.press_args(), cursor)
+            {
+                if let Some(point) = pick(&scene, &view, &render_options, position) {
+                    measurement.add(point, position);
+                    if let Some(report) = measurement.report(&scene.units) {
+                        println!("\n{}", report);
+                    }
+                }
+            }
+        }
+
+        if render_options.lod_pixels > 0.0 {
+            lod::select_lod(&mut scene, &view, &render_options);
+        }
+        if moved && progressive {
+            accumulator.reset(None);
+        }
+        // The options and wipe for the frame at the size it's traced
+        let traced = resolution::scaled(&render_options, scale);
+        let traced_wipe = wipe.as_ref().map(|w| w.scaled(scale));
+        let frame_complete = scheduler.render(|rects| {
+            if progressive {
+                let took = render_parallel(rects, &mut radiance, |rect, tile| {
+                    render_split(&scene, &view, &traced, traced_wipe.as_ref(), rect, tile)
+                });
+                for rect in rects {
+                    accumulator.add(&radiance, rect);
+                    show_average(&accumulator, &traced, traced_wipe.as_ref(), rect, &mut frame);
+                }
+                took
+            } else {
+                render_parallel(rects, &mut frame, |rect, tile| {
+                    render_split(&scene, &view, &traced, traced_wipe.as_ref(), rect, tile)
+                })
+            }
+        });
+
+        frame_stats.tick();
+        print!("{}", frame_stats);
+        if resolution.is_some() {
+            print!(" at {:.0}% size ", scale * 100.0);
+        }
+        if let Some(rate) = render_options.shading_cache.as_ref().and_then(|c| c.take_hit_rate()) {
+            print!(" {:.0}% lighting cached ", rate * 100.0);
+        }
+        let _ = io::stdout().flush(); // Don't care if flush fails
+
+        let seen = if frame.dimensions() == shown.dimensions() {
+            &frame
+        } else {
+            resolution::resample(&frame, &mut shown);
+            &shown
+        };
+        // Labels are drawn on a copy, so they never end up in the frame
+        // passes are shown in
+        let mut labels = Vec::new();
+        if let Some(ref wipe) = wipe {
+            labels.extend(wipe.labels(&render_options));
+        }
+        if let Some(ref tutorial) = tutorial {
+            let shown_options = match wipe {
+                Some(ref wipe) if wipe.editing_right => &wipe.right,
+                _ => &render_options,
+            };
+            labels.extend(tutorial.labels(shown_options));
+        }
+        let labelled;
+        let seen = if labels.is_empty() {
+            seen
+        } else {
+            let mut copy = seen.clone();
+            hud::draw_lines(&labels, &mut copy, 0);
+            labelled = copy;
+            &labelled
+        };
+        match Texture::from_image(&mut window.factory, seen, &TextureSettings::new()) {
+            Ok(texture) => {
+                window.draw_2d(&e, |c, g| {
+                    clear([1.0; 4], g);
+                    image(&texture, c.transform, g);
+                    if let Some(ref wipe) = wipe {
+                        let divider = [
+                            wipe.divider as f64 - 1.0,
+                            0.0,
+                            2.0,
+                            render_options.height as f64,
+                        ];
+                        rectangle([1.0, 0.0, 0.0, 1.0], divider, c.transform, g);
+                    }
+                    if let Some(ref measurement) = measurement {
+                        measurement.draw(c, g);
+                    }
+                });
+            }
+            Err(_) => print!("Failed to produce frame texture"),
+        };
+        // Only move on once a whole frame has been traced, so a frame never
+        // mixes tiles from two different scene states
+        if frame_complete {
+            frames += 1;
+            // Lay out the next pass's samples afresh, or the average would
+            // never get past the first
+            pass = if progressive { pass.wrapping_add(1) } else { 0 };
+            render_options.pass = pass;
+            if let Some(ref mut wipe) = wipe {
+                wipe.right.pass = pass;
+            }
+            let now = Instant::now();
+            if !paused {
+                scene.advance(now.duration_since(last_step).as_secs_f32());
+                if let Some(ref audio) = audio {
+                    audio.apply(&mut scene);
+                }
+            }
+            last_step = now;
+            // Edits since the last frame, so the tiles they touch are
+            // redrawn first
+            let mut damage = edit::Damage::Nothing;
+            if let Some(ref osc) = osc {
+                for message in osc.poll() {
+                    match osc::apply(&message, &mut scene, &mut camera, &mut render_options) {
+                        Ok(d) => damage = damage.union(d),
+                        Err(e) => eprintln!("\nosc: {}", e),
+                    }
+                }
+            }
+            if let Some(ref api) = api {
+                api.serve(&mut api::Session {
+                    scene: &mut scene,
+                    camera: &mut camera,
+                    render_options: &mut render_options,
+                    paused: &mut paused,
+                    damage: &mut damage,
+                    scenes: &mut scenes,
+                    switch: &mut switch,
+                    frames,
+                    fps: frame_stats.fps(),
+                });
+            }
+            if let Some(index) = switch.take() {
+                // Audio scales the values each scene had before it took over
+                if let Some(ref audio) = audio {
+                    audio.restore(&mut scene);
+                }
+                match scenes.activate(index, &mut scene, &mut camera) {
+                    Ok(()) => damage = edit::Damage::Everything,
+                    Err(e) => eprintln!("\nscenes: {}", e),
+                }
+                if let Some(ref mut audio) = audio {
+                    audio.rebase(&scene);
+                }
+            }
+            if now.duration_since(last_reload) >= RELOAD_INTERVAL {
+                let (reloaded, errors) = scenes.reload(&mut scene);
+                for e in errors {
+                    eprintln!("\nreload: {}", e);
+                }
+                damage = damage.union(reloaded);
+                last_reload = now;
+            }
+            // Passes of a scene in motion go stale as soon as they're traced
+            let in_motion =
+                scene.is_animated() || audio.is_some() || render_options.shake.is_some();
+            if progressive && !paused && in_motion {
+                damage = edit::Damage::Everything;
+            }
+            // Lighting cached before the scene changed may no longer hold
+            if let Some(ref cache) = render_options.shading_cache {
+                let moving = !paused && (scene.is_animated() || audio.is_some());
+                if moving || !matches!(damage, edit::Damage::Nothing) {
+                    cache.clear();
+                }
+                cache.set_lights(&scene.lights);
+            }
+            // Tracing at a new size starts the frame afresh, from the last
+            // one resized so there's something to show as it fills in
+            if resolution.as_mut().is_some_and(|r| r.frame_finished()) {
+                scale = resolution.as_ref().map_or(1.0, DynamicResolution::scale);
+                let resized = resolution::scaled(&render_options, scale);
+                let (width, height) = (resized.width, resized.height);
+                let mut smaller = RgbaImage::new(width, height);
+                resolution::resample(&frame, &mut smaller);
+                frame = smaller;
+                scheduler = tiles::TileScheduler::new(
+                    width,
+                    height,
+                    render_options.tile_size,
+                    rayon::current_num_threads(),
+                    pacing.frame_budget,
+                );
+                accumulator = Accumulator::new(width, height);
+                radiance = Frame::new(width, height);
+                damage = edit::Damage::Everything;
+            }
+            let traced = resolution::scaled(&render_options, scale);
+            let rect = damage.screen_rect(&camera, &traced);
+            if progressive {
+                accumulator.reset(rect);
+            }
+            scheduler.set_damage(rect);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Dithering for 8-bit quantization, which breaks the banding that smooth
+//! gradients otherwise show into noise the eye averages out.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    None,
+    // 8x8 Bayer matrix
+    Ordered,
+    // Interleaved gradient noise, a cheap stand-in for blue noise
+    Noise,
+}
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+impl Dither {
+    pub fn from_name(name: &str) -> Option<Dither> {
+        match name {
+            "none" => Some(Dither::None),
+            "ordered" => Some(Dither::Ordered),
+            "noise" => Some(Dither::Noise),
+            _ => None,
+        }
+    }
+
+    /// Threshold in [0, 1) added to a pixel's scaled value before it is
+    /// truncated to an integer level.
+    pub fn offset(self, px_x: u32, px_y: u32) -> f32 {
+        match self {
+            Dither::None => 0.0,
+            Dither::Ordered => {
+                (BAYER[(px_y % 8) as usize][(px_x % 8) as usize] as f32 + 0.5) / 64.0
+            }
+            Dither::Noise => {
+                let f = 0.067_110_56 * px_x as f32 + 0.005_837_15 * px_y as f32;
+                (52.982_918 * f.fract()).fract()
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Changes to a loaded scene made in place, for tools that steer a running
+//! session: moving an object, swapping its material, changing one of the
+//! scene's named materials everywhere it is used and adding or removing
+//! lights.
+//!
+//! An edit refits only the bounds of the object it touches rather than
+//! rebuilding the scene, and reports its damage, the part of the scene
+//! whose look it may have changed, so the frame can be redrawn there first.
+//! Moving an object also moves its shadow and its reflections, and a light
+//! lights everything, so those edits damage the whole scene. Swapping a
+//! material only changes the object itself, unless a mirror or glass shows
+//! it elsewhere or either material is one.
+//!
+//! Objects and lights are named by their handles, which leave out spheres
+//! gathered into LOD clusters.
+
+use cgmath::{Point3, Vector3};
+
+use camera::{project, Camera};
+use handle::Handle;
+use light::Light;
+use material::Material;
+use primitive::Primitive;
+use render::{Rect, RenderOptions};
+use scene::Scene;
+
+pub enum Edit {
+    Move { object: Handle<Primitive>, offset: Vector3<f32> },
+    // Gives the object a material of its own
+    SetMaterial { object: Handle<Primitive>, material: Material },
+    // Gives the object one of the scene's named materials
+    AssignMaterial { object: Handle<Primitive>, material: Handle<Material> },
+    // Changes a named material, and every object given it
+    UpdateMaterial { material: Handle<Material>, value: Material },
+    AddLight(Light),
+    RemoveLight(Handle<Light>),
+}
+
+/// What an edit may have changed.
+#[derive(Clone, Copy, Debug)]
+pub enum Damage {
+    Nothing,
+    // Only what's seen of the box between these corners, (min, max)
+    Bounds(Point3<f32>, Point3<f32>),
+    Everything,
+}
+
+impl Damage {
+    /// Damage covering both `self` and `other`.
+    pub fn union(self, other: Damage) -> Damage {
+        match (self, other) {
+            (Damage::Nothing, damage) | (damage, Damage::Nothing) => damage,
+            (Damage::Bounds(a_min, a_max), Damage::Bounds(b_min, b_max)) => Damage::Bounds(
+                Point3::new(a_min.x.min(b_min.x), a_min.y.min(b_min.y), a_min.z.min(b_min.z)),
+                Point3::new(a_max.x.max(b_max.x), a_max.y.max(b_max.y), a_max.z.max(b_max.z)),
+            ),
+            _ => Damage::Everything,
+        }
+    }
+
+    /// Pixels of the frame seen through `camera` that the damage may show
+    /// in, or None if it could be anywhere.
+    pub fn screen_rect(&self, camera: &Camera, render_options: &RenderOptions) -> Option<Rect> {
+        let (min, max) = match *self {
+            Damage::Nothing => return Some(Rect { x: 0, y: 0, width: 0, height: 0 }),
+            Damage::Bounds(min, max) => (min, max),
+            Damage::Everything => return None,
+        };
+        // The box is on screen within its corners' projections, as long as
+        // none of it is behind the camera
+        let mut corners = Vec::with_capacity(8);
+        for &x in &[min.x, max.x] {
+            for &y in &[min.y, max.y] {
+                for &z in &[min.z, max.z] {
+                    corners.push(project(camera, render_options, Point3::new(x, y, z))?);
+                }
+            }
+        }
+        let (w, h) = (render_options.width as f32, render_options.height as f32);
+        let left = corners.iter().map(|c| c.0).fold(w, f32::min).max(0.0).floor();
+        let right = corners.iter().map(|c| c.0).fold(0.0, f32::max).min(w).ceil();
+        let top = corners.iter().map(|c| c.1).fold(h, f32::min).max(0.0).floor();
+        let bottom = corners.iter().map(|c| c.1).fold(0.0, f32::max).min(h).ceil();
+        Some(Rect {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left).max(0.0) as u32,
+            height: (bottom - top).max(0.0) as u32,
+        })
+    }
+}
+
+// Whether any shape besides object `except` reflects or refracts, and so
+// might show it
+fn others_specular(scene: &Scene, except: Handle<Primitive>) -> bool {
+    let primitives = scene
+        .primitives
+        .entries()
+        .filter(|&(handle, _)| handle != except)
+        .map(|(_, primitive)| primitive.shape().material().is_specular());
+    let clustered = scene
+        .clusters
+        .iter()
+        .flat_map(|cluster| cluster.spheres())
+        .map(|sphere| sphere.material.is_specular());
+    let particles = scene
+        .emitters
+        .iter()
+        .flat_map(|emitter| emitter.spheres())
+        .map(|sphere| sphere.material.is_specular());
+    primitives.chain(clustered).chain(particles).any(|specular| specular)
+}
+
+fn no_object(object: Handle<Primitive>) -> String {
+    format!("no object {}", object)
+}
+
+fn no_material(material: Handle<Material>) -> String {
+    format!("no material {}", material)
+}
+
+// Makes `object` of `material`
+fn set_material(
+    scene: &mut Scene,
+    object: Handle<Primitive>,
+    material: Material,
+) -> Result<Damage, String> {
+    let everything = others_specular(scene, object);
+    let primitive = scene.primitives.get_mut(object).ok_or_else(|| no_object(object))?;
+    let old = *primitive.shape().material();
+    primitive.set_material(material);
+    if everything || old.is_specular() || material.is_specular() {
+        Ok(Damage::Everything)
+    } else {
+        let (min, max) = primitive.shape().bounds();
+        Ok(Damage::Bounds(min, max))
+    }
+}
+
+/// Makes `edit` to `scene`, or describes why it can't be made.
+pub fn apply(scene: &mut Scene, edit: Edit) -> Result<Damage, String> {
+    match edit {
+        Edit::Move { object, offset } => {
+            let primitive = scene.primitives.get_mut(object).ok_or_else(|| no_object(object))?;
+            primitive.translate(offset);
+            Ok(Damage::Everything)
+        }
+        Edit::SetMaterial { object, material } => {
+            let damage = set_material(scene, object, material)?;
+            scene.material_bindings.remove(object);
+            Ok(damage)
+        }
+        Edit::AssignMaterial { object, material } => {
+            let value = *scene.materials.get(material).ok_or_else(|| no_material(material))?;
+            let damage = set_material(scene, object, value)?;
+            scene.material_bindings.insert(object, material);
+            Ok(damage)
+        }
+        Edit::UpdateMaterial { material, value } => {
+            *scene.materials.get_mut(material).ok_or_else(|| no_material(material))? = value;
+            let objects: Vec<Handle<Primitive>> = scene
+                .material_bindings
+                .iter()
+                .filter(|&(_, &bound)| bound == material)
+                .map(|(object, _)| object)
+                .collect();
+            let mut damage = Damage::Nothing;
+            for object in objects {
+                damage = damage.union(set_material(scene, object, value)?);
+            }
+            Ok(damage)
+        }
+        Edit::AddLight(light) => {
+            scene.lights.push(light);
+            Ok(Damage::Everything)
+        }
+        Edit::RemoveLight(light) => {
+            scene.lights.remove(light).ok_or_else(|| format!("no light {}", light))?;
+            Ok(Damage::Everything)
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Rendering into pixel buffers the caller owns, for embedding the tracer
+//! in a game engine or UI toolkit that already has somewhere for the pixels
+//! to go, such as a mapped texture.
+//!
+//! Buffers are plain slices of RGBA pixels, four elements each, with rows
+//! `stride` elements apart so padded rows can be written in place; the
+//! elements between the end of one row and the start of the next are left
+//! alone. Any band of the frame's rows can be rendered, so a frame can be
+//! built up a band at a time. Rows are traced in parallel on the render
+//! thread pool.
+
+use rayon::prelude::*;
+use std::ops::Range;
+
+use camera::Camera;
+use render::{display_color, pixel_radiance, to_rgba, Color, RenderOptions};
+use scene::Scene;
+
+// Traces `rows` of the frame into `pixels`, handing each pixel's radiance
+// and coordinates to `write` along with its four elements
+fn render_rows<T, F>(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    rows: Range<u32>,
+    pixels: &mut [T],
+    stride: usize,
+    write: F,
+) -> Result<(), String>
+where
+    T: Send,
+    F: Fn(Color, u32, u32, &mut [T]) + Sync,
+{
+    let width = render_options.width as usize;
+    if rows.start > rows.end || rows.end > render_options.height {
+        return Err(format!(
+            "rows {}..{} are outside a frame {} high",
+            rows.start, rows.end, render_options.height
+        ));
+    }
+    if stride < width * 4 || stride == 0 {
+        return Err(format!("stride {} is shorter than a row of {} pixels", stride, width));
+    }
+    let count = (rows.end - rows.start) as usize;
+    // The last row needn't be padded out to the stride
+    let needed = if count > 0 { stride * (count - 1) + width * 4 } else { 0 };
+    if pixels.len() < needed {
+        return Err(format!("{} rows need {} elements, not {}", count, needed, pixels.len()));
+    }
+    pixels
+        .par_chunks_mut(stride)
+        .take(count)
+        .enumerate()
+        .for_each(|(i, row)| {
+            let px_y = rows.start + i as u32;
+            for (px_x, pixel) in (0..render_options.width).zip(row.chunks_mut(4)) {
+                let color = pixel_radiance(scene, camera, render_options, px_x, px_y);
+                write(color, px_x, px_y, pixel);
+            }
+        });
+    Ok(())
+}
+
+/// Renders `rows` of the frame into `pixels` as 8-bit RGBA, exposed,
+/// encoded in the output space and dithered as an image output would be.
+/// The first row of `pixels` holds `rows.start`.
+pub fn render_rgba8(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    rows: Range<u32>,
+    pixels: &mut [u8],
+    stride: usize,
+) -> Result<(), String> {
+    render_rows(scene, camera, render_options, rows, pixels, stride, |color, x, y, pixel| {
+        let dither = render_options.dither.offset(x, y);
+        pixel.copy_from_slice(&to_rgba(display_color(color, render_options), dither).data);
+    })
+}
+
+/// Renders `rows` of the frame into `pixels` as floating-point RGBA: the
+/// exposed radiance, linear and unclamped, with an alpha of 1. The first
+/// row of `pixels` holds `rows.start`.
+pub fn render_rgba32f(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    rows: Range<u32>,
+    pixels: &mut [f32],
+    stride: usize,
+) -> Result<(), String> {
+    let scale = render_options.exposure.exp2();
+    render_rows(scene, camera, render_options, rows, pixels, stride, |color, _, _, pixel| {
+        let exposed = color * scale;
+        pixel.copy_from_slice(&[exposed.x, exposed.y, exposed.z, 1.0]);
+    })
+}
@@ -0,0 +1,160 @@
+//! Skies for dome lights that vary with direction: a gradient from the
+//! ground through the horizon to the zenith, or an equirectangular Radiance
+//! HDR image of a real sky. A dome light with one shows it to every ray
+//! that escapes the scene and lights the scene with it, its colour and
+//! intensity scaling the sky's.
+//!
+//! Maps are laid out as `panorama` writes them, so a panorama rendered from
+//! one scene can light another: the image centre looks down -Z, +X is to
+//! its right and +Y is the top row. A map is turned into the scene by its
+//! light's transform, and onto the scene's up axis.
+
+use cgmath::{InnerSpace, Matrix3, Vector3};
+use im::hdr::HDRDecoder;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use panorama::equirect_uv;
+use render::Color;
+use resolve::{self, AssetResolver};
+
+/// Radiance over a sphere of directions, as a 2:1 equirectangular image.
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+// The pixels are too many to be worth printing
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EnvironmentMap({}x{})", self.width, self.height)
+    }
+}
+
+impl EnvironmentMap {
+    fn pixel(&self, px_x: u32, px_y: u32) -> Color {
+        self.pixels[(px_y * self.width + px_x) as usize]
+    }
+
+    /// Radiance at (`u`, `v`) in the unit square, blended between the four
+    /// nearest pixels. The image wraps around horizontally.
+    pub fn lookup(&self, u: f32, v: f32) -> Color {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).max(0.0).min(self.height as f32 - 1.0);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let x0 = (x.floor() as i64).rem_euclid(self.width as i64) as u32;
+        let x1 = (x0 + 1) % self.width;
+        let y0 = y.floor() as u32;
+        let y1 = (y0 + 1).min(self.height - 1);
+        let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x1, y0) * fx;
+        let bottom = self.pixel(x0, y1) * (1.0 - fx) + self.pixel(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+/// Reads the Radiance HDR environment map at `path`.
+pub fn load(path: &Path) -> Result<EnvironmentMap, String> {
+    let describe = |e: &dyn fmt::Display| format!("{}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| describe(&e))?;
+    let decoder = HDRDecoder::new(BufReader::new(file)).map_err(|e| describe(&e))?;
+    let metadata = decoder.metadata();
+    let (width, height) = (metadata.width, metadata.height);
+    if width == 0 || height == 0 {
+        return Err(format!("{}: empty image", path.display()));
+    }
+    let pixels = decoder.read_image_hdr().map_err(|e| describe(&e))?;
+    Ok(EnvironmentMap {
+        width,
+        height,
+        pixels: pixels
+            .into_iter()
+            .map(|p| Vector3::new(p.data[0], p.data[1], p.data[2]))
+            .collect(),
+    })
+}
+
+/// How a dome light's radiance varies with direction, as a multiple of its
+/// colour and intensity.
+#[derive(Clone, Debug)]
+pub enum Environment {
+    // Blends from `horizon` to `zenith` going up from the horizon, and to
+    // `ground` going down; `up` is a unit vector
+    Gradient {
+        up: Vector3<f32>,
+        zenith: Color,
+        horizon: Color,
+        ground: Color,
+    },
+    Map {
+        map: Arc<EnvironmentMap>,
+        // The file the map was read from, and when it was modified as it
+        // was read, so the map can be read again when the file changes
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        // Turns scene directions into the map's
+        to_map: Matrix3<f32>,
+    },
+}
+
+impl Environment {
+    /// The environment map at resolved `path`, read through `resolver`,
+    /// with `to_map` turning scene directions into the map's.
+    pub fn map(
+        path: PathBuf,
+        to_map: Matrix3<f32>,
+        resolver: &AssetResolver,
+    ) -> Result<Environment, String> {
+        let modified = resolve::modified(&path);
+        let map = resolver.environment(&path)?;
+        Ok(Environment::Map {
+            map,
+            path,
+            modified,
+            to_map,
+        })
+    }
+
+    /// Radiance arriving from direction `direction`, relative to the
+    /// light's own.
+    pub fn radiance(&self, direction: Vector3<f32>) -> Color {
+        let direction = direction.normalize();
+        match *self {
+            Environment::Gradient {
+                up,
+                zenith,
+                horizon,
+                ground,
+            } => {
+                let height = direction.dot(up);
+                if height >= 0.0 {
+                    horizon + (zenith - horizon) * height
+                } else {
+                    horizon + (ground - horizon) * -height
+                }
+            }
+            Environment::Map {
+                ref map, to_map, ..
+            } => {
+                let (u, v) = equirect_uv((to_map * direction).normalize());
+                map.lookup(u, v)
+            }
+        }
+    }
+}
+
+/// Rotation from a scene whose up is unit `up` into the frame maps are
+/// laid out in, with +Y up. Scenes that are Z up keep -Y at the centre of
+/// the map, as Y up scenes keep -Z.
+pub fn up_to_map(up: Vector3<f32>) -> Matrix3<f32> {
+    if up.z.abs() > up.y.abs() {
+        // (x, y, z) to (x, z, -y)
+        Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0)
+    } else {
+        Matrix3::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0)
+    }
+}
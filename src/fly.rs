@@ -0,0 +1,128 @@
+//! Flying the camera around the interactive view. W, A, S and D move it
+//! forward, left, back and right, Q and E down and up, and holding Shift
+//! moves faster; dragging with the right mouse button turns it, and the
+//! scroll wheel zooms by narrowing or widening the field of view.
+//!
+//! Turning keeps the camera's up vector, so the horizon stays level.
+
+use cgmath::{InnerSpace, Matrix3, Rad};
+use piston_window::{
+    Button, Event, Key, MouseButton, MouseCursorEvent, MouseScrollEvent, PressEvent,
+    ReleaseEvent,
+};
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use camera::Camera;
+
+// Walking pace, in metres per second
+const SPEED: f32 = 2.0;
+const FAST_FACTOR: f32 = 5.0;
+// Radians turned per pixel dragged
+const TURN_RATE: f32 = 0.005;
+// Closest the view may come to looking straight up or down, in radians
+const MIN_POLE_ANGLE: f32 = 0.02;
+// Field of view scale per notch of the scroll wheel
+const ZOOM_STEP: f32 = 0.9;
+const MIN_FOV: f32 = 5.0;
+const MAX_FOV: f32 = 150.0;
+
+pub struct FlyController {
+    held: HashSet<Key>,
+    dragging: bool,
+    // Where the cursor was last seen, for how far each drag moves it
+    cursor: Option<[f64; 2]>,
+    speed: f32,
+    last_step: Instant,
+}
+
+impl FlyController {
+    /// A controller for a scene of `meters_per_unit`.
+    pub fn new(meters_per_unit: f32) -> FlyController {
+        FlyController {
+            held: HashSet::new(),
+            dragging: false,
+            cursor: None,
+            speed: SPEED / meters_per_unit,
+            last_step: Instant::now(),
+        }
+    }
+
+    /// Takes in an input event, turning or zooming `camera` straight away.
+    /// Returns whether the camera changed.
+    pub fn event(&mut self, e: &Event, camera: &mut Camera) -> bool {
+        match e.press_args() {
+            Some(Button::Keyboard(key)) => {
+                self.held.insert(key);
+            }
+            Some(Button::Mouse(MouseButton::Right)) => self.dragging = true,
+            _ => {}
+        }
+        match e.release_args() {
+            Some(Button::Keyboard(key)) => {
+                self.held.remove(&key);
+            }
+            Some(Button::Mouse(MouseButton::Right)) => self.dragging = false,
+            _ => {}
+        }
+
+        let mut changed = false;
+        if let Some(position) = e.mouse_cursor_args() {
+            if let (true, Some(last)) = (self.dragging, self.cursor) {
+                let dx = (position[0] - last[0]) as f32;
+                let dy = (position[1] - last[1]) as f32;
+                turn(camera, -dx * TURN_RATE, -dy * TURN_RATE);
+                changed = true;
+            }
+            self.cursor = Some(position);
+        }
+        if let Some([_, notches]) = e.mouse_scroll_args() {
+            let fov = camera.fov * ZOOM_STEP.powf(notches as f32);
+            camera.fov = fov.clamp(MIN_FOV, MAX_FOV);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Moves `camera` for the keys held since the last step. Returns
+    /// whether it moved.
+    pub fn step(&mut self, camera: &mut Camera) -> bool {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_step).as_secs_f32();
+        self.last_step = now;
+
+        let (right, _, forward) = camera.basis();
+        let up = camera.up.normalize();
+        let axis = |positive: Key, negative: Key| {
+            let held = |key| if self.held.contains(&key) { 1.0 } else { 0.0 };
+            held(positive) - held(negative)
+        };
+        let direction = forward * axis(Key::W, Key::S)
+            + right * axis(Key::D, Key::A)
+            + up * axis(Key::E, Key::Q);
+        if direction.magnitude2() == 0.0 {
+            return false;
+        }
+        let fast = if self.held.contains(&Key::LShift) { FAST_FACTOR } else { 1.0 };
+        camera.position += direction.normalize() * (self.speed * fast * dt);
+        true
+    }
+}
+
+// Turns `camera` by `yaw` about its up vector, then by `pitch` about its
+// right, stopping short of looking along the up vector
+fn turn(camera: &mut Camera, yaw: f32, pitch: f32) {
+    let up = camera.up.normalize();
+    let at = Matrix3::from_axis_angle(up, Rad(yaw)) * camera.at.normalize();
+    let right = at.cross(up);
+    if right.magnitude2() == 0.0 {
+        camera.at = at;
+        return;
+    }
+    let pole_angle = at.angle(up).0;
+    let pitch = pitch
+        .min(pole_angle - MIN_POLE_ANGLE)
+        .max(pole_angle - (PI - MIN_POLE_ANGLE));
+    camera.at = Matrix3::from_axis_angle(right.normalize(), Rad(pitch)) * at;
+}
@@ -0,0 +1,295 @@
+//! White furnace tests, which check that materials neither create nor lose
+//! light.
+//!
+//! A sphere of each material is rendered alone under an even white dome
+//! light. A material that sends on all the light reaching it disappears
+//! into the background, so every ray that meets it should come back at the
+//! brightness of the sky; one that comes back brighter is making light,
+//! and one that comes back darker is losing it. Materials that absorb some
+//! light by design, such as a grey surface, only have to pass the weak
+//! test: never brighter than the sky. Mistakes in how a material divides
+//! light show up here as a whole scene that is slightly too bright or too
+//! dark, which is hard to spot in a render.
+//!
+//! Dome lights make no highlights, so a shiny sphere in the furnace only
+//! shows what it scatters diffusely. The light its highlights return is
+//! found separately, by summing its highlight lobe over every direction
+//! light could arrive from.
+
+use cgmath::{Point3, Vector3};
+use std::f32::consts::PI;
+
+use geometry::{Ray, Sphere};
+use light::Light;
+use material::Material;
+use primitive::Primitive;
+use render::{radiance, RenderOptions};
+use sampling;
+use scene::Scene;
+
+// Rays across the sphere's diameter, covering every angle of incidence
+const RAYS_ACROSS: u32 = 64;
+// Enough that the sky sampled from each point is within a fraction of a
+// percent of its total
+const FURNACE_LIGHT_SAMPLES: u32 = 256;
+// Deep enough that light bouncing around inside glass has all but died out
+const FURNACE_MAX_DEPTH: u32 = 16;
+// Directions a highlight lobe is summed over, and the angles it is seen
+// from
+const LOBE_SAMPLES: u32 = 1 << 16;
+const LOBE_VIEWS: u32 = 16;
+// How far from the sky's brightness a ray may come back
+const TOLERANCE: f32 = 0.01;
+
+pub struct Case {
+    pub name: &'static str,
+    pub material: Material,
+    // Whether the material is meant to keep all the light reaching it,
+    // rather than only never adding to it
+    pub lossless: bool,
+}
+
+/// A material of each kind the renderer shades, alone and mixed.
+pub fn cases() -> Vec<Case> {
+    let case = |name, material, lossless| Case {
+        name,
+        material,
+        lossless,
+    };
+    let grey = Vector3::new(0.5, 0.5, 0.5);
+    vec![
+        case("white diffuse", Material::default(), true),
+        case("grey diffuse", Material::diffuse(grey), false),
+        case("red diffuse", Material::diffuse(Vector3::new(0.9, 0.1, 0.1)), false),
+        case(
+            "mirror",
+            Material {
+                reflectivity: 1.0,
+                ..Material::default()
+            },
+            true,
+        ),
+        case(
+            "half mirror",
+            Material {
+                reflectivity: 0.5,
+                ..Material::default()
+            },
+            true,
+        ),
+        case(
+            "glass",
+            Material {
+                transparency: 1.0,
+                ..Material::default()
+            },
+            true,
+        ),
+        case(
+            "diamond",
+            Material {
+                transparency: 1.0,
+                ior: 2.42,
+                ..Material::default()
+            },
+            true,
+        ),
+        case(
+            "frosted glass",
+            Material {
+                transparency: 0.5,
+                reflectivity: 0.2,
+                ..Material::default()
+            },
+            true,
+        ),
+        case(
+            "satin",
+            Material {
+                specular: 0.3,
+                shininess: 8.0,
+                ..Material::default()
+            },
+            false,
+        ),
+        case(
+            "gloss",
+            Material {
+                specular: 0.5,
+                shininess: 512.0,
+                ..Material::default()
+            },
+            false,
+        ),
+        case(
+            "glossy mirror",
+            Material {
+                specular: 0.2,
+                reflectivity: 0.5,
+                ..Material::default()
+            },
+            false,
+        ),
+    ]
+}
+
+/// What rays meeting a sphere of some material in the furnace came back
+/// with, as fractions of the sky's brightness.
+pub struct Measurement {
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+    // Most light the material's highlights return, from any viewing angle
+    pub highlights: f32,
+}
+
+impl Measurement {
+    /// Whether `case` neither made light nor, if it should keep it all,
+    /// lost any.
+    pub fn passes(&self, case: &Case) -> bool {
+        let bright_enough = !case.lossless || self.min >= 1.0 - TOLERANCE;
+        self.max + self.highlights <= 1.0 + TOLERANCE && bright_enough
+    }
+}
+
+// Fraction of even light from every direction above a surface with normal
+// +Z that `material`'s highlights return towards the worst of a range of
+// viewing angles. Highlights are part of what the surface doesn't mirror or
+// let through.
+fn highlight_albedo(material: &Material) -> f32 {
+    let normal = Vector3::new(0.0, 0.0, 1.0);
+    let share = material.split(-normal, normal).diffuse;
+    let mut most: f32 = 0.0;
+    for view in 0..LOBE_VIEWS {
+        // From straight on to nearly grazing
+        let cos_view = 1.0 - view as f32 / LOBE_VIEWS as f32;
+        let sin_view = (1.0 - cos_view * cos_view).sqrt();
+        let to_viewer = Vector3::new(sin_view, 0.0, cos_view);
+        let mut sum = 0.0;
+        for i in 0..LOBE_SAMPLES {
+            let sample = sampling::hammersley(i, LOBE_SAMPLES, (0.0, 0.0));
+            let to_light = sampling::uniform_hemisphere(normal, sample);
+            sum += material.highlight(normal, to_light, to_viewer) * to_light.z;
+        }
+        // Over the 1 / 2 pi density, and relative to the pi a white diffuse
+        // surface returns
+        most = most.max(sum * 2.0 * PI / LOBE_SAMPLES as f32 / PI);
+    }
+    most * share
+}
+
+/// Renders a sphere of `material` alone under a white dome, with
+/// `render_options` except for sampling and depth, which are raised enough
+/// that only the material's own errors remain.
+pub fn measure(material: &Material, render_options: &RenderOptions) -> Measurement {
+    let render_options = RenderOptions {
+        light_samples: FURNACE_LIGHT_SAMPLES,
+        max_depth: FURNACE_MAX_DEPTH,
+        caustic_spread: 0.0,
+        heatmap: None,
+        clipping: false,
+        ..render_options.clone()
+    };
+    let sphere = Sphere {
+        center: Point3::new(0.0, 0.0, 0.0),
+        radius: 1.0,
+        material: *material,
+    };
+    let scene = Scene {
+        primitives: Some(Primitive::Sphere(sphere)).into_iter().collect(),
+        lights: Some(Light::Dome {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            portals: Vec::new(),
+            environment: None,
+        })
+        .into_iter()
+        .collect(),
+        ..Scene::empty()
+    };
+    // The dome is even, so any direction sees all of it
+    let up = Vector3::new(0.0, 1.0, 0.0);
+    let sky = scene.lights.iter().map(|light| light.background(up)).fold(0.0, |sum, c| sum + c.x);
+
+    let mut measurement = Measurement {
+        mean: 0.0,
+        min: f32::INFINITY,
+        max: 0.0,
+        highlights: highlight_albedo(material),
+    };
+    let mut count = 0;
+    for y in 0..RAYS_ACROSS {
+        for x in 0..RAYS_ACROSS {
+            let across = |i: u32| 2.0 * (i as f32 + 0.5) / RAYS_ACROSS as f32 - 1.0;
+            let (sx, sy) = (across(x), across(y));
+            // Grazing rays barely meet the sphere at all
+            if sx * sx + sy * sy > 0.98 {
+                continue;
+            }
+            let ray = Ray {
+                origin: Point3::new(sx, sy, 3.0),
+                direction: Vector3::new(0.0, 0.0, -1.0),
+            };
+            let color = radiance(&scene, &ray, &render_options, 0) / sky;
+            for &channel in &[color.x, color.y, color.z] {
+                measurement.min = measurement.min.min(channel);
+                measurement.max = measurement.max.max(channel);
+            }
+            measurement.mean += (color.x + color.y + color.z) / 3.0;
+            count += 1;
+        }
+    }
+    measurement.mean /= count as f32;
+    measurement
+}
+
+/// Measures every case, printing a line for each, and returns the names of
+/// those that fail.
+pub fn run(render_options: &RenderOptions) -> Vec<&'static str> {
+    let mut failed = Vec::new();
+    for case in cases() {
+        let measurement = measure(&case.material, render_options);
+        let passes = measurement.passes(&case);
+        println!(
+            "{:<14} {:<6} mean {:.4}  min {:.4}  max {:.4}  highlights {:.4}  {}",
+            case.name,
+            if case.lossless { "white" } else { "weak" },
+            measurement.mean,
+            measurement.min,
+            measurement.max,
+            measurement.highlights,
+            if passes { "ok" } else { "FAILED" }
+        );
+        if !passes {
+            failed.push(case.name);
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn every_material_neither_makes_nor_loses_light() {
+        let render_options = RenderOptions::default();
+        let failed: Vec<_> = cases()
+            .par_iter()
+            .filter(|case| !measure(&case.material, &render_options).passes(case))
+            .map(|case| case.name)
+            .collect();
+        assert!(failed.is_empty(), "failed the furnace test: {:?}", failed);
+    }
+
+    #[test]
+    fn a_material_making_light_fails() {
+        let case = Case {
+            name: "too white",
+            material: Material::diffuse(Vector3::new(1.1, 1.1, 1.1)),
+            lossless: false,
+        };
+        assert!(!measure(&case.material, &RenderOptions::default()).passes(&case));
+    }
+}
@@ -0,0 +1,262 @@
+//! Spheres, the simplest shape, and the rays traced against every shape.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use clip;
+use material::Material;
+use primitive::{Hit, Intersectable};
+use render::RenderOptions;
+
+// Relative band around tangency in which robust mode re-tests a sphere hit
+const GRAZING_EPSILON: f32 = 1e-3;
+
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Sphere {
+    pub fn intersects(&self, ray: &Ray, robust: bool) -> Option<f32> {
+        let radius_squared = self.radius * self.radius;
+        // A sphere of no size is never hit, even dead centre
+        if radius_squared == 0.0 {
+            return None;
+        }
+        let l = self.center - ray.origin;
+        let tca = l.dot(ray.direction);
+
+        // Heading away from the centre, only a ray from inside can hit
+        if tca < 0.0 && l.dot(l) > radius_squared {
+            return None;
+        }
+
+        let d2 = l.dot(l) - tca * tca;
+        if robust && (radius_squared - d2).abs() < GRAZING_EPSILON * radius_squared {
+            return self.intersects_f64(ray);
+        }
+        if d2 > radius_squared {
+            return None;
+        }
+
+        let thc = (radius_squared - d2).sqrt();
+        let t0 = tca - thc;
+        let t1 = tca + thc;
+
+        if t0 < 0.0 && t1 < 0.0 {
+            return None;
+        }
+
+        // Return shortest distance ahead along the line, which from inside
+        // is where the ray leaves, rejecting degenerate spheres (zero or
+        // non-finite radius, NaN centre) that produce no usable hit
+        let t = if t0 >= 0.0 { t0 } else { t1 };
+        if t.is_finite() {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    // Near the silhouette l.l - tca^2 cancels catastrophically in f32, which
+    // shows up as speckled, banded edges. Redo the test in f64 using the
+    // numerically stable form of the quadratic roots.
+    fn intersects_f64(&self, ray: &Ray) -> Option<f32> {
+        let l = (self.center - ray.origin).cast::<f64>();
+        let d = ray.direction.cast::<f64>();
+        let radius = self.radius as f64;
+
+        let a = d.dot(d);
+        let half_b = -l.dot(d);
+        let c = l.dot(l) - radius * radius;
+        if half_b > 0.0 && c > 0.0 {
+            return None;
+        }
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        // q takes the sign of -half_b, so it never suffers cancellation
+        let root = discriminant.sqrt();
+        let q = if half_b > 0.0 { -half_b - root } else { root - half_b };
+        let (t0, t1) = (c / q, q / a);
+        let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+        if far < 0.0 {
+            return None;
+        }
+
+        let t = (if near >= 0.0 { near } else { far }) as f32;
+        if t.is_finite() {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    pub fn normal(&self, surface_point: Point3<f32>) -> Vector3<f32> {
+        surface_point - self.center
+    }
+}
+
+impl Intersectable for Sphere {
+    fn intersect(&self, ray: &Ray, render_options: &RenderOptions) -> Option<Hit> {
+        let hit = if render_options.clipping {
+            let capped = render_options.clip_cap.is_some();
+            clip::intersect(self, ray, &render_options.clip_planes, capped)
+        } else {
+            self.intersects(ray, render_options.robust_intersections)
+                .map(|distance| (distance, None))
+        };
+        hit.map(|(distance, cap)| Hit {
+            distance,
+            part: 0,
+            cap,
+        })
+    }
+
+    fn normal(&self, point: Point3<f32>, _part: usize) -> Vector3<f32> {
+        Sphere::normal(self, point)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        (self.center + -r, self.center + r)
+    }
+}
+
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /// Ray leaving the surface at `point` along `direction`, which must be
+    /// on the side `normal` points to. The origin is nudged off the surface
+    /// so the ray doesn't hit it again through rounding error.
+    pub fn from_surface(point: Point3<f32>, normal: Vector3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray {
+            origin: offset_origin(point, normal),
+            direction,
+        }
+    }
+}
+
+// Moves `point` off its surface along unit `normal` by an amount that scales
+// with the point's magnitude, so the offset suits both tiny and huge scenes
+// ("A Fast and Robust Method for Avoiding Self-Intersection", Wachter and
+// Binder). Far from the origin the coordinates are stepped a fixed number of
+// ulps; near it, where ulps get tiny, a small fixed offset is added instead.
+fn offset_origin(point: Point3<f32>, normal: Vector3<f32>) -> Point3<f32> {
+    const ORIGIN: f32 = 1.0 / 32.0;
+    const FLOAT_SCALE: f32 = 1.0 / 65536.0;
+    const INT_SCALE: f32 = 256.0;
+    let offset = |p: f32, n: f32| {
+        if p.abs() < ORIGIN {
+            return p + FLOAT_SCALE * n;
+        }
+        let ulps = (INT_SCALE * n) as i32;
+        let ulps = if p < 0.0 { -ulps } else { ulps };
+        f32::from_bits((p.to_bits() as i32 + ulps) as u32)
+    };
+    Point3::new(
+        offset(point.x, normal.x),
+        offset(point.y, normal.y),
+        offset(point.z, normal.z),
+    )
+}
+
+// `direction` mirrored about unit `normal`
+pub fn reflect(direction: Vector3<f32>, normal: Vector3<f32>) -> Vector3<f32> {
+    direction - normal * (2.0 * direction.dot(normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere(center: Point3<f32>, radius: f32) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            material: Material::default(),
+        }
+    }
+
+    fn ray(origin: Point3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray { origin, direction }
+    }
+
+    // The sphere tested both ways, which must agree
+    fn hit(sphere: &Sphere, ray: &Ray) -> Option<f32> {
+        let fast = sphere.intersects(ray, false);
+        assert_eq!(fast, sphere.intersects(ray, true));
+        fast
+    }
+
+    #[test]
+    fn hits_the_near_side() {
+        let unit = sphere(Point3::new(0.0, 0.0, -5.0), 1.0);
+        let forward = ray(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(hit(&unit, &forward), Some(4.0));
+        let backward = ray(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit(&unit, &backward), None);
+    }
+
+    #[test]
+    fn nan_centre_is_never_hit() {
+        let nan = sphere(Point3::new(f32::NAN, 0.0, -5.0), 1.0);
+        let forward = ray(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(hit(&nan, &forward), None);
+    }
+
+    #[test]
+    fn zero_radius_is_never_hit() {
+        let point = sphere(Point3::new(0.0, 0.0, -5.0), 0.0);
+        let through = ray(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(hit(&point, &through), None);
+    }
+
+    #[test]
+    fn negative_radius_is_hit_as_its_size() {
+        let inverted = sphere(Point3::new(0.0, 0.0, -5.0), -1.0);
+        let forward = ray(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(hit(&inverted, &forward), Some(4.0));
+    }
+
+    #[test]
+    fn infinite_radius_is_never_hit() {
+        let endless = sphere(Point3::new(0.0, 0.0, -5.0), f32::INFINITY);
+        for &z in &[-1.0, 1.0] {
+            let ray = ray(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, z));
+            assert_eq!(hit(&endless, &ray), None);
+        }
+    }
+
+    #[test]
+    fn ray_from_the_surface_hits_it_at_zero() {
+        // Which is why secondary rays leave through `Ray::from_surface`
+        let unit = sphere(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let on_surface = Point3::new(0.0, 0.0, 1.0);
+        let outward = ray(on_surface, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit(&unit, &outward), Some(0.0));
+        let inward = ray(on_surface, Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(hit(&unit, &inward), Some(0.0));
+        let nudged = Ray::from_surface(on_surface, Vector3::new(0.0, 0.0, 1.0), outward.direction);
+        assert_eq!(hit(&unit, &nudged), None);
+    }
+
+    #[test]
+    fn grazing_ray_touches_the_silhouette() {
+        let unit = sphere(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let tangent = ray(Point3::new(-5.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(hit(&unit, &tangent), Some(5.0));
+        let past = ray(Point3::new(-5.0, 1.001, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(hit(&unit, &past), None);
+    }
+}
@@ -0,0 +1,693 @@
+//! Loader for glTF 2.0 scenes (`.gltf`, and binary `.glb`), the documented
+//! intermediate format for bringing in scenes from other packages: Blender
+//! exports it directly, and FBX files convert to it with tools such as
+//! FBX2glTF. The `convert` command writes a loaded scene back out as one of
+//! the tracer's own JSON scenes.
+//!
+//! The nodes of the file's default scene, else of its first, are walked
+//! with their `matrix` or `translation`, `rotation` and `scale`. Triangle
+//! mesh primitives become triangle meshes, smooth shaded if they have
+//! `NORMAL`s, and made of their material's `pbrMetallicRoughness` factors:
+//! base colour as albedo, metallic as reflectivity and roughness as it is,
+//! with emission brightened by `KHR_materials_emissive_strength`, the
+//! `KHR_materials_ior` and, from the base colour's alpha when the material
+//! is blended or from `KHR_materials_transmission`, transparency. Textures
+//! are skipped, leaving their factors, and primitives without a material
+//! are matte white. Perspective cameras are collected by node name, and
+//! `KHR_lights_punctual` point and directional lights become lights in
+//! candela and lux, as USD lights given those units do; spot lights become
+//! point lights.
+//!
+//! glTF is always in metres with +Y up, so geometry, cameras and lights are
+//! converted from those into the units of the scene being loaded into, and
+//! scenes authored Z-up or in centimetres need no fixing up by hand.
+//! Buffers are read from `data:` URIs, the binary chunk of a `.glb`, or
+//! files found as USD assets are. Anything else is skipped with a warning.
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Transform, Vector3, Vector4, Zero,
+};
+use std::fs;
+use std::path::Path;
+use std::str;
+
+use camera::Camera;
+use handle::Handle;
+use json::{self, Value};
+use light::{self, Light, LightUnit};
+use material::{self, Material};
+use mesh::Mesh;
+use primitive::Primitive;
+use resolve::AssetResolver;
+use scene::{Scene, Units, UpAxis};
+use usd::Stage;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+// Types of the chunks of a `.glb`, "JSON" and "BIN" as little-endian words
+const JSON_CHUNK: u32 = 0x4e4f_534a;
+const BIN_CHUNK: u32 = 0x004e_4942;
+
+// Mode of primitives that are lists of triangles
+const TRIANGLES: f64 = 4.0;
+
+// Component types of accessors
+const UNSIGNED_BYTE: u32 = 5121;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+// Extensions a file can require that are loaded, or safely ignored
+const EXTENSIONS: [&str; 4] = [
+    "KHR_lights_punctual",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_ior",
+    "KHR_materials_transmission",
+];
+
+fn index(value: &Value, key: &str) -> Option<usize> {
+    value
+        .get(key)
+        .and_then(Value::as_f64)
+        .filter(|&n| n >= 0.0)
+        .map(|n| n as usize)
+}
+
+fn items<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).and_then(Value::as_array).unwrap_or(&[])
+}
+
+fn extension<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    value.get("extensions")?.get(name)
+}
+
+fn number(value: &Value, key: &str, default: f32) -> Result<f32, String> {
+    match value.get(key) {
+        Some(n) => n.as_f64().map(|n| n as f32).ok_or(format!("{} is not a number", key)),
+        None => Ok(default),
+    }
+}
+
+// The list of as many numbers as `default` under `key`, or `default`
+fn numbers(value: &Value, key: &str, default: &[f32]) -> Result<Vec<f32>, String> {
+    let list = match value.get(key) {
+        Some(list) => list,
+        None => return Ok(default.to_vec()),
+    };
+    let numbers: Option<Vec<f32>> = list
+        .as_array()
+        .and_then(|items| items.iter().map(|n| n.as_f64().map(|n| n as f32)).collect());
+    match numbers {
+        Some(numbers) if numbers.len() == default.len() => Ok(numbers),
+        _ => Err(format!("{} is not {} numbers", key, default.len())),
+    }
+}
+
+// The JSON and binary chunks of a `.glb` file
+fn chunks(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    let word = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    if word(4) != Some(2) {
+        return Err("not a version 2 binary glTF".to_string());
+    }
+    let (mut json, mut bin) = (None, None);
+    let mut at = 12;
+    while let (Some(length), Some(kind)) = (word(at), word(at + 4)) {
+        let end = at + 8 + length as usize;
+        let data = bytes.get(at + 8..end).ok_or("truncated chunk")?;
+        match kind {
+            JSON_CHUNK if json.is_none() => json = Some(data),
+            BIN_CHUNK if bin.is_none() => bin = Some(data),
+            _ => {}
+        }
+        at = end;
+    }
+    Ok((json.ok_or("no JSON chunk")?, bin))
+}
+
+fn base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(format!("bad base64 character {:?}", c as char)),
+        };
+        // Only the bits not yet written are kept
+        bits = (bits << 6 | value as u32) & 0xffff;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// The bytes of a `data:` URI, which glTF writes in base64
+fn data_uri(uri: &str) -> Result<Vec<u8>, String> {
+    let comma = uri.find(',').ok_or("data URI without data")?;
+    if !uri[..comma].ends_with(";base64") {
+        return Err("data URI is not base64".to_string());
+    }
+    base64(&uri[comma + 1..])
+}
+
+// `uri` with its %XX escapes decoded into the file name they stand for
+fn unescape(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut name = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = uri
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                name.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                name.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+// A parsed glTF file and the contents of its buffers
+struct Document {
+    root: Value,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Document {
+    fn read(path: &Path, resolver: &AssetResolver) -> Result<Document, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let (text, bin) = if bytes.starts_with(GLB_MAGIC) {
+            chunks(&bytes)?
+        } else {
+            (&bytes[..], None)
+        };
+        let text = str::from_utf8(text).map_err(|_| "JSON is not UTF-8")?;
+        let root = json::parse(text)?;
+        let version = root.get("asset").and_then(|a| a.get("version")).and_then(Value::as_str);
+        match version {
+            Some(version) if version.starts_with("2.") => {}
+            Some(version) => return Err(format!("glTF {} is not supported", version)),
+            None => return Err("no asset version".to_string()),
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut buffers = Vec::new();
+        for (i, buffer) in items(&root, "buffers").iter().enumerate() {
+            let data = match buffer.get("uri").and_then(Value::as_str) {
+                Some(uri) if uri.starts_with("data:") => {
+                    data_uri(uri).map_err(|e| format!("buffer {}: {}", i, e))?
+                }
+                Some(uri) => {
+                    let file = resolver.resolve(&unescape(uri), dir)?;
+                    fs::read(&file).map_err(|e| format!("{}: {}", file.display(), e))?
+                }
+                // Only the first buffer can be the binary chunk
+                None if i == 0 => bin.ok_or("buffer 0 has no data")?.to_vec(),
+                None => return Err(format!("buffer {} has no uri", i)),
+            };
+            buffers.push(data);
+        }
+        Ok(Document { root, buffers })
+    }
+
+    // Every component of every element of accessor `index`, which must
+    // hold elements of `kind` with components of one of `types`
+    fn components(&self, index: usize, kind: &str, types: &[u32]) -> Result<Vec<f64>, String> {
+        let accessor = items(&self.root, "accessors")
+            .get(index)
+            .ok_or(format!("no accessor {}", index))?;
+        let width = if kind == "VEC3" { 3 } else { 1 };
+        if accessor.get("type").and_then(Value::as_str) != Some(kind) {
+            return Err(format!("accessor {} is not {}", index, kind));
+        }
+        if accessor.get("sparse").is_some() {
+            return Err(format!("accessor {} is sparse", index));
+        }
+        let component = self::index(accessor, "componentType").unwrap_or(0) as u32;
+        if !types.contains(&component) {
+            return Err(format!("accessor {} has components of type {}", index, component));
+        }
+        let size = match component {
+            UNSIGNED_BYTE => 1,
+            UNSIGNED_SHORT => 2,
+            _ => 4,
+        };
+        let count = self::index(accessor, "count")
+            .ok_or(format!("accessor {} has no count", index))?;
+        let view = match self::index(accessor, "bufferView") {
+            Some(view) => items(&self.root, "bufferViews")
+                .get(view)
+                .ok_or(format!("no buffer view {}", view))?,
+            // Without a view every component is zero
+            None => return Ok(vec![0.0; count * width]),
+        };
+        let buffer = self::index(view, "buffer")
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or(format!("accessor {} has no buffer", index))?;
+        let view_start = self::index(view, "byteOffset").unwrap_or(0);
+        let view_end = view_start + self::index(view, "byteLength").unwrap_or(0);
+        let start = view_start + self::index(accessor, "byteOffset").unwrap_or(0);
+        let stride = self::index(view, "byteStride").unwrap_or(width * size);
+        let end = match count {
+            0 => Some(start),
+            _ => (count - 1)
+                .checked_mul(stride)
+                .and_then(|last| last.checked_add(start + width * size)),
+        };
+        match end {
+            Some(end) if end <= view_end.min(buffer.len()) => {}
+            _ => return Err(format!("accessor {} runs past its buffer", index)),
+        }
+
+        let mut components = Vec::with_capacity(count * width);
+        for i in 0..count {
+            for c in 0..width {
+                let at = start + i * stride + c * size;
+                let b = &buffer[at..at + size];
+                components.push(match component {
+                    UNSIGNED_BYTE => b[0] as f64,
+                    UNSIGNED_SHORT => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    UNSIGNED_INT => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                });
+            }
+        }
+        Ok(components)
+    }
+
+    fn vectors(&self, index: usize) -> Result<Vec<Vector3<f32>>, String> {
+        let components = self.components(index, "VEC3", &[FLOAT])?;
+        Ok(components
+            .chunks(3)
+            .map(|v| Vector3::new(v[0] as f32, v[1] as f32, v[2] as f32))
+            .collect())
+    }
+
+    fn indices(&self, index: usize) -> Result<Vec<u32>, String> {
+        let types = [UNSIGNED_BYTE, UNSIGNED_SHORT, UNSIGNED_INT];
+        let components = self.components(index, "SCALAR", &types)?;
+        Ok(components.into_iter().map(|i| i as u32).collect())
+    }
+}
+
+// A material's `pbrMetallicRoughness` factors and the extensions to them
+// that have a counterpart here
+fn material(value: &Value, warnings: &mut Vec<String>) -> Result<Material, String> {
+    let none = Value::Null;
+    let pbr = value.get("pbrMetallicRoughness").unwrap_or(&none);
+    let base = numbers(pbr, "baseColorFactor", &[1.0; 4])?;
+    let roughness = number(pbr, "roughnessFactor", 1.0)?;
+    let emissive = numbers(value, "emissiveFactor", &[0.0; 3])?;
+    let strength = match extension(value, "KHR_materials_emissive_strength") {
+        Some(strength) => number(strength, "emissiveStrength", 1.0)?,
+        None => 1.0,
+    };
+    let mut transparency = match value.get("alphaMode").and_then(Value::as_str) {
+        Some("BLEND") => 1.0 - base[3],
+        _ => 0.0,
+    };
+    if let Some(transmission) = extension(value, "KHR_materials_transmission") {
+        transparency = transparency.max(number(transmission, "transmissionFactor", 0.0)?);
+    }
+    let ior = match extension(value, "KHR_materials_ior") {
+        Some(ior) => number(ior, "ior", 1.5)?,
+        None => 1.5,
+    };
+    let textures = ["baseColorTexture", "metallicRoughnessTexture"]
+        .iter()
+        .any(|key| pbr.get(key).is_some())
+        || ["normalTexture", "occlusionTexture", "emissiveTexture"]
+            .iter()
+            .any(|key| value.get(key).is_some());
+    if textures {
+        warnings.push("textures skipped, leaving their factors".to_string());
+    }
+    Ok(Material {
+        albedo: Vector3::new(base[0], base[1], base[2]),
+        specular: 0.0,
+        shininess: material::shininess(roughness),
+        reflectivity: number(pbr, "metallicFactor", 1.0)?,
+        roughness,
+        emissive: Vector3::new(emissive[0], emissive[1], emissive[2]) * strength,
+        transparency,
+        ior,
+        texture: None,
+    })
+}
+
+// A node's `matrix`, else its `translation`, `rotation` and `scale`
+fn local_transform(node: &Value) -> Result<Matrix4<f32>, String> {
+    if node.get("matrix").is_some() {
+        // Given column by column, as cgmath takes them
+        let mut identity = [0.0; 16];
+        for i in 0..4 {
+            identity[i * 5] = 1.0;
+        }
+        let m = numbers(node, "matrix", &identity)?;
+        let column = |i: usize| Vector4::new(m[i], m[i + 1], m[i + 2], m[i + 3]);
+        return Ok(Matrix4::from_cols(column(0), column(4), column(8), column(12)));
+    }
+    let t = numbers(node, "translation", &[0.0; 3])?;
+    let r = numbers(node, "rotation", &[0.0, 0.0, 0.0, 1.0])?;
+    let s = numbers(node, "scale", &[1.0; 3])?;
+    // Written x, y, z and then w
+    let rotation = Quaternion::new(r[3], r[0], r[1], r[2]);
+    if rotation.is_zero() {
+        return Err("rotation is zero".to_string());
+    }
+    Ok(Matrix4::from_translation(Vector3::new(t[0], t[1], t[2]))
+        * Matrix4::from(rotation.normalize())
+        * Matrix4::from_nonuniform_scale(s[0], s[1], s[2]))
+}
+
+struct Loader<'a> {
+    document: &'a Document,
+    stage: Stage,
+    // Every material of the file, in the scene's palette
+    materials: Vec<(Handle<Material>, Material)>,
+    // Nodes being walked, so a node can't contain itself
+    walking: Vec<usize>,
+}
+
+impl<'a> Loader<'a> {
+    fn walk(&mut self, index: usize, parent: &Matrix4<f32>) -> Result<(), String> {
+        if self.walking.contains(&index) {
+            return Err(format!("node {} contains itself", index));
+        }
+        let node = items(&self.document.root, "nodes")
+            .get(index)
+            .ok_or(format!("no node {}", index))?;
+        let name = match node.get("name").and_then(Value::as_str) {
+            Some(name) => name.to_string(),
+            None => format!("node{}", index),
+        };
+        let local = local_transform(node).map_err(|e| format!("node {}: {}", name, e))?;
+        let world = parent * local;
+        if let Some(mesh) = self::index(node, "mesh") {
+            self.mesh(mesh, &world)?;
+        }
+        if let Some(camera) = self::index(node, "camera") {
+            self.camera(camera, &world, name)?;
+        }
+        if let Some(light) = extension(node, "KHR_lights_punctual") {
+            let light = self::index(light, "light").ok_or("light is not an index")?;
+            self.light(light, &world)?;
+        }
+        self.walking.push(index);
+        for child in items(node, "children") {
+            let child = child.as_f64().ok_or("children are not node indices")?;
+            self.walk(child as usize, &world)?;
+        }
+        self.walking.pop();
+        Ok(())
+    }
+
+    fn mesh(&mut self, index: usize, world: &Matrix4<f32>) -> Result<(), String> {
+        let document = self.document;
+        let mesh = items(&document.root, "meshes")
+            .get(index)
+            .ok_or(format!("no mesh {}", index))?;
+        for (i, primitive) in items(mesh, "primitives").iter().enumerate() {
+            let what = format!("mesh {} primitive {}", index, i);
+            let mode = primitive.get("mode").and_then(Value::as_f64);
+            if mode.unwrap_or(TRIANGLES) != TRIANGLES {
+                self.stage.warnings.push(format!("skipping {}: not triangles", what));
+                continue;
+            }
+            if primitive.get("targets").is_some() {
+                self.stage.warnings.push(format!("{}: morph targets skipped", what));
+            }
+            let read = || -> Result<Mesh, String> {
+                let none = Value::Null;
+                let attributes = primitive.get("attributes").unwrap_or(&none);
+                let positions = self::index(attributes, "POSITION").ok_or("no POSITION")?;
+                let positions: Vec<Point3<f32>> = document
+                    .vectors(positions)?
+                    .into_iter()
+                    .map(Point3::from_vec)
+                    .collect();
+                let indices = match self::index(primitive, "indices") {
+                    Some(indices) => document.indices(indices)?,
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let triangles: Vec<[u32; 3]> = indices
+                    .chunks(3)
+                    .filter(|t| t.len() == 3)
+                    .map(|t| [t[0], t[1], t[2]])
+                    .collect();
+                let corners = triangles.iter().cloned().map(Some).collect();
+                let mut shape = Mesh::new(positions, triangles)?;
+                if let Some(normals) = self::index(attributes, "NORMAL") {
+                    shape = shape.with_normals(document.vectors(normals)?, corners)?;
+                }
+                Ok(shape.transformed(world))
+            };
+            let shape = read().map_err(|e| format!("{}: {}", what, e))?;
+            let material = match self::index(primitive, "material") {
+                Some(m) => Some(*self.materials.get(m).ok_or(format!("no material {}", m))?),
+                None => None,
+            };
+            let shape = shape.with_material(material.map_or(Material::default(), |m| m.1));
+            let object = self.stage.scene.primitives.push(Primitive::Mesh(shape));
+            if let Some((handle, _)) = material {
+                self.stage.scene.material_bindings.insert(object, handle);
+            }
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, index: usize, world: &Matrix4<f32>, name: String) -> Result<(), String> {
+        let document = self.document;
+        let camera = items(&document.root, "cameras")
+            .get(index)
+            .ok_or(format!("no camera {}", index))?;
+        let perspective = match camera.get("perspective") {
+            Some(perspective) => perspective,
+            None => {
+                let warning = format!("skipping camera {}: not perspective", name);
+                self.stage.warnings.push(warning);
+                return Ok(());
+            }
+        };
+        let fov = perspective
+            .get("yfov")
+            .and_then(Value::as_f64)
+            .ok_or(format!("camera {} has no yfov", name))?;
+        // Cameras look down their -Z axis with +Y up
+        let camera = Camera {
+            position: world.transform_point(Point3::origin()),
+            up: world.transform_vector(Vector3::unit_y()).normalize(),
+            at: world.transform_vector(-Vector3::unit_z()).normalize(),
+            fov: (fov as f32).to_degrees(),
+            ..Camera::default()
+        };
+        self.stage.cameras.push((name, camera));
+        Ok(())
+    }
+
+    fn light(&mut self, index: usize, world: &Matrix4<f32>) -> Result<(), String> {
+        let document = self.document;
+        let lights = extension(&document.root, "KHR_lights_punctual")
+            .map_or(&[][..], |lights| items(lights, "lights"));
+        let light = lights.get(index).ok_or(format!("no light {}", index))?;
+        let mut read = || -> Result<Option<Light>, String> {
+            let color = numbers(light, "color", &[1.0; 3])?;
+            let color = light::unit_luminance(Vector3::new(color[0], color[1], color[2]));
+            let intensity = number(light, "intensity", 1.0)?;
+            let meters_per_unit = self.stage.scene.units.meters_per_unit;
+            match light.get("type").and_then(Value::as_str) {
+                Some(kind @ "point") | Some(kind @ "spot") => {
+                    if kind == "spot" {
+                        let warning = format!("spot light {} loaded as a point light", index);
+                        self.stage.warnings.push(warning);
+                    }
+                    Ok(Some(Light::Point {
+                        position: world.transform_point(Point3::origin()),
+                        color,
+                        intensity: LightUnit::Candela.point_intensity(intensity, meters_per_unit)?,
+                        shaping: None,
+                    }))
+                }
+                // Shining down its -Z axis
+                Some("directional") => Ok(Some(Light::Directional {
+                    direction: world.transform_vector(-Vector3::unit_z()).normalize(),
+                    color,
+                    intensity: LightUnit::Lux.directional_intensity(intensity)?,
+                })),
+                Some(other) => {
+                    let warning = format!("skipping light {}: unknown type {}", index, other);
+                    self.stage.warnings.push(warning);
+                    Ok(None)
+                }
+                None => Err("no type".to_string()),
+            }
+        };
+        if let Some(light) = read().map_err(|e| format!("light {}: {}", index, e))? {
+            self.stage.scene.lights.push(light);
+        }
+        Ok(())
+    }
+}
+
+// Builds the stage from the document's default scene
+fn build(document: &Document, units: &Units) -> Result<Stage, String> {
+    let root = &document.root;
+    let mut stage = Stage {
+        scene: Scene {
+            units: *units,
+            ..Scene::empty()
+        },
+        cameras: Vec::new(),
+        materials: Vec::new(),
+        warnings: Vec::new(),
+    };
+    for required in items(root, "extensionsRequired") {
+        let name = required.as_str().unwrap_or_default();
+        if !EXTENSIONS.contains(&name) {
+            stage.warnings.push(format!("required extension {} is not supported", name));
+        }
+    }
+
+    let mut materials = Vec::new();
+    for (i, value) in items(root, "materials").iter().enumerate() {
+        let name = match value.get("name").and_then(Value::as_str) {
+            Some(name) if !stage.materials.iter().any(|m| m.0 == name) => name.to_string(),
+            _ => format!("material{}", i),
+        };
+        let mut warnings = Vec::new();
+        let material = self::material(value, &mut warnings)
+            .map_err(|e| format!("material {}: {}", name, e))?;
+        for warning in warnings {
+            stage.warnings.push(format!("material {}: {}", name, warning));
+        }
+        materials.push((stage.add_material(name, material), material));
+    }
+
+    let scene = index(root, "scene").unwrap_or(0);
+    let nodes = match items(root, "scenes").get(scene) {
+        Some(scene) => items(scene, "nodes"),
+        None => {
+            stage.warnings.push("no scene to load".to_string());
+            &[]
+        }
+    };
+    let mut loader = Loader {
+        document,
+        stage,
+        materials,
+        walking: Vec::new(),
+    };
+    let gltf_units = Units {
+        meters_per_unit: 1.0,
+        up_axis: UpAxis::Y,
+    };
+    let world = gltf_units.conversion_to(units);
+    for node in nodes {
+        let node = node.as_f64().ok_or("scene nodes are not node indices")?;
+        loader.walk(node as usize, &world)?;
+    }
+    Ok(loader.stage)
+}
+
+/// Loads the meshes, materials, cameras and lights of the default scene of
+/// the glTF file at `path`, `.gltf` or `.glb`, into a scene using `units`.
+pub fn load(path: &Path, units: &Units, resolver: &AssetResolver) -> Result<Stage, String> {
+    Document::read(path, resolver)
+        .and_then(|document| build(&document, units))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use primitive::Intersectable;
+    use resolve::scratch_dir;
+
+    // One triangle, with corners at the origin and along +X and +Y
+    const TRIANGLE: &str = "data:application/octet-stream;base64,\
+                            AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA";
+
+    fn load_text(name: &str, text: &str) -> Result<Stage, String> {
+        let path = scratch_dir(name).join("scene.gltf");
+        fs::write(&path, text).unwrap();
+        load(&path, &Units::default(), &AssetResolver::new(Vec::new()))
+    }
+
+    fn document(buffers: &str, accessor_count: usize) -> String {
+        format!(
+            r#"{{
+              "asset": {{ "version": "2.0" }},
+              "scene": 0,
+              "scenes": [ {{ "nodes": [0, 1, 2] }} ],
+              "nodes": [
+                {{ "mesh": 0, "translation": [0, 0, -3] }},
+                {{ "name": "Main", "camera": 0, "translation": [0, 1, 5] }},
+                {{ "extensions": {{ "KHR_lights_punctual": {{ "light": 0 }} }} }}
+              ],
+              "meshes": [ {{ "primitives": [ {{ "attributes": {{ "POSITION": 0 }},
+                                               "material": 0 }} ] }} ],
+              "materials": [ {{ "name": "red",
+                                "pbrMetallicRoughness": {{
+                                  "baseColorFactor": [0.9, 0.1, 0.1, 1],
+                                  "metallicFactor": 0 }} }} ],
+              "cameras": [ {{ "type": "perspective",
+                              "perspective": {{ "yfov": 0.5, "znear": 0.1 }} }} ],
+              "extensions": {{ "KHR_lights_punctual": {{
+                "lights": [ {{ "type": "point", "intensity": 5 }} ] }} }},
+              "accessors": [ {{ "bufferView": 0, "componentType": 5126, "count": {},
+                                "type": "VEC3" }} ],
+              "bufferViews": [ {{ "buffer": 0, "byteLength": 36 }} ],
+              "buffers": [ {} ]
+            }}"#,
+            accessor_count, buffers
+        )
+    }
+
+    #[test]
+    fn scene_loads() {
+        let buffer = format!(r#"{{ "byteLength": 36, "uri": "{}" }}"#, TRIANGLE);
+        let stage = load_text("gltf-scene", &document(&buffer, 3)).unwrap();
+        assert!(stage.warnings.is_empty(), "{:?}", stage.warnings);
+        let primitives: Vec<&Primitive> = stage.scene.primitives.iter().collect();
+        let mesh = match primitives[..] {
+            [Primitive::Mesh(ref mesh)] => mesh,
+            _ => panic!("not one mesh"),
+        };
+        assert_eq!(mesh.len(), 1);
+        assert_eq!(mesh.positions()[1], Point3::new(1.0, 0.0, -3.0));
+        assert_eq!(mesh.material().albedo, Vector3::new(0.9, 0.1, 0.1));
+        assert_eq!(stage.scene.lights.len(), 1);
+        assert_eq!(stage.cameras[0].0, "Main");
+        assert_eq!(stage.cameras[0].1.position, Point3::new(0.0, 1.0, 5.0));
+    }
+
+    #[test]
+    fn malformed_scenes_are_refused() {
+        let buffer = format!(r#"{{ "byteLength": 36, "uri": "{}" }}"#, TRIANGLE);
+        let scenes = [
+            r#"{ "asset": { "version": "2.0" }, "nodes": [ "#.to_string(),
+            document(&buffer, 4),
+            document(r#"{ "byteLength": 36, "uri": "missing.bin" }"#, 3),
+            document(r#"{ "byteLength": 36, "uri": "data:;base64,AAA*" }"#, 3),
+        ];
+        for (i, text) in scenes.iter().enumerate() {
+            assert!(load_text(&format!("gltf-malformed-{}", i), text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Stable handles to a scene's objects, lights and materials.
+//!
+//! A `Pool` keeps its values in slots. Removing a value frees its slot for
+//! the next one added but bumps the slot's generation, so the handle to
+//! the removed value stops working instead of quietly naming whatever
+//! takes its place. Handles to everything else are unaffected.
+//!
+//! Handles read as their slot index, followed by `.` and the generation
+//! once the slot has been reused, so those of a freshly loaded scene are
+//! just its objects' positions in order: `3`, then `3.1` for the next
+//! value to take slot 3.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::slice;
+use std::str::FromStr;
+
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    // Handles are plain numbers, whatever `T` is
+    kind: PhantomData<fn() -> T>,
+}
+
+// Derives would ask the same of `T`
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Handle<T>) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.index, self.generation).hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self)
+    }
+}
+
+impl<T> fmt::Display for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.generation == 0 {
+            write!(f, "{}", self.index)
+        } else {
+            write!(f, "{}.{}", self.index, self.generation)
+        }
+    }
+}
+
+impl<T> FromStr for Handle<T> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Handle<T>, String> {
+        let (index, generation) = s.split_once('.').unwrap_or((s, "0"));
+        match (index.parse(), generation.parse()) {
+            (Ok(index), Ok(generation)) => Ok(Handle {
+                index,
+                generation,
+                kind: PhantomData,
+            }),
+            _ => Err(format!("bad handle {}", s)),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Values reached through handles that outlive the removal of others.
+/// Iteration goes in slot order.
+#[derive(Clone)]
+pub struct Pool<T> {
+    slots: Vec<Slot<T>>,
+    // Empty slots, the most recently emptied last
+    free: Vec<u32>,
+}
+
+impl<T> Pool<T> {
+    pub fn new() -> Pool<T> {
+        Pool {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, value: T) -> Handle<T> {
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.slots[index as usize].value = Some(value);
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                self.slots.len() as u32 - 1
+            }
+        };
+        Handle {
+            index,
+            generation: self.slots[index as usize].generation,
+            kind: PhantomData,
+        }
+    }
+
+    // The slot `handle` names, if it is still live
+    fn slot(&self, handle: Handle<T>) -> Option<&Slot<T>> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation && slot.value.is_some())
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slot(handle)?.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.slot(handle)?;
+        self.slots[handle.index as usize].value.as_mut()
+    }
+
+    /// Takes out the value `handle` names, after which the handle names
+    /// nothing.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        self.slot(handle)?;
+        let slot = &mut self.slots[handle.index as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        slot.value.take()
+    }
+
+    /// Removes every value `keep` rejects.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) {
+        let doomed: Vec<Handle<T>> = self
+            .entries()
+            .filter(|&(_, value)| !keep(value))
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in doomed {
+            self.remove(handle);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether every handle is just its value's position, as when nothing
+    /// has been removed, so pushing the values in order into a new pool
+    /// gives them the same handles.
+    pub fn is_packed(&self) -> bool {
+        self.free.is_empty() && self.slots.iter().all(|slot| slot.generation == 0)
+    }
+
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter {
+            slots: self.slots.iter(),
+        }
+    }
+
+    pub fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut T> + 'a {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+
+    /// Every value along with its handle.
+    pub fn entries<'a>(&'a self) -> impl Iterator<Item = (Handle<T>, &'a T)> + 'a {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let handle = Handle {
+                index: index as u32,
+                generation: slot.generation,
+                kind: PhantomData,
+            };
+            slot.value.as_ref().map(|value| (handle, value))
+        })
+    }
+}
+
+impl<T> Default for Pool<T> {
+    fn default() -> Pool<T> {
+        Pool::new()
+    }
+}
+
+impl<T> FromIterator<T> for Pool<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Pool<T> {
+        let mut pool = Pool::new();
+        for value in values {
+            pool.push(value);
+        }
+        pool
+    }
+}
+
+pub struct Iter<'a, T: 'a> {
+    slots: slice::Iter<'a, Slot<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.slots.find_map(|slot| slot.value.as_ref())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Pool<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
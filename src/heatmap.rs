@@ -0,0 +1,100 @@
+//! False-colour shading that maps a scalar measured at each hit point
+//! through a colormap, for using the tracer as a visualization tool.
+//!
+//! The scalar is the distance from a point, the height, or a per-vertex
+//! attribute of meshes, blended across each triangle from its corners.
+//! Surfaces without the attribute, such as spheres, are left grey.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use primitive::Intersectable;
+use render::Color;
+
+#[derive(Clone)]
+pub enum Scalar {
+    // Distance from a point
+    Distance(Point3<f32>),
+    // Height along the scene's up axis
+    Height,
+    // A mesh attribute by name
+    Attribute(String),
+}
+
+#[derive(Clone, Copy)]
+pub enum Colormap {
+    Viridis,
+    Inferno,
+    Grey,
+}
+
+// Evenly spaced samples of each map, interpolated linearly in between
+const VIRIDIS: [[f32; 3]; 5] = [
+    [0.267, 0.005, 0.329],
+    [0.229, 0.322, 0.545],
+    [0.128, 0.567, 0.551],
+    [0.369, 0.789, 0.383],
+    [0.993, 0.906, 0.144],
+];
+const INFERNO: [[f32; 3]; 6] = [
+    [0.001, 0.000, 0.014],
+    [0.258, 0.039, 0.406],
+    [0.578, 0.148, 0.404],
+    [0.865, 0.317, 0.226],
+    [0.988, 0.645, 0.040],
+    [0.988, 0.998, 0.645],
+];
+const GREY: [[f32; 3]; 2] = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]];
+
+impl Colormap {
+    pub fn from_name(name: &str) -> Option<Colormap> {
+        match name {
+            "viridis" => Some(Colormap::Viridis),
+            "inferno" => Some(Colormap::Inferno),
+            "grey" | "gray" => Some(Colormap::Grey),
+            _ => None,
+        }
+    }
+
+    /// Colour at `t`, clamped to [0, 1].
+    pub fn sample(self, t: f32) -> Color {
+        let stops: &[[f32; 3]] = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Grey => &GREY,
+        };
+        let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (x as usize).min(stops.len() - 2);
+        let (a, b) = (Vector3::from(stops[i]), Vector3::from(stops[i + 1]));
+        a + (b - a) * (x - i as f32)
+    }
+}
+
+#[derive(Clone)]
+pub struct Heatmap {
+    pub scalar: Scalar,
+    pub colormap: Colormap,
+    // Scalar values mapped to either end of the colormap
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Heatmap {
+    /// Colour of `shape` at `point` on `part`.
+    pub fn color(
+        &self,
+        shape: &dyn Intersectable,
+        point: Point3<f32>,
+        part: usize,
+        up: Vector3<f32>,
+    ) -> Color {
+        let value = match self.scalar {
+            Scalar::Distance(origin) => (point - origin).magnitude(),
+            Scalar::Height => point.to_vec().dot(up),
+            Scalar::Attribute(ref name) => match shape.attribute(name, point, part) {
+                Some(value) => value,
+                None => return Vector3::new(0.5, 0.5, 0.5),
+            },
+        };
+        self.colormap.sample((value - self.min) / (self.max - self.min))
+    }
+}
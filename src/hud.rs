@@ -0,0 +1,146 @@
+//! Burn-in of frame number, timecode, scene and camera onto rendered frames
+//! for review, drawn in the top-left corner with a built-in bitmap font.
+
+use cgmath::Vector3;
+
+use render::{Frame, FramePixel};
+
+// Frame rate timecodes are counted at
+const HUD_FPS: u32 = 24;
+
+// Each glyph is 5x7 font pixels, drawn `SCALE` image pixels square
+const SCALE: u32 = 2;
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+const ADVANCE: u32 = (GLYPH_WIDTH + 1) * SCALE;
+const LINE_HEIGHT: u32 = (GLYPH_HEIGHT + 2) * SCALE;
+const MARGIN: u32 = 8;
+const PADDING: u32 = 4;
+
+pub struct Hud {
+    pub frame: u32,
+    pub scene: String,
+    pub camera: String,
+}
+
+// `frame` as an `HH:MM:SS:FF` timecode at `HUD_FPS`
+fn timecode(frame: u32) -> String {
+    let seconds = frame / HUD_FPS;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        frame % HUD_FPS
+    )
+}
+
+// Rows of a glyph, top first, with the leftmost pixel in bit 4. The font has
+// capitals only; lower case is drawn as upper case and anything else it
+// lacks as `?`.
+pub fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+impl Hud {
+    fn lines(&self) -> Vec<String> {
+        vec![
+            format!("FRAME {:04}  TC {}", self.frame, timecode(self.frame)),
+            self.scene.clone(),
+            self.camera.clone(),
+        ]
+    }
+
+    /// Draws the burn-in over `img`, white on a black panel. `img` may be a
+    /// strip of a taller frame whose top row is frame row `top`; only the
+    /// part of the burn-in that falls on the strip is drawn.
+    pub fn draw<P: FramePixel>(&self, img: &mut Frame<P>, top: u32) {
+        draw_lines(&self.lines(), img, top);
+    }
+}
+
+/// Draws `lines` of text over the top-left corner of `img` as the burn-in
+/// is drawn, white on a black panel, with `top` as for `Hud::draw`.
+pub fn draw_lines<P: FramePixel>(lines: &[String], img: &mut Frame<P>, top: u32) {
+    let longest = lines.iter().map(|l| l.chars().count() as u32).max().unwrap_or(0);
+    let panel_width = longest * ADVANCE + 2 * PADDING;
+    let panel_height = lines.len() as u32 * LINE_HEIGHT + 2 * PADDING;
+    let black = P::from_color(Vector3::new(0.0, 0.0, 0.0), 0.0);
+    let white = P::from_color(Vector3::new(1.0, 1.0, 1.0), 0.0);
+    let (width, height) = img.dimensions();
+    let mut put = |x: u32, y: u32, pixel: P| {
+        if x < width && y >= top && y - top < height {
+            img.put_pixel(x, y - top, pixel);
+        }
+    };
+
+    for y in MARGIN..(MARGIN + panel_height) {
+        for x in MARGIN..(MARGIN + panel_width) {
+            put(x, y, black);
+        }
+    }
+    for (row, line) in lines.iter().enumerate() {
+        let line_y = MARGIN + PADDING + row as u32 * LINE_HEIGHT;
+        for (column, c) in line.chars().enumerate() {
+            let glyph_x = MARGIN + PADDING + column as u32 * ADVANCE;
+            for (gy, bits) in glyph(c).iter().enumerate() {
+                for gx in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> gx) == 0 {
+                        continue;
+                    }
+                    for sy in 0..SCALE {
+                        for sx in 0..SCALE {
+                            let x = glyph_x + gx * SCALE + sx;
+                            let y = line_y + gy as u32 * SCALE + sy;
+                            put(x, y, white);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
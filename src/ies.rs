@@ -0,0 +1,175 @@
+//! Loader for IES LM-63 photometric files, which describe how the light of
+//! a real fixture varies with direction.
+//!
+//! Only type C photometry, used by nearly all architectural fixtures, is
+//! supported: vertical angles run from 0 at the nadir (straight down) to
+//! 180 at the zenith, and horizontal angles turn about the vertical axis.
+//! Tilt data is skipped.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// Photometric type code of type C files
+const TYPE_C: f32 = 1.0;
+
+#[derive(Debug)]
+pub struct Profile {
+    // Both in degrees, ascending
+    vertical: Vec<f32>,
+    horizontal: Vec<f32>,
+    // Candela for each horizontal angle, then each vertical angle
+    candela: Vec<Vec<f32>>,
+    peak: f32,
+}
+
+// Position of `x` in ascending `xs` as a lower index and a blend towards the
+// next, clamped to the ends
+fn locate(xs: &[f32], x: f32) -> (usize, f32) {
+    if xs.len() < 2 || x <= xs[0] {
+        return (0, 0.0);
+    }
+    let last = xs.len() - 1;
+    if x >= xs[last] {
+        return (last, 0.0);
+    }
+    let i = xs.iter().rposition(|&v| v <= x).unwrap_or(0).min(last - 1);
+    let span = xs[i + 1] - xs[i];
+    let t = if span > 0.0 { (x - xs[i]) / span } else { 0.0 };
+    (i, t)
+}
+
+impl Profile {
+    /// Candela in the direction at `vertical` degrees from the nadir and
+    /// `horizontal` degrees around, blended between measured angles.
+    pub fn candela(&self, vertical: f32, horizontal: f32) -> f32 {
+        if vertical < self.vertical[0] || vertical > self.vertical[self.vertical.len() - 1] {
+            return 0.0;
+        }
+        let horizontal = self.fold(horizontal);
+        let (v, vt) = locate(&self.vertical, vertical);
+        let (h, ht) = locate(&self.horizontal, horizontal);
+        let at = |h: usize, v: usize| {
+            let row = &self.candela[h.min(self.horizontal.len() - 1)];
+            row[v.min(self.vertical.len() - 1)]
+        };
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let near = lerp(at(h, v), at(h, v + 1), vt);
+        let far = lerp(at(h + 1, v), at(h + 1, v + 1), vt);
+        lerp(near, far, ht)
+    }
+
+    /// Candela in a direction as a fraction of the profile's brightest.
+    pub fn relative(&self, vertical: f32, horizontal: f32) -> f32 {
+        if self.peak > 0.0 {
+            self.candela(vertical, horizontal) / self.peak
+        } else {
+            0.0
+        }
+    }
+
+    // Maps a horizontal angle onto the range the file measures, using the
+    // symmetry its last angle implies
+    fn fold(&self, horizontal: f32) -> f32 {
+        let h = horizontal.rem_euclid(360.0);
+        match self.horizontal[self.horizontal.len() - 1] as i32 {
+            // Rotationally symmetric
+            0 => 0.0,
+            // Symmetric in each quadrant
+            90 => {
+                let h = if h > 180.0 { 360.0 - h } else { h };
+                if h > 90.0 {
+                    180.0 - h
+                } else {
+                    h
+                }
+            }
+            // Symmetric about the 0-180 plane
+            180 => {
+                if h > 180.0 {
+                    360.0 - h
+                } else {
+                    h
+                }
+            }
+            _ => h,
+        }
+    }
+}
+
+/// Parses the text of an IES file.
+pub fn parse(src: &str) -> Result<Profile, String> {
+    let mut lines = src.lines();
+    let tilt = loop {
+        match lines.next() {
+            Some(line) if line.trim_start().starts_with("TILT=") => {
+                break line.trim_start()["TILT=".len()..].trim().to_string()
+            }
+            Some(_) => continue,
+            None => return Err("no TILT line".to_string()),
+        }
+    };
+    let rest: Vec<&str> = lines.collect();
+    let mut numbers = rest
+        .iter()
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f32>().map_err(|_| format!("bad number {:?}", s)));
+    let mut next = || numbers.next().unwrap_or_else(|| Err("file cut short".to_string()));
+
+    if tilt == "INCLUDE" {
+        // Lamp geometry, then angle and factor pairs
+        next()?;
+        let pairs = next()? as usize;
+        for _ in 0..2 * pairs {
+            next()?;
+        }
+    }
+
+    // Lamp count, lumens per lamp, candela multiplier, vertical and
+    // horizontal angle counts, photometric type, then unit and size fields
+    // this loader doesn't need
+    let _lamps = next()?;
+    let _lumens = next()?;
+    let multiplier = next()?;
+    let vertical_count = next()? as usize;
+    let horizontal_count = next()? as usize;
+    let photometric_type = next()?;
+    for _ in 0..7 {
+        next()?;
+    }
+    if photometric_type != TYPE_C {
+        return Err(format!("unsupported photometric type {}", photometric_type));
+    }
+    if vertical_count == 0 || horizontal_count == 0 {
+        return Err("no angles".to_string());
+    }
+
+    let mut read = |count: usize| (0..count).map(|_| next()).collect::<Result<Vec<f32>, String>>();
+    let vertical = read(vertical_count)?;
+    let horizontal = read(horizontal_count)?;
+    let mut candela = Vec::with_capacity(horizontal_count);
+    for _ in 0..horizontal_count {
+        candela.push(read(vertical_count)?.iter().map(|c| c * multiplier).collect::<Vec<_>>());
+    }
+    let ascending = |xs: &[f32]| xs.windows(2).all(|w| w[0] <= w[1]);
+    if !ascending(&vertical) || !ascending(&horizontal) {
+        return Err("angles out of order".to_string());
+    }
+    let peak = candela.iter().flatten().cloned().fold(0.0, f32::max);
+    Ok(Profile {
+        vertical,
+        horizontal,
+        candela,
+        peak,
+    })
+}
+
+/// Reads the IES file at `path`.
+pub fn load(path: &Path) -> Result<Profile, String> {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&src).map_err(|e| format!("{}: {}", path.display(), e))
+}
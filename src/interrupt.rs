@@ -0,0 +1,26 @@
+//! Ctrl+C handling for offline renders: the first interrupt asks the render
+//! to stop at the next tile and save what it has, a second one exits at once.
+
+use ctrlc;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use report;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+pub fn install() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(report::EXIT_INTERRUPTED);
+        }
+        eprintln!("\ninterrupted, finishing the current tile (Ctrl+C again to quit now)");
+    });
+    if let Err(e) = result {
+        println!("Failed to install Ctrl+C handler: {}", e);
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
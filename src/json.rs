@@ -0,0 +1,1017 @@
+//! Loader for scenes described in JSON, for trying out scenes without
+//! writing USD.
+//!
+//! A scene file is an object of the format `version` it's written in and up
+//! to eight lists, each optional:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "libraries": [ "metals.json" ],
+//!   "materials": { "red": { "albedo": [0.9, 0.1, 0.1], "specular": 0.3 } },
+//!   "cameras": [ { "name": "main", "position": [0, 1, 5], "direction": [0, 0, -1] } ],
+//!   "spheres": [ { "center": [0, 1, 0], "radius": 1, "material": "red" } ],
+//!   "boxes": [ { "min": [-2, -0.1, -2], "max": [2, 0, 2], "material": "red" } ],
+//!   "models": [ { "file": "bunny.obj", "material": { "albedo": [0.8, 0.8, 0.8] } } ],
+//!   "labels": [ { "text": "Bunny", "position": [0, 2, 0], "height": 0.3 } ],
+//!   "lights": [ { "type": "point", "position": [2, 4, 2], "intensity": 50 } ]
+//! }
+//! ```
+//!
+//! Materials take any of the fields of `Material` and are given by name or
+//! written in place; shininess follows roughness unless given itself. A
+//! material with a `base` takes the fields it doesn't give from the
+//! material of that name, so an object can use a named material with a
+//! field or two changed, as in `{ "base": "steel", "roughness": 0.6 }`.
+//! Libraries are files of named materials, written as a scene's
+//! `materials` are and found as models are, for sharing materials between
+//! scenes; materials are named in the order the libraries are listed and
+//! then the scene's own, and one named again replaces the one before. A
+//! material's `texture` varies its albedo procedurally, as in
+//! `{ "type": "checker", "colors": [[1, 1, 1], [0.1, 0.1, 0.1]], "scale": 0.5 }`,
+//! or with types `noise` and `marble` (which takes a `turbulence`).
+//! Boxes are aligned with the axes and given by opposite corners, in either
+//! order. Labels are a line of `text` centred on `position`, `height` tall,
+//! in a `color` that isn't lit, white by default, and set in the TrueType
+//! `font` file if given or the burn-in's capitals if not; they turn to face
+//! the camera. Spheres, boxes and labels given a `velocity` move at it, in
+//! scene units a second, as the scene clock runs and while a shutter is
+//! open. Cameras take `position`, `direction`, `up`, `fov` in degrees and,
+//! for depth of field, the lens diameter `aperture` and the distance in
+//! `focus`. Lights are `point` (`position`), `directional` (`direction`,
+//! the way the light travels) or `dome` (`portals`, each a `corner` and
+//! edges `u` and `v` letting light in against `u` x `v`, and either a
+//! `texture`, a Radiance HDR environment map, or a gradient of `zenith`,
+//! `horizon` and `ground` colours), all with `color` and `intensity`.
+//! Coordinates are in the scene's units, and model and texture files are
+//! found as USD assets are. Fields that aren't understood are skipped with
+//! a warning.
+//!
+//! Spheres, boxes, models and labels are keyframed by a list of `keys`,
+//! each a `time` on the scene clock in seconds and the `translate` moving
+//! the object from where it's placed, and cameras by keys of a `time` and
+//! the `position` and `direction` the camera has then, each its own unless
+//! given. Keys are listed in time order; between two, things move in a
+//! straight line, and before the first or after the last they hold still.
+//!
+//! A scene of an older version is migrated to this one as it loads, with a
+//! warning, and one with no version is taken to be version 1; a scene of a
+//! version newer than `VERSION` is refused. The `schema` command writes a
+//! JSON Schema of the format for editors to check scenes against.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use camera::{Camera, CameraKey};
+use components::{Animation, Keyframes, MeshSource};
+use cuboid::Cuboid;
+use environment::{self, Environment};
+use geometry::Sphere;
+use handle::Handle;
+use label::Label;
+use light::{Light, Portal};
+use material::{self, Material};
+use primitive::Primitive;
+use render::Color;
+use report;
+use resolve::{self, AssetResolver};
+use scene::{Scene, Units};
+use texture::{Checker, Marble, Noise, Pattern};
+use usd::Stage;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // Members in the order written
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub fn is_object(&self) -> bool {
+        matches!(*self, Value::Object(_))
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec3(&self) -> Option<Vector3<f32>> {
+        match self.as_array() {
+            Some([x, y, z]) => Some(Vector3::new(
+                x.as_f64()? as f32,
+                y.as_f64()? as f32,
+                z.as_f64()? as f32,
+            )),
+            _ => None,
+        }
+    }
+
+    /// The value as JSON text, laid out a member or item to a line down to
+    /// `depth` levels in, and within a line below that.
+    pub fn to_json(&self, depth: usize) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0, depth);
+        out
+    }
+
+    fn write(&self, out: &mut String, level: usize, depth: usize) {
+        let (open, close, count) = match *self {
+            Value::Null => return out.push_str("null"),
+            Value::Bool(b) => return out.push_str(if b { "true" } else { "false" }),
+            // JSON has no infinities or NaNs
+            Value::Number(n) if !n.is_finite() => return out.push_str("null"),
+            Value::Number(n) => return out.push_str(&n.to_string()),
+            Value::String(ref s) => return out.push_str(&report::json_string(s)),
+            Value::Array(ref items) => ('[', ']', items.len()),
+            Value::Object(ref members) => ('{', '}', members.len()),
+        };
+        let nested = |v: &Value| matches!(*v, Value::Array(_) | Value::Object(_));
+        let broken = level < depth
+            && match *self {
+                Value::Array(ref items) => items.iter().any(nested),
+                _ => true,
+            };
+        out.push(open);
+        for i in 0..count {
+            if i > 0 {
+                out.push(',');
+            }
+            if broken {
+                out.push('\n');
+                out.push_str(&"  ".repeat(level + 1));
+            } else if i > 0 {
+                out.push(' ');
+            }
+            match *self {
+                Value::Array(ref items) => items[i].write(out, level + 1, depth),
+                Value::Object(ref members) => {
+                    out.push_str(&report::json_string(&members[i].0));
+                    out.push_str(": ");
+                    members[i].1.write(out, level + 1, depth);
+                }
+                _ => {}
+            }
+        }
+        if broken && count > 0 {
+            out.push('\n');
+            out.push_str(&"  ".repeat(level));
+        }
+        out.push(close);
+    }
+}
+
+// How deeply arrays and objects may nest, so a hostile file can't overflow
+// the stack
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    src: &'a [u8],
+    at: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        let line = 1 + self.src[..self.at].iter().filter(|&&c| c == b'\n').count();
+        format!("line {}: {}", line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.at < self.src.len() && (self.src[self.at] as char).is_ascii_whitespace() {
+            self.at += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.src.get(self.at).cloned()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected {:?}", c as char)));
+        }
+        self.at += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        if !self.src[self.at..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected character"));
+        }
+        self.at += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some(b'{') | Some(b'[') if self.depth == MAX_DEPTH => {
+                Err(self.error(&format!("nested more than {} deep", MAX_DEPTH)))
+            }
+            Some(b'{') => self.nested(Parser::object),
+            Some(b'[') => self.nested(Parser::array),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.at += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.at += 1,
+                Some(b'}') => {
+                    self.at += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.at += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.at += 1,
+                Some(b']') => {
+                    self.at += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.at;
+        while self.at < self.src.len() && b"+-.eE0123456789".contains(&self.src[self.at]) {
+            self.at += 1;
+        }
+        let text = String::from_utf8_lossy(&self.src[start..self.at]);
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("bad number {:?}", text)))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self
+                .src
+                .get(self.at)
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.at += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = *self
+                        .src
+                        .get(self.at)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.at += 1;
+                    match escaped {
+                        b'n' => bytes.push(b'\n'),
+                        b't' => bytes.push(b'\t'),
+                        b'r' => bytes.push(b'\r'),
+                        b'b' => bytes.push(8),
+                        b'f' => bytes.push(12),
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // Characters beyond the first plane are escaped as
+                            // a pair of surrogates, high then low
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.src[self.at..].starts_with(b"\\u")
+                            {
+                                self.at += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("unpaired surrogate in \\u escape"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            let c = std::char::from_u32(code)
+                                .ok_or_else(|| self.error("bad \\u escape"))?;
+                            let mut buffer = [0; 4];
+                            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                        }
+                        other => bytes.push(other),
+                    }
+                }
+                _ => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("string is not UTF-8"))
+    }
+
+    // The four hex digits of a \u escape
+    fn hex4(&mut self) -> Result<u32, String> {
+        let hex = self.src.get(self.at..self.at + 4).unwrap_or(&[]);
+        let code = u32::from_str_radix(&String::from_utf8_lossy(hex), 16)
+            .map_err(|_| self.error("bad \\u escape"))?;
+        self.at += 4;
+        Ok(code)
+    }
+}
+
+/// Parses JSON text into a value.
+pub fn parse(src: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        src: src.as_bytes(),
+        at: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("text after the scene"));
+    }
+    Ok(value)
+}
+
+// Warns of each member of `object` not among `known`
+fn check_fields(object: &Value, known: &[&str], what: &str, warnings: &mut Vec<String>) {
+    if let Value::Object(ref members) = *object {
+        for (key, _) in members {
+            if !known.contains(&key.as_str()) {
+                warnings.push(format!("skipping unknown field {} of {}", key, what));
+            }
+        }
+    }
+}
+
+fn vec3(object: &Value, key: &str, default: Vector3<f32>) -> Result<Vector3<f32>, String> {
+    match object.get(key) {
+        Some(value) => value.as_vec3().ok_or(format!("{} is not three numbers", key)),
+        None => Ok(default),
+    }
+}
+
+fn point(object: &Value, key: &str) -> Result<Point3<f32>, String> {
+    let v = vec3(object, key, Vector3::new(0.0, 0.0, 0.0))?;
+    Ok(Point3::new(v.x, v.y, v.z))
+}
+
+fn float(object: &Value, key: &str, default: f32) -> Result<f32, String> {
+    match object.get(key) {
+        Some(value) => value.as_f64().map(|n| n as f32).ok_or(format!("{} is not a number", key)),
+        None => Ok(default),
+    }
+}
+
+// The fields each object may have, which `schema` describes
+pub const SCENE_FIELDS: &[&str] = &[
+    "version", "libraries", "materials", "cameras", "spheres", "boxes", "models", "labels",
+    "lights",
+];
+pub const MATERIAL_FIELDS: &[&str] = &[
+    "base",
+    "albedo",
+    "specular",
+    "shininess",
+    "reflectivity",
+    "roughness",
+    "emissive",
+    "transparency",
+    "ior",
+    "texture",
+];
+pub const TEXTURE_FIELDS: &[&str] = &["type", "colors", "scale", "turbulence"];
+pub const CAMERA_FIELDS: &[&str] =
+    &["name", "position", "direction", "up", "fov", "aperture", "focus", "keys"];
+pub const CAMERA_KEY_FIELDS: &[&str] = &["time", "position", "direction"];
+pub const SPHERE_FIELDS: &[&str] = &["center", "radius", "material", "velocity", "keys"];
+pub const BOX_FIELDS: &[&str] = &["min", "max", "material", "velocity", "keys"];
+pub const MODEL_FIELDS: &[&str] = &["file", "material", "keys"];
+pub const LABEL_FIELDS: &[&str] =
+    &["text", "position", "height", "color", "font", "velocity", "keys"];
+pub const KEY_FIELDS: &[&str] = &["time", "translate"];
+pub const POINT_LIGHT_FIELDS: &[&str] = &["type", "position", "color", "intensity"];
+pub const DIRECTIONAL_LIGHT_FIELDS: &[&str] = &["type", "direction", "color", "intensity"];
+pub const DOME_LIGHT_FIELDS: &[&str] = &[
+    "type", "portals", "texture", "zenith", "horizon", "ground", "color", "intensity",
+];
+pub const PORTAL_FIELDS: &[&str] = &["corner", "u", "v"];
+
+// A procedural texture: `checker`, `noise` or `marble`, blending between
+// two `colors`, by default `albedo` and black, in features `scale` across,
+// and for marble, veins bent by `turbulence`
+fn parse_texture(
+    object: &Value,
+    albedo: Color,
+    warnings: &mut Vec<String>,
+) -> Result<Pattern, String> {
+    check_fields(object, TEXTURE_FIELDS, "texture", warnings);
+    let colors = match object.get("colors").map(Value::as_array) {
+        Some(Some([first, second])) => match (first.as_vec3(), second.as_vec3()) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return Err("colors are not three numbers each".to_string()),
+        },
+        Some(_) => return Err("colors is not two colours".to_string()),
+        None => (albedo, Vector3::new(0.0, 0.0, 0.0)),
+    };
+    let scale = float(object, "scale", 1.0)?;
+    if scale <= 0.0 {
+        return Err("scale is not positive".to_string());
+    }
+    match object.get("type").and_then(Value::as_str) {
+        Some("checker") => Ok(Pattern::Checker(Checker {
+            even: colors.0,
+            odd: colors.1,
+            scale,
+        })),
+        Some("noise") => Ok(Pattern::Noise(Noise {
+            low: colors.0,
+            high: colors.1,
+            scale,
+        })),
+        Some("marble") => Ok(Pattern::Marble(Marble {
+            base: colors.0,
+            vein: colors.1,
+            scale,
+            turbulence: float(object, "turbulence", 2.0)?,
+        })),
+        Some(other) => Err(format!("unknown type {}", other)),
+        None => Err("no type".to_string()),
+    }
+}
+
+// A material, its fields defaulting to those of the material of `palette`
+// named by its `base`, if it has one
+fn parse_material(
+    object: &Value,
+    palette: &[(String, Material)],
+    warnings: &mut Vec<String>,
+) -> Result<Material, String> {
+    if !object.is_object() {
+        return Err("not an object".to_string());
+    }
+    check_fields(object, MATERIAL_FIELDS, "material", warnings);
+    let default = match object.get("base") {
+        Some(Value::String(name)) => named(palette, name)?,
+        Some(_) => return Err("base is not a name".to_string()),
+        None => Material::default(),
+    };
+    let roughness = float(object, "roughness", default.roughness)?;
+    let shininess = if object.get("roughness").is_some() {
+        material::shininess(roughness)
+    } else {
+        default.shininess
+    };
+    let albedo = vec3(object, "albedo", default.albedo)?;
+    let texture = match object.get("texture") {
+        Some(texture) => {
+            let texture = parse_texture(texture, albedo, warnings);
+            Some(texture.map_err(|e| format!("texture: {}", e))?)
+        }
+        None => default.texture,
+    };
+    Ok(Material {
+        albedo,
+        specular: float(object, "specular", default.specular)?,
+        shininess: float(object, "shininess", shininess)?,
+        reflectivity: float(object, "reflectivity", default.reflectivity)?,
+        roughness,
+        emissive: vec3(object, "emissive", default.emissive)?,
+        transparency: float(object, "transparency", default.transparency)?,
+        ior: float(object, "ior", default.ior)?,
+        texture,
+    })
+}
+
+// The material of `palette` called `name`
+fn named(palette: &[(String, Material)], name: &str) -> Result<Material, String> {
+    palette
+        .iter()
+        .find(|m| m.0 == name)
+        .map(|m| m.1)
+        .ok_or(format!("no material named {}", name))
+}
+
+// Adds the materials `value` names to `palette`, each in place of any
+// already there by its name
+fn add_materials(
+    value: &Value,
+    palette: &mut Vec<(String, Material)>,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    let members = match *value {
+        Value::Object(ref members) => members,
+        _ => return Err("materials is not an object".to_string()),
+    };
+    for (name, value) in members {
+        let material = parse_material(value, palette, warnings)
+            .map_err(|e| format!("material {}: {}", name, e))?;
+        match palette.iter_mut().find(|m| m.0 == *name) {
+            Some(entry) => entry.1 = material,
+            None => palette.push((name.clone(), material)),
+        }
+    }
+    Ok(())
+}
+
+// Sets `object` moving at the `velocity` its entry gives, if any, and
+// along the `keys` it gives, moving it to where the first puts it
+fn animate(
+    scene: &mut Scene,
+    object: Handle<Primitive>,
+    entry: &Value,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    if entry.get("velocity").is_some() {
+        let velocity = vec3(entry, "velocity", Vector3::new(0.0, 0.0, 0.0))?;
+        scene.animations.insert(object, Animation { velocity });
+    }
+    let mut keyframes = Keyframes { keys: Vec::new() };
+    for (time, key) in keys(entry, KEY_FIELDS, warnings)? {
+        keyframes.keys.push((time, vec3(key, "translate", Vector3::new(0.0, 0.0, 0.0))?));
+    }
+    if !keyframes.keys.is_empty() {
+        if let Some(primitive) = scene.primitives.get_mut(object) {
+            primitive.translate(keyframes.offset(scene.time));
+        }
+        scene.keyframes.insert(object, keyframes);
+    }
+    Ok(())
+}
+
+// The entries of the `keys` list of `object`, each with its `time`, which
+// must increase down the list
+fn keys<'a>(
+    object: &'a Value,
+    fields: &[&str],
+    warnings: &mut Vec<String>,
+) -> Result<Vec<(f32, &'a Value)>, String> {
+    let mut keys: Vec<(f32, &Value)> = Vec::new();
+    for (i, key) in list(object, "keys")?.iter().enumerate() {
+        check_fields(key, fields, "key", warnings);
+        let time = match key.get("time").and_then(Value::as_f64) {
+            Some(time) => time as f32,
+            None => return Err(format!("key {} has no time", i + 1)),
+        };
+        if keys.last().is_some_and(|&(last, _)| time <= last) {
+            return Err(format!("key {} is not later than the one before", i + 1));
+        }
+        keys.push((time, key));
+    }
+    Ok(keys)
+}
+
+fn list<'a>(scene: &'a Value, key: &str) -> Result<&'a [Value], String> {
+    match scene.get(key) {
+        Some(value) => value.as_array().ok_or(format!("{} is not a list", key)),
+        None => Ok(&[]),
+    }
+}
+
+/// The version of the scene format this module reads and `convert` writes.
+pub const VERSION: u32 = 1;
+
+// Each step upgrades a scene from one version to the next, the first from
+// version 1; the format has had only the one version so far
+const MIGRATIONS: [fn(&mut Value, &mut Vec<String>); 0] = [];
+
+// Brings a scene of an older version up to this one, warning that it has,
+// and refuses one newer than this module reads
+fn upgrade(root: &mut Value, warnings: &mut Vec<String>) -> Result<(), String> {
+    let version = match root.get("version") {
+        Some(value) => match value.as_f64() {
+            Some(n) if n >= 1.0 && n.fract() == 0.0 => n as u32,
+            _ => return Err("version is not a whole number from 1".to_string()),
+        },
+        None => {
+            warnings.push("the scene has no version; reading it as version 1".to_string());
+            1
+        }
+    };
+    if version > VERSION {
+        return Err(format!(
+            "the scene is version {} of the format, newer than the version {} this reads",
+            version, VERSION
+        ));
+    }
+    if version < VERSION {
+        warnings.push(format!("migrating the scene from version {} to {}", version, VERSION));
+    }
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        migrate(root, warnings);
+    }
+    Ok(())
+}
+
+// Builds the stage from the parsed scene, finding models from `dir`
+fn build(
+    mut root: Value,
+    dir: &Path,
+    units: &Units,
+    resolver: &AssetResolver,
+) -> Result<Stage, String> {
+    let mut stage = Stage {
+        scene: Scene {
+            units: *units,
+            ..Scene::empty()
+        },
+        cameras: Vec::new(),
+        materials: Vec::new(),
+        warnings: Vec::new(),
+    };
+    if !root.is_object() {
+        return Err("the scene is not an object".to_string());
+    }
+    upgrade(&mut root, &mut stage.warnings)?;
+    let root = &root;
+    check_fields(root, SCENE_FIELDS, "the scene", &mut stage.warnings);
+
+    let mut materials = Vec::new();
+    for library in list(root, "libraries")? {
+        let file = library.as_str().ok_or("libraries are not file names")?;
+        let path = resolver.resolve(file, dir)?;
+        let value = read(&path)?;
+        add_materials(&value, &mut materials, &mut stage.warnings)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    if let Some(value) = root.get("materials") {
+        add_materials(value, &mut materials, &mut stage.warnings)?;
+    }
+    for &(ref name, material) in &materials {
+        stage.add_material(name.clone(), material);
+    }
+    // A material by name or written in place, white by default
+    let material_of = |object: &Value, warnings: &mut Vec<String>| match object.get("material") {
+        None => Ok(Material::default()),
+        Some(Value::String(name)) => named(&materials, name),
+        Some(value) => parse_material(value, &materials, warnings),
+    };
+
+    for (i, camera) in list(root, "cameras")?.iter().enumerate() {
+        check_fields(camera, CAMERA_FIELDS, "camera", &mut stage.warnings);
+        let name = match camera.get("name").and_then(Value::as_str) {
+            Some(name) => name.to_string(),
+            None => format!("camera{}", i + 1),
+        };
+        let mut read = || -> Result<Camera, String> {
+            let position = point(camera, "position")?;
+            let at = vec3(camera, "direction", Vector3::new(0.0, 0.0, -1.0))?;
+            let mut keys = Vec::new();
+            for (time, key) in self::keys(camera, CAMERA_KEY_FIELDS, &mut stage.warnings)? {
+                keys.push(CameraKey {
+                    time,
+                    position: Point3::from_vec(vec3(key, "position", position.to_vec())?),
+                    at: vec3(key, "direction", at)?,
+                });
+            }
+            Ok(Camera {
+                position,
+                up: vec3(camera, "up", Vector3::new(0.0, 1.0, 0.0))?,
+                at,
+                fov: float(camera, "fov", 90.0)?,
+                aperture: float(camera, "aperture", 0.0)?,
+                focal_distance: float(camera, "focus", 1.0)?,
+                keys,
+            })
+        };
+        let camera = read().map_err(|e| format!("camera {}: {}", name, e))?;
+        stage.cameras.push((name, camera));
+    }
+
+    for (i, sphere) in list(root, "spheres")?.iter().enumerate() {
+        check_fields(sphere, SPHERE_FIELDS, "sphere", &mut stage.warnings);
+        let mut read = || -> Result<Sphere, String> {
+            let radius = float(sphere, "radius", 1.0)?;
+            if !(radius.is_finite() && radius > 0.0) {
+                return Err("radius is not a positive number".to_string());
+            }
+            Ok(Sphere {
+                center: point(sphere, "center")?,
+                radius,
+                material: material_of(sphere, &mut stage.warnings)?,
+            })
+        };
+        let shape = read().map_err(|e| format!("sphere {}: {}", i + 1, e))?;
+        let object = stage.scene.primitives.push(Primitive::Sphere(shape));
+        animate(&mut stage.scene, object, sphere, &mut stage.warnings)
+            .map_err(|e| format!("sphere {}: {}", i + 1, e))?;
+        if let Some(name) = sphere.get("material").and_then(Value::as_str) {
+            stage.bind_material(object, name);
+        }
+    }
+
+    for (i, cuboid) in list(root, "boxes")?.iter().enumerate() {
+        check_fields(cuboid, BOX_FIELDS, "box", &mut stage.warnings);
+        let mut read = || -> Result<Cuboid, String> {
+            let (a, b) = (point(cuboid, "min")?, point(cuboid, "max")?);
+            Ok(Cuboid {
+                min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+                max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+                material: material_of(cuboid, &mut stage.warnings)?,
+            })
+        };
+        let shape = read().map_err(|e| format!("box {}: {}", i + 1, e))?;
+        let object = stage.scene.primitives.push(Primitive::Cuboid(shape));
+        animate(&mut stage.scene, object, cuboid, &mut stage.warnings)
+            .map_err(|e| format!("box {}: {}", i + 1, e))?;
+        if let Some(name) = cuboid.get("material").and_then(Value::as_str) {
+            stage.bind_material(object, name);
+        }
+    }
+
+    for (i, model) in list(root, "models")?.iter().enumerate() {
+        check_fields(model, MODEL_FIELDS, "model", &mut stage.warnings);
+        let file = model
+            .get("file")
+            .and_then(Value::as_str)
+            .ok_or(format!("model {}: no file", i + 1))?;
+        let material = material_of(model, &mut stage.warnings)
+            .map_err(|e| format!("model {}: {}", i + 1, e))?;
+        let path = resolver.resolve(file, dir)?;
+        let source = MeshSource {
+            modified: resolve::modified(&path),
+            path,
+            transform: Matrix4::identity(),
+        };
+        let mesh = resolver.mesh(&source.path)?;
+        let object = stage.scene.primitives.push(Primitive::Mesh(mesh.with_material(material)));
+        stage.scene.mesh_sources.insert(object, source);
+        animate(&mut stage.scene, object, model, &mut stage.warnings)
+            .map_err(|e| format!("model {}: {}", i + 1, e))?;
+        if let Some(name) = model.get("material").and_then(Value::as_str) {
+            stage.bind_material(object, name);
+        }
+    }
+
+    for (i, label) in list(root, "labels")?.iter().enumerate() {
+        check_fields(label, LABEL_FIELDS, "label", &mut stage.warnings);
+        let read = || -> Result<Label, String> {
+            let text = label.get("text").and_then(Value::as_str).ok_or("no text")?;
+            let position = point(label, "position")?;
+            let height = float(label, "height", 1.0)?;
+            let color = vec3(label, "color", Vector3::new(1.0, 1.0, 1.0))?;
+            match label.get("font").and_then(Value::as_str) {
+                Some(file) => {
+                    let path = resolver.resolve(file, dir)?;
+                    let font = resolver.font(&path)?;
+                    Label::new(text, Some((&font, &path)), position, height, units.up(), color)
+                }
+                None => Label::new(text, None, position, height, units.up(), color),
+            }
+        };
+        let shape = read().map_err(|e| format!("label {}: {}", i + 1, e))?;
+        let object = stage.scene.primitives.push(Primitive::Label(shape));
+        animate(&mut stage.scene, object, label, &mut stage.warnings)
+            .map_err(|e| format!("label {}: {}", i + 1, e))?;
+    }
+
+    for (i, light) in list(root, "lights")?.iter().enumerate() {
+        let read = |warnings: &mut Vec<String>| -> Result<Light, String> {
+            let white = Vector3::new(1.0, 1.0, 1.0);
+            let color = vec3(light, "color", white)?;
+            let intensity = float(light, "intensity", 1.0)?;
+            match light.get("type").and_then(Value::as_str) {
+                Some("point") => {
+                    check_fields(light, POINT_LIGHT_FIELDS, "light", warnings);
+                    Ok(Light::Point {
+                        position: point(light, "position")?,
+                        color,
+                        intensity,
+                        shaping: None,
+                    })
+                }
+                Some("directional") => {
+                    check_fields(light, DIRECTIONAL_LIGHT_FIELDS, "light", warnings);
+                    let direction = vec3(light, "direction", Vector3::new(0.0, -1.0, 0.0))?;
+                    if direction == Vector3::new(0.0, 0.0, 0.0) {
+                        return Err("direction is zero".to_string());
+                    }
+                    Ok(Light::Directional {
+                        direction: direction.normalize(),
+                        color,
+                        intensity,
+                    })
+                }
+                Some("dome") => {
+                    check_fields(light, DOME_LIGHT_FIELDS, "light", warnings);
+                    let portals = list(light, "portals")?
+                        .iter()
+                        .map(|portal| {
+                            check_fields(portal, PORTAL_FIELDS, "portal", warnings);
+                            Ok(Portal {
+                                corner: point(portal, "corner")?,
+                                u: vec3(portal, "u", Vector3::new(0.0, 0.0, 0.0))?,
+                                v: vec3(portal, "v", Vector3::new(0.0, 0.0, 0.0))?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>, String>>()?;
+                    let gradient = ["zenith", "horizon", "ground"]
+                        .iter()
+                        .any(|key| light.get(key).is_some());
+                    let environment = match light.get("texture").and_then(Value::as_str) {
+                        Some(_) if gradient => {
+                            return Err("both a texture and a gradient".to_string())
+                        }
+                        Some(file) => {
+                            let path = resolver.resolve(file, dir)?;
+                            let to_map = environment::up_to_map(units.up());
+                            Some(Environment::map(path, to_map, resolver)?)
+                        }
+                        None if gradient => {
+                            let horizon = vec3(light, "horizon", white)?;
+                            Some(Environment::Gradient {
+                                up: units.up(),
+                                zenith: vec3(light, "zenith", horizon)?,
+                                horizon,
+                                ground: vec3(light, "ground", horizon)?,
+                            })
+                        }
+                        None => None,
+                    };
+                    Ok(Light::Dome {
+                        color,
+                        intensity,
+                        portals,
+                        environment,
+                    })
+                }
+                Some(other) => Err(format!("unknown type {}", other)),
+                None => Err("no type".to_string()),
+            }
+        };
+        let light = read(&mut stage.warnings).map_err(|e| format!("light {}: {}", i + 1, e))?;
+        stage.scene.lights.push(light);
+    }
+    Ok(stage)
+}
+
+// The JSON in the file at `path`
+fn read(path: &Path) -> Result<Value, String> {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&src).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Loads the material written in the JSON file at `path`, an object of
+/// the fields materials take in scene files, and any warnings about it.
+pub fn load_material(path: &Path) -> Result<(Material, Vec<String>), String> {
+    let root = read(path)?;
+    let mut warnings = Vec::new();
+    let material = parse_material(&root, &[], &mut warnings)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok((material, warnings))
+}
+
+/// Loads the cameras, spheres, models, materials and lights of the JSON
+/// scene file at `path` into a scene using `units`.
+pub fn load(path: &Path, units: &Units, resolver: &AssetResolver) -> Result<Stage, String> {
+    let root = read(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    build(root, dir, units, resolver).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use resolve::scratch_dir;
+
+    fn load_text(name: &str, text: &str) -> Result<Stage, String> {
+        let path = scratch_dir(name).join("scene.json");
+        fs::write(&path, text).unwrap();
+        load(&path, &Units::default(), &AssetResolver::new(Vec::new()))
+    }
+
+    #[test]
+    fn scene_loads() {
+        let stage = load_text(
+            "json-scene",
+            r#"{
+              "version": 1,
+              "materials": { "red": { "albedo": [0.9, 0.1, 0.1] } },
+              "cameras": [ { "name": "main", "position": [0, 1, 5] } ],
+              "spheres": [ { "center": [0, 1, 0], "radius": 2, "material": "red" } ],
+              "boxes": [ { "min": [1, 1, 1], "max": [-1, 0, -1] } ],
+              "lights": [ { "type": "point", "position": [0, 4, 0], "intensity": 5 } ]
+            }"#,
+        )
+        .unwrap();
+        assert!(stage.warnings.is_empty());
+        let spheres: Vec<&Sphere> = stage.scene.spheres().collect();
+        assert_eq!(spheres.len(), 1);
+        assert_eq!(spheres[0].radius, 2.0);
+        assert_eq!(spheres[0].material.albedo, Vector3::new(0.9, 0.1, 0.1));
+        assert_eq!(stage.scene.primitives.len(), 2);
+        assert_eq!(stage.scene.lights.len(), 1);
+        assert_eq!(stage.cameras[0].0, "main");
+        assert_eq!(stage.cameras[0].1.position, Point3::new(0.0, 1.0, 5.0));
+        assert_eq!(stage.materials[0].0, "red");
+    }
+
+    #[test]
+    fn unversioned_and_misspelled_scenes_load_with_warnings() {
+        let text = r#"{ "spheres": [ { "radius": 1, "colour": 2 } ] }"#;
+        let stage = load_text("json-warnings", text);
+        assert_eq!(
+            stage.unwrap().warnings,
+            [
+                "the scene has no version; reading it as version 1",
+                "skipping unknown field colour of sphere"
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_scenes_are_refused() {
+        let deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+        let scenes = [
+            r#"{ "spheres": [ } "#,
+            r#"{ "version": 2 }"#,
+            r#"{ "version": 1.5 }"#,
+            r#"{ "spheres": [ { "radius": 0 } ] }"#,
+            r#"{ "spheres": [ { "radius": -1 } ] }"#,
+            r#"{ "spheres": [ { "material": "missing" } ] }"#,
+            r#"{ "lights": [ { "type": "spot" } ] }"#,
+            r#"{ "models": [ { "file": "missing.obj" } ] }"#,
+            &deep,
+        ];
+        for (i, text) in scenes.iter().enumerate() {
+            assert!(load_text(&format!("json-malformed-{}", i), text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+    }
+
+    #[test]
+    fn escapes_decode() {
+        let string = |text| parse(text).map(|v| v.as_str().map(str::to_string));
+        assert_eq!(string(r#""a\n\u00e9""#), Ok(Some("a\n\u{e9}".to_string())));
+        assert_eq!(string(r#""\ud83d\ude00""#), Ok(Some("\u{1f600}".to_string())));
+        assert!(string(r#""\ud83d""#).is_err());
+        assert!(string(r#""\ud83dA""#).is_err());
+        assert!(string(r#""\ude00""#).is_err());
+    }
+}
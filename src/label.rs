@@ -0,0 +1,284 @@
+//! Text labels, for annotating scenes in demonstrations: a line of text on
+//! a billboard that turns to face whatever looks at it, so it reads the
+//! right way round from any camera.
+//!
+//! The text is rasterized once, when the label is made, from a TrueType
+//! font or, without one, the burn-in's bitmap font, into a coverage map
+//! the billboard is cut out by. A ray meets the billboard on the plane
+//! through its centre facing the ray's origin, kept upright along the
+//! scene's up axis. Labels are drawn in their own colour whatever lights
+//! the scene, and cast no shadows, so they annotate the scene without
+//! changing how it looks.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use rusttype::{self, Font, Scale};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clip;
+use geometry::Ray;
+use hud;
+use material::Material;
+use primitive::{Hit, Intersectable};
+use render::{Color, RenderOptions};
+
+// Rows of coverage a line of TrueType text is rasterized into
+const PIXELS_PER_LINE: f32 = 64.0;
+
+// Coverage from which a point counts as part of the text
+const INK: f32 = 0.5;
+
+/// How much of each pixel of a rasterized line of text is covered, from 0
+/// to 1, row by row from the top.
+pub struct Coverage {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(width: usize, height: usize) -> Coverage {
+        Coverage {
+            width,
+            height,
+            values: vec![0.0; width * height],
+        }
+    }
+
+    // Whether the text covers the point `u` across and `v` down the map,
+    // each from 0 to 1
+    fn inked(&self, u: f32, v: f32) -> bool {
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return false;
+        }
+        let x = (u * self.width as f32) as usize;
+        let y = (v * self.height as f32) as usize;
+        self.values[y * self.width + x] >= INK
+    }
+}
+
+pub struct Label {
+    pub text: String,
+    // File the text's font was read from; the bitmap font without one
+    pub font: Option<PathBuf>,
+    pub center: Point3<f32>,
+    // Height of the line of text, in scene units
+    pub height: f32,
+    // Direction the text stands up along, the scene's up axis
+    pub up: Vector3<f32>,
+    pub material: Material,
+    pub coverage: Arc<Coverage>,
+}
+
+impl Label {
+    /// A label reading `text`, `height` tall, centred on `center` and drawn
+    /// in `color`, set in `font` if given, read from the file it names.
+    pub fn new(
+        text: &str,
+        font: Option<(&Font<'static>, &Path)>,
+        center: Point3<f32>,
+        height: f32,
+        up: Vector3<f32>,
+        color: Color,
+    ) -> Result<Label, String> {
+        if text.trim().is_empty() {
+            return Err("no text".to_string());
+        }
+        let coverage = match font {
+            Some((font, _)) => rasterize(text, font),
+            None => bitmap(text),
+        };
+        if coverage.width == 0 || coverage.height == 0 {
+            return Err(format!("\"{}\" has no glyphs in its font", text));
+        }
+        Ok(Label {
+            text: text.to_string(),
+            font: font.map(|f| f.1.to_path_buf()),
+            center,
+            height,
+            up,
+            material: Material {
+                albedo: Vector3::new(0.0, 0.0, 0.0),
+                specular: 0.0,
+                emissive: color,
+                ..Material::default()
+            },
+            coverage: Arc::new(coverage),
+        })
+    }
+
+    /// Width of the line of text, in scene units.
+    pub fn width(&self) -> f32 {
+        self.height * self.coverage.width as f32 / self.coverage.height as f32
+    }
+
+    // Unit vectors across the text, up it and out of it towards `eye`, or
+    // None if `eye` is on the label's centre
+    fn frame(&self, eye: Point3<f32>) -> Option<(Vector3<f32>, Vector3<f32>, Vector3<f32>)> {
+        let facing = eye - self.center;
+        if facing.magnitude2() == 0.0 {
+            return None;
+        }
+        let facing = facing.normalize();
+        let mut right = self.up.cross(facing);
+        // Seen from straight above or below the text can't stand upright,
+        // so it lies along whichever axis is furthest from the view
+        if right.magnitude2() < 1e-12 {
+            let axis = if facing.x.abs() < 0.5 { Vector3::unit_x() } else { Vector3::unit_z() };
+            right = axis - facing * axis.dot(facing);
+        }
+        let right = right.normalize();
+        Some((right, facing.cross(right), facing))
+    }
+}
+
+impl Intersectable for Label {
+    fn intersect(&self, ray: &Ray, render_options: &RenderOptions) -> Option<Hit> {
+        let (right, up, facing) = self.frame(ray.origin)?;
+        let distance = (self.center - ray.origin).dot(facing) / ray.direction.dot(facing);
+        if distance < 0.0 || !distance.is_finite() {
+            return None;
+        }
+        let point = ray.origin + ray.direction * distance;
+        let offset = point - self.center;
+        let u = offset.dot(right) / self.width() + 0.5;
+        let v = 0.5 - offset.dot(up) / self.height;
+        if !self.coverage.inked(u, v) {
+            return None;
+        }
+        if render_options.clipping && !clip::keeps(&render_options.clip_planes, point) {
+            return None;
+        }
+        Some(Hit {
+            distance,
+            part: 0,
+            cap: None,
+        })
+    }
+
+    fn occludes(&self, _ray: &Ray, _max_distance: f32, _render_options: &RenderOptions) -> bool {
+        false
+    }
+
+    // A label turns to face each ray, so it has no one normal. It's lit by
+    // nothing but itself, which shades it alike whatever the normal, so the
+    // up axis stands in
+    fn normal(&self, _point: Point3<f32>, _part: usize) -> Vector3<f32> {
+        self.up
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn two_sided(&self) -> bool {
+        true
+    }
+
+    // Whichever way it faces, the label stays within its half diagonal of
+    // its centre
+    fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        let reach = 0.5 * self.width().hypot(self.height);
+        let reach = Vector3::new(reach, reach, reach);
+        (self.center + -reach, self.center + reach)
+    }
+}
+
+/// The TrueType font in the file at `path`.
+pub fn load_font(path: &Path) -> Result<Font<'static>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Font::try_from_vec(bytes).ok_or_else(|| format!("{}: not a TrueType font", path.display()))
+}
+
+// `text` set in `font`, `PIXELS_PER_LINE` tall from its ascent to its
+// descent
+fn rasterize(text: &str, font: &Font) -> Coverage {
+    let scale = Scale::uniform(PIXELS_PER_LINE);
+    let metrics = font.v_metrics(scale);
+    let start = rusttype::point(0.0, metrics.ascent);
+    let glyphs: Vec<_> = font.layout(text, scale, start).collect();
+    let width = glyphs
+        .last()
+        .map_or(0.0, |g| g.position().x + g.unpositioned().h_metrics().advance_width);
+    let height = (metrics.ascent - metrics.descent).ceil();
+    let mut coverage = Coverage::new(width.ceil() as usize, height as usize);
+    for glyph in &glyphs {
+        let bounds = match glyph.pixel_bounding_box() {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        glyph.draw(|x, y, value| {
+            let x = bounds.min.x + x as i32;
+            let y = bounds.min.y + y as i32;
+            if x >= 0 && y >= 0 && (x as usize) < coverage.width && (y as usize) < coverage.height
+            {
+                let i = y as usize * coverage.width + x as usize;
+                coverage.values[i] = coverage.values[i].max(value);
+            }
+        });
+    }
+    coverage
+}
+
+// `text` in the burn-in's bitmap font, a font pixel to a pixel, with a
+// column between characters
+fn bitmap(text: &str) -> Coverage {
+    let (glyph_width, glyph_height) = (hud::GLYPH_WIDTH as usize, hud::GLYPH_HEIGHT as usize);
+    let advance = glyph_width + 1;
+    let mut coverage = Coverage::new(text.chars().count() * advance - 1, glyph_height);
+    for (column, c) in text.chars().enumerate() {
+        for (y, bits) in hud::glyph(c).iter().enumerate() {
+            for x in 0..glyph_width {
+                if bits & (1 << (glyph_width - 1 - x)) != 0 {
+                    coverage.values[y * coverage.width + column * advance + x] = 1.0;
+                }
+            }
+        }
+    }
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ray(origin: Point3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray { origin, direction }
+    }
+
+    #[test]
+    fn faces_rays_from_every_side() {
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let center = Point3::new(1.0, 2.0, 3.0);
+        let label = Label::new("I", None, center, 1.0, up, Vector3::new(1.0, 1.0, 1.0)).unwrap();
+        let options = RenderOptions::default();
+        // The stem of the I is inked straight through the middle, whichever
+        // way the label turns to face
+        let sides = [
+            Vector3::new(0.0, 0.0, 4.0),
+            Vector3::new(-4.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, -4.0),
+            Vector3::new(3.0, 1.0, 2.0),
+            Vector3::new(0.0, 4.0, 0.0),
+        ];
+        for &side in &sides {
+            let hit = label.intersect(&ray(center + side, -side), &options);
+            let distance = hit.expect("label missed").distance;
+            assert!((distance - 1.0).abs() < 1e-5, "hit at {} from {:?}", distance, side);
+            // Beside the stem, and beyond the end of the line, are clear
+            let (right, _, _) = label.frame(center + side).unwrap();
+            let beside = ray(center + side, -side + right * 0.3);
+            assert!(label.intersect(&beside, &options).is_none());
+            assert!(label.intersect(&ray(center + side, side), &options).is_none());
+        }
+        let shadow = ray(center + sides[0], -sides[0]);
+        assert!(!label.occludes(&shadow, f32::INFINITY, &options));
+    }
+
+    #[test]
+    fn needs_text() {
+        let (center, up) = (Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        assert!(Label::new(" ", None, center, 1.0, up, up).is_err());
+    }
+}
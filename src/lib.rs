@@ -0,0 +1,109 @@
+//! A ray tracer for spheres, meshes and particles, rendering scenes loaded
+//! from USD, pbrt, glTF or JSON files into images or an interactive window.
+//!
+//! Embedding the tracer takes a `Scene`, built in, loaded from a file or
+//! made in memory from spheres such as those `procedural` scatters, a
+//! `Camera` and `RenderOptions`. `render_frame` renders a whole frame into
+//! an image; `render_rgba8` and `render_rgba32f` render bands of rows into
+//! buffers the caller owns.
+
+extern crate cgmath;
+extern crate ctrlc;
+extern crate image as im;
+#[cfg(unix)]
+extern crate libc;
+extern crate piston_window;
+extern crate png;
+extern crate rand;
+extern crate rayon;
+extern crate rusttype;
+extern crate tiff;
+
+mod accumulate;
+mod api;
+mod audio;
+mod bake;
+mod batch;
+mod bench;
+mod builtin;
+mod bvh;
+mod cache;
+mod camera;
+mod caustics;
+mod chi_squared;
+mod checkpoint;
+pub mod cli;
+mod clip;
+mod color;
+mod components;
+mod convert;
+mod cuboid;
+mod dither;
+mod display;
+pub mod edit;
+mod embed;
+mod environment;
+mod fly;
+mod furnace;
+pub mod geometry;
+mod gltf;
+pub mod handle;
+mod heatmap;
+mod hud;
+mod ies;
+mod interrupt;
+mod json;
+mod label;
+mod light;
+mod lod;
+mod mapped;
+pub mod material;
+mod measure;
+mod mesh;
+mod motion;
+mod obj;
+mod osc;
+mod output;
+mod packet;
+mod panorama;
+mod particles;
+mod path;
+mod pbrt;
+mod primitive;
+mod probe;
+pub mod procedural;
+mod progress;
+mod reference;
+mod reload;
+mod render;
+mod report;
+mod resolution;
+mod resolve;
+mod sampling;
+mod scene;
+mod scenes;
+mod schema;
+mod shading_cache;
+mod shake;
+mod sheet;
+mod snapshot;
+mod stats;
+mod stereo;
+mod stream;
+mod texture;
+mod tiles;
+mod timeline;
+mod tutorial;
+mod usd;
+mod watch;
+mod wavefront;
+
+pub use camera::Camera;
+pub use embed::{render_rgba32f, render_rgba8};
+pub use geometry::Sphere;
+pub use light::{Light, Portal};
+pub use material::Material;
+pub use mesh::Mesh;
+pub use primitive::Primitive;
+pub use render::{render_frame, RenderOptions};
+pub use scene::Scene;
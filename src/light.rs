@@ -0,0 +1,484 @@
+//! Light sources, and the light each sends to a point.
+//!
+//! Intensities can be authored in physical units. Those are converted so
+//! that a white surface renders at its illuminance in lux: point lights
+//! hold candela per square scene unit, directional lights lux and dome
+//! lights nits.
+//! Intensities without units are used as they are.
+
+use cgmath::{ElementWise, InnerSpace, Point3, Vector3};
+use std::f32::{self, consts::PI};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use environment::Environment;
+use ies::Profile;
+use render::Color;
+use sampling;
+
+// Lumens per watt of light at 555 nm, where the eye is most sensitive
+const LUMINOUS_EFFICACY: f32 = 683.0;
+
+// Distance, in edge lengths, outside a portal that a ray still counts as
+// passing through it, so that rounding can't turn away the directions
+// sampled at its very edges
+const PORTAL_EDGE_TOLERANCE: f32 = 1e-5;
+
+#[derive(Clone, Debug)]
+pub enum Light {
+    // Emits from `position`, falling off with the square of the distance;
+    // equally in every direction unless shaped by a photometric profile
+    Point {
+        position: Point3<f32>,
+        color: Color,
+        intensity: f32,
+        shaping: Option<Shaping>,
+    },
+    // Infinitely far away, shining along `direction` with no falloff
+    Directional {
+        direction: Vector3<f32>,
+        color: Color,
+        intensity: f32,
+    },
+    // Radiance from every direction, like a sky: even, like an overcast
+    // one, unless `environment` varies it; sampled through `portals` at
+    // points behind them, otherwise over the whole hemisphere
+    Dome {
+        color: Color,
+        intensity: f32,
+        portals: Vec<Portal>,
+        environment: Option<Environment>,
+    },
+}
+
+/// An opening through which a dome light reaches the inside of a room, such
+/// as a window. Interiors lit through small openings are mostly walls when
+/// seen from inside, so sampling a dome light over the whole hemisphere
+/// wastes nearly every sample on directions the sky can't be seen in;
+/// sampling its portals only aims at directions it can.
+///
+/// A portal lets light in against `u` x `v`, as a USD `PortalLight` lets
+/// it in along its -Z, so the room is the side behind it. Only points
+/// behind every portal are sampled through them; anywhere else the dome
+/// light is sampled as it would be without portals, since the sky isn't
+/// only seen through them from there.
+#[derive(Clone, Debug)]
+pub struct Portal {
+    // A parallelogram: one corner and the two edges leaving it
+    pub corner: Point3<f32>,
+    pub u: Vector3<f32>,
+    pub v: Vector3<f32>,
+}
+
+impl Portal {
+    /// Whether `point` is on the side of the portal's plane light comes
+    /// in to.
+    pub fn behind(&self, point: Point3<f32>) -> bool {
+        (point - self.corner).dot(self.u.cross(self.v)) < 0.0
+    }
+
+    /// Distance along the ray from `point` along unit `direction` to where
+    /// it passes through the portal, if it does.
+    pub fn crossing(&self, point: Point3<f32>, direction: Vector3<f32>) -> Option<f32> {
+        let across = self.u.cross(self.v);
+        let facing = direction.dot(across);
+        if facing == 0.0 {
+            return None;
+        }
+        let distance = (self.corner - point).dot(across) / facing;
+        if distance <= 0.0 {
+            return None;
+        }
+        // Where the ray meets the portal's plane, in edge coordinates
+        let offset = point + direction * distance - self.corner;
+        let (u, v) = (self.u, self.v);
+        let (uu, uv, vv) = (u.dot(u), u.dot(v), v.dot(v));
+        let (ou, ov) = (offset.dot(u), offset.dot(v));
+        let det = uu * vv - uv * uv;
+        let a = (ou * vv - ov * uv) / det;
+        let b = (ov * uu - ou * uv) / det;
+        let span = -PORTAL_EDGE_TOLERANCE..=1.0 + PORTAL_EDGE_TOLERANCE;
+        if span.contains(&a) && span.contains(&b) {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+}
+
+/// Units a light's intensity can be given in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightUnit {
+    // Luminous intensity, lumens per steradian
+    Candela,
+    // Luminance, candela per square metre
+    Nits,
+    // Luminous flux, summed over every direction
+    Lumens,
+    // Radiant flux, converted at `LUMINOUS_EFFICACY`
+    Watts,
+    // Illuminance, lumens per square metre
+    Lux,
+    // Irradiance, converted at `LUMINOUS_EFFICACY`
+    WattsPerSquareMetre,
+}
+
+impl LightUnit {
+    pub fn from_name(name: &str) -> Option<LightUnit> {
+        match name {
+            "candela" | "cd" => Some(LightUnit::Candela),
+            "nits" | "cd/m2" => Some(LightUnit::Nits),
+            "lumens" | "lm" => Some(LightUnit::Lumens),
+            "watts" | "W" => Some(LightUnit::Watts),
+            "lux" | "lx" => Some(LightUnit::Lux),
+            "W/m2" => Some(LightUnit::WattsPerSquareMetre),
+            _ => None,
+        }
+    }
+
+    /// `value` in this unit as a point light's intensity in a scene of
+    /// `meters_per_unit`.
+    pub fn point_intensity(self, value: f32, meters_per_unit: f32) -> Result<f32, String> {
+        let candela = match self {
+            LightUnit::Candela => value,
+            // Emitted evenly over the sphere's 4 pi steradians
+            LightUnit::Lumens => value / (4.0 * PI),
+            LightUnit::Watts => value * LUMINOUS_EFFICACY / (4.0 * PI),
+            _ => return Err(format!("{:?} is not a unit of point light intensity", self)),
+        };
+        // Falloff is over scene units, so per square metre becomes per
+        // square scene unit
+        Ok(candela / (meters_per_unit * meters_per_unit))
+    }
+
+    /// `value` in this unit as the luminance of a dome light, or of an area
+    /// light's surface. A white surface under a dome of L nits receives
+    /// pi L lux.
+    pub fn dome_intensity(self, value: f32) -> Result<f32, String> {
+        match self {
+            LightUnit::Nits => Ok(value),
+            _ => Err(format!("{:?} is not a unit of luminance", self)),
+        }
+    }
+
+    /// `value` in this unit as a directional light's illuminance in lux.
+    pub fn directional_intensity(self, value: f32) -> Result<f32, String> {
+        match self {
+            LightUnit::Lux => Ok(value),
+            LightUnit::WattsPerSquareMetre => Ok(value * LUMINOUS_EFFICACY),
+            _ => Err(format!("{:?} is not a unit of directional light intensity", self)),
+        }
+    }
+}
+
+/// `color` scaled to a luminance of 1, so a light given in physical units
+/// keeps its stated brightness whatever its colour. Black is left alone.
+pub fn unit_luminance(color: Color) -> Color {
+    // Rec. 709 luminance weights
+    let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+    if luminance > 0.0 {
+        color / luminance
+    } else {
+        color
+    }
+}
+
+/// Stops of exposure for a camera set to `ev100`, the exposure value at ISO
+/// 100, so that lux-valued renders come out as a real camera would see
+/// them. EV100 15 suits sunlight, about 100000 lux.
+pub fn ev100_exposure(ev100: f32) -> f32 {
+    // A white surface at E lux has a luminance of E / pi, and a saturating
+    // luminance of 1.2 x 2^EV100 is the usual sensor model ("Moving Frostbite
+    // to Physically Based Rendering", Lagarde and de Rousiers)
+    -(ev100 + (1.2 * PI).log2())
+}
+
+/// A photometric profile aimed in the scene. The light's intensity is its
+/// brightest, in the profile's brightest direction.
+#[derive(Clone, Debug)]
+pub struct Shaping {
+    pub profile: Arc<Profile>,
+    // The IES file the profile was read from, and when it was modified as
+    // it was read, so the profile can be read again when the file changes
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+    // Unit directions of the profile's nadir and of horizontal angle 0,
+    // perpendicular to each other
+    pub down: Vector3<f32>,
+    pub across: Vector3<f32>,
+}
+
+impl Shaping {
+    /// Fraction of the light's intensity sent along unit `direction`.
+    fn scale(&self, direction: Vector3<f32>) -> f32 {
+        let vertical = direction.dot(self.down).clamp(-1.0, 1.0).acos();
+        // Horizontal angles turn anticlockwise seen from above
+        let side = self.across.cross(self.down);
+        let horizontal = direction.dot(side).atan2(direction.dot(self.across));
+        self.profile
+            .relative(vertical.to_degrees(), horizontal.to_degrees())
+    }
+}
+
+/// Light arriving at a point from one source, or for lights sampled in
+/// several directions, one sample's estimate of all of it.
+pub struct Incident {
+    // Unit vector from the point towards the light
+    pub direction: Vector3<f32>,
+    // Distance to the light, infinite for directional and dome lights
+    pub distance: f32,
+    pub color: Color,
+}
+
+impl Light {
+    fn intensity(&self) -> f32 {
+        match *self {
+            Light::Point { intensity, .. }
+            | Light::Directional { intensity, .. }
+            | Light::Dome { intensity, .. } => intensity,
+        }
+    }
+
+    /// The same light with its intensity set to `value`.
+    pub fn with_intensity(&self, value: f32) -> Light {
+        let mut light = self.clone();
+        match light {
+            Light::Point {
+                ref mut intensity, ..
+            }
+            | Light::Directional {
+                ref mut intensity, ..
+            }
+            | Light::Dome {
+                ref mut intensity, ..
+            } => *intensity = value,
+        }
+        light
+    }
+
+    /// The same light with its intensity multiplied by `factor`.
+    pub fn scaled(&self, factor: f32) -> Light {
+        self.with_intensity(self.intensity() * factor)
+    }
+
+    /// Whether the light reaches any point from a single direction.
+    pub fn is_singular(&self) -> bool {
+        match *self {
+            Light::Point { .. } | Light::Directional { .. } => true,
+            Light::Dome { .. } => false,
+        }
+    }
+
+    /// Samples to take of the light arriving at a point: one for singular
+    /// lights, else `area_samples`.
+    pub fn sample_count(&self, area_samples: u32) -> u32 {
+        if self.is_singular() {
+            1
+        } else {
+            area_samples
+        }
+    }
+
+    /// What a ray that escapes the scene along `direction` sees of the
+    /// light. Dome lights show the illuminance they give a white surface
+    /// facing any way, so a mirror passes on as much of the sky as a matte
+    /// surface under it; an even sky, that is, and a varying one in
+    /// proportion.
+    pub fn background(&self, direction: Vector3<f32>) -> Color {
+        match *self {
+            Light::Dome { .. } => self.dome_radiance(direction) * PI,
+            Light::Point { .. } | Light::Directional { .. } => Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // The portals a dome light is sampled through at `point`: all of them
+    // if it's behind every one, and none otherwise
+    fn portals_guiding(&self, point: Point3<f32>) -> &[Portal] {
+        match *self {
+            Light::Dome { ref portals, .. } if portals.iter().all(|p| p.behind(point)) => portals,
+            _ => &[],
+        }
+    }
+
+    // Radiance a dome light sends the scene from `direction`
+    fn dome_radiance(&self, direction: Vector3<f32>) -> Color {
+        match *self {
+            Light::Dome {
+                color,
+                intensity,
+                ref environment,
+                ..
+            } => match *environment {
+                Some(ref environment) => {
+                    (color * intensity).mul_element_wise(environment.radiance(direction))
+                }
+                None => color * intensity,
+            },
+            Light::Point { .. } | Light::Directional { .. } => Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Light arriving at `point`, on a surface facing unit `normal`, in the
+    /// direction `sample` picks. For lights with more than one sample,
+    /// averaging `sample_count` of these over the unit square estimates the
+    /// total.
+    pub fn incident(
+        &self,
+        point: Point3<f32>,
+        normal: Vector3<f32>,
+        sample: (f32, f32),
+    ) -> Incident {
+        match *self {
+            Light::Point {
+                position,
+                color,
+                intensity,
+                ref shaping,
+            } => {
+                let to_light = position - point;
+                let distance_squared = to_light.magnitude2();
+                let distance = distance_squared.sqrt();
+                let direction = to_light / distance;
+                let shape = shaping.as_ref().map_or(1.0, |s| s.scale(-direction));
+                Incident {
+                    direction,
+                    distance,
+                    color: color * (intensity * shape / distance_squared),
+                }
+            }
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => Incident {
+                direction: -direction,
+                distance: f32::INFINITY,
+                color: color * intensity,
+            },
+            Light::Dome { .. } => {
+                let portals = self.portals_guiding(point);
+                if portals.is_empty() {
+                    // Divided by the density of 1 / 2 pi
+                    let direction = sampling::uniform_hemisphere(normal, sample);
+                    return Incident {
+                        direction,
+                        distance: f32::INFINITY,
+                        color: self.dome_radiance(direction) * (2.0 * PI),
+                    };
+                }
+                // The first coordinate picks a portal and then a point along
+                // it, so samples spread over every portal evenly
+                let scaled = sample.0 * portals.len() as f32;
+                let index = (scaled as usize).min(portals.len() - 1);
+                let portal = &portals[index];
+                let along = scaled - index as f32;
+                let on_portal = portal.corner + portal.u * along + portal.v * sample.1;
+                let to_portal = on_portal - point;
+                let distance_squared = to_portal.magnitude2();
+                if distance_squared <= 0.0 {
+                    // On the portal itself; no direction to sample
+                    return Incident {
+                        direction: normal,
+                        distance: 0.0,
+                        color: Vector3::new(0.0, 0.0, 0.0),
+                    };
+                }
+                let direction = to_portal / distance_squared.sqrt();
+                // Density per steradian is distance^2 / (area cos), over the
+                // number of portals; area cos is |direction . (u x v)|
+                let projected = direction.dot(portal.u.cross(portal.v)).abs();
+                Incident {
+                    direction,
+                    distance: f32::INFINITY,
+                    color: self.dome_radiance(direction)
+                        * (projected * portals.len() as f32 / distance_squared),
+                }
+            }
+        }
+    }
+
+    /// Radiance seen looking from `point` along unit `direction` straight
+    /// at the light, if nothing is in the way. Only dome lights can be seen
+    /// this way, and those with portals only through a portal.
+    pub fn seen(&self, point: Point3<f32>, direction: Vector3<f32>) -> Color {
+        match *self {
+            Light::Dome { .. } => {
+                let portals = self.portals_guiding(point);
+                let through = |portal: &Portal| portal.crossing(point, direction).is_some();
+                if portals.is_empty() || portals.iter().any(through) {
+                    self.dome_radiance(direction)
+                } else {
+                    Vector3::new(0.0, 0.0, 0.0)
+                }
+            }
+            Light::Point { .. } | Light::Directional { .. } => Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Density per steradian with which `incident` picks `direction` at
+    /// `point` on a surface facing unit `normal`. Singular lights pick a
+    /// single direction, which has no density, and give 0.
+    pub fn pdf(&self, point: Point3<f32>, normal: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        if let Light::Point { .. } | Light::Directional { .. } = *self {
+            return 0.0;
+        }
+        let portals = self.portals_guiding(point);
+        if portals.is_empty() {
+            return if direction.dot(normal) > 0.0 { 1.0 / (2.0 * PI) } else { 0.0 };
+        }
+        let mut sum = 0.0;
+        for portal in portals {
+            if let Some(distance) = portal.crossing(point, direction) {
+                let projected = direction.dot(portal.u.cross(portal.v)).abs();
+                sum += distance * distance / projected;
+            }
+        }
+        sum / portals.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dome(portals: Vec<Portal>) -> Light {
+        Light::Dome {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            portals,
+            environment: None,
+        }
+    }
+
+    #[test]
+    fn portals_only_guide_points_behind_them() {
+        // A window in the wall x = 0 letting light in towards -x
+        let window = Portal {
+            corner: Point3::new(0.0, 1.0, -1.0),
+            u: Vector3::new(0.0, 1.0, 0.0),
+            v: Vector3::new(0.0, 0.0, 2.0),
+        };
+        let (guided, unguided) = (dome(vec![window]), dome(Vec::new()));
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        let sample = (0.3, 0.7);
+
+        let inside = Point3::new(-2.0, 0.0, 0.0);
+        let incident = guided.incident(inside, normal, sample);
+        assert!(incident.direction.x > 0.0, "sampled away from the window");
+        let outward = Vector3::new(1.0, 1.0, 0.0).normalize();
+        assert!(guided.pdf(inside, normal, outward) > 0.0);
+        assert_eq!(guided.pdf(inside, normal, normal), 0.0);
+        assert_eq!(guided.seen(inside, normal), Vector3::new(0.0, 0.0, 0.0));
+
+        // Outside, and beside the window's plane, the sky is sampled as if
+        // there were no window
+        for &outside in &[Point3::new(2.0, 0.0, 0.0), Point3::new(0.0, 0.0, 5.0)] {
+            let a = guided.incident(outside, normal, sample);
+            let b = unguided.incident(outside, normal, sample);
+            assert_eq!((a.direction, a.distance, a.color), (b.direction, b.distance, b.color));
+            assert_eq!(guided.pdf(outside, normal, normal), unguided.pdf(outside, normal, normal));
+            assert_eq!(guided.seen(outside, normal), unguided.seen(outside, normal));
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Level of detail for scenes made of many small spheres.
+//!
+//! Small spheres are grouped into clusters on a coarse grid. Each cluster
+//! keeps a bounding sphere that doubles as its proxy: once the cluster
+//! covers fewer than `RenderOptions::lod_pixels` pixels on screen the proxy
+//! is intersected instead of its members, and otherwise it still culls rays
+//! that miss the whole group.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use std::collections::HashMap;
+use std::slice;
+
+use camera::Camera;
+use geometry::{Ray, Sphere};
+use handle::Handle;
+use material::Material;
+use primitive::Primitive;
+use render::RenderOptions;
+use scene::Scene;
+
+// Grid cells are sized so an evenly spread scene puts about this many
+// spheres in each
+const TARGET_CLUSTER_SIZE: f32 = 32.0;
+// Spheres larger than this fraction of a cell are left unclustered
+const SMALL_SPHERE_FRACTION: f32 = 0.25;
+const MIN_CLUSTER_SIZE: usize = 8;
+
+pub struct SphereCluster {
+    spheres: Vec<Sphere>,
+    proxy: Sphere,
+    use_proxy: bool,
+}
+
+impl SphereCluster {
+    fn new(spheres: Vec<Sphere>) -> SphereCluster {
+        let sum = spheres
+            .iter()
+            .fold(Point3::new(0.0, 0.0, 0.0), |sum, s| sum + s.center.to_vec());
+        let center = sum / spheres.len() as f32;
+        let radius = spheres
+            .iter()
+            .map(|s| (s.center - center).magnitude() + s.radius)
+            .fold(0.0, f32::max);
+        // Seen from far enough to use the proxy, members blend into their
+        // average colour
+        let albedo = spheres
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, s| sum + s.material.albedo)
+            / spheres.len() as f32;
+        SphereCluster {
+            spheres,
+            proxy: Sphere {
+                center,
+                radius,
+                material: Material::diffuse(albedo),
+            },
+            use_proxy: false,
+        }
+    }
+
+    fn bounds_hit(&self, ray: &Ray) -> bool {
+        let l = self.proxy.center - ray.origin;
+        let radius_squared = self.proxy.radius * self.proxy.radius;
+        if l.dot(l) <= radius_squared {
+            return true;
+        }
+        let tca = l.dot(ray.direction);
+        tca >= 0.0 && l.dot(l) - tca * tca <= radius_squared
+    }
+
+    /// Sphere enclosing every member of the cluster.
+    pub fn bounds(&self) -> &Sphere {
+        &self.proxy
+    }
+
+    /// Every member of the cluster.
+    pub fn spheres(&self) -> &[Sphere] {
+        &self.spheres
+    }
+
+    /// Spheres `ray` has to be tested against for this cluster.
+    pub fn candidates(&self, ray: &Ray) -> &[Sphere] {
+        if self.use_proxy {
+            slice::from_ref(&self.proxy)
+        } else if self.bounds_hit(ray) {
+            &self.spheres
+        } else {
+            &[]
+        }
+    }
+}
+
+/// Moves small spheres that share a grid cell with enough others out of
+/// `scene.primitives` and into clusters.
+pub fn build_clusters(scene: &mut Scene) {
+    let count = scene.spheres().count();
+    if count < MIN_CLUSTER_SIZE {
+        return;
+    }
+    let first = match scene.spheres().next() {
+        Some(sphere) => sphere.center,
+        None => return,
+    };
+    let (min, max) = scene.spheres().fold((first, first), |(min, max), s| {
+        (
+            Point3::new(min.x.min(s.center.x), min.y.min(s.center.y), min.z.min(s.center.z)),
+            Point3::new(max.x.max(s.center.x), max.y.max(s.center.y), max.z.max(s.center.z)),
+        )
+    });
+    let extent = max - min;
+    let cell_fraction = (TARGET_CLUSTER_SIZE / count as f32).cbrt();
+    let cell_size = extent.x.max(extent.y).max(extent.z) * cell_fraction;
+    if !cell_size.is_normal() {
+        return;
+    }
+
+    let mut cells: HashMap<(i32, i32, i32), Vec<Handle<Primitive>>> = HashMap::new();
+    for (handle, primitive) in scene.primitives.entries() {
+        match *primitive {
+            Primitive::Sphere(ref sphere) if sphere.radius < cell_size * SMALL_SPHERE_FRACTION => {
+                let cell = (sphere.center - min) / cell_size;
+                let key = (cell.x as i32, cell.y as i32, cell.z as i32);
+                cells.entry(key).or_default().push(handle);
+            }
+            _ => {}
+        }
+    }
+    // Spheres left out keep their handles
+    for (_, handles) in cells {
+        if handles.len() >= MIN_CLUSTER_SIZE {
+            let spheres = handles
+                .into_iter()
+                .filter_map(|handle| match scene.remove_object(handle) {
+                    Some(Primitive::Sphere(sphere)) => Some(sphere),
+                    _ => None,
+                })
+                .collect();
+            scene.clusters.push(SphereCluster::new(spheres));
+        }
+    }
+}
+
+/// Chooses, for the coming frame, which clusters are small enough on screen
+/// to be drawn as their proxy.
+pub fn select_lod(scene: &mut Scene, camera: &Camera, render_options: &RenderOptions) {
+    let fov_scalar = (camera.fov.to_radians() / 2.0).tan();
+    for cluster in &mut scene.clusters {
+        let distance = (cluster.proxy.center - camera.position).magnitude();
+        let pixels =
+            cluster.proxy.radius * render_options.height as f32 / (distance * fov_scalar);
+        cluster.use_proxy = distance > cluster.proxy.radius && pixels < render_options.lod_pixels;
+    }
+}
@@ -0,0 +1,212 @@
+//! Read-only memory mappings of files, and buffers that are either owned or
+//! lie in one, so the meshes of a cached scene can be used straight out of
+//! its cache file.
+//!
+//! A mapped mesh isn't copied into the process at all: its pages are read
+//! in as rays first touch them, and every process rendering the same
+//! cached scene shares them with the others. Mappings are shared with the
+//! file, so the file must never be written in place or truncated while
+//! mapped, which is why mapping one is unsafe; the scene cache is always
+//! replaced whole with a rename instead.
+//!
+//! Buffers keep values as they are laid out in memory, so values are only
+//! mapped where that is how the file stores them: little-endian, and
+//! aligned. Anything else is copied. Changing a mapped buffer copies it
+//! first.
+
+use cgmath::{Point3, Vector3};
+#[cfg(unix)]
+use libc;
+#[cfg(unix)]
+use std::fs::File;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+#[cfg(unix)]
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+/// A file mapped into memory, read-only.
+pub struct Mapping {
+    ptr: *const u8,
+    len: usize,
+}
+
+// The mapping is never written, so it can be read from any thread
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Maps the whole file at `path`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written to or truncated, by this process or
+    /// any other, for as long as the mapping or any buffer lying in it is
+    /// alive. Changes made to it show through the mapping, breaking the
+    /// immutability of the slices it hands out, and reading past a
+    /// truncated end kills the process with `SIGBUS`. Replacing the file
+    /// by renaming another over it is fine, since the mapping keeps the
+    /// original.
+    #[cfg(unix)]
+    pub unsafe fn open(path: &Path) -> io::Result<Mapping> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty file"));
+        }
+        // The mapping outlives the file handle
+        let ptr = libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    /// Files can only be mapped on Unix; elsewhere they are read instead.
+    ///
+    /// # Safety
+    ///
+    /// As on Unix, so callers needn't tell the two apart.
+    #[cfg(not(unix))]
+    pub unsafe fn open(_path: &Path) -> io::Result<Mapping> {
+        Err(io::Error::new(io::ErrorKind::Other, "memory mapping is unsupported"))
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// Values that can be used as they lie in a mapped file.
+///
+/// # Safety
+///
+/// Every bit pattern must be one of the values, and they must hold only
+/// 32-bit words, with no padding.
+pub unsafe trait Plain: Copy {}
+
+unsafe impl Plain for f32 {}
+unsafe impl Plain for [u32; 3] {}
+unsafe impl Plain for Point3<f32> {}
+unsafe impl Plain for Vector3<f32> {}
+
+enum Storage<T> {
+    Owned(Vec<T>),
+    Mapped {
+        mapping: Arc<Mapping>,
+        offset: usize,
+        len: usize,
+        values: PhantomData<T>,
+    },
+}
+
+/// A buffer of values, owned or lying in a mapped file.
+pub struct Buffer<T: Plain> {
+    storage: Storage<T>,
+}
+
+impl<T: Plain> Buffer<T> {
+    /// The `len` values starting `offset` bytes into `mapping`, unless they
+    /// can't be used where they lie there.
+    pub fn mapped(mapping: &Arc<Mapping>, offset: usize, len: usize) -> Option<Buffer<T>> {
+        let end = len.checked_mul(mem::size_of::<T>())?.checked_add(offset)?;
+        let aligned = mapping.ptr.wrapping_add(offset).align_offset(mem::align_of::<T>()) == 0;
+        if !cfg!(target_endian = "little") || !aligned || end > mapping.len {
+            return None;
+        }
+        Some(Buffer {
+            storage: Storage::Mapped {
+                mapping: mapping.clone(),
+                offset,
+                len,
+                values: PhantomData,
+            },
+        })
+    }
+
+    /// The values to change in place, copied out of the mapping first if
+    /// they lie in one.
+    pub fn to_mut(&mut self) -> &mut Vec<T> {
+        if let Storage::Mapped { .. } = self.storage {
+            self.storage = Storage::Owned(self.to_vec());
+        }
+        match self.storage {
+            Storage::Owned(ref mut values) => values,
+            Storage::Mapped { .. } => unreachable!(),
+        }
+    }
+}
+
+impl<T: Plain> Deref for Buffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self.storage {
+            Storage::Owned(ref values) => values,
+            Storage::Mapped {
+                ref mapping,
+                offset,
+                len,
+                ..
+            } => unsafe { slice::from_raw_parts(mapping.ptr.add(offset) as *const T, len) },
+        }
+    }
+}
+
+// Mapped buffers share their mapping
+impl<T: Plain> Clone for Buffer<T> {
+    fn clone(&self) -> Buffer<T> {
+        let storage = match self.storage {
+            Storage::Owned(ref values) => Storage::Owned(values.clone()),
+            Storage::Mapped {
+                ref mapping,
+                offset,
+                len,
+                ..
+            } => Storage::Mapped {
+                mapping: mapping.clone(),
+                offset,
+                len,
+                values: PhantomData,
+            },
+        };
+        Buffer { storage }
+    }
+}
+
+impl<T: Plain> From<Vec<T>> for Buffer<T> {
+    fn from(values: Vec<T>) -> Buffer<T> {
+        Buffer {
+            storage: Storage::Owned(values),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Surface properties shapes are shaded with.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use render::Color;
+use texture::{Pattern, Texture};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    // Fraction of light the surface scatters diffusely, per channel
+    pub albedo: Color,
+    // Strength of highlights from lights, 0 to 1; the rest of the light
+    // the surface doesn't mirror or let through is scattered diffusely
+    pub specular: f32,
+    // Blinn-Phong exponent of highlights: the higher, the smaller and
+    // sharper they are
+    pub shininess: f32,
+    // Fraction of light mirrored, 0 to 1
+    pub reflectivity: f32,
+    // How far highlights and reflections spread, from 0 (polished) to 1
+    pub roughness: f32,
+    // Radiance given off by the surface itself, independent of any light
+    pub emissive: Color,
+    // Fraction of light that enters the surface, as through glass or
+    // water, rather than being reflected or scattered
+    pub transparency: f32,
+    // Index of refraction of whatever is inside the surface
+    pub ior: f32,
+    // Varies the albedo over the surface in place of `albedo`
+    pub texture: Option<Pattern>,
+}
+
+/// How light arriving at a surface divides between diffuse scattering,
+/// mirror reflection and refraction. The weights sum to 1.
+pub struct Split {
+    pub diffuse: f32,
+    pub reflected: f32,
+    pub refracted: f32,
+    // Unit direction of the refracted ray, when there is one
+    pub refraction: Vector3<f32>,
+}
+
+/// Blinn-Phong exponent whose highlights spread about as far as those of a
+/// microfacet surface of `roughness` ("Microfacet Models for Refraction
+/// through Rough Surfaces", Walter et al.), taking the microfacet width as
+/// the square of the roughness, as UsdPreviewSurface does.
+pub fn shininess(roughness: f32) -> f32 {
+    // Below this highlights are already too small to see get any smaller
+    let width = (roughness * roughness).clamp(0.01, 1.0);
+    2.0 / (width * width) - 2.0
+}
+
+impl Default for Material {
+    /// Matte white that gives off no light.
+    fn default() -> Material {
+        Material {
+            albedo: Vector3::new(1.0, 1.0, 1.0),
+            specular: 0.0,
+            shininess: 32.0,
+            reflectivity: 0.0,
+            roughness: 1.0,
+            emissive: Vector3::new(0.0, 0.0, 0.0),
+            transparency: 0.0,
+            // Glass
+            ior: 1.5,
+            texture: None,
+        }
+    }
+}
+
+impl Material {
+    /// Matte material of `albedo`.
+    pub fn diffuse(albedo: Color) -> Material {
+        Material {
+            albedo,
+            ..Material::default()
+        }
+    }
+
+    /// Albedo of the surface at `point` on it.
+    pub fn albedo_at(&self, point: Point3<f32>) -> Color {
+        match self.texture {
+            Some(ref texture) => texture.color(point),
+            None => self.albedo,
+        }
+    }
+
+    /// Blinn-Phong highlight of light arriving from unit `to_light`, seen
+    /// from unit `to_viewer`, as a multiple of what a white diffuse surface
+    /// would scatter towards the viewer. The lobe is normalized so that,
+    /// however shiny the surface, the highlight returns at most `specular`
+    /// of the light reaching it, all of it when seen straight on ("The
+    /// Blinn-Phong Normalization Zoo", Giesen).
+    pub fn highlight(
+        &self,
+        normal: Vector3<f32>,
+        to_light: Vector3<f32>,
+        to_viewer: Vector3<f32>,
+    ) -> f32 {
+        let half = to_light + to_viewer;
+        if self.specular <= 0.0 || half.magnitude2() <= 0.0 {
+            return 0.0;
+        }
+        let n = self.shininess;
+        let scale = (n + 2.0) * (n + 4.0) / (8.0 * (2f32.powf(-n / 2.0) + n));
+        let cosine = normal.dot(half.normalize()).max(0.0);
+        self.specular * scale * cosine.powf(n)
+    }
+
+    /// Whether any light leaves the surface in a single direction, by
+    /// reflection or refraction.
+    pub fn is_specular(&self) -> bool {
+        self.reflectivity > 0.0 || self.transparency > 0.0
+    }
+
+    /// How light arriving along unit `direction` at a surface whose outward
+    /// unit normal is `normal` divides. Light entering a transparent surface
+    /// from outside goes from air into `ior`, and light leaving it the other
+    /// way. How much of it the interface reflects follows Schlick's
+    /// approximation of the Fresnel equations, and all of it is reflected
+    /// past the critical angle.
+    pub fn split(&self, direction: Vector3<f32>, normal: Vector3<f32>) -> Split {
+        let mut split = Split {
+            diffuse: (1.0 - self.reflectivity) * (1.0 - self.transparency),
+            reflected: self.reflectivity * (1.0 - self.transparency),
+            refracted: 0.0,
+            refraction: direction,
+        };
+        if self.transparency <= 0.0 {
+            return split;
+        }
+        let entering = direction.dot(normal) < 0.0;
+        let (n1, n2, facing) = if entering {
+            (1.0, self.ior, normal)
+        } else {
+            (self.ior, 1.0, -normal)
+        };
+        let eta = n1 / n2;
+        let cos_i = -direction.dot(facing);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            // Total internal reflection
+            split.reflected += self.transparency;
+            return split;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+        // Schlick's formula takes the angle on the less dense side
+        let cosine = if n1 > n2 { cos_t } else { cos_i };
+        let fresnel = r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
+        split.reflected += self.transparency * fresnel;
+        split.refracted = self.transparency * (1.0 - fresnel);
+        split.refraction = (direction * eta + facing * (eta * cos_i - cos_t)).normalize();
+        split
+    }
+}
@@ -0,0 +1,64 @@
+//! Interactive distance and angle measurement between picked surface points.
+
+use cgmath::{Deg, InnerSpace, Point3};
+use piston_window::{line, rectangle, Context, G2d};
+
+use scene::Units;
+
+const MARKER_COLOR: [f32; 4] = [1.0, 0.8, 0.0, 1.0];
+
+/// Up to three picked points: two give a distance, a third gives the angle
+/// at the second point as well.
+pub struct Measurement {
+    points: Vec<(Point3<f32>, [f64; 2])>,
+}
+
+impl Measurement {
+    pub fn new() -> Measurement {
+        Measurement { points: Vec::new() }
+    }
+
+    /// Adds a picked point and where it was picked on screen, starting a new
+    /// measurement once three points have been taken.
+    pub fn add(&mut self, point: Point3<f32>, screen: [f64; 2]) {
+        if self.points.len() == 3 {
+            self.points.clear();
+        }
+        self.points.push((point, screen));
+    }
+
+    pub fn report(&self, units: &Units) -> Option<String> {
+        let p = |i: usize| self.points[i].0;
+        match self.points.len() {
+            2 => {
+                let distance = (p(1) - p(0)).magnitude();
+                Some(format!(
+                    "distance: {:.4} units ({:.4} m)",
+                    distance,
+                    distance * units.meters_per_unit
+                ))
+            }
+            3 => {
+                let angle: Deg<f32> = (p(0) - p(1)).angle(p(2) - p(1)).into();
+                let distance = (p(2) - p(1)).magnitude();
+                Some(format!(
+                    "angle: {:.2} degrees, distance: {:.4} units ({:.4} m)",
+                    angle.0,
+                    distance,
+                    distance * units.meters_per_unit
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, c: Context, g: &mut G2d) {
+        for (i, &(_, [x, y])) in self.points.iter().enumerate() {
+            rectangle(MARKER_COLOR, [x - 3.0, y - 3.0, 6.0, 6.0], c.transform, g);
+            if i > 0 {
+                let [px, py] = self.points[i - 1].1;
+                line(MARKER_COLOR, 1.0, [px, py, x, y], c.transform, g);
+            }
+        }
+    }
+}
@@ -0,0 +1,593 @@
+//! Triangles and indexed triangle meshes, for geometry that can't be built
+//! from spheres.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use std::mem;
+use std::sync::{Arc, OnceLock};
+
+use bvh::{Traversal, WideBvh};
+use clip;
+use geometry::Ray;
+use mapped::Buffer;
+use material::Material;
+use primitive::{Hit, Intersectable};
+use render::RenderOptions;
+
+/// Normal indices of a flat shaded triangle in a smooth shaded mesh.
+pub const FLAT: [u32; 3] = [u32::MAX; 3];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle {
+    pub a: Point3<f32>,
+    pub b: Point3<f32>,
+    pub c: Point3<f32>,
+}
+
+impl Triangle {
+    /// Distance along `ray` to the triangle, from either side, using the
+    /// watertight test of Woop, Benthin and Wald ("Watertight Ray/Triangle
+    /// Intersection"), so a ray through an edge or vertex that triangles
+    /// share hits at least one of them rather than leaking between.
+    pub fn intersects(&self, ray: &Ray) -> Option<f32> {
+        let d = ray.direction;
+        // Turn the ray's largest axis into z, keeping the axes right-handed
+        let kz = if d.x.abs() > d.y.abs() {
+            if d.x.abs() > d.z.abs() {
+                0
+            } else {
+                2
+            }
+        } else if d.y.abs() > d.z.abs() {
+            1
+        } else {
+            2
+        };
+        let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+        if d[kz] < 0.0 {
+            mem::swap(&mut kx, &mut ky);
+        }
+        // Shear taking the ray to the +z axis from the origin
+        let (sx, sy, sz) = (d[kx] / d[kz], d[ky] / d[kz], 1.0 / d[kz]);
+        let corner = |p: Point3<f32>| {
+            let p = p - ray.origin;
+            (p[kx] - sx * p[kz], p[ky] - sy * p[kz], sz * p[kz])
+        };
+        let (a, b, c) = (corner(self.a), corner(self.b), corner(self.c));
+
+        // Signed areas of the edges seen from the ray, redone in f64 when
+        // one rounds to zero so its sign on a shared edge is exact
+        let mut u = c.0 * b.1 - c.1 * b.0;
+        let mut v = a.0 * c.1 - a.1 * c.0;
+        let mut w = b.0 * a.1 - b.1 * a.0;
+        if u == 0.0 || v == 0.0 || w == 0.0 {
+            let area = |p: (f32, f32, f32), q: (f32, f32, f32)| {
+                (p.0 as f64 * q.1 as f64 - p.1 as f64 * q.0 as f64) as f32
+            };
+            u = area(c, b);
+            v = area(a, c);
+            w = area(b, a);
+        }
+        // Inside when no edge sees the ray on the other side from the rest
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+
+        let t = (u * a.2 + v * b.2 + w * c.2) / det;
+        if t >= 0.0 && t.is_finite() {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    /// Face normal, unnormalized, pointing to the side the vertices wind
+    /// anticlockwise on.
+    pub fn normal(&self) -> Vector3<f32> {
+        (self.b - self.a).cross(self.c - self.a)
+    }
+
+    // Weights of `b` and `c` in `point`, which lies on the triangle's plane
+    fn barycentric(&self, point: Point3<f32>) -> (f32, f32) {
+        let e1 = self.b - self.a;
+        let e2 = self.c - self.a;
+        let p = point - self.a;
+        let (d11, d12, d22) = (e1.dot(e1), e1.dot(e2), e2.dot(e2));
+        let (dp1, dp2) = (p.dot(e1), p.dot(e2));
+        let denominator = d11 * d22 - d12 * d12;
+        (
+            (d22 * dp1 - d12 * dp2) / denominator,
+            (d11 * dp2 - d12 * dp1) / denominator,
+        )
+    }
+}
+
+/// A triangle and the texture coordinates of its corners, where a model's
+/// UV layout puts it in a texture.
+#[derive(Clone, Copy, Debug)]
+pub struct UvTriangle {
+    pub triangle: Triangle,
+    pub uvs: [[f32; 2]; 3],
+}
+
+/// Triangles sharing a vertex buffer, each naming its corners by index.
+/// The buffers may lie in a mapped scene cache, and are copied out of it
+/// once the mesh is moved.
+#[derive(Clone)]
+pub struct Mesh {
+    positions: Buffer<Point3<f32>>,
+    indices: Buffer<[u32; 3]>,
+    // Vertex normals, and for each triangle the normals of its corners or
+    // `FLAT`, if the mesh is smooth shaded
+    normals: Buffer<Vector3<f32>>,
+    normal_indices: Buffer<[u32; 3]>,
+    // Scalars by name with a value at every vertex, such as a simulation's
+    // results, for false colour
+    attributes: Vec<(String, Buffer<f32>)>,
+    // Sphere around every vertex, so rays that miss it skip the triangles
+    center: Point3<f32>,
+    radius: f32,
+    material: Material,
+    // How far `translate` has moved the mesh in all
+    moved: Vector3<f32>,
+    // Hierarchy over the triangles, built the first time it is traversed;
+    // None if the mesh is too large for one
+    bvh: OnceLock<Option<Arc<WideBvh>>>,
+}
+
+impl Mesh {
+    /// A mesh of `indices` into `positions`, or why it can't be built.
+    pub fn new(positions: Vec<Point3<f32>>, indices: Vec<[u32; 3]>) -> Result<Mesh, String> {
+        Mesh::from_buffers(positions.into(), indices.into())
+    }
+
+    /// A mesh of `indices` into `positions`, as `new` builds, from buffers
+    /// that may be mapped.
+    pub fn from_buffers(
+        positions: Buffer<Point3<f32>>,
+        indices: Buffer<[u32; 3]>,
+    ) -> Result<Mesh, String> {
+        if let Some(&bad) = indices.iter().flatten().find(|&&i| i as usize >= positions.len()) {
+            return Err(format!(
+                "index {} out of range of {} vertices",
+                bad,
+                positions.len()
+            ));
+        }
+        let mut mesh = Mesh {
+            positions,
+            indices,
+            normals: Vec::new().into(),
+            normal_indices: Vec::new().into(),
+            attributes: Vec::new(),
+            center: Point3::new(0.0, 0.0, 0.0),
+            radius: 0.0,
+            material: Material::default(),
+            moved: Vector3::new(0.0, 0.0, 0.0),
+            bvh: OnceLock::new(),
+        };
+        mesh.update_bounds();
+        Ok(mesh)
+    }
+
+    /// The mesh smooth shaded with `normals`, which `indices` assigns to
+    /// the corners of each triangle in turn. Triangles without normal
+    /// indices stay flat.
+    pub fn with_normals(
+        self,
+        normals: Vec<Vector3<f32>>,
+        indices: Vec<Option<[u32; 3]>>,
+    ) -> Result<Mesh, String> {
+        let indices: Vec<[u32; 3]> = indices.into_iter().map(|i| i.unwrap_or(FLAT)).collect();
+        self.with_normal_buffers(normals.into(), indices.into())
+    }
+
+    /// The mesh smooth shaded as `with_normals` shades it, from buffers
+    /// that may be mapped; triangles whose indices are `FLAT` stay flat.
+    pub fn with_normal_buffers(
+        mut self,
+        normals: Buffer<Vector3<f32>>,
+        indices: Buffer<[u32; 3]>,
+    ) -> Result<Mesh, String> {
+        if indices.len() != self.indices.len() {
+            return Err(format!(
+                "{} normal indices for {} triangles",
+                indices.len(),
+                self.indices.len()
+            ));
+        }
+        let mut all = indices.iter().filter(|&&i| i != FLAT).flatten();
+        if let Some(&bad) = all.find(|&&i| i as usize >= normals.len()) {
+            return Err(format!("normal index {} out of range of {} normals", bad, normals.len()));
+        }
+        self.normals = normals;
+        self.normal_indices = indices;
+        Ok(self)
+    }
+
+    /// Gives the mesh the scalar attribute `name`, taking `values` at its
+    /// vertices, in place of any it had by that name.
+    pub fn set_attribute(&mut self, name: &str, values: Buffer<f32>) -> Result<(), String> {
+        if values.len() != self.positions.len() {
+            return Err(format!(
+                "{} values of {} for {} vertices",
+                values.len(),
+                name,
+                self.positions.len()
+            ));
+        }
+        self.attributes.retain(|a| a.0 != name);
+        self.attributes.push((name.to_string(), values));
+        Ok(())
+    }
+
+    /// The mesh made of `material`.
+    pub fn with_material(mut self, material: Material) -> Mesh {
+        self.material = material;
+        self
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    /// Moves every vertex by `offset`. The bounding sphere moves with them
+    /// rather than being refitted, so this is cheap enough to do between
+    /// frames.
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        for p in self.positions.to_mut() {
+            *p += offset;
+        }
+        self.center += offset;
+        self.moved += offset;
+    }
+
+    /// Sum of the offsets the mesh has been translated by.
+    pub fn moved(&self) -> Vector3<f32> {
+        self.moved
+    }
+
+    /// The mesh with `transform` applied to its vertices and normals.
+    pub fn transformed(mut self, transform: &Matrix4<f32>) -> Mesh {
+        for p in self.positions.to_mut() {
+            *p = transform.transform_point(*p);
+        }
+        // Normals take the inverse transpose, so they stay perpendicular
+        // under non-uniform scaling
+        match transform.invert() {
+            Some(inverse) => {
+                let normal_matrix = inverse.transpose();
+                for n in self.normals.to_mut() {
+                    *n = normal_matrix.transform_vector(*n);
+                }
+            }
+            None => {
+                self.normals = Vec::new().into();
+                self.normal_indices = Vec::new().into();
+            }
+        }
+        self.update_bounds();
+        self
+    }
+
+    fn update_bounds(&mut self) {
+        // Whatever moved the vertices other than a translation has left any
+        // hierarchy behind
+        self.bvh = OnceLock::new();
+        self.center = if self.positions.is_empty() {
+            Point3::new(0.0, 0.0, 0.0)
+        } else {
+            Point3::centroid(&self.positions)
+        };
+        let center = self.center;
+        self.radius = self
+            .positions
+            .iter()
+            .map(|&p| (p - center).magnitude())
+            .fold(0.0, f32::max);
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn positions(&self) -> &[Point3<f32>] {
+        &self.positions
+    }
+
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    /// Vertex normals, empty for flat shaded meshes.
+    pub fn normals(&self) -> &[Vector3<f32>] {
+        &self.normals
+    }
+
+    /// Indices into `normals` of the corners of each triangle, or `FLAT`,
+    /// if the mesh is smooth shaded.
+    pub fn normal_indices(&self) -> &[[u32; 3]] {
+        &self.normal_indices
+    }
+
+    /// Names and vertex values of the mesh's scalar attributes.
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &[f32])> {
+        self.attributes.iter().map(|a| (a.0.as_str(), &a.1[..]))
+    }
+
+    pub fn triangle(&self, index: usize) -> Triangle {
+        let [a, b, c] = self.indices[index];
+        Triangle {
+            a: self.positions[a as usize],
+            b: self.positions[b as usize],
+            c: self.positions[c as usize],
+        }
+    }
+
+    /// Shading normal of triangle `index` at `point` on it, unnormalized:
+    /// the corner normals blended across the triangle if it has them, else
+    /// its face normal.
+    pub fn shading_normal(&self, index: usize, point: Point3<f32>) -> Vector3<f32> {
+        let triangle = self.triangle(index);
+        let corners = match self.normal_indices.get(index) {
+            Some(&corners) if corners != FLAT => corners,
+            _ => return triangle.normal(),
+        };
+        let (v, w) = triangle.barycentric(point);
+        let [a, b, c] = corners;
+        let n = self.normals[a as usize].normalize() * (1.0 - v - w)
+            + self.normals[b as usize].normalize() * v
+            + self.normals[c as usize].normalize() * w;
+        // Degenerate normals fall back on the face
+        if is_usable(n) {
+            n
+        } else {
+            triangle.normal()
+        }
+    }
+
+    // Whether `ray` passes within the bounding sphere ahead of its origin
+    fn bounds_hit(&self, ray: &Ray) -> bool {
+        let l = self.center - ray.origin;
+        let tca = l.dot(ray.direction);
+        let d2 = l.magnitude2() - tca * tca;
+        let radius_squared = self.radius * self.radius;
+        // Slightly enlarged so vertices on the sphere aren't lost to rounding
+        let inside = l.magnitude2() <= radius_squared * 1.0001;
+        inside || (tca >= 0.0 && d2 <= radius_squared * 1.0001)
+    }
+
+    // Distance along `ray` to triangle `index`, if the ray hits it where
+    // clipping keeps it. Meshes are open surfaces, so clipping just cuts
+    // triangles away.
+    fn hit(&self, index: usize, ray: &Ray, render_options: &RenderOptions) -> Option<f32> {
+        self.triangle(index).intersects(ray).filter(|&t| {
+            !render_options.clipping
+                || clip::keeps(&render_options.clip_planes, ray.origin + ray.direction * t)
+        })
+    }
+
+    // Index and distance of every triangle `ray` hits that clipping keeps,
+    // in no particular order
+    fn hits<'a>(
+        &'a self,
+        ray: &'a Ray,
+        render_options: &'a RenderOptions,
+    ) -> impl Iterator<Item = (usize, f32)> + 'a {
+        let count = if self.bounds_hit(ray) { self.len() } else { 0 };
+        (0..count).filter_map(move |i| self.hit(i, ray, render_options).map(|t| (i, t)))
+    }
+
+    // The hierarchy over the triangles, built now if this is the first ray
+    // to need it
+    fn bvh(&self) -> Option<&WideBvh> {
+        let bvh = self.bvh.get_or_init(|| {
+            let min = |p: Point3<f32>, q: Point3<f32>| {
+                Point3::new(p.x.min(q.x), p.y.min(q.y), p.z.min(q.z))
+            };
+            let max = |p: Point3<f32>, q: Point3<f32>| {
+                Point3::new(p.x.max(q.x), p.y.max(q.y), p.z.max(q.z))
+            };
+            let bounds: Vec<_> = (0..self.len())
+                .map(|i| {
+                    let Triangle { a, b, c } = self.triangle(i);
+                    (min(min(a, b), c), max(max(a, b), c))
+                })
+                .collect();
+            WideBvh::build(&bounds, self.moved).map(Arc::new)
+        });
+        bvh.as_ref().map(|bvh| &**bvh)
+    }
+}
+
+impl Intersectable for Mesh {
+    fn intersect(&self, ray: &Ray, render_options: &RenderOptions) -> Option<Hit> {
+        let nearest = match (render_options.traversal, self.bounds_hit(ray)) {
+            (_, false) => None,
+            (Traversal::WideBvh, true) if self.bvh().is_some() => {
+                let bvh = self.bvh().expect("hierarchy was just built");
+                bvh.closest(ray, self.moved, |i| self.hit(i, ray, render_options))
+            }
+            (_, true) => self.hits(ray, render_options).min_by(|a, b| a.1.total_cmp(&b.1)),
+        };
+        nearest.map(|(part, distance)| Hit {
+            distance,
+            part,
+            cap: None,
+        })
+    }
+
+    fn occludes(&self, ray: &Ray, max_distance: f32, render_options: &RenderOptions) -> bool {
+        let blocks = |distance: f32| distance > 0.0 && distance < max_distance;
+        match (render_options.traversal, self.bounds_hit(ray)) {
+            (_, false) => false,
+            (Traversal::WideBvh, true) if self.bvh().is_some() => {
+                let bvh = self.bvh().expect("hierarchy was just built");
+                bvh.any(ray, self.moved, max_distance, |i| {
+                    self.hit(i, ray, render_options).is_some_and(blocks)
+                })
+            }
+            (_, true) => self.hits(ray, render_options).any(|(_, distance)| blocks(distance)),
+        }
+    }
+
+    fn normal(&self, point: Point3<f32>, part: usize) -> Vector3<f32> {
+        self.shading_normal(part, point)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    // Blended across the triangle from its corners
+    fn attribute(&self, name: &str, point: Point3<f32>, part: usize) -> Option<f32> {
+        let values = &self.attributes.iter().find(|a| a.0 == name)?.1;
+        let (v, w) = self.triangle(part).barycentric(point);
+        let [a, b, c] = self.indices[part];
+        Some(values[a as usize] * (1.0 - v - w) + values[b as usize] * v + values[c as usize] * w)
+    }
+
+    // Meshes needn't be closed, so either side of a triangle may be seen
+    fn two_sided(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        let first = self.positions.first().cloned().unwrap_or(self.center);
+        self.positions.iter().fold((first, first), |(min, max), p| {
+            (
+                Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        })
+    }
+}
+
+fn is_usable(v: Vector3<f32>) -> bool {
+    let length = v.magnitude2();
+    length > 0.0 && length.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+
+    // A closed box from -1 to 1 on each axis, every face a grid of
+    // `cells` by `cells` squares split into triangles, turned off the axes
+    // so its vertices aren't round numbers
+    fn closed_box(cells: u32) -> Mesh {
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        let faces = [
+            (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()),
+            (Vector3::unit_y(), Vector3::unit_z(), Vector3::unit_x()),
+            (Vector3::unit_z(), Vector3::unit_x(), Vector3::unit_y()),
+        ];
+        for &(normal, u, v) in &faces {
+            for &side in &[-1.0, 1.0] {
+                let first = positions.len() as u32;
+                for j in 0..=cells {
+                    for i in 0..=cells {
+                        let (s, t) = (i as f32 / cells as f32, j as f32 / cells as f32);
+                        let p = normal * side + u * (2.0 * s - 1.0) + v * (2.0 * t - 1.0);
+                        positions.push(Point3::from_vec(p));
+                    }
+                }
+                let corner = |i: u32, j: u32| first + j * (cells + 1) + i;
+                for j in 0..cells {
+                    for i in 0..cells {
+                        let (a, b) = (corner(i, j), corner(i + 1, j));
+                        let (c, d) = (corner(i + 1, j + 1), corner(i, j + 1));
+                        indices.push([a, b, c]);
+                        indices.push([a, c, d]);
+                    }
+                }
+            }
+        }
+        let axis = Vector3::new(1.0, 2.0, 3.0).normalize();
+        let turn = Matrix4::from_axis_angle(axis, Deg(37.0));
+        Mesh::new(positions, indices).unwrap().transformed(&turn)
+    }
+
+    #[test]
+    fn rays_through_shared_edges_and_vertices_hit() {
+        let mesh = closed_box(8);
+        let origin = Point3::new(0.1, 0.05, -0.07);
+        // Aimed at every vertex and at the middle of every edge, where rays
+        // leak between triangles if the test isn't watertight
+        let mut targets = mesh.positions().to_vec();
+        for &[a, b, c] in mesh.indices() {
+            let p = |i: u32| mesh.positions()[i as usize].to_vec();
+            for &(i, j) in &[(a, b), (b, c), (c, a)] {
+                targets.push(Point3::from_vec((p(i) + p(j)) / 2.0));
+            }
+        }
+        let hits = |ray: &Ray| (0..mesh.len()).any(|i| mesh.triangle(i).intersects(ray).is_some());
+        let leaks = targets
+            .iter()
+            .map(|&target| Ray {
+                origin,
+                direction: (target - origin).normalize(),
+            })
+            .filter(|ray| !hits(ray))
+            .count();
+        assert_eq!(leaks, 0, "{} of {} rays leaked", leaks, targets.len());
+    }
+
+    #[test]
+    fn hits_from_either_side() {
+        let triangle = Triangle {
+            a: Point3::new(0.0, 0.0, -4.0),
+            b: Point3::new(2.0, 0.0, -4.0),
+            c: Point3::new(0.0, 2.0, -4.0),
+        };
+        let toward = Ray {
+            origin: Point3::new(0.5, 0.5, 0.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        assert_eq!(triangle.intersects(&toward), Some(4.0));
+        let behind = Ray {
+            origin: Point3::new(0.5, 0.5, -8.0),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+        };
+        assert_eq!(triangle.intersects(&behind), Some(4.0));
+        let away = Ray {
+            origin: Point3::new(0.5, 0.5, 0.0),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+        };
+        assert_eq!(triangle.intersects(&away), None);
+        let beside = Ray {
+            origin: Point3::new(1.5, 1.5, 0.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        assert_eq!(triangle.intersects(&beside), None);
+    }
+
+    #[test]
+    fn degenerate_triangles_are_missed() {
+        let ray = Ray {
+            origin: Point3::new(0.5, 0.0, 0.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        let line = Triangle {
+            a: Point3::new(0.0, 0.0, -4.0),
+            b: Point3::new(1.0, 0.0, -4.0),
+            c: Point3::new(2.0, 0.0, -4.0),
+        };
+        assert_eq!(line.intersects(&ray), None);
+        let nan = Triangle {
+            a: Point3::new(f32::NAN, 0.0, -4.0),
+            ..line
+        };
+        assert_eq!(nan.intersects(&ray), None);
+    }
+}
@@ -0,0 +1,97 @@
+//! Motion blur for offline renders. While the shutter is open a scene's
+//! moving objects keep moving, from where they stand at the scene clock to
+//! where their velocities or keyframes take them by the time the shutter
+//! closes.
+//!
+//! A blurred frame is traced as a run of sub-frames, each seeing the
+//! objects where they are at its own moment of the shutter interval, and
+//! the sub-frames are averaged. Moments are spread evenly over the
+//! interval, one per pixel sample, and each sub-frame lays its one sample
+//! per pixel out as a progressive pass would, so the frame is antialiased
+//! and blurred by the same rays.
+
+use cgmath::Vector3;
+use im::Rgb;
+
+use accumulate::Accumulator;
+use camera::Camera;
+use interrupt;
+use progress;
+use render::{render_parallel, render_rect, Frame, FramePixel, Rect, RenderOptions};
+use scene::Scene;
+use tiles;
+
+// Fewest moments the shutter is sampled at, so a frame with one sample per
+// pixel still blurs smoothly
+const MIN_MOMENTS: u32 = 8;
+
+/// Whether `render_options` open the shutter on a scene with anything
+/// moving in it.
+pub fn is_blurred(scene: &Scene, render_options: &RenderOptions) -> bool {
+    render_options.shutter > 0.0 && scene.is_moving()
+}
+
+/// Radiance of the frame `camera` sees of `scene` with the shutter open
+/// for `RenderOptions::shutter` seconds from the scene clock, or None if
+/// the render was interrupted. The scene is left as it was found.
+pub fn render(
+    scene: &mut Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+) -> Option<Frame<Rgb<f32>>> {
+    let (width, height) = (render_options.width, render_options.height);
+    let rects = tiles::tiles(width, height, render_options.tile_size);
+    let whole = Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    let moments = render_options.samples_per_pixel.max(MIN_MOMENTS);
+    let mut accumulator = Accumulator::new(width, height);
+    let mut sub_frame: Frame<Rgb<f32>> = Frame::new(width, height);
+    let mut progress = progress::Progress::new("moments", moments as usize);
+    // Seconds into the shutter interval the objects stand at
+    let mut at = 0.0;
+    let mut finished = true;
+    for moment in 0..moments {
+        if interrupt::interrupted() {
+            finished = false;
+            break;
+        }
+        let time = render_options.shutter * (moment as f32 + 0.5) / moments as f32;
+        scene.displace(scene.time + at, time - at);
+        at = time;
+        let options = RenderOptions {
+            samples_per_pixel: 1,
+            pass: moment,
+            ..render_options.clone()
+        };
+        let still = &*scene;
+        render_parallel(&rects, &mut sub_frame, |rect, tile| {
+            render_rect(still, camera, &options, rect, tile)
+        });
+        accumulator.add(&sub_frame, &whole);
+        progress.tick();
+    }
+    scene.displace(scene.time + at, -at);
+    if !finished {
+        return None;
+    }
+    Some(Frame::from_fn(width, height, |px_x, px_y| {
+        let color = accumulator.average(px_x, px_y).expect("every moment covers every pixel");
+        Rgb([color.x, color.y, color.z])
+    }))
+}
+
+/// `radiance` as a frame of pixels of type `P`, exposed, encoded and
+/// dithered as if it had been traced straight into one.
+pub fn resolve<P>(radiance: &Frame<Rgb<f32>>, render_options: &RenderOptions) -> Frame<P>
+where
+    P: FramePixel,
+{
+    Frame::from_fn(radiance.width(), radiance.height(), |px_x, px_y| {
+        let [r, g, b] = radiance.get_pixel(px_x, px_y).data;
+        P::from_radiance(Vector3::new(r, g, b), render_options, px_x, px_y)
+    })
+}
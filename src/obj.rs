@@ -0,0 +1,250 @@
+//! Loader for Wavefront `.obj` models.
+//!
+//! Vertex positions (`v`), normals (`vn`) and faces (`f`) are read, and
+//! every object and group in the file goes into one mesh. Faces of more than
+//! three vertices are split into fans, and negative indices count back from
+//! the latest vertex as the format allows. Materials and other statements
+//! are skipped, as are texture coordinates (`vt`) except when a model's UV
+//! layout is read for baking. Meshes are written back out as positions,
+//! normals and triangles.
+
+use cgmath::{Point3, Vector3};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use mesh::{Mesh, Triangle, UvTriangle, FLAT};
+
+// Index into a list of `count` elements from its 1-based or negative OBJ form
+fn resolve_index(field: &str, count: usize) -> Result<u32, String> {
+    let index: i64 = field
+        .parse()
+        .map_err(|_| format!("bad index {:?}", field))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} out of range of {}", index, count));
+    }
+    Ok(resolved as u32)
+}
+
+fn floats(fields: &[&str], min: usize) -> Result<Vec<f32>, String> {
+    if fields.len() < min {
+        return Err(format!("expected {} numbers", min));
+    }
+    fields
+        .iter()
+        .map(|f| f.parse().map_err(|_| format!("bad number {:?}", f)))
+        .collect()
+}
+
+/// Parses the text of an `.obj` file into a mesh.
+pub fn parse(src: &str) -> Result<Mesh, String> {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut triangles = Vec::new();
+    let mut normal_indices = Vec::new();
+
+    for (number, line) in src.lines().enumerate() {
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        let line = line.split('#').next().unwrap_or("");
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.first() {
+            Some(&"v") => {
+                let v = floats(&fields[1..], 3).map_err(error)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            Some(&"vn") => {
+                let n = floats(&fields[1..], 3).map_err(error)?;
+                normals.push(Vector3::new(n[0], n[1], n[2]));
+            }
+            Some(&"f") => {
+                // Each corner is v, v/vt, v//vn or v/vt/vn
+                let mut corners = Vec::new();
+                for corner in &fields[1..] {
+                    let mut parts = corner.split('/');
+                    let position = parts.next().unwrap_or("");
+                    let position = resolve_index(position, positions.len()).map_err(error)?;
+                    let normal = match parts.nth(1) {
+                        Some(n) if !n.is_empty() => {
+                            Some(resolve_index(n, normals.len()).map_err(error)?)
+                        }
+                        _ => None,
+                    };
+                    corners.push((position, normal));
+                }
+                if corners.len() < 3 {
+                    return Err(error("face with fewer than 3 vertices".to_string()));
+                }
+                for i in 2..corners.len() {
+                    let (a, b, c) = (corners[0], corners[i - 1], corners[i]);
+                    triangles.push([a.0, b.0, c.0]);
+                    // Only faces with a normal at every corner are smooth
+                    normal_indices.push(match (a.1, b.1, c.1) {
+                        (Some(na), Some(nb), Some(nc)) => Some([na, nb, nc]),
+                        _ => None,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Mesh::new(positions, triangles)?.with_normals(normals, normal_indices)
+}
+
+/// Parses the UV layout of the text of an `.obj` file: every triangle
+/// whose corners all have texture coordinates, split from faces as `parse`
+/// splits them.
+pub fn parse_uv_layout(src: &str) -> Result<Vec<UvTriangle>, String> {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+
+    for (number, line) in src.lines().enumerate() {
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        let line = line.split('#').next().unwrap_or("");
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.first() {
+            Some(&"v") => {
+                let v = floats(&fields[1..], 3).map_err(error)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            Some(&"vt") => {
+                let t = floats(&fields[1..], 2).map_err(error)?;
+                uvs.push([t[0], t[1]]);
+            }
+            Some(&"f") => {
+                let mut corners = Vec::new();
+                for corner in &fields[1..] {
+                    let mut parts = corner.split('/');
+                    let position = parts.next().unwrap_or("");
+                    let position = resolve_index(position, positions.len()).map_err(error)?;
+                    let uv = match parts.next() {
+                        Some(t) if !t.is_empty() => {
+                            Some(resolve_index(t, uvs.len()).map_err(error)?)
+                        }
+                        _ => None,
+                    };
+                    corners.push((positions[position as usize], uv.map(|t| uvs[t as usize])));
+                }
+                if corners.len() < 3 {
+                    return Err(error("face with fewer than 3 vertices".to_string()));
+                }
+                for i in 2..corners.len() {
+                    let (a, b, c) = (corners[0], corners[i - 1], corners[i]);
+                    if let (Some(ta), Some(tb), Some(tc)) = (a.1, b.1, c.1) {
+                        triangles.push(UvTriangle {
+                            triangle: Triangle {
+                                a: a.0,
+                                b: b.0,
+                                c: c.0,
+                            },
+                            uvs: [ta, tb, tc],
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+// The text of the file at `path`
+fn read(path: &Path) -> Result<String, String> {
+    let mut src = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(src)
+}
+
+/// Reads the `.obj` file at `path` into a mesh.
+pub fn load(path: &Path) -> Result<Mesh, String> {
+    parse(&read(path)?).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Reads the UV layout of the `.obj` file at `path`, as `parse_uv_layout`
+/// does.
+pub fn load_uv_layout(path: &Path) -> Result<Vec<UvTriangle>, String> {
+    parse_uv_layout(&read(path)?).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The text of an `.obj` file of `mesh`, its triangles smooth shaded where
+/// the mesh's are.
+pub fn write(mesh: &Mesh) -> String {
+    let mut src = String::new();
+    for p in mesh.positions() {
+        src.push_str(&format!("v {} {} {}\n", p.x, p.y, p.z));
+    }
+    for n in mesh.normals() {
+        src.push_str(&format!("vn {} {} {}\n", n.x, n.y, n.z));
+    }
+    for (i, triangle) in mesh.indices().iter().enumerate() {
+        src.push('f');
+        match mesh.normal_indices().get(i) {
+            Some(normals) if *normals != FLAT => {
+                for (v, n) in triangle.iter().zip(normals) {
+                    src.push_str(&format!(" {}//{}", v + 1, n + 1));
+                }
+            }
+            _ => {
+                for v in triangle {
+                    src.push_str(&format!(" {}", v + 1));
+                }
+            }
+        }
+        src.push('\n');
+    }
+    src
+}
+
+/// Writes `mesh` to an `.obj` file at `path`, as `write` gives it.
+pub fn save(mesh: &Mesh, path: &Path) -> Result<(), String> {
+    fs::write(path, write(mesh)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "# a unit square\n\
+                        v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                        vn 0 0 1\n\
+                        o square\n\
+                        f 1//1 2//1 3//1 -1//-1\n\
+                        f 1 2 3\n";
+
+    #[test]
+    fn faces_split_into_fans() {
+        let mesh = parse(QUAD).unwrap();
+        assert_eq!(mesh.positions().len(), 4);
+        assert_eq!(mesh.indices(), &[[0, 1, 2], [0, 2, 3], [0, 1, 2]]);
+        assert_eq!(mesh.normal_indices(), &[[0, 0, 0], [0, 0, 0], FLAT]);
+    }
+
+    #[test]
+    fn written_meshes_read_back() {
+        let mesh = parse(QUAD).unwrap();
+        let read = parse(&write(&mesh)).unwrap();
+        assert_eq!(read.positions(), mesh.positions());
+        assert_eq!(read.normals(), mesh.normals());
+        assert_eq!(read.indices(), mesh.indices());
+        assert_eq!(read.normal_indices(), mesh.normal_indices());
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        let files = [
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 -4\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//1 2//1 3//1\n",
+            "v 0 0\n",
+            "v 0 zero 0\n",
+        ];
+        for file in &files {
+            assert!(parse(file).is_err(), "{}", file);
+        }
+    }
+}
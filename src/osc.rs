@@ -0,0 +1,212 @@
+//! Open Sound Control input, so controllers and installation software can
+//! drive a running interactive session. Messages arrive over UDP and set
+//! named parameters:
+//!
+//! - `/exposure <stops>`
+//! - `/light/<handle>/intensity <value>` and `/light/<handle>/remove`
+//! - `/light/add <x> <y> <z> <intensity>`, adding a white point light
+//! - `/sphere/<index>/position <x> <y> <z>` and `/sphere/<index>/radius <r>`
+//! - `/object/<handle>/move <dx> <dy> <dz>`, `/object/<handle>/albedo <r> <g>
+//!   <b>` and `/object/<handle>/material/<handle>`, the last giving the
+//!   object one of the scene's named materials
+//! - `/material/<handle>/albedo <r> <g> <b>`, changing a named material on
+//!   every object given it
+//! - `/camera/position <x> <y> <z>`
+//!
+//! Integer, float and double arguments are all accepted as numbers, and
+//! bundles are unpacked.
+
+use cgmath::{Point3, Vector3};
+use std::io;
+use std::net::UdpSocket;
+
+use camera::Camera;
+use edit::{self, Damage, Edit};
+use geometry::Sphere;
+use light::Light;
+use material::Material;
+use render::RenderOptions;
+use scene::Scene;
+
+// Larger than any message a controller sends
+const MAX_PACKET: usize = 65536;
+
+pub struct Message {
+    pub address: String,
+    pub args: Vec<f32>,
+}
+
+pub struct OscListener {
+    socket: UdpSocket,
+}
+
+// A null-terminated string padded to a multiple of four bytes, and what
+// follows it
+fn read_string(bytes: &[u8]) -> Option<(String, &[u8])> {
+    let end = bytes.iter().position(|&b| b == 0)?;
+    let s = String::from_utf8(bytes[..end].to_vec()).ok()?;
+    let padded = (end + 4) & !3;
+    Some((s, bytes.get(padded..)?))
+}
+
+fn read_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let b = bytes.get(..4)?;
+    Some((u32::from_be_bytes([b[0], b[1], b[2], b[3]]), &bytes[4..]))
+}
+
+// Appends the messages in `packet`, a message or a bundle, to `messages`.
+// Arguments of types other than numbers are skipped.
+fn parse(packet: &[u8], messages: &mut Vec<Message>) -> Option<()> {
+    let (address, mut rest) = read_string(packet)?;
+    if address == "#bundle" {
+        // Time tag, then size-prefixed elements
+        rest = rest.get(8..)?;
+        while !rest.is_empty() {
+            let (size, after) = read_u32(rest)?;
+            let element = after.get(..size as usize)?;
+            parse(element, messages)?;
+            rest = &after[size as usize..];
+        }
+        return Some(());
+    }
+
+    let (tags, mut rest) = read_string(rest)?;
+    let mut args = Vec::new();
+    for tag in tags.chars().skip_while(|&c| c == ',') {
+        match tag {
+            'f' => {
+                let (bits, after) = read_u32(rest)?;
+                args.push(f32::from_bits(bits));
+                rest = after;
+            }
+            'i' => {
+                let (bits, after) = read_u32(rest)?;
+                args.push(bits as i32 as f32);
+                rest = after;
+            }
+            'd' => {
+                let (high, after) = read_u32(rest)?;
+                let (low, after) = read_u32(after)?;
+                args.push(f64::from_bits(u64::from(high) << 32 | u64::from(low)) as f32);
+                rest = after;
+            }
+            's' => rest = read_string(rest)?.1,
+            // Tags that carry no data
+            'T' | 'F' | 'N' | 'I' => {}
+            _ => return None,
+        }
+    }
+    messages.push(Message { address, args });
+    Some(())
+}
+
+impl OscListener {
+    pub fn bind(address: &str) -> io::Result<OscListener> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(OscListener { socket })
+    }
+
+    /// Messages received since the last poll, without waiting for more.
+    /// Malformed packets are dropped.
+    pub fn poll(&self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        let mut packet = vec![0; MAX_PACKET];
+        while let Ok(size) = self.socket.recv(&mut packet) {
+            parse(&packet[..size], &mut messages);
+        }
+        messages
+    }
+}
+
+fn point(args: &[f32]) -> Option<Point3<f32>> {
+    match *args {
+        [x, y, z] => Some(Point3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+// The scene's sphere numbered `index`, counting spheres only
+fn sphere<'a>(scene: &'a mut Scene, index: &str) -> Result<&'a mut Sphere, String> {
+    let nth = index.parse().map_err(|_| format!("no sphere {}", index))?;
+    scene.spheres_mut().nth(nth).ok_or_else(|| format!("no sphere {}", index))
+}
+
+/// Sets the parameter `message` names and returns what that changed, or
+/// describes why it couldn't.
+pub fn apply(
+    message: &Message,
+    scene: &mut Scene,
+    camera: &mut Camera,
+    render_options: &mut RenderOptions,
+) -> Result<Damage, String> {
+    let path: Vec<&str> = message.address.trim_start_matches('/').split('/').collect();
+    let args = message.args.as_slice();
+    let bad = || format!("bad arguments for {}", message.address);
+    match (path.as_slice(), args) {
+        (["exposure"], &[stops]) => render_options.exposure = stops,
+        (["light", i, "intensity"], &[value]) => {
+            let light = scene.lights.get_mut(i.parse()?).ok_or_else(|| format!("no light {}", i))?;
+            *light = light.with_intensity(value);
+        }
+        (["light", "add"], &[x, y, z, intensity]) => {
+            let light = Light::Point {
+                position: Point3::new(x, y, z),
+                color: Vector3::new(1.0, 1.0, 1.0),
+                intensity,
+                shaping: None,
+            };
+            return edit::apply(scene, Edit::AddLight(light));
+        }
+        (["light", i, "remove"], &[]) => {
+            return edit::apply(scene, Edit::RemoveLight(i.parse()?));
+        }
+        (["object", i, "move"], &[x, y, z]) => {
+            let offset = Vector3::new(x, y, z);
+            return edit::apply(scene, Edit::Move { object: i.parse()?, offset });
+        }
+        (["object", i, "material", m], &[]) => {
+            let (object, material) = (i.parse()?, m.parse()?);
+            return edit::apply(scene, Edit::AssignMaterial { object, material });
+        }
+        (["material", m, "albedo"], &[r, g, b]) => {
+            let material = m.parse()?;
+            let value = match scene.materials.get(material) {
+                Some(value) => Material {
+                    albedo: Vector3::new(r, g, b),
+                    ..*value
+                },
+                None => return Err(format!("no material {}", m)),
+            };
+            return edit::apply(scene, Edit::UpdateMaterial { material, value });
+        }
+        (["object", i, "albedo"], &[r, g, b]) => {
+            let object = i.parse()?;
+            let material = match scene.primitives.get(object) {
+                Some(primitive) => Material {
+                    albedo: Vector3::new(r, g, b),
+                    ..*primitive.shape().material()
+                },
+                None => return Err(format!("no object {}", i)),
+            };
+            return edit::apply(scene, Edit::SetMaterial { object, material });
+        }
+        (["sphere", i, "position"], _) => {
+            let center = point(args).ok_or_else(bad)?;
+            sphere(scene, i)?.center = center;
+        }
+        (["sphere", i, "radius"], &[radius]) => sphere(scene, i)?.radius = radius,
+        (["camera", "position"], _) => camera.position = point(args).ok_or_else(bad)?,
+        (["exposure"], _)
+        | (["light", _, "intensity"], _)
+        | (["light", "add"], _)
+        | (["light", _, "remove"], _)
+        | (["sphere", _, "radius"], _)
+        | (["object", _, "move"], _)
+        | (["object", _, "material", _], _)
+        | (["object", _, "albedo"], _)
+        | (["material", _, "albedo"], _) => return Err(bad()),
+        _ => return Err(format!("unknown address {}", message.address)),
+    }
+    Ok(Damage::Everything)
+}
@@ -0,0 +1,294 @@
+//! Writing offline frames at 8 or 16 bits per channel, as TIFF for `.tif`
+//! and `.tiff` paths and otherwise in the format the extension names, or as
+//! linear floating point, Radiance HDR for `.hdr` paths and OpenEXR for
+//! `.exr` paths.
+//!
+//! PNG and TIFF outputs carry the settings they were rendered with, as
+//! `tEXt` chunks and as the TIFF `ImageDescription`, so an image can be
+//! traced back to what produced it.
+
+use im::hdr::HDREncoder;
+use im::{Rgb, Rgba, RgbaImage};
+use png::{self, text_metadata::TEXtChunk};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::tags::Tag;
+
+use render::Frame;
+
+/// Key/value settings embedded in an output, plus the time from `new` to
+/// the output being written.
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+    started: Instant,
+}
+
+impl Metadata {
+    pub fn new() -> Metadata {
+        Metadata {
+            entries: Vec::new(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn add(&mut self, key: &str, value: String) {
+        self.entries.push((key.to_string(), value));
+    }
+
+    /// The value added for `key`, if any.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|e| e.0 == key).map(|e| e.1.as_str())
+    }
+
+    fn entries(&self) -> Vec<(String, String)> {
+        let mut entries = vec![(
+            "Software".to_string(),
+            format!("rs-tracer {}", env!("CARGO_PKG_VERSION")),
+        )];
+        entries.extend(self.entries.iter().cloned());
+        let seconds = self.started.elapsed().as_secs_f64();
+        entries.push(("render-seconds".to_string(), format!("{:.3}", seconds)));
+        entries
+    }
+
+    /// Writes the entries as PNG text chunks. PNG allows these after the
+    /// image data, by which point the render time is known.
+    pub fn write_png<W: Write>(&self, writer: &mut png::Writer<W>) -> Result<(), String> {
+        for (key, value) in self.entries() {
+            writer
+                .write_text_chunk(&TEXtChunk::new(key, value))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        let lines: Vec<String> = self
+            .entries()
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// The 64-bit FNV-1a hash of no bytes, from which hashes start.
+pub const FNV1A_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV1A_PRIME: u64 = 0x100_0000_01b3;
+
+/// Carries the 64-bit FNV-1a hash `hash` on over `bytes`, so that from
+/// `FNV1A_OFFSET` it's the hash of `bytes` alone.
+pub fn fnv1a(bytes: &[u8], hash: u64) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(FNV1A_PRIME))
+}
+
+/// FNV-1a hash of a file's contents, as hex.
+pub fn file_hash(path: &Path) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(format!("{:016x}", fnv1a(&bytes, FNV1A_OFFSET)))
+}
+
+fn is_tiff(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"))
+}
+
+/// Whether `path` names a linear floating-point output, `.hdr` or `.exr`.
+pub fn is_hdr(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("hdr") || e.eq_ignore_ascii_case("exr"))
+}
+
+fn is_exr(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("exr"))
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"))
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write_png(
+    path: &Path,
+    (width, height): (u32, u32),
+    depth: png::BitDepth,
+    data: &[u8],
+    metadata: &Metadata,
+) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(create(path)?, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(data).map_err(|e| e.to_string())?;
+    metadata.write_png(&mut writer)?;
+    writer.finish().map_err(|e| e.to_string())
+}
+
+pub fn save(img: &RgbaImage, path: &Path, metadata: &Metadata) -> Result<(), String> {
+    if is_tiff(path) {
+        let mut encoder = TiffEncoder::new(create(path)?).map_err(|e| e.to_string())?;
+        let mut image = encoder
+            .new_image::<colortype::RGBA8>(img.width(), img.height())
+            .map_err(|e| e.to_string())?;
+        image
+            .encoder()
+            .write_tag(Tag::ImageDescription, metadata.description().as_str())
+            .map_err(|e| e.to_string())?;
+        return image.write_data(img).map_err(|e| e.to_string());
+    }
+    if is_png(path) {
+        return write_png(path, img.dimensions(), png::BitDepth::Eight, img, metadata);
+    }
+    img.save(path).map_err(|e| e.to_string())
+}
+
+/// Writes a 16-bit frame, which only PNG and TIFF can hold.
+pub fn save16(img: &Frame<Rgba<u16>>, path: &Path, metadata: &Metadata) -> Result<(), String> {
+    if is_tiff(path) {
+        let mut encoder = TiffEncoder::new(create(path)?).map_err(|e| e.to_string())?;
+        let mut image = encoder
+            .new_image::<colortype::RGBA16>(img.width(), img.height())
+            .map_err(|e| e.to_string())?;
+        image
+            .encoder()
+            .write_tag(Tag::ImageDescription, metadata.description().as_str())
+            .map_err(|e| e.to_string())?;
+        return image.write_data(img).map_err(|e| e.to_string());
+    }
+    if !is_png(path) {
+        return Err("16-bit output needs a .png or .tif path".to_string());
+    }
+    // PNG stores samples big-endian
+    let bytes: Vec<u8> = img.iter().flat_map(|s| s.to_be_bytes()).collect();
+    write_png(path, img.dimensions(), png::BitDepth::Sixteen, &bytes, metadata)
+}
+
+/// Writes floating-point RGBA `pixels`, `width` by `height`, as OpenEXR
+/// for `.exr` paths and otherwise as Radiance HDR, which has no room for
+/// metadata or alpha.
+pub fn save_hdr(pixels: &[f32], (width, height): (u32, u32), path: &Path) -> Result<(), String> {
+    if is_exr(path) {
+        let mut out = create(path)?;
+        return write_exr(pixels, (width, height), &mut out)
+            .and_then(|()| out.flush())
+            .map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let rgb: Vec<Rgb<f32>> = pixels.chunks(4).map(|p| Rgb([p[0], p[1], p[2]])).collect();
+    HDREncoder::new(create(path)?)
+        .encode(&rgb, width as usize, height as usize)
+        .map_err(|e| e.to_string())
+}
+
+// Channels in the order EXR keeps them, by name, with their place in a pixel
+const EXR_CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    for text in &[name, kind] {
+        header.extend_from_slice(text.as_bytes());
+        header.push(0);
+    }
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// A single-part scanline OpenEXR image of 32-bit float channels, a line to
+// a chunk and uncompressed
+fn write_exr<W: Write>(pixels: &[f32], (width, height): (u32, u32), out: &mut W) -> io::Result<()> {
+    let mut channels = Vec::new();
+    for &(name, _) in &EXR_CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        // FLOAT samples, not perceptually linear, sampled at every pixel
+        for field in &[2i32, 0, 1, 1] {
+            channels.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    channels.push(0);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|n: &i32| n.to_le_bytes())
+        .collect();
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630i32.to_le_bytes());
+    header.extend_from_slice(&2i32.to_le_bytes());
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    out.write_all(&header)?;
+
+    // The offset of every line's chunk, after this table
+    let line_bytes = width as usize * EXR_CHANNELS.len() * 4;
+    let first = (header.len() + height as usize * 8) as u64;
+    for y in 0..height as u64 {
+        out.write_all(&(first + y * (8 + line_bytes as u64)).to_le_bytes())?;
+    }
+    let mut line = Vec::with_capacity(line_bytes);
+    for (y, row) in pixels.chunks(width as usize * 4).enumerate() {
+        line.clear();
+        for &(_, offset) in &EXR_CHANNELS {
+            for pixel in row.chunks(4) {
+                line.extend_from_slice(&pixel[offset].to_le_bytes());
+            }
+        }
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(line_bytes as i32).to_le_bytes())?;
+        out.write_all(&line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn exr_lines_follow_their_offsets() {
+        let pixels = [0.5, 1.5, 2.5, 1.0, 3.0, 4.0, 5.0, 0.0, 6.0, 7.0, 8.0, 1.0];
+        let mut bytes = Vec::new();
+        write_exr(&pixels, (1, 3), &mut bytes).unwrap();
+        assert_eq!(int(&bytes, 0), 20000630);
+        assert_eq!(int(&bytes, 4), 2);
+        // The offset table ends the header, one offset to a line
+        let first = bytes.len() - 3 * (8 + 16);
+        for y in 0..3 {
+            let entry = first - (3 - y) * 8;
+            let mut offset = [0; 8];
+            offset.copy_from_slice(&bytes[entry..entry + 8]);
+            let chunk = u64::from_le_bytes(offset) as usize;
+            assert_eq!(chunk, first + y * 24);
+            assert_eq!(int(&bytes, chunk), y as i32);
+            assert_eq!(int(&bytes, chunk + 4), 16);
+            let samples: Vec<f32> =
+                (0..4).map(|i| f32::from_bits(int(&bytes, chunk + 8 + i * 4) as u32)).collect();
+            let pixel = &pixels[y * 4..y * 4 + 4];
+            assert_eq!(samples, [pixel[3], pixel[2], pixel[1], pixel[0]]);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use cgmath::{Point3, Vector3};
+use im::Rgb;
+use std::f32::consts::PI;
+use std::path::Path;
+
+use geometry::Ray;
+use output;
+use progress::Progress;
+use render::{checked_radiance, RenderOptions};
+use scene::Scene;
+
+// Direction through an equirectangular texel. The image centre looks down -Z,
+// matching the interactive camera, with +Y at the top row.
+fn equirect_direction(u: f32, v: f32) -> Vector3<f32> {
+    let phi = 2.0 * PI * u - PI;
+    let theta = PI * v;
+    Vector3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+/// Where unit `direction` falls in an equirectangular image laid out as
+/// `render_equirect` lays it out, as (u, v) in the unit square.
+pub fn equirect_uv(direction: Vector3<f32>) -> (f32, f32) {
+    let phi = direction.x.atan2(-direction.z);
+    let theta = direction.y.clamp(-1.0, 1.0).acos();
+    ((phi + PI) / (2.0 * PI), theta / PI)
+}
+
+/// Renders the scene as seen from `position` in every direction into a
+/// 2:1 equirectangular image of unclamped linear radiance.
+pub fn render_equirect(
+    scene: &Scene,
+    render_options: &RenderOptions,
+    position: Point3<f32>,
+    height: u32,
+) -> Vec<Rgb<f32>> {
+    let width = height * 2;
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    let mut progress = Progress::new("rows", height as usize);
+    for px_y in 0..height {
+        for px_x in 0..width {
+            let u = ((px_x as f32) + 0.5) / (width as f32);
+            let v = ((px_y as f32) + 0.5) / (height as f32);
+            let ray = Ray {
+                origin: position,
+                direction: equirect_direction(u, v),
+            };
+            let color = checked_radiance(scene, &ray, render_options, px_x, px_y);
+            pixels.push(Rgb([color.x, color.y, color.z]));
+        }
+        progress.tick();
+    }
+    pixels
+}
+
+/// Writes an equirectangular environment map captured at `position`, as
+/// OpenEXR for `.exr` paths and otherwise as Radiance HDR.
+pub fn export_hdri(
+    scene: &Scene,
+    render_options: &RenderOptions,
+    position: Point3<f32>,
+    height: u32,
+    path: &Path,
+) -> Result<(), String> {
+    let pixels: Vec<f32> = render_equirect(scene, render_options, position, height)
+        .iter()
+        .flat_map(|p| [p.data[0], p.data[1], p.data[2], 1.0])
+        .collect();
+    output::save_hdr(&pixels, (height * 2, height), path)
+}
@@ -0,0 +1,111 @@
+//! Particle emitters whose particles are traced as small spheres.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use geometry::Sphere;
+use material::Material;
+
+pub struct EmitterSettings {
+    pub position: Point3<f32>,
+    // Particles spawned per second
+    pub rate: f32,
+    pub velocity: Vector3<f32>,
+    // Radius of the ball of random velocity added to `velocity`
+    pub spread: f32,
+    pub acceleration: Vector3<f32>,
+    // Seconds a particle lives for
+    pub lifetime: f32,
+    // Particle radius at birth and at the end of its life
+    pub start_radius: f32,
+    pub end_radius: f32,
+    pub seed: u32,
+}
+
+struct Particle {
+    sphere: Sphere,
+    velocity: Vector3<f32>,
+    age: f32,
+}
+
+pub struct Emitter {
+    settings: EmitterSettings,
+    particles: Vec<Particle>,
+    // Fraction of a particle owed from previous steps
+    pending: f32,
+    rng: XorShiftRng,
+}
+
+impl Emitter {
+    pub fn new(settings: EmitterSettings) -> Emitter {
+        // XorShift needs a non-zero seed
+        let rng = XorShiftRng::from_seed([settings.seed, 0x9e37_79b9, 0x7f4a_7c15, 1]);
+        Emitter {
+            settings,
+            particles: Vec::new(),
+            pending: 0.0,
+            rng,
+        }
+    }
+
+    pub fn settings(&self) -> &EmitterSettings {
+        &self.settings
+    }
+
+    fn random_in_unit_ball(&mut self) -> Vector3<f32> {
+        loop {
+            let v = Vector3::new(
+                self.rng.gen_range(-1.0, 1.0),
+                self.rng.gen_range(-1.0, 1.0),
+                self.rng.gen_range(-1.0, 1.0),
+            );
+            if v.magnitude2() <= 1.0 {
+                return v;
+            }
+        }
+    }
+
+    /// Advances the simulation by `dt` seconds: ages and moves live
+    /// particles, retires expired ones and spawns new ones at `rate`.
+    pub fn step(&mut self, dt: f32) {
+        let lifetime = self.settings.lifetime;
+        self.particles.retain(|p| p.age + dt < lifetime);
+
+        let (acceleration, start_radius, end_radius) = (
+            self.settings.acceleration,
+            self.settings.start_radius,
+            self.settings.end_radius,
+        );
+        for particle in &mut self.particles {
+            particle.age += dt;
+            particle.velocity += acceleration * dt;
+            particle.sphere.center += particle.velocity * dt;
+            let t = particle.age / lifetime;
+            particle.sphere.radius = start_radius + (end_radius - start_radius) * t;
+        }
+
+        self.pending += self.settings.rate * dt;
+        while self.pending >= 1.0 {
+            self.pending -= 1.0;
+            let velocity = self.settings.velocity + self.random_in_unit_ball() * self.settings.spread;
+            self.particles.push(Particle {
+                sphere: Sphere {
+                    center: self.settings.position,
+                    radius: start_radius,
+                    material: Material::default(),
+                },
+                velocity,
+                age: 0.0,
+            });
+        }
+    }
+
+    pub fn spheres<'a>(&'a self) -> impl Iterator<Item = &'a Sphere> + 'a {
+        self.particles.iter().map(|p| &p.sphere)
+    }
+
+    /// Live particles as their sphere, velocity and age in seconds.
+    pub fn particles<'a>(&'a self) -> impl Iterator<Item = (&'a Sphere, Vector3<f32>, f32)> + 'a {
+        self.particles.iter().map(|p| (&p.sphere, p.velocity, p.age))
+    }
+}
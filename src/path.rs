@@ -0,0 +1,291 @@
+//! A path tracing integrator, for global illumination: light that reaches a
+//! surface off other diffuse surfaces, and from emissive ones.
+//!
+//! The usual integrator stops at the first diffuse surface a camera ray
+//! meets, through any mirrors and glass in front of it. A path carries on
+//! from there in a random direction, cosine-weighted about the normal, and
+//! at every diffuse surface along it gathers the light its shadow rays find
+//! straight from the scene's lights, as the usual integrator does. Whatever
+//! emissive surfaces the path runs into light it too, so they act as area
+//! lights. At a surface that also mirrors or refracts, the path takes one of
+//! the ways on at random in proportion to how the light divides there.
+//!
+//! Paths end after `RenderOptions::max_depth` bounces, or sooner by Russian
+//! roulette once their throughput has dwindled, which trims long paths that
+//! carry little light without biasing the average.
+
+use cgmath::{ElementWise, InnerSpace, Vector3};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use caustics;
+use geometry::{reflect, Ray};
+use material::Material;
+use primitive::{Hit, Intersectable};
+use render::{self, shadow_rays, Color, LightSum, RenderOptions, ShadowRay};
+use sampling;
+use scene::{closest_intersection, occluded, Scene};
+
+// Bounces after which paths are subject to Russian roulette
+const ROULETTE_DEPTH: u32 = 3;
+// Lowest chance a path has of surviving a round of roulette, so a dim path
+// that survives isn't weighted up enough to show as a firefly
+const MIN_SURVIVAL: f32 = 0.05;
+
+/// How the light arriving along camera rays is estimated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    // Lights sampled at the first diffuse surface, seen through mirrors and
+    // glass
+    Direct,
+    // Paths that bounce off diffuse surfaces too
+    Path,
+    // The same paths, traced a tile at a time in phases over queues of rays
+    Wavefront,
+}
+
+impl Integrator {
+    pub fn from_name(name: &str) -> Option<Integrator> {
+        match name {
+            "direct" => Some(Integrator::Direct),
+            "path" => Some(Integrator::Path),
+            "wavefront" => Some(Integrator::Wavefront),
+            _ => None,
+        }
+    }
+
+    /// The name `from_name` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Integrator::Direct => "direct",
+            Integrator::Path => "path",
+            Integrator::Wavefront => "wavefront",
+        }
+    }
+}
+
+// Finalizer of MurmurHash3, to spread the bits of similar seeds
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+// Generator for the path starting along `ray` in progressive pass `pass`.
+// Seeding it from the ray gives every camera ray a path of its own, while
+// rendering the same frame twice gives the same image.
+fn path_rng(ray: &Ray, pass: u32) -> XorShiftRng {
+    let d = ray.direction;
+    XorShiftRng::from_seed([
+        mix(d.x.to_bits()),
+        mix(d.y.to_bits()),
+        mix(d.z.to_bits() ^ pass),
+        // Never all zero, which the generator can't start from
+        0x9e37_79b9,
+    ])
+}
+
+/// A path being traced, with the light it has gathered so far.
+pub struct Path {
+    rng: XorShiftRng,
+    color: Color,
+    // Fraction of the light arriving at the current surface that makes it
+    // back along the path to the camera
+    throughput: Color,
+    // Whether the path got here by a diffuse bounce, in which case dome
+    // lights were already sampled at the surface it left
+    diffuse_bounce: bool,
+    // Bounces so far
+    depth: u32,
+    // Light at the surface last reached that waits on its shadow rays
+    pending: Option<Shading>,
+}
+
+// What a surface a path reaches adds to the path's light, but for the light
+// its shadow rays find
+struct Shading {
+    // Light that needs no shadow rays: caustics in a scene with lights, and
+    // the headlight of one without
+    light: Color,
+    lit: bool,
+    albedo: Color,
+    specular: f32,
+    emissive: Color,
+    // The path's throughput at the surface, and the share of its light the
+    // surface scatters diffusely
+    throughput: Color,
+    diffuse: f32,
+}
+
+impl Path {
+    /// A path starting along camera ray `ray` in progressive pass `pass`.
+    pub fn new(ray: &Ray, pass: u32) -> Path {
+        Path {
+            rng: path_rng(ray, pass),
+            color: Vector3::new(0.0, 0.0, 0.0),
+            throughput: Vector3::new(1.0, 1.0, 1.0),
+            diffuse_bounce: false,
+            depth: 0,
+            pending: None,
+        }
+    }
+
+    /// The light the path has gathered.
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Takes the path on along `ray`, its latest, to `found`, the closest
+    /// intersection along it: gathers the light there and picks the ray the
+    /// path carries on along, or None once the path ends.
+    pub fn extend(
+        &mut self,
+        scene: &Scene,
+        ray: &Ray,
+        found: Option<(&dyn Intersectable, Hit)>,
+        render_options: &RenderOptions,
+    ) -> Option<Ray> {
+        let mut light = LightSum::new();
+        let next = self.shade(scene, ray, found, render_options, |shadow| {
+            let blocked = occluded(scene, &shadow.ray, shadow.distance, render_options);
+            light.add(&shadow, blocked);
+        });
+        self.gather(light.total());
+        next
+    }
+
+    /// `extend`, but rather than tracing the shadow rays towards the lights
+    /// at the surface hit, hands each to `shadow`. Their light is added by
+    /// `gather` once they have been traced.
+    pub fn shade<F>(
+        &mut self,
+        scene: &Scene,
+        ray: &Ray,
+        found: Option<(&dyn Intersectable, Hit)>,
+        render_options: &RenderOptions,
+        shadow: F,
+    ) -> Option<Ray>
+    where
+        F: FnMut(ShadowRay),
+    {
+        let (shape, hit) = match found {
+            Some(found) => found,
+            None => {
+                if !self.diffuse_bounce {
+                    let sky = scene.lights.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, light| {
+                        sum + light.background(ray.direction)
+                    });
+                    self.color += self.throughput.mul_element_wise(sky);
+                }
+                return None;
+            }
+        };
+        let point = ray.origin + ray.direction * hit.distance;
+        let (outward, material) = match (hit.cap, render_options.clip_cap) {
+            (Some(cap), Some(color)) => (cap, Material::diffuse(color)),
+            _ => (shape.normal(point, hit.part), *shape.material()),
+        };
+        let outward = outward.normalize();
+        // Shaded from the side the ray arrives on, for the same surfaces as
+        // the usual integrator
+        let facing = outward.dot(-ray.direction);
+        let two_sided = hit.cap.is_some() || render_options.clipping || shape.two_sided();
+        let normal = if two_sided && facing < 0.0 { -outward } else { outward };
+
+        // Light at this surface straight from the lights, as the usual
+        // integrator finds it, and from the surface itself
+        let to_viewer = -ray.direction.normalize();
+        let lit = !scene.lights.is_empty();
+        let light = if lit {
+            shadow_rays(scene, point, normal, to_viewer, &material, render_options, shadow);
+            caustics::caustic_light(scene, point, normal, render_options)
+        } else {
+            Vector3::new(1.0, 1.0, 1.0) * 0f32.max(normal.dot(to_viewer))
+        };
+        let albedo = material.albedo_at(point);
+        let split = material.split(ray.direction, outward);
+        self.pending = Some(Shading {
+            light,
+            lit,
+            albedo,
+            specular: material.specular,
+            emissive: material.emissive,
+            throughput: self.throughput,
+            diffuse: split.diffuse,
+        });
+        if self.depth >= render_options.max_depth {
+            return None;
+        }
+
+        // Carry on one way, chosen in proportion to the light taking it, so
+        // the choice needs no weighting of its own
+        let side = if normal.dot(ray.direction) > 0.0 { -normal } else { normal };
+        let choice: f32 = self.rng.gen();
+        let next = if choice < split.diffuse {
+            let direction = sampling::cosine_hemisphere(normal, (self.rng.gen(), self.rng.gen()));
+            self.throughput.mul_assign_element_wise(albedo * (1.0 - material.specular));
+            self.diffuse_bounce = true;
+            Ray::from_surface(point, normal, direction)
+        } else if !render_options.reflections {
+            return None;
+        } else if choice < split.diffuse + split.reflected {
+            self.diffuse_bounce = false;
+            Ray::from_surface(point, side, reflect(ray.direction, side))
+        } else if split.refracted > 0.0 {
+            self.diffuse_bounce = false;
+            Ray::from_surface(point, -side, split.refraction)
+        } else {
+            return None;
+        };
+
+        if self.depth + 1 >= ROULETTE_DEPTH {
+            let throughput = self.throughput;
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+            let survival = survival.max(MIN_SURVIVAL);
+            if self.rng.gen::<f32>() >= survival {
+                return None;
+            }
+            self.throughput /= survival;
+        }
+        self.depth += 1;
+        Some(next)
+    }
+
+    /// Adds the light at the surface `shade` last reached, given the light
+    /// and highlights its shadow rays let through.
+    pub fn gather(&mut self, (direct, highlights): (Color, Color)) {
+        let shading = match self.pending.take() {
+            Some(shading) => shading,
+            None => return,
+        };
+        let (light, highlights) = if shading.lit {
+            (direct + shading.light, highlights)
+        } else {
+            (shading.light, Vector3::new(0.0, 0.0, 0.0))
+        };
+        let diffuse = light.mul_element_wise(shading.albedo) * (1.0 - shading.specular);
+        let local = diffuse + highlights + shading.emissive;
+        self.color += shading.throughput.mul_element_wise(local) * shading.diffuse;
+    }
+}
+
+/// Radiance along camera ray `ray`, estimated from a single path.
+pub fn radiance(scene: &Scene, ray: &Ray, render_options: &RenderOptions) -> Color {
+    // False colour has no light to gather
+    if render_options.heatmap.is_some() {
+        return render::radiance(scene, ray, render_options, 0);
+    }
+    let mut path = Path::new(ray, render_options.pass);
+    let mut ray = Ray {
+        origin: ray.origin,
+        direction: ray.direction,
+    };
+    loop {
+        let found = closest_intersection(scene, &ray, render_options);
+        match path.extend(scene, &ray, found, render_options) {
+            Some(next) => ray = next,
+            None => return path.color(),
+        }
+    }
+}
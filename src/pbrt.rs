@@ -0,0 +1,730 @@
+//! Loader for a subset of pbrt-v3 scene files (`.pbrt`), so the same scene
+//! can be rendered here and by pbrt and the results compared.
+//!
+//! `LookAt`, `Translate`, `Scale`, `Rotate`, `Transform`, `ConcatTransform`
+//! and `Identity` build the current transform, which `AttributeBegin`/`End`
+//! and `TransformBegin`/`End` save and restore. The transform in effect at
+//! `Camera` places a `perspective` camera, whose `fov` is taken as the
+//! vertical one, as pbrt has it for images wider than they are tall, and
+//! whose `lensradius` and `focaldistance` give depth of field.
+//!
+//! `sphere` and `trianglemesh` shapes are loaded, made of the current
+//! material: `matte`, `plastic`, `mirror`, `glass` or `metal`, set directly
+//! or through `MakeNamedMaterial` and `NamedMaterial`. A `diffuse` area
+//! light makes the shapes after it glow. The `path` and `wavefront`
+//! integrators find glowing surfaces and are lit by them; under the
+//! `direct` integrator they glow only to the eye and light nothing.
+//! `point`, `distant` and `infinite` light sources become point,
+//! directional and dome lights.
+//!
+//! pbrt measures light as radiance, where the tracer shows a white diffuse
+//! surface at the illuminance reaching it, pi times brighter; lights are
+//! scaled down to match, so both renderers give the same pixel values.
+//! pbrt's coordinates are left-handed, so the scene is mirrored in Z to keep
+//! its images the right way round. Film, sampler and integrator settings
+//! come from the command line instead, and anything else is skipped with a
+//! warning. `Include` files are found as USD assets are.
+
+use cgmath::{
+    Deg, ElementWise, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform,
+    Vector3, Vector4,
+};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use camera::Camera;
+use geometry::Sphere;
+use light::Light;
+use material::{self, Material};
+use mesh::Mesh;
+use primitive::Primitive;
+use render::Color;
+use resolve::AssetResolver;
+use scene::{Scene, Units};
+use usd::Stage;
+
+// pbrt's own default field of view, in degrees, and distance in focus
+const DEFAULT_FOV: f32 = 90.0;
+const DEFAULT_FOCAL_DISTANCE: f32 = 1e6;
+
+// Render settings the command line gives instead
+const RENDER_SETTINGS: [&str; 5] = ["Film", "Sampler", "Integrator", "PixelFilter", "Accelerator"];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f32),
+    Str(String),
+    Open,
+    Close,
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '[' || c == ']' {
+            tokens.push((if c == '[' { Token::Open } else { Token::Close }, line));
+            i += 1;
+        } else if c == '"' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\n' {
+                    return Err(format!("line {}: unterminated string", line));
+                }
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(format!("line {}: unterminated string", line));
+            }
+            tokens.push((Token::Str(chars[start..i].iter().collect()), line));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            let start = i;
+            let in_number = |c: char| c.is_ascii_alphanumeric() || "+-.".contains(c);
+            while i < chars.len() && in_number(chars[i]) {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text.parse().map_err(|_| format!("line {}: bad number {}", line, text))?;
+            tokens.push((Token::Number(n), line));
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else {
+            return Err(format!("line {}: unexpected character {:?}", line, c));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f32),
+    Str(String),
+}
+
+/// A parameter of a directive, such as `"float radius" [2]`.
+#[derive(Clone, Debug)]
+struct Param {
+    kind: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+/// One directive with its arguments and parameters.
+#[derive(Clone, Debug)]
+struct Directive {
+    name: String,
+    line: usize,
+    // Leading arguments, such as a shape's type or `LookAt`'s numbers, with
+    // brackets flattened away
+    args: Vec<Value>,
+    params: Vec<Param>,
+}
+
+impl Directive {
+    fn numbers(&self, count: usize) -> Result<Vec<f32>, String> {
+        let numbers: Vec<f32> = self
+            .args
+            .iter()
+            .filter_map(|a| match *a {
+                Value::Number(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        if numbers.len() != count {
+            return Err(format!("{} takes {} numbers", self.name, count));
+        }
+        Ok(numbers)
+    }
+
+    // The first argument, for directives naming a type or a file
+    fn kind(&self) -> Result<&str, String> {
+        match self.args.first() {
+            Some(Value::Str(s)) => Ok(s),
+            _ => Err(format!("{} needs a name", self.name)),
+        }
+    }
+
+    fn param(&self, name: &str) -> Option<&Param> {
+        self.params.iter().find(|p| p.name == name)
+    }
+
+    fn floats(&self, name: &str) -> Option<Vec<f32>> {
+        self.param(name).map(|p| {
+            p.values
+                .iter()
+                .filter_map(|v| match *v {
+                    Value::Number(n) => Some(n),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    fn float(&self, name: &str, default: f32) -> f32 {
+        self.floats(name).and_then(|v| v.first().cloned()).unwrap_or(default)
+    }
+
+    // An RGB parameter, or a grey from a single number; spectra and
+    // blackbodies aren't understood
+    fn color(&self, name: &str, default: Color) -> Color {
+        let is_rgb = self.param(name).is_some_and(|p| {
+            ["rgb", "color", "float"].contains(&p.kind.as_str())
+        });
+        if !is_rgb {
+            return default;
+        }
+        match self.floats(name).as_deref() {
+            Some(&[r, g, b]) => Vector3::new(r, g, b),
+            Some(&[grey]) => Vector3::new(grey, grey, grey),
+            _ => default,
+        }
+    }
+
+    fn point(&self, name: &str, default: Point3<f32>) -> Point3<f32> {
+        match self.floats(name).as_deref() {
+            Some(&[x, y, z]) => Point3::new(x, y, z),
+            _ => default,
+        }
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        self.param(name).and_then(|p| match p.values.first() {
+            Some(Value::Str(s)) => Some(s.as_str()),
+            _ => None,
+        })
+    }
+}
+
+fn parse(tokens: &[(Token, usize)]) -> Result<Vec<Directive>, String> {
+    let mut directives = Vec::new();
+    let mut i = 0;
+    // One value or a bracketed list of them
+    let values = |i: &mut usize| -> Result<Vec<Value>, String> {
+        let mut values = Vec::new();
+        let bracketed = tokens.get(*i).map(|t| &t.0) == Some(&Token::Open);
+        if bracketed {
+            *i += 1;
+        }
+        while let Some(&(ref token, line)) = tokens.get(*i) {
+            match *token {
+                Token::Number(n) => values.push(Value::Number(n)),
+                Token::Str(ref s) => values.push(Value::Str(s.clone())),
+                Token::Ident(ref word) if word == "true" || word == "false" => {
+                    values.push(Value::Str(word.clone()))
+                }
+                Token::Close if bracketed => {
+                    *i += 1;
+                    return Ok(values);
+                }
+                _ => return Err(format!("line {}: expected a value", line)),
+            }
+            *i += 1;
+            if !bracketed {
+                return Ok(values);
+            }
+        }
+        Err("unexpected end of file in a list".to_string())
+    };
+    while i < tokens.len() {
+        let (name, line) = match tokens[i] {
+            (Token::Ident(ref name), line) => (name.clone(), line),
+            (_, line) => return Err(format!("line {}: expected a directive", line)),
+        };
+        i += 1;
+        let mut directive = Directive {
+            name,
+            line,
+            args: Vec::new(),
+            params: Vec::new(),
+        };
+        // Arguments run until the first parameter, whose declaration names
+        // a type and a name
+        while let Some((token, _)) = tokens.get(i) {
+            match *token {
+                Token::Str(ref s) if s.split_whitespace().count() == 2 => break,
+                Token::Number(_) | Token::Str(_) | Token::Open => {
+                    directive.args.extend(values(&mut i)?);
+                }
+                _ => break,
+            }
+        }
+        while let Some(&(Token::Str(ref declaration), _)) = tokens.get(i) {
+            let mut words = declaration.split_whitespace();
+            let (kind, name) = match (words.next(), words.next()) {
+                (Some(kind), Some(name)) => (kind.to_string(), name.to_string()),
+                _ => return Err(format!("line {}: bad parameter {:?}", line, declaration)),
+            };
+            i += 1;
+            directive.params.push(Param {
+                kind,
+                name,
+                values: values(&mut i)?,
+            });
+        }
+        directives.push(directive);
+    }
+    Ok(directives)
+}
+
+// pbrt's mapping of a roughness to a microfacet width, fitted to match
+// perceived roughness
+fn roughness_to_width(roughness: f32) -> f32 {
+    let x = roughness.max(1e-3).ln();
+    1.62142 + 0.819955 * x + 0.1734 * x * x + 0.0171201 * x * x * x + 0.000640711 * x * x * x * x
+}
+
+// The material a `Material` or `MakeNamedMaterial` directive describes
+fn material(directive: &Directive, kind: &str, warnings: &mut Vec<String>) -> Material {
+    let grey = |v: f32| Vector3::new(v, v, v);
+    let strongest = |c: Color| c.x.max(c.y).max(c.z);
+    // pbrt takes roughness as a microfacet width, first remapping it unless
+    // told not to; the tracer's roughness is the square root of the width
+    let roughness = |default: f32| {
+        let value = directive.float("roughness", default);
+        let width = if directive.string("remaproughness") == Some("false") {
+            value
+        } else {
+            roughness_to_width(value)
+        };
+        width.max(0.0).sqrt()
+    };
+    match kind {
+        "matte" => Material::diffuse(directive.color("Kd", grey(0.5))),
+        "plastic" => {
+            let roughness = roughness(0.1);
+            Material {
+                albedo: directive.color("Kd", grey(0.25)),
+                specular: strongest(directive.color("Ks", grey(0.25))),
+                shininess: material::shininess(roughness),
+                roughness,
+                ..Material::default()
+            }
+        }
+        "mirror" => Material {
+            reflectivity: strongest(directive.color("Kr", grey(0.9))),
+            roughness: 0.0,
+            ..Material::default()
+        },
+        "glass" => Material {
+            transparency: 1.0,
+            ior: directive.float("index", 1.5),
+            roughness: 0.0,
+            ..Material::default()
+        },
+        "metal" => {
+            warnings.push(format!(
+                "line {}: metal loaded as a perfect mirror",
+                directive.line
+            ));
+            Material {
+                reflectivity: 1.0,
+                roughness: roughness(0.01),
+                ..Material::default()
+            }
+        }
+        other => {
+            warnings.push(format!(
+                "line {}: unsupported material {}, loaded as matte",
+                directive.line, other
+            ));
+            Material::diffuse(grey(0.5))
+        }
+    }
+}
+
+fn max_scale(m: &Matrix4<f32>) -> f32 {
+    m.x.truncate()
+        .magnitude()
+        .max(m.y.truncate().magnitude())
+        .max(m.z.truncate().magnitude())
+}
+
+// Triangles of a `trianglemesh` shape, placed by `transform`
+fn triangle_mesh(directive: &Directive, transform: &Matrix4<f32>) -> Result<Mesh, String> {
+    let triples = |name: &str| {
+        directive.floats(name).map(|values| {
+            values
+                .chunks(3)
+                .filter(|v| v.len() == 3)
+                .map(|v| Vector3::new(v[0], v[1], v[2]))
+                .collect::<Vec<_>>()
+        })
+    };
+    let positions = triples("P").ok_or("trianglemesh without P")?;
+    let indices = match directive.floats("indices") {
+        Some(indices) => indices,
+        // Three points make a triangle without indices
+        None if positions.len() == 3 => vec![0.0, 1.0, 2.0],
+        None => return Err("trianglemesh without indices".to_string()),
+    };
+    if indices.len() % 3 != 0 {
+        return Err(format!("{} indices don't make whole triangles", indices.len()));
+    }
+    let triangles: Vec<[u32; 3]> = indices
+        .chunks(3)
+        .map(|t| [t[0] as u32, t[1] as u32, t[2] as u32])
+        .collect();
+    let corners = triangles.iter().map(|&t| Some(t)).collect();
+    let mut mesh = Mesh::new(positions.into_iter().map(Point3::from_vec).collect(), triangles)?;
+    // Normals, when given, are one per point
+    if let Some(normals) = triples("N") {
+        mesh = mesh.with_normals(normals, corners)?;
+    }
+    Ok(mesh.transformed(transform))
+}
+
+// What `AttributeBegin` saves
+#[derive(Clone)]
+struct Attributes {
+    transform: Matrix4<f32>,
+    material: Material,
+    // Name of the material, if it was one made with `MakeNamedMaterial`
+    material_name: Option<String>,
+    // Radiance shapes glow with, from a `diffuse` area light
+    emission: Option<Color>,
+}
+
+struct Loader<'a> {
+    resolver: &'a AssetResolver,
+    stage: Stage,
+    current: Attributes,
+    attributes: Vec<Attributes>,
+    transforms: Vec<Matrix4<f32>>,
+    named: HashMap<String, Material>,
+}
+
+impl<'a> Loader<'a> {
+    fn include(&mut self, path: &Path) -> Result<(), String> {
+        let mut src = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut src))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let directives = tokenize(&src)
+            .and_then(|tokens| parse(&tokens))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        for directive in &directives {
+            self.apply(directive, dir)
+                .map_err(|e| format!("{}: line {}: {}", path.display(), directive.line, e))?;
+        }
+        Ok(())
+    }
+
+    // The current transform into the tracer's world: pbrt's, then mirrored
+    // from left-handed to right-handed coordinates
+    fn world(&self) -> Matrix4<f32> {
+        Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0) * self.current.transform
+    }
+
+    fn apply(&mut self, directive: &Directive, dir: &Path) -> Result<(), String> {
+        let transform = &mut self.current.transform;
+        match directive.name.as_str() {
+            "WorldBegin" | "Identity" => *transform = Matrix4::identity(),
+            "WorldEnd" => {}
+            "Translate" => {
+                let v = directive.numbers(3)?;
+                *transform = *transform * Matrix4::from_translation(Vector3::new(v[0], v[1], v[2]));
+            }
+            "Scale" => {
+                let v = directive.numbers(3)?;
+                *transform = *transform * Matrix4::from_nonuniform_scale(v[0], v[1], v[2]);
+            }
+            "Rotate" => {
+                let v = directive.numbers(4)?;
+                let axis = Vector3::new(v[1], v[2], v[3]).normalize();
+                *transform = *transform * Matrix4::from_axis_angle(axis, Deg(v[0]));
+            }
+            "LookAt" => {
+                let v = directive.numbers(9)?;
+                let eye = Point3::new(v[0], v[1], v[2]);
+                let look = Point3::new(v[3], v[4], v[5]);
+                let up = Vector3::new(v[6], v[7], v[8]);
+                // pbrt's camera looks down its +Z axis with +X to its right,
+                // as a left-handed look-at puts it
+                let forward = (look - eye).normalize();
+                let right = up.normalize().cross(forward).normalize();
+                let new_up = forward.cross(right);
+                let camera_to_world = Matrix4::from_cols(
+                    right.extend(0.0),
+                    new_up.extend(0.0),
+                    forward.extend(0.0),
+                    eye.to_homogeneous(),
+                );
+                let look_at = camera_to_world.invert().ok_or("degenerate LookAt")?;
+                *transform = *transform * look_at;
+            }
+            "Transform" | "ConcatTransform" => {
+                // Given column by column, as cgmath takes them
+                let m = directive.numbers(16)?;
+                let column = |i: usize| Vector4::new(m[i], m[i + 1], m[i + 2], m[i + 3]);
+                let matrix = Matrix4::from_cols(column(0), column(4), column(8), column(12));
+                if directive.name == "Transform" {
+                    *transform = matrix;
+                } else {
+                    *transform = *transform * matrix;
+                }
+            }
+            "AttributeBegin" => self.attributes.push(self.current.clone()),
+            "AttributeEnd" => {
+                self.current = self.attributes.pop().ok_or("AttributeEnd without AttributeBegin")?;
+            }
+            "TransformBegin" => self.transforms.push(*transform),
+            "TransformEnd" => {
+                *transform = self.transforms.pop().ok_or("TransformEnd without TransformBegin")?;
+            }
+            "Camera" => {
+                if directive.kind()? != "perspective" {
+                    let warning = format!("{} camera loaded as perspective", directive.kind()?);
+                    self.stage.warnings.push(format!("line {}: {}", directive.line, warning));
+                }
+                // The transform so far takes the world into camera space
+                let to_camera = self.current.transform;
+                let to_world = to_camera.invert().ok_or("degenerate camera transform")?;
+                let to_world = Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0) * to_world;
+                let camera = Camera {
+                    position: to_world.transform_point(Point3::origin()),
+                    up: to_world.transform_vector(Vector3::unit_y()).normalize(),
+                    at: to_world.transform_vector(Vector3::unit_z()).normalize(),
+                    fov: directive.float("fov", DEFAULT_FOV),
+                    aperture: 2.0 * directive.float("lensradius", 0.0),
+                    focal_distance: directive.float("focaldistance", DEFAULT_FOCAL_DISTANCE),
+                    keys: Vec::new(),
+                };
+                self.stage.cameras.push(("camera".to_string(), camera));
+            }
+            "Material" => {
+                let kind = directive.kind()?;
+                self.current.material = material(directive, kind, &mut self.stage.warnings);
+                self.current.material_name = None;
+            }
+            "MakeNamedMaterial" => {
+                let kind = directive.string("type").ok_or("named material without a type")?;
+                let material = material(directive, kind, &mut self.stage.warnings);
+                let name = directive.kind()?.to_string();
+                self.stage.add_material(name.clone(), material);
+                self.named.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let name = directive.kind()?;
+                self.current.material = *self
+                    .named
+                    .get(name)
+                    .ok_or_else(|| format!("no material named {}", name))?;
+                self.current.material_name = Some(name.to_string());
+            }
+            "AreaLightSource" => {
+                let kind = directive.kind()?;
+                if kind != "diffuse" {
+                    return Err(format!("unsupported area light {}", kind));
+                }
+                let white = Vector3::new(1.0, 1.0, 1.0);
+                let scale = directive.color("scale", white);
+                self.current.emission = Some(directive.color("L", white).mul_element_wise(scale));
+            }
+            "LightSource" => {
+                let light = self.light(directive)?;
+                self.stage.scene.lights.push(light);
+            }
+            "Shape" => self.shape(directive)?,
+            "Include" => {
+                let asset = self.resolver.resolve(directive.kind()?, dir)?;
+                self.include(&asset)?;
+            }
+            name if RENDER_SETTINGS.contains(&name) => self.stage.warnings.push(format!(
+                "line {}: {} taken from the command line instead",
+                directive.line, name
+            )),
+            other => self
+                .stage
+                .warnings
+                .push(format!("line {}: skipping unsupported {}", directive.line, other)),
+        }
+        Ok(())
+    }
+
+    fn light(&mut self, directive: &Directive) -> Result<Light, String> {
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        let scale = directive.color("scale", white);
+        let world = self.world();
+        // pbrt's radiance makes pixels pi times darker than the tracer's
+        // illuminance does
+        let intensity = 1.0 / PI;
+        match directive.kind()? {
+            "point" => Ok(Light::Point {
+                position: world.transform_point(directive.point("from", Point3::origin())),
+                color: directive.color("I", white).mul_element_wise(scale),
+                intensity,
+                shaping: None,
+            }),
+            "distant" => {
+                let from = directive.point("from", Point3::origin());
+                let to = directive.point("to", Point3::new(0.0, 0.0, 1.0));
+                Ok(Light::Directional {
+                    direction: world.transform_vector(to - from).normalize(),
+                    color: directive.color("L", white).mul_element_wise(scale),
+                    intensity,
+                })
+            }
+            "infinite" => {
+                if directive.string("mapname").is_some() {
+                    let warning = "environment map skipped, infinite light loaded as uniform";
+                    self.stage.warnings.push(format!("line {}: {}", directive.line, warning));
+                }
+                Ok(Light::Dome {
+                    color: directive.color("L", white).mul_element_wise(scale),
+                    intensity,
+                    portals: Vec::new(),
+                    environment: None,
+                })
+            }
+            other => Err(format!("unsupported light {}", other)),
+        }
+    }
+
+    fn shape(&mut self, directive: &Directive) -> Result<(), String> {
+        let world = self.world();
+        let mut material = self.current.material;
+        if let Some(emission) = self.current.emission {
+            // Both renderers show emission as it is, so it isn't scaled
+            // like the lights
+            material.emissive = emission;
+        }
+        let primitive = match directive.kind()? {
+            "sphere" => {
+                let radius = directive.float("radius", 1.0);
+                Primitive::Sphere(Sphere {
+                    center: world.transform_point(Point3::origin()),
+                    radius: radius * max_scale(&world),
+                    material,
+                })
+            }
+            "trianglemesh" => {
+                let mesh = triangle_mesh(directive, &world)?;
+                Primitive::Mesh(mesh.with_material(material))
+            }
+            other => {
+                let warning = format!("skipping unsupported {} shape", other);
+                self.stage.warnings.push(format!("line {}: {}", directive.line, warning));
+                return Ok(());
+            }
+        };
+        let object = self.stage.scene.primitives.push(primitive);
+        // Glowing shapes have a material of their own
+        if let (Some(name), None) = (&self.current.material_name, self.current.emission) {
+            self.stage.bind_material(object, name);
+        }
+        Ok(())
+    }
+}
+
+/// Loads the spheres, triangle meshes, lights and camera of the pbrt file
+/// at `path`, and of any files it includes, into a scene using `units`.
+pub fn load(path: &Path, units: &Units, resolver: &AssetResolver) -> Result<Stage, String> {
+    let mut loader = Loader {
+        resolver,
+        stage: Stage {
+            scene: Scene {
+                units: *units,
+                ..Scene::empty()
+            },
+            cameras: Vec::new(),
+            materials: Vec::new(),
+            warnings: Vec::new(),
+        },
+        current: Attributes {
+            transform: Matrix4::identity(),
+            material: Material::diffuse(Vector3::new(0.5, 0.5, 0.5)),
+            material_name: None,
+            emission: None,
+        },
+        attributes: Vec::new(),
+        transforms: Vec::new(),
+        named: HashMap::new(),
+    };
+    loader.include(path)?;
+    Ok(loader.stage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use resolve::scratch_dir;
+
+    fn load_text(name: &str, text: &str) -> Result<Stage, String> {
+        let path = scratch_dir(name).join("scene.pbrt");
+        fs::write(&path, text).unwrap();
+        load(&path, &Units::default(), &AssetResolver::new(Vec::new()))
+    }
+
+    #[test]
+    fn scene_loads() {
+        let stage = load_text(
+            "pbrt-scene",
+            r#"LookAt 0 1 5  0 1 0  0 1 0
+Camera "perspective" "float fov" [40]
+WorldBegin
+LightSource "point" "point from" [0 4 0] "rgb I" [1 1 1]
+MakeNamedMaterial "red" "string type" "matte" "rgb Kd" [0.9 0.1 0.1]
+AttributeBegin
+  NamedMaterial "red"
+  Translate 0 1 -2
+  Shape "sphere" "float radius" 2
+AttributeEnd
+Shape "trianglemesh" "integer indices" [0 1 2] "point P" [0 0 0 1 0 0 0 1 0]
+Shape "disk"
+WorldEnd
+"#,
+        )
+        .unwrap();
+        assert_eq!(stage.warnings, ["line 12: skipping unsupported disk shape"]);
+        let spheres: Vec<&Sphere> = stage.scene.spheres().collect();
+        assert_eq!(spheres.len(), 1);
+        assert_eq!(spheres[0].radius, 2.0);
+        // Mirrored in Z from pbrt's left-handed coordinates
+        assert_eq!(spheres[0].center, Point3::new(0.0, 1.0, 2.0));
+        assert_eq!(spheres[0].material.albedo, Vector3::new(0.9, 0.1, 0.1));
+        assert_eq!(stage.scene.primitives.len(), 2);
+        assert_eq!(stage.scene.lights.len(), 1);
+        assert_eq!(stage.cameras[0].1.fov, 40.0);
+        assert_eq!(stage.materials[0].0, "red");
+    }
+
+    #[test]
+    fn malformed_scenes_are_refused() {
+        let scenes = [
+            "WorldBegin\nNamedMaterial \"missing\"\nWorldEnd\n",
+            "WorldBegin\nAttributeEnd\nWorldEnd\n",
+            "WorldBegin\nLightSource \"spot\"\nWorldEnd\n",
+            "WorldBegin\nShape \"trianglemesh\" \"integer indices\" [0 1 3] \"point P\" [0 0 0]\n",
+            "WorldBegin\nShape \"sphere\" \"float radius [1\n",
+            "Include \"missing.pbrt\"\n",
+        ];
+        for (i, text) in scenes.iter().enumerate() {
+            assert!(load_text(&format!("pbrt-malformed-{}", i), text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Shapes rays are traced against. Intersection queries go through the
+//! `Intersectable` trait, so they don't change as kinds of shape are added;
+//! scenes hold their shapes as `Primitive`s.
+
+use cgmath::{Point3, Vector3};
+
+use cuboid::Cuboid;
+use geometry::{Ray, Sphere};
+use label::Label;
+use material::Material;
+use mesh::Mesh;
+use render::RenderOptions;
+
+/// Where a ray meets a shape.
+pub struct Hit {
+    pub distance: f32,
+    // Which part of the shape was hit, such as a mesh's triangle; 0 for
+    // shapes in one piece
+    pub part: usize,
+    // Normal of the cut face when the hit is on the cap of a clipped shape
+    pub cap: Option<Vector3<f32>>,
+}
+
+pub trait Intersectable: Sync {
+    /// Nearest hit along `ray` at or beyond its origin, with the render
+    /// options' clip planes applied.
+    fn intersect(&self, ray: &Ray, render_options: &RenderOptions) -> Option<Hit>;
+
+    /// Whether `ray` hits the shape beyond its origin and closer than
+    /// `max_distance`. Shapes with many parts can stop at the first one.
+    fn occludes(&self, ray: &Ray, max_distance: f32, render_options: &RenderOptions) -> bool {
+        self.intersect(ray, render_options)
+            .is_some_and(|hit| hit.distance > 0.0 && hit.distance < max_distance)
+    }
+
+    /// Surface normal, unnormalized, at `point` on `part`.
+    fn normal(&self, point: Point3<f32>, part: usize) -> Vector3<f32>;
+
+    /// What the surface is made of.
+    fn material(&self) -> &Material;
+
+    /// Value of the scalar attribute `name` at `point` on `part`, if the
+    /// shape has one by that name.
+    fn attribute(&self, _name: &str, _point: Point3<f32>, _part: usize) -> Option<f32> {
+        None
+    }
+
+    /// Whether the surface is shaded from both sides, like a sheet, rather
+    /// than only from outside, like a solid.
+    fn two_sided(&self) -> bool {
+        false
+    }
+
+    /// Corners of an axis-aligned box around the shape, (min, max).
+    fn bounds(&self) -> (Point3<f32>, Point3<f32>);
+}
+
+pub enum Primitive {
+    Sphere(Sphere),
+    Mesh(Mesh),
+    Cuboid(Cuboid),
+    Label(Label),
+}
+
+impl Primitive {
+    pub fn shape(&self) -> &dyn Intersectable {
+        match *self {
+            Primitive::Sphere(ref sphere) => sphere,
+            Primitive::Mesh(ref mesh) => mesh,
+            Primitive::Cuboid(ref cuboid) => cuboid,
+            Primitive::Label(ref label) => label,
+        }
+    }
+
+    pub fn as_sphere(&self) -> Option<&Sphere> {
+        match *self {
+            Primitive::Sphere(ref sphere) => Some(sphere),
+            _ => None,
+        }
+    }
+
+    pub fn as_sphere_mut(&mut self) -> Option<&mut Sphere> {
+        match *self {
+            Primitive::Sphere(ref mut sphere) => Some(sphere),
+            _ => None,
+        }
+    }
+
+    /// Moves the shape by `offset`.
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        match *self {
+            Primitive::Sphere(ref mut sphere) => sphere.center += offset,
+            Primitive::Mesh(ref mut mesh) => mesh.translate(offset),
+            Primitive::Cuboid(ref mut cuboid) => {
+                cuboid.min += offset;
+                cuboid.max += offset;
+            }
+            Primitive::Label(ref mut label) => label.center += offset,
+        }
+    }
+
+    pub fn set_material(&mut self, material: Material) {
+        match *self {
+            Primitive::Sphere(ref mut sphere) => sphere.material = material,
+            Primitive::Mesh(ref mut mesh) => mesh.set_material(material),
+            Primitive::Cuboid(ref mut cuboid) => cuboid.material = material,
+            Primitive::Label(ref mut label) => label.material = material,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Primitive::Sphere(_) => "sphere",
+            Primitive::Mesh(_) => "mesh",
+            Primitive::Cuboid(_) => "box",
+            Primitive::Label(_) => "label",
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+use im::RgbaImage;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use geometry::Ray;
+use progress::Progress;
+use render::{get_pixel_color, RenderOptions};
+use scene::Scene;
+
+// Faces in the usual cubemap order (+X, -X, +Y, -Y, +Z, -Z), named the way
+// most engines expect to find them on disk.
+const FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+// Direction through a face texel, following the OpenGL cubemap convention.
+// `s` and `t` are the texel coordinates remapped to [-1, 1], t pointing down.
+fn face_direction(face: usize, s: f32, t: f32) -> Vector3<f32> {
+    let direction = match face {
+        0 => Vector3::new(1.0, -t, -s),
+        1 => Vector3::new(-1.0, -t, s),
+        2 => Vector3::new(s, 1.0, t),
+        3 => Vector3::new(s, -1.0, -t),
+        4 => Vector3::new(s, -t, 1.0),
+        _ => Vector3::new(-s, -t, -1.0),
+    };
+    direction.normalize()
+}
+
+pub fn render_cubemap(
+    scene: &Scene,
+    render_options: &RenderOptions,
+    position: Point3<f32>,
+    size: u32,
+) -> Vec<RgbaImage> {
+    (0..FACES.len())
+        .map(|face| {
+            let mut img = RgbaImage::new(size, size);
+            for px_x in 0..size {
+                for px_y in 0..size {
+                    let s = 2.0 * ((px_x as f32) + 0.5) / (size as f32) - 1.0;
+                    let t = 2.0 * ((px_y as f32) + 0.5) / (size as f32) - 1.0;
+                    let ray = Ray {
+                        origin: position,
+                        direction: face_direction(face, s, t),
+                    };
+                    let color = get_pixel_color(scene, &ray, render_options, px_x, px_y);
+                    img.put_pixel(px_x, px_y, color);
+                }
+            }
+            img
+        })
+        .collect()
+}
+
+/// Renders a cubemap at each probe position and writes the faces to
+/// `<out_dir>/probe_<n>/<face>.png`.
+pub fn bake_probes(
+    scene: &Scene,
+    render_options: &RenderOptions,
+    probes: &[Point3<f32>],
+    size: u32,
+    out_dir: &Path,
+) -> io::Result<()> {
+    let mut progress = Progress::new("probes", probes.len());
+    for (i, probe) in probes.iter().enumerate() {
+        let probe_dir = out_dir.join(format!("probe_{}", i));
+        fs::create_dir_all(&probe_dir)?;
+        let faces = render_cubemap(scene, render_options, *probe, size);
+        progress.tick();
+        for (name, face) in FACES.iter().zip(faces.iter()) {
+            face.save(probe_dir.join(format!("{}.png", name)))?;
+        }
+    }
+    Ok(())
+}
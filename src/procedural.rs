@@ -0,0 +1,253 @@
+//! Helpers for building scenes procedurally: gradient noise, random points
+//! over surfaces and Poisson-disk scattering, for stress-test scenes of
+//! thousands of spheres and for layouts that look less regular than a grid.
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use geometry::Sphere;
+
+// Candidates tried around each active sample before it is retired, as in
+// Bridson's algorithm
+const POISSON_CANDIDATES: u32 = 30;
+// Consecutive rejections after which dart throwing gives the surface up as
+// full
+const MAX_REJECTIONS: u32 = 1000;
+
+// Directions to the edge midpoints of a cube, the gradients of Perlin's
+// improved noise
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Random number generator for procedural helpers, seeded so the same seed
+/// always builds the same scene.
+pub fn rng(seed: u32) -> XorShiftRng {
+    // XorShift needs a non-zero seed
+    XorShiftRng::from_seed([seed, 0x85eb_ca6b, 0xc2b2_ae35, 1])
+}
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^ (h >> 15)
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Gradient noise at `p`, smooth and roughly in [-1, 1], with features
+/// about one unit across. Different seeds give unrelated noise.
+pub fn noise(p: Point3<f32>, seed: u32) -> f32 {
+    let cell = Point3::new(p.x.floor(), p.y.floor(), p.z.floor());
+    let offset = p - cell;
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let h = hash(
+            cell.x as i32 + dx,
+            cell.y as i32 + dy,
+            cell.z as i32 + dz,
+            seed,
+        );
+        let g = GRADIENTS[h as usize % GRADIENTS.len()];
+        let d = offset - Vector3::new(dx as f32, dy as f32, dz as f32);
+        g[0] * d.x + g[1] * d.y + g[2] * d.z
+    };
+    let (u, v, w) = (fade(offset.x), fade(offset.y), fade(offset.z));
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+    lerp(lerp(x00, x10, v), lerp(x01, x11, v), w)
+}
+
+/// Fractal noise: `octaves` layers of `noise`, each twice the frequency
+/// and half the amplitude of the last, normalised back to about [-1, 1].
+pub fn fbm(p: Point3<f32>, seed: u32, octaves: u32) -> f32 {
+    let (mut sum, mut amplitude, mut total, mut frequency) = (0.0, 1.0, 0.0, 1.0);
+    for octave in 0..octaves {
+        sum += amplitude
+            * noise(
+                Point3::from_vec(p.to_vec() * frequency),
+                seed.wrapping_add(octave),
+            );
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    if total > 0.0 {
+        sum / total
+    } else {
+        0.0
+    }
+}
+
+/// Uniformly distributed point on the surface of `sphere`.
+pub fn on_sphere<R: Rng>(rng: &mut R, sphere: &Sphere) -> Point3<f32> {
+    let z: f32 = rng.gen_range(-1.0, 1.0);
+    let phi = rng.gen_range(0.0, 2.0 * PI);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    sphere.center + Vector3::new(r * phi.cos(), r * phi.sin(), z) * sphere.radius
+}
+
+/// Parallelogram of a plane, spanned by the edges `u` and `v` from `origin`.
+pub struct Patch {
+    pub origin: Point3<f32>,
+    pub u: Vector3<f32>,
+    pub v: Vector3<f32>,
+}
+
+impl Patch {
+    /// Point at parameters (`s`, `t`), each in [0, 1] across the patch.
+    pub fn point(&self, s: f32, t: f32) -> Point3<f32> {
+        self.origin + self.u * s + self.v * t
+    }
+
+    /// Points spread over the patch no closer than `min_distance` to each
+    /// other, filling it with Bridson's algorithm.
+    pub fn poisson_disk<R: Rng>(&self, rng: &mut R, min_distance: f32) -> Vec<Point3<f32>> {
+        let (width, depth) = (self.u.magnitude(), self.v.magnitude());
+        poisson_disk(rng, width, depth, min_distance)
+            .into_iter()
+            .map(|(x, y)| self.point(x / width, y / depth))
+            .collect()
+    }
+}
+
+/// Points in the `width` x `depth` rectangle from the origin no closer than
+/// `min_distance` to each other, filling it with Bridson's algorithm.
+pub fn poisson_disk<R: Rng>(
+    rng: &mut R,
+    width: f32,
+    depth: f32,
+    min_distance: f32,
+) -> Vec<(f32, f32)> {
+    if !(width > 0.0 && depth > 0.0 && min_distance > 0.0) {
+        return Vec::new();
+    }
+    // Cells small enough to hold at most one sample
+    let cell = min_distance / 2f32.sqrt();
+    let columns = (width / cell).ceil() as usize;
+    let rows = (depth / cell).ceil() as usize;
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    let cell_of = |(x, y): (f32, f32)| {
+        (
+            ((x / cell) as usize).min(columns - 1),
+            ((y / cell) as usize).min(rows - 1),
+        )
+    };
+
+    let mut samples = Vec::new();
+    let mut active = Vec::new();
+    let first = (rng.gen_range(0.0, width), rng.gen_range(0.0, depth));
+    let (cx, cy) = cell_of(first);
+    grid[cy * columns + cx] = Some(0);
+    samples.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let index = rng.gen_range(0, active.len());
+        let (x, y) = samples[active[index]];
+        let mut found = false;
+        for _ in 0..POISSON_CANDIDATES {
+            let angle = rng.gen_range(0.0, 2.0 * PI);
+            let distance = rng.gen_range(min_distance, 2.0 * min_distance);
+            let candidate = (x + distance * angle.cos(), y + distance * angle.sin());
+            if candidate.0 < 0.0
+                || candidate.0 >= width
+                || candidate.1 < 0.0
+                || candidate.1 >= depth
+            {
+                continue;
+            }
+            let (cx, cy) = cell_of(candidate);
+            let clear = (cy.saturating_sub(2)..(cy + 3).min(rows)).all(|ny| {
+                (cx.saturating_sub(2)..(cx + 3).min(columns)).all(|nx| {
+                    match grid[ny * columns + nx] {
+                        Some(other) => {
+                            let (ox, oy) = samples[other];
+                            let (dx, dy) = (candidate.0 - ox, candidate.1 - oy);
+                            dx * dx + dy * dy >= min_distance * min_distance
+                        }
+                        None => true,
+                    }
+                })
+            });
+            if clear {
+                grid[cy * columns + cx] = Some(samples.len());
+                active.push(samples.len());
+                samples.push(candidate);
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            active.swap_remove(index);
+        }
+    }
+    samples
+}
+
+/// Points on the surface of `sphere` no closer than `min_distance` to each
+/// other, thrown at random until the surface stops accepting more.
+pub fn poisson_on_sphere<R: Rng>(
+    rng: &mut R,
+    sphere: &Sphere,
+    min_distance: f32,
+) -> Vec<Point3<f32>> {
+    if !(min_distance > 0.0 && min_distance.is_finite()) {
+        return Vec::new();
+    }
+    let cell_of = |p: Point3<f32>| {
+        let c = p / min_distance;
+        (c.x.floor() as i32, c.y.floor() as i32, c.z.floor() as i32)
+    };
+    let mut grid: HashMap<(i32, i32, i32), Vec<Point3<f32>>> = HashMap::new();
+    let mut points = Vec::new();
+    let mut rejections = 0;
+    while rejections < MAX_REJECTIONS {
+        let candidate = on_sphere(rng, sphere);
+        let (cx, cy, cz) = cell_of(candidate);
+        let mut clear = true;
+        for dx in -1..2 {
+            for dy in -1..2 {
+                for dz in -1..2 {
+                    if let Some(near) = grid.get(&(cx + dx, cy + dy, cz + dz)) {
+                        clear &= near
+                            .iter()
+                            .all(|p| (candidate - p).magnitude2() >= min_distance * min_distance);
+                    }
+                }
+            }
+        }
+        if clear {
+            grid.entry((cx, cy, cz)).or_default().push(candidate);
+            points.push(candidate);
+            rejections = 0;
+        } else {
+            rejections += 1;
+        }
+    }
+    points
+}
@@ -0,0 +1,86 @@
+//! Terminal progress bar for offline renders.
+
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use report;
+
+const BAR_WIDTH: usize = 30;
+// Shortest time between redraws, so drawing doesn't slow the render
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress through `total` units of work (tiles, faces, rows), with the
+/// ray rate and an estimate of the time left. Only drawn when stderr is a
+/// terminal, so logs and pipes stay clean.
+pub struct Progress {
+    unit: &'static str,
+    total: usize,
+    done: usize,
+    started: Instant,
+    rays_at_start: usize,
+    last_drawn: Option<Instant>,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(unit: &'static str, total: usize) -> Progress {
+        Progress {
+            unit,
+            total,
+            done: 0,
+            started: Instant::now(),
+            rays_at_start: report::rays_traced(),
+            last_drawn: None,
+            visible: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Marks one more unit done.
+    pub fn tick(&mut self) {
+        self.done += 1;
+        let now = Instant::now();
+        let due = self
+            .last_drawn
+            .is_none_or(|last| now.duration_since(last) >= REDRAW_INTERVAL);
+        if self.visible && (due || self.done == self.total) {
+            self.last_drawn = Some(now);
+            eprint!("{}", self);
+            let _ = io::stderr().flush(); // Don't care if flush fails
+            if self.done == self.total {
+                eprintln!();
+            }
+        }
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let per_unit = self.started.elapsed().as_secs_f64() / self.done as f64;
+        Some(Duration::from_secs_f64(per_unit * (self.total - self.done) as f64))
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let filled = BAR_WIDTH * self.done / self.total.max(1);
+        let seconds = self.started.elapsed().as_secs_f64();
+        let rays = report::rays_traced() - self.rays_at_start;
+        let rate = if seconds > 0.0 { rays as f64 / seconds / 1e6 } else { 0.0 };
+        write!(
+            f,
+            "\r [{}{}] {}/{} {}, {:.2} Mrays/s",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            self.unit,
+            rate
+        )?;
+        match self.eta() {
+            Some(eta) => write!(f, ", ETA {}s ", eta.as_secs()),
+            None => write!(f, ", ETA --"),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! A reference integrator: a deliberately plain, slow estimate of the same
+//! light the renderer computes, for checking that the renderer's faster
+//! estimates converge to the right answer.
+//!
+//! Where the renderer samples each light directly, the reference follows
+//! paths that leave diffuse surfaces in random directions spread evenly
+//! over the hemisphere, and counts whatever light they run into: the sky
+//! of dome lights when they escape the scene, and emissive surfaces, which
+//! are the scene's area lights. It takes no shortcuts besides following
+//! mirrors and glass exactly, as the renderer does, so with enough samples
+//! any difference between the two is a bias in the renderer.
+//!
+//! The reference gathers the light the integrator being checked sets out
+//! to: for the direct integrator, light off the first diffuse surface
+//! seen, through mirrors and glass, from the sky alone; for path tracing,
+//! light along paths of up to `RenderOptions::max_depth` bounces of every
+//! kind. The wavefront integrator traces the same paths as the path
+//! integrator and is checked as it is. Point and directional lights can't
+//! be found by random rays, so comparisons leave them out of both, along
+//! with the highlights only they make.
+
+use cgmath::{ElementWise, InnerSpace, Vector3};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use rayon::prelude::*;
+
+use camera::{primary_ray, Camera};
+use geometry::{reflect, Ray};
+use light::Light;
+use path::Integrator;
+use render::{checked_radiance, Color, RenderOptions};
+use sampling;
+use scene::{closest_intersection, Scene};
+
+// Standard errors the renders' averages may be apart. Noise alone puts
+// them further apart less than once in ten thousand comparisons.
+const MAX_DEVIATIONS: f32 = 4.0;
+
+/// How a render compares with the reference.
+pub struct Comparison {
+    // Average brightness of each, over every pixel and channel
+    pub mean: f32,
+    pub reference_mean: f32,
+    // Standard error of the difference between the averages, from how much
+    // the difference varies from pixel to pixel
+    pub standard_error: f32,
+}
+
+impl Comparison {
+    /// Difference in average brightness as a fraction of the reference's.
+    pub fn bias(&self) -> f32 {
+        if self.reference_mean > 0.0 {
+            (self.mean - self.reference_mean) / self.reference_mean
+        } else {
+            0.0
+        }
+    }
+
+    /// Difference in average brightness in standard errors. Renders of few
+    /// samples are noisy enough to hide small biases; more samples of each
+    /// narrow the standard error.
+    pub fn deviations(&self) -> f32 {
+        let difference = self.mean - self.reference_mean;
+        if self.standard_error > 0.0 {
+            difference / self.standard_error
+        } else if difference == 0.0 {
+            0.0
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    pub fn passes(&self) -> bool {
+        self.deviations().abs() <= MAX_DEVIATIONS
+    }
+}
+
+// Light the sky of every dome light sends along `direction`
+fn sky(scene: &Scene, direction: Vector3<f32>) -> Color {
+    scene.lights.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, light| {
+        sum + light.background(direction)
+    })
+}
+
+// Radiance along `ray` from a single random path, for the integrator in
+// `render_options`. `depth` counts the bounces `ray` has already been
+// through that the integrator counts against its maximum, and a ray
+// `sky_only` counts the sky it escapes to but not the surfaces it meets.
+fn radiance(
+    scene: &Scene,
+    ray: &Ray,
+    render_options: &RenderOptions,
+    rng: &mut XorShiftRng,
+    depth: u32,
+    sky_only: bool,
+) -> Color {
+    let (shape, hit) = match closest_intersection(scene, ray, render_options) {
+        Some(found) => found,
+        None => return sky(scene, ray.direction),
+    };
+    let black = Vector3::new(0.0, 0.0, 0.0);
+    if sky_only {
+        return black;
+    }
+    let point = ray.origin + ray.direction * hit.distance;
+    let material = shape.material();
+    let outward = shape.normal(point, hit.part).normalize();
+    let facing = if shape.two_sided() && outward.dot(ray.direction) > 0.0 {
+        -outward
+    } else {
+        outward
+    };
+
+    // Light scattered diffusely, from a direction taken over its uniform
+    // density of 1 / 2 pi through the diffuse reflectance of 1 / pi. The
+    // direct integrator counts only the sky there, as do paths that have
+    // used up their bounces.
+    let paths = render_options.integrator != Integrator::Direct;
+    let direction = sampling::uniform_hemisphere(facing, (rng.gen(), rng.gen()));
+    let bounce = Ray::from_surface(point, facing, direction);
+    let beyond = !paths || depth >= render_options.max_depth;
+    let incoming = radiance(scene, &bounce, render_options, rng, depth + 1, beyond);
+    let light = incoming * (facing.dot(direction) * 2.0);
+    let diffuse = light.mul_element_wise(material.albedo_at(point)) * (1.0 - material.specular);
+    let local = diffuse + material.emissive;
+    if !material.is_specular() || !render_options.reflections {
+        return local;
+    }
+
+    let split = material.split(ray.direction, outward);
+    if depth >= render_options.max_depth {
+        // Where the direct integrator stops following mirrors and glass it
+        // shades the surface as wholly diffuse, while a path just ends
+        return if paths { local * split.diffuse } else { local };
+    }
+    let mut color = local * split.diffuse;
+    let normal = if outward.dot(ray.direction) > 0.0 { -outward } else { outward };
+    if split.reflected > 0.0 {
+        let reflected_ray = Ray::from_surface(point, normal, reflect(ray.direction, normal));
+        let reflected = radiance(scene, &reflected_ray, render_options, rng, depth + 1, false);
+        color += reflected * split.reflected;
+    }
+    if split.refracted > 0.0 {
+        let refracted_ray = Ray::from_surface(point, -normal, split.refraction);
+        let refracted = radiance(scene, &refracted_ray, render_options, rng, depth + 1, false);
+        color += refracted * split.refracted;
+    }
+    color
+}
+
+/// Renders `scene` with the integrator in `render_options` and with the
+/// reference, each averaging `samples` rays through points spread over
+/// every pixel, and compares them. Point and directional lights are left
+/// out of both.
+pub fn compare(
+    mut scene: Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    samples: u32,
+) -> Result<Comparison, String> {
+    scene.lights.retain(|light| !light.is_singular());
+    if scene.lights.is_empty() {
+        let emissive = scene.primitives.iter().any(|p| {
+            let emissive = p.shape().material().emissive;
+            emissive.x > 0.0 || emissive.y > 0.0 || emissive.z > 0.0
+        });
+        if !emissive {
+            return Err("the scene has no dome lights or emissive surfaces to compare under"
+                .to_string());
+        }
+        // A black sky, so the renderer shades the scene as lit rather than
+        // by the headlight it gives scenes without lights
+        scene.lights.push(Light::Dome {
+            color: Vector3::new(0.0, 0.0, 0.0),
+            intensity: 0.0,
+            portals: Vec::new(),
+            environment: None,
+        });
+    }
+    let render_options = RenderOptions {
+        clipping: false,
+        heatmap: None,
+        caustic_spread: 0.0,
+        ..render_options.clone()
+    };
+    let (width, height) = (render_options.width, render_options.height);
+    // Sums, row by row, of each render's pixels and of the difference
+    // between them and its square, each pixel taken as its average channel
+    let rows: Vec<[f64; 4]> = (0..height)
+        .into_par_iter()
+        .map(|px_y| {
+            let mut rng = XorShiftRng::from_seed([px_y + 1, 0x6c07_8965, 0x9e37_79b9, 1]);
+            let mut pass_options = render_options.clone();
+            let mut sums = [0.0; 4];
+            for px_x in 0..width {
+                let black = Vector3::new(0.0, 0.0, 0.0);
+                let (mut color, mut truth) = (black, black);
+                let rotation = sampling::pixel_rotation(px_x, px_y, 0);
+                for pass in 0..samples {
+                    let offset = sampling::hammersley(pass, samples, rotation);
+                    let (x, y) = (px_x as f32 + offset.0, px_y as f32 + offset.1);
+                    let ray = primary_ray(camera, &render_options, x, y);
+                    pass_options.pass = pass;
+                    color += checked_radiance(&scene, &ray, &pass_options, px_x, px_y);
+                    truth += radiance(&scene, &ray, &render_options, &mut rng, 0, false);
+                }
+                color /= samples as f32;
+                truth /= samples as f32;
+                let value = ((color.x + color.y + color.z) / 3.0) as f64;
+                let reference_value = ((truth.x + truth.y + truth.z) / 3.0) as f64;
+                let difference = value - reference_value;
+                sums[0] += value;
+                sums[1] += reference_value;
+                sums[2] += difference;
+                sums[3] += difference * difference;
+            }
+            sums
+        })
+        .collect();
+    let pixels = (width * height) as f64;
+    let mean = |i: usize| rows.iter().map(|r| r[i]).sum::<f64>() / pixels;
+    let variance = (mean(3) - mean(2) * mean(2)).max(0.0) * pixels / (pixels - 1.0).max(1.0);
+    Ok(Comparison {
+        mean: mean(0) as f32,
+        reference_mean: mean(1) as f32,
+        standard_error: (variance / pixels).sqrt() as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+
+    use cuboid::Cuboid;
+    use geometry::Sphere;
+    use light::{Light, Portal};
+    use material::Material;
+    use primitive::Primitive;
+
+    // Paths the reference averages at each pixel
+    const SAMPLES: u32 = 64;
+
+    fn sky(portals: Vec<Portal>) -> Light {
+        Light::Dome {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            portals,
+            environment: None,
+        }
+    }
+
+    fn slab(min: [f32; 3], max: [f32; 3]) -> Primitive {
+        Primitive::Cuboid(Cuboid {
+            min: Point3::new(min[0], min[1], min[2]),
+            max: Point3::new(max[0], max[1], max[2]),
+            material: Material::diffuse(Vector3::new(0.7, 0.7, 0.7)),
+        })
+    }
+
+    fn sphere(center: [f32; 3], radius: f32, material: Material) -> Primitive {
+        Primitive::Sphere(Sphere {
+            center: Point3::new(center[0], center[1], center[2]),
+            radius,
+            material,
+        })
+    }
+
+    fn glow(color: [f32; 3]) -> Material {
+        Material {
+            albedo: Vector3::new(0.0, 0.0, 0.0),
+            emissive: Vector3::new(color[0], color[1], color[2]),
+            ..Material::default()
+        }
+    }
+
+    // A small render of `scene` from `camera` with `integrator`, which has
+    // to come within the reference's tolerance of it
+    fn assert_matches(scene: Scene, camera: Camera, integrator: Integrator) {
+        let render_options = RenderOptions {
+            width: 24,
+            height: 24,
+            integrator,
+            ..RenderOptions::default()
+        };
+        let comparison = compare(scene, &camera, &render_options, SAMPLES).unwrap();
+        assert!(
+            comparison.passes(),
+            "mean {} against a reference of {}, {} standard errors apart",
+            comparison.mean,
+            comparison.reference_mean,
+            comparison.deviations()
+        );
+    }
+
+    // Spheres of `materials` in a row on a floor, seen from in front
+    fn spheres_on_a_floor(materials: &[Material]) -> (Scene, Camera) {
+        let mut scene = Scene::empty();
+        scene.lights.push(sky(Vec::new()));
+        scene.primitives.push(slab([-4.0, -0.1, -8.0], [4.0, 0.0, 0.0]));
+        for (i, material) in materials.iter().enumerate() {
+            let x = 1.2 * (i as f32 - (materials.len() - 1) as f32 / 2.0);
+            scene.primitives.push(sphere([x, 0.5, -4.0], 0.5, *material));
+        }
+        let camera = Camera {
+            position: Point3::new(0.0, 1.0, -1.0),
+            at: Vector3::new(0.0, -0.2, -1.0),
+            fov: 60.0,
+            ..Camera::default()
+        };
+        (scene, camera)
+    }
+
+    #[test]
+    fn diffuse_and_glossy_surfaces_under_a_dome() {
+        let (scene, camera) = spheres_on_a_floor(&[
+            Material::diffuse(Vector3::new(0.8, 0.3, 0.2)),
+            Material {
+                specular: 0.4,
+                shininess: 64.0,
+                ..Material::default()
+            },
+        ]);
+        assert_matches(scene, camera, Integrator::Direct);
+    }
+
+    #[test]
+    fn mirrors_and_glass_under_a_dome() {
+        let (scene, camera) = spheres_on_a_floor(&[
+            Material {
+                reflectivity: 0.8,
+                ..Material::default()
+            },
+            Material {
+                transparency: 1.0,
+                ..Material::default()
+            },
+        ]);
+        assert_matches(scene, camera, Integrator::Direct);
+    }
+
+    // A room 4 by 5 and 2.5 high, with a window 2 wide and 1 high in the
+    // wall at x = -2 and a ball on the floor, seen from a corner
+    fn room() -> (Scene, Camera) {
+        let mut scene = Scene::empty();
+        let walls = [
+            ([-2.0, -0.1, -4.0], [2.0, 0.0, 1.0]),
+            ([-2.0, 2.5, -4.0], [2.0, 2.6, 1.0]),
+            ([-2.0, 0.0, -4.1], [2.0, 2.5, -4.0]),
+            ([-2.0, 0.0, 1.0], [2.0, 2.5, 1.1]),
+            ([2.0, 0.0, -4.0], [2.1, 2.5, 1.0]),
+            // The wall around the window
+            ([-2.1, 0.0, -4.0], [-2.0, 1.0, 1.0]),
+            ([-2.1, 2.0, -4.0], [-2.0, 2.5, 1.0]),
+            ([-2.1, 1.0, -4.0], [-2.0, 2.0, -3.0]),
+            ([-2.1, 1.0, -1.0], [-2.0, 2.0, 1.0]),
+        ];
+        for &(min, max) in &walls {
+            scene.primitives.push(slab(min, max));
+        }
+        let ball = Material::diffuse(Vector3::new(0.9, 0.5, 0.2));
+        scene.primitives.push(sphere([0.5, 0.5, -2.5], 0.5, ball));
+        let camera = Camera {
+            position: Point3::new(1.5, 1.2, 0.5),
+            at: Vector3::new(-0.5, -0.2, -1.0),
+            fov: 70.0,
+            ..Camera::default()
+        };
+        (scene, camera)
+    }
+
+    #[test]
+    fn room_lit_through_a_window() {
+        let (mut scene, camera) = room();
+        // Letting light in towards +x
+        scene.lights.push(sky(vec![Portal {
+            corner: Point3::new(-2.0, 1.0, -3.0),
+            u: Vector3::new(0.0, 0.0, 2.0),
+            v: Vector3::new(0.0, 1.0, 0.0),
+        }]));
+        assert_matches(scene, camera, Integrator::Direct);
+    }
+
+    #[test]
+    fn paths_under_a_dome_among_glowing_and_glass_spheres() {
+        let (mut scene, camera) = spheres_on_a_floor(&[
+            Material::diffuse(Vector3::new(0.8, 0.3, 0.2)),
+            glow([2.0, 1.5, 1.0]),
+            Material {
+                transparency: 1.0,
+                ..Material::default()
+            },
+        ]);
+        // A wall behind, for light to bounce between it and the floor
+        scene.primitives.push(slab([-4.0, 0.0, -6.1], [4.0, 3.0, -6.0]));
+        assert_matches(scene, camera, Integrator::Path);
+    }
+
+    #[test]
+    fn paths_in_a_room_lit_through_a_window_and_by_a_panel() {
+        let (mut scene, camera) = room();
+        scene.lights.push(sky(vec![Portal {
+            corner: Point3::new(-2.0, 1.0, -3.0),
+            u: Vector3::new(0.0, 0.0, 2.0),
+            v: Vector3::new(0.0, 1.0, 0.0),
+        }]));
+        // An area light on the ceiling, lighting the room mostly by way of
+        // its walls
+        scene.primitives.push(Primitive::Cuboid(Cuboid {
+            min: Point3::new(0.5, 2.45, -3.0),
+            max: Point3::new(1.5, 2.5, -2.0),
+            material: glow([4.0, 4.0, 4.0]),
+        }));
+        assert_matches(scene, camera, Integrator::Path);
+    }
+}
@@ -0,0 +1,109 @@
+//! Reloading of the assets a scene's files name while it is being viewed,
+//! so a model, IES profile or environment map can be reworked without
+//! reloading the scene.
+//!
+//! Each mesh read from a model file, each profile shaping a light and each
+//! map lighting a dome remembers its file and when that was modified. A file modified since is
+//! read again through the session's resolver and the new asset takes the
+//! old one's place, keeping what has happened to it since: its material,
+//! and for a mesh how far it has been moved. Any other scene reading the
+//! same file picks the change up once it is active and checked in turn.
+
+use std::time::SystemTime;
+
+use edit::Damage;
+use environment::Environment;
+use handle::Handle;
+use light::Light;
+use primitive::{Intersectable, Primitive};
+use resolve::{modified, AssetResolver};
+use scene::Scene;
+
+/// Reads again each mesh, profile and environment map of `scene` whose file has changed,
+/// returning the damage done and the files that failed to load. A file
+/// that failed is tried again once it changes again.
+pub fn reload(scene: &mut Scene, resolver: &AssetResolver) -> (Damage, Vec<String>) {
+    let mut damage = Damage::Nothing;
+    let mut errors = Vec::new();
+
+    let stale: Vec<(Handle<Primitive>, Option<SystemTime>)> = scene
+        .mesh_sources
+        .iter()
+        .filter_map(|(object, source)| {
+            let stamp = modified(&source.path);
+            if stamp.is_some() && stamp != source.modified {
+                Some((object, stamp))
+            } else {
+                None
+            }
+        })
+        .collect();
+    for (object, stamp) in stale {
+        let source = match scene.mesh_sources.get_mut(object) {
+            Some(source) => source,
+            None => continue,
+        };
+        source.modified = stamp;
+        let old = match scene.primitives.get_mut(object) {
+            Some(&mut Primitive::Mesh(ref mut old)) => old,
+            _ => continue,
+        };
+        match resolver.mesh(&source.path) {
+            Ok(mesh) => {
+                let mut mesh = mesh.transformed(&source.transform).with_material(*old.material());
+                mesh.translate(old.moved());
+                *old = mesh;
+                // Its shadow and reflections change along with it
+                damage = Damage::Everything;
+            }
+            Err(e) => errors.push(format!("{}: {}", source.path.display(), e)),
+        }
+    }
+
+    for light in scene.lights.iter_mut() {
+        if let Light::Dome {
+            environment:
+                Some(Environment::Map {
+                    ref mut map,
+                    ref path,
+                    modified: ref mut loaded,
+                    ..
+                }),
+            ..
+        } = *light
+        {
+            let stamp = modified(path);
+            if stamp.is_some() && stamp != *loaded {
+                *loaded = stamp;
+                match resolver.environment(path) {
+                    Ok(reloaded) => {
+                        *map = reloaded;
+                        damage = Damage::Everything;
+                    }
+                    Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+                }
+            }
+            continue;
+        }
+        let shaping = match *light {
+            Light::Point {
+                shaping: Some(ref mut shaping),
+                ..
+            } => shaping,
+            _ => continue,
+        };
+        let stamp = modified(&shaping.path);
+        if stamp.is_none() || stamp == shaping.modified {
+            continue;
+        }
+        shaping.modified = stamp;
+        match resolver.profile(&shaping.path) {
+            Ok(profile) => {
+                shaping.profile = profile;
+                damage = Damage::Everything;
+            }
+            Err(e) => errors.push(format!("{}: {}", shaping.path.display(), e)),
+        }
+    }
+    (damage, errors)
+}
@@ -0,0 +1,813 @@
+//! Turning a scene into pixels: the radiance along each ray, the options
+//! that shape it, and frames traced a tile at a time on the render thread
+//! pool.
+
+use cgmath::{ElementWise, InnerSpace, Point3, Vector3};
+use im::{GenericImage, ImageBuffer, Pixel, Rgb, Rgba, RgbaImage};
+use rayon;
+use rayon::prelude::*;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bvh;
+use camera::{lens_ray, primary_ray, Camera};
+use caustics;
+use clip;
+use color;
+use dither;
+use geometry::{reflect, Ray};
+use heatmap;
+use interrupt;
+use material::Material;
+use path;
+use primitive::{Hit, Intersectable};
+use progress;
+use report;
+use sampling;
+use scene::{closest_intersection, closest_intersections, occluded, Scene};
+use shading_cache::ShadingCache;
+use shake;
+use tiles;
+use wavefront;
+
+const DEFAULT_LIGHT_SAMPLES: u32 = 16;
+const DEFAULT_MAX_DEPTH: u32 = 4;
+/// Deepest `max_depth` the command line takes. Rays are followed by
+/// recursion, and well before this much deeper would only risk a render
+/// thread's stack for light too faint to see.
+pub const MAX_DEPTH_LIMIT: u32 = 64;
+
+/// How a frame is rendered.
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    // Re-test near-tangent sphere hits in double precision
+    pub robust_intersections: bool,
+    // Sphere clusters smaller than this on screen are drawn as a proxy; 0
+    // disables clustering
+    pub(crate) lod_pixels: f32,
+    // Bits per channel of offline outputs, 8 or 16
+    pub(crate) bit_depth: u8,
+    // Stops of exposure applied before converting to the output space
+    pub exposure: f32,
+    // Colour space pixels are converted to when quantized
+    pub(crate) output_space: color::ColorSpace,
+    // Dither applied when quantizing to 8 bits
+    pub(crate) dither: dither::Dither,
+    // Section planes, applied while `clipping` is on
+    pub(crate) clip_planes: Vec<clip::ClipPlane>,
+    pub(crate) clipping: bool,
+    // Colour the cut faces of clipped spheres are filled with; without one
+    // clipped spheres are left open
+    pub(crate) clip_cap: Option<Color>,
+    // False-colour shading in place of the usual facing ratio
+    pub(crate) heatmap: Option<heatmap::Heatmap>,
+    // Burn frame number, timecode, scene and camera into batch outputs
+    pub(crate) burn_in: bool,
+    // Handheld shake added to the camera as the scene clock runs
+    pub(crate) shake: Option<shake::Shake>,
+    // Render threads; 0 uses one per core
+    pub(crate) threads: usize,
+    // Edge length of the square tiles frames are split into
+    pub tile_size: u32,
+    // Shadow rays per shading point for lights that cover many directions,
+    // such as dome lights
+    pub light_samples: u32,
+    // Most reflections and refractions a camera ray is followed through,
+    // counting diffuse bounces too under the path integrator; 0 turns them
+    // all off, and the command line takes no more than `MAX_DEPTH_LIMIT`
+    pub max_depth: u32,
+    // Degrees off a mirror direction at which mirrors still reflect lights
+    // onto diffuse surfaces; 0 turns caustics off
+    pub caustic_spread: f32,
+    // Whether anything between a surface and a light shades it; without
+    // shadows every light reaches every surface facing it
+    pub(crate) shadows: bool,
+    // Whether mirrors and glass are followed; without reflections rays and
+    // paths stop at them, keeping only the light they scatter diffusely
+    pub(crate) reflections: bool,
+    // Compress radiance with a tone curve before encoding it, rather than
+    // clipping whatever is brighter than white
+    pub(crate) tone_mapping: bool,
+    // Camera rays averaged into each pixel; 1 traces only the centre
+    pub samples_per_pixel: u32,
+    // Vertical field of view in degrees given every camera in place of its
+    // own
+    pub(crate) fov: Option<f32>,
+    // Lens diameter and distance in focus given every camera in place of
+    // its own
+    pub(crate) aperture: Option<f32>,
+    pub(crate) focal_distance: Option<f32>,
+    // Seconds the shutter of offline renders stays open from the scene
+    // clock, blurring whatever moves meanwhile; 0 freezes motion
+    pub(crate) shutter: f32,
+    // Eye separation in scene units of red/cyan anaglyphs offline renders
+    // are made as, instead of the camera's own view
+    pub(crate) anaglyph: Option<f32>,
+    // Pass of a progressive render being traced; each pass lays its pixel
+    // samples out differently, and pass 0 of one ray per pixel traces the
+    // pixel centre
+    pub(crate) pass: u32,
+    // How the light along camera rays is estimated
+    pub(crate) integrator: path::Integrator,
+    // How rays find the triangles of meshes they hit
+    pub(crate) traversal: bvh::Traversal,
+    // Direct lighting kept from frame to frame by the interactive window
+    pub(crate) shading_cache: Option<Arc<ShadingCache>>,
+}
+
+impl Default for RenderOptions {
+    /// A 640 by 640 frame with one ray per pixel, written linear and
+    /// undithered.
+    fn default() -> RenderOptions {
+        RenderOptions {
+            width: 640,
+            height: 640,
+            robust_intersections: false,
+            lod_pixels: 0.0,
+            bit_depth: 8,
+            exposure: 0.0,
+            output_space: color::ColorSpace::linear(),
+            dither: dither::Dither::None,
+            clip_planes: Vec::new(),
+            clipping: false,
+            clip_cap: None,
+            heatmap: None,
+            burn_in: false,
+            shake: None,
+            threads: 0,
+            tile_size: tiles::DEFAULT_TILE_SIZE,
+            light_samples: DEFAULT_LIGHT_SAMPLES,
+            max_depth: DEFAULT_MAX_DEPTH,
+            caustic_spread: 0.0,
+            shadows: true,
+            reflections: true,
+            tone_mapping: false,
+            samples_per_pixel: 1,
+            fov: None,
+            aperture: None,
+            focal_distance: None,
+            shutter: 0.0,
+            anaglyph: None,
+            pass: 0,
+            integrator: path::Integrator::Direct,
+            traversal: bvh::Traversal::Linear,
+            shading_cache: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Linear RGB radiance, unbounded above so it can be written to HDR outputs
+pub type Color = Vector3<f32>;
+
+// `depth` counts the reflections and refractions `ray` has already been
+// through
+pub fn radiance(scene: &Scene, ray: &Ray, render_options: &RenderOptions, depth: u32) -> Color {
+    let closest_intersection = closest_intersection(scene, ray, render_options);
+    shade(scene, ray, closest_intersection, render_options, depth)
+}
+
+// `radiance` along `ray`, given `found`, the closest intersection along it
+fn shade(
+    scene: &Scene,
+    ray: &Ray,
+    found: Option<(&dyn Intersectable, Hit)>,
+    render_options: &RenderOptions,
+    depth: u32,
+) -> Color {
+    match found {
+        Some(i) => {
+            let (shape, hit) = i;
+            let intersection_point = ray.origin + (ray.direction * hit.distance);
+            if let Some(ref heatmap) = render_options.heatmap {
+                let up = scene.units.up();
+                return heatmap.color(shape, intersection_point, hit.part, up);
+            }
+            let (outward, material) = match (hit.cap, render_options.clip_cap) {
+                (Some(cap), Some(color)) => (cap, Material::diffuse(color)),
+                _ => (shape.normal(intersection_point, hit.part), *shape.material()),
+            };
+            // Cut faces and two-sided shapes are seen from either side, and
+            // clipping exposes the inside of open spheres, so those are
+            // shaded from the side the ray arrives on
+            let facing = outward.dot(-ray.direction);
+            let two_sided = hit.cap.is_some() || render_options.clipping || shape.two_sided();
+            let normal = if two_sided && facing < 0.0 { -outward } else { outward };
+            // Scenes without lights keep the facing ratio as a headlight
+            let black = Vector3::new(0.0, 0.0, 0.0);
+            let (light, highlights) = if scene.lights.is_empty() {
+                (Vector3::new(1.0, 1.0, 1.0) * 0f32.max(normal.dot(-ray.direction)), black)
+            } else {
+                let normal = normal.normalize();
+                let to_viewer = -ray.direction.normalize();
+                let point = intersection_point;
+                let (light, highlights) = match render_options.shading_cache {
+                    Some(ref cache) => cache.direct_light(
+                        scene,
+                        point,
+                        normal,
+                        to_viewer,
+                        &material,
+                        render_options,
+                    ),
+                    None => {
+                        direct_light(scene, point, normal, to_viewer, &material, render_options)
+                    }
+                };
+                let caustic = caustics::caustic_light(scene, point, normal, render_options);
+                (light + caustic, highlights)
+            };
+            // Light that makes highlights is no longer there to scatter
+            let albedo = material.albedo_at(intersection_point);
+            let diffuse = light.mul_element_wise(albedo) * (1.0 - material.specular);
+            let local = diffuse + highlights + material.emissive;
+            let follow = material.is_specular() && render_options.reflections;
+            if !follow || depth >= render_options.max_depth {
+                return local;
+            }
+            // Whether a ray enters or leaves a transparent solid depends on
+            // which way its outward normal faces
+            let split = material.split(ray.direction, outward.normalize());
+            let mut color = local * split.diffuse;
+            // Mirrored about the side the ray arrives on, even for solids
+            // seen from inside, and refracted through to the other
+            let normal = normal.normalize();
+            let normal = if normal.dot(ray.direction) > 0.0 { -normal } else { normal };
+            if split.reflected > 0.0 {
+                let direction = reflect(ray.direction, normal);
+                let reflected_ray = Ray::from_surface(intersection_point, normal, direction);
+                let reflected = radiance(scene, &reflected_ray, render_options, depth + 1);
+                color += reflected * split.reflected;
+            }
+            if split.refracted > 0.0 {
+                let refraction = split.refraction;
+                let refracted_ray = Ray::from_surface(intersection_point, -normal, refraction);
+                let refracted = radiance(scene, &refracted_ray, render_options, depth + 1);
+                color += refracted * split.refracted;
+            }
+            color
+        }
+        None if render_options.heatmap.is_some() => Vector3::new(0.0, 0.0, 0.0),
+        None => scene.lights.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, light| {
+            sum + light.background(ray.direction)
+        }),
+    }
+}
+
+// Lambertian light reaching a white surface at `point` with unit `normal`
+// from every light a shadow ray finds unblocked, and the highlights point and
+// directional lights make on `material` there, seen from unit `to_viewer`.
+// Lights with more than one sample are averaged over their samples, and give
+// no highlights, since a few samples of a sharp highlight would be speckle.
+pub fn direct_light(
+    scene: &Scene,
+    point: Point3<f32>,
+    normal: Vector3<f32>,
+    to_viewer: Vector3<f32>,
+    material: &Material,
+    render_options: &RenderOptions,
+) -> (Color, Color) {
+    let mut light = LightSum::new();
+    shadow_rays(scene, point, normal, to_viewer, material, render_options, |shadow| {
+        let blocked = occluded(scene, &shadow.ray, shadow.distance, render_options);
+        light.add(&shadow, blocked);
+    });
+    light.total()
+}
+
+/// A shadow ray towards a sample of a light, and the light that arrives
+/// along it if nothing blocks it.
+pub struct ShadowRay {
+    pub ray: Ray,
+    // Distance to the sample
+    pub distance: f32,
+    // Which of the scene's lights it samples, and how many samples of that
+    // light are taken
+    light: usize,
+    samples: u32,
+    // Light arriving, times its cosine on the surface, and the highlight it
+    // makes there if the light makes highlights
+    color: Color,
+    highlight: Option<Color>,
+}
+
+// Calls `visit` with the shadow ray of every sample of every light in front
+// of a surface at `point`, for `direct_light`, in order of light and then
+// of sample.
+pub fn shadow_rays<F>(
+    scene: &Scene,
+    point: Point3<f32>,
+    normal: Vector3<f32>,
+    to_viewer: Vector3<f32>,
+    material: &Material,
+    render_options: &RenderOptions,
+    mut visit: F,
+) where
+    F: FnMut(ShadowRay),
+{
+    let rotation = sampling::rotation(point);
+    for (index, light) in scene.lights.iter().enumerate() {
+        let count = light.sample_count(render_options.light_samples);
+        for i in 0..count {
+            let sample = sampling::hammersley(i, count, rotation);
+            let incident = light.incident(point, normal, sample);
+            let cosine = normal.dot(incident.direction);
+            if cosine <= 0.0 {
+                continue;
+            }
+            let highlight = if light.is_singular() {
+                let highlight = material.highlight(normal, incident.direction, to_viewer);
+                Some(incident.color * (cosine * highlight))
+            } else {
+                None
+            };
+            visit(ShadowRay {
+                ray: Ray::from_surface(point, normal, incident.direction),
+                distance: incident.distance,
+                light: index,
+                samples: count,
+                color: incident.color * cosine,
+                highlight,
+            });
+        }
+    }
+}
+
+/// The light and highlights `direct_light` finds, summed from shadow rays
+/// as `shadow_rays` gives them once each is known to be blocked or not.
+pub struct LightSum {
+    sum: Color,
+    highlights: Color,
+    // The light being summed, its samples and their sum so far
+    light: usize,
+    samples: u32,
+    light_sum: Color,
+}
+
+impl LightSum {
+    pub fn new() -> LightSum {
+        let black = Vector3::new(0.0, 0.0, 0.0);
+        LightSum {
+            sum: black,
+            highlights: black,
+            light: usize::MAX,
+            samples: 0,
+            light_sum: black,
+        }
+    }
+
+    pub fn add(&mut self, shadow: &ShadowRay, blocked: bool) {
+        if shadow.light != self.light {
+            self.finish_light();
+            self.light = shadow.light;
+            self.samples = shadow.samples;
+        }
+        if !blocked {
+            self.light_sum += shadow.color;
+            if let Some(highlight) = shadow.highlight {
+                self.highlights += highlight;
+            }
+        }
+    }
+
+    // Averages the light being summed over its samples
+    fn finish_light(&mut self) {
+        if self.samples > 0 {
+            self.sum += self.light_sum / self.samples as f32;
+        }
+        self.light_sum = Vector3::new(0.0, 0.0, 0.0);
+    }
+
+    /// Lambertian light and highlights, as `direct_light` returns them.
+    pub fn total(mut self) -> (Color, Color) {
+        self.finish_light();
+        (self.sum, self.highlights)
+    }
+}
+
+// `dither` is added to each channel's scaled value before truncating it
+pub fn to_rgba(color: Color, dither: f32) -> Rgba<u8> {
+    let r = (255.0 * color.x + dither) as u8;
+    let g = (255.0 * color.y + dither) as u8;
+    let b = (255.0 * color.z + dither) as u8;
+    Rgba([r, g, b, 255])
+}
+
+pub fn to_rgba16(color: Color) -> Rgba<u16> {
+    let r = (65535.0 * color.x) as u16;
+    let g = (65535.0 * color.y) as u16;
+    let b = (65535.0 * color.z) as u16;
+    Rgba([r, g, b, 65535])
+}
+
+pub type Frame<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+// Pixel formats a frame can be rendered into
+pub trait FramePixel: Pixel + 'static {
+    // `dither` is an offset in [0, 1) for formats coarse enough to need it
+    fn from_color(color: Color, dither: f32) -> Self;
+    // Opaque magenta, for pixels whose rendering panicked
+    fn panic_color() -> Self;
+
+    // Pixel (`px_x`, `px_y`) of radiance `color`, exposed, encoded and
+    // dithered for display unless the format keeps radiance as it is
+    fn from_radiance(color: Color, render_options: &RenderOptions, px_x: u32, px_y: u32) -> Self {
+        let dither = render_options.dither.offset(px_x, px_y);
+        Self::from_color(display_color(color, render_options), dither)
+    }
+}
+
+impl FramePixel for Rgba<u8> {
+    fn from_color(color: Color, dither: f32) -> Self {
+        to_rgba(color, dither)
+    }
+
+    fn panic_color() -> Self {
+        Rgba([255, 0, 255, 255])
+    }
+}
+
+impl FramePixel for Rgba<u16> {
+    fn from_color(color: Color, _dither: f32) -> Self {
+        to_rgba16(color)
+    }
+
+    fn panic_color() -> Self {
+        Rgba([65535, 0, 65535, 65535])
+    }
+}
+
+// Radiance as traced, for progressive passes to be summed
+impl FramePixel for Rgb<f32> {
+    fn from_color(color: Color, _dither: f32) -> Self {
+        Rgb([color.x, color.y, color.z])
+    }
+
+    fn panic_color() -> Self {
+        Rgb([1.0, 0.0, 1.0])
+    }
+
+    fn from_radiance(color: Color, _: &RenderOptions, _: u32, _: u32) -> Self {
+        Self::from_color(color, 0.0)
+    }
+}
+
+pub fn is_finite(v: Vector3<f32>) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+// Watchdog for NaN/Inf: debug builds report the pixel and object where a
+// non-finite direction or radiance first shows up, and every build replaces
+// it with black rather than letting it leak into the image as speckles.
+pub fn checked_radiance(
+    scene: &Scene,
+    ray: &Ray,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+) -> Color {
+    checked(scene, ray, render_options, px_x, px_y, || {
+        match render_options.integrator {
+            path::Integrator::Direct => radiance(scene, ray, render_options, 0),
+            path::Integrator::Path | path::Integrator::Wavefront => {
+                path::radiance(scene, ray, render_options)
+            }
+        }
+    })
+}
+
+// `checked_radiance`, with `radiance` giving the radiance along `ray`
+fn checked<F>(
+    scene: &Scene,
+    ray: &Ray,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+    radiance: F,
+) -> Color
+where
+    F: FnOnce() -> Color,
+{
+    let black = Vector3::new(0.0, 0.0, 0.0);
+    if !is_finite(ray.direction) {
+        if cfg!(debug_assertions) {
+            eprintln!(
+                "non-finite ray direction {:?} at pixel ({}, {})",
+                ray.direction, px_x, px_y
+            );
+        }
+        return black;
+    }
+
+    let color = radiance();
+    if is_finite(color) {
+        return color;
+    }
+    if cfg!(debug_assertions) {
+        let object = match closest_intersection(scene, ray, render_options) {
+            Some((shape, _)) => {
+                let shape = shape as *const dyn Intersectable;
+                let found = scene
+                    .primitives
+                    .entries()
+                    .find(|&(_, p)| ptr::addr_eq(p.shape(), shape));
+                match found {
+                    Some((handle, primitive)) => format!("{} {}", primitive.kind(), handle),
+                    None => "clustered or particle sphere".to_string(),
+                }
+            }
+            None => "background".to_string(),
+        };
+        eprintln!(
+            "non-finite radiance {:?} at pixel ({}, {}) on {}",
+            color, px_x, px_y, object
+        );
+    }
+    black
+}
+
+pub fn get_pixel_color(
+    scene: &Scene,
+    ray: &Ray,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+) -> Rgba<u8> {
+    let color = checked_radiance(scene, ray, render_options, px_x, px_y);
+    let dither = render_options.dither.offset(px_x, px_y);
+    to_rgba(display_color(color, render_options), dither)
+}
+
+// Radiance seen by `camera` through pixel (`px_x`, `px_y`), averaged over
+// `RenderOptions::samples_per_pixel` rays. A single ray goes through the
+// pixel's centre; more, or any in a later progressive pass, are jittered
+// over the pixel by a Hammersley set rotated differently in each pixel and
+// pass, which smooths edges without leaving a regular pattern in them.
+pub fn pixel_radiance(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+) -> Color {
+    let count = pixel_ray_count(render_options);
+    if count == 1 {
+        let ray = pixel_ray(camera, render_options, px_x, px_y, 0);
+        return checked_radiance(scene, &ray, render_options, px_x, px_y);
+    }
+    let mut sum = Vector3::new(0.0, 0.0, 0.0);
+    for i in 0..count {
+        let ray = pixel_ray(camera, render_options, px_x, px_y, i);
+        sum += checked_radiance(scene, &ray, render_options, px_x, px_y);
+    }
+    sum / count as f32
+}
+
+// Number of camera rays `pixel_radiance` averages over each pixel
+pub fn pixel_ray_count(render_options: &RenderOptions) -> u32 {
+    let count = render_options.samples_per_pixel;
+    if count <= 1 && render_options.pass == 0 {
+        1
+    } else {
+        count
+    }
+}
+
+// Camera ray `i` of those `pixel_radiance` averages over pixel (`px_x`,
+// `px_y`)
+pub fn pixel_ray(
+    camera: &Camera,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+    i: u32,
+) -> Ray {
+    let count = render_options.samples_per_pixel;
+    if count <= 1 && render_options.pass == 0 && camera.aperture <= 0.0 {
+        return primary_ray(camera, render_options, px_x as f32 + 0.5, px_y as f32 + 0.5);
+    }
+    let rotation = sampling::pixel_rotation(px_x, px_y, render_options.pass);
+    let (dx, dy) = sampling::hammersley(i, count, rotation);
+    let lens = sampling::hammersley(sampling::shuffle(i, count), count, (rotation.1, rotation.0));
+    lens_ray(camera, render_options, px_x as f32 + dx, px_y as f32 + dy, lens)
+}
+
+// `get_pixel_color` for the pixel's camera rays
+pub fn camera_pixel_color(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+) -> Rgba<u8> {
+    let color = pixel_radiance(scene, camera, render_options, px_x, px_y);
+    let dither = render_options.dither.offset(px_x, px_y);
+    to_rgba(display_color(color, render_options), dither)
+}
+
+// Radiance exposed, tone mapped if asked and encoded in the output space,
+// ready to quantize
+pub fn display_color(color: Color, render_options: &RenderOptions) -> Color {
+    let exposed = color * render_options.exposure.exp2();
+    let mapped = if render_options.tone_mapping {
+        // Reinhard's curve, each channel on its own
+        let curve = |c: f32| c / (1.0 + c.max(0.0));
+        Vector3::new(curve(exposed.x), curve(exposed.y), curve(exposed.z))
+    } else {
+        exposed
+    };
+    render_options.output_space.encode(mapped)
+}
+
+pub fn render_rect<P: FramePixel>(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    rect: &Rect,
+    tile: &mut TileBuffer<P>,
+) {
+    if render_options.integrator == path::Integrator::Wavefront {
+        wavefront::render_rect(scene, camera, render_options, rect, tile);
+        return;
+    }
+    // Quads of pixels traced as packets where each pixel has one ray, and
+    // whatever's left over a pixel at a time
+    let packets = render_options.integrator == path::Integrator::Direct
+        && pixel_ray_count(render_options) == 1
+        && !render_options.clipping
+        && !render_options.robust_intersections;
+    let (quads_x, quads_y) = if packets { (rect.width / 2, rect.height / 2) } else { (0, 0) };
+    for quad_x in 0..quads_x {
+        for quad_y in 0..quads_y {
+            let (px_x, px_y) = (rect.x + quad_x * 2, rect.y + quad_y * 2);
+            render_quad(scene, camera, render_options, px_x, px_y, tile);
+        }
+    }
+    for px_x in rect.x..(rect.x + rect.width) {
+        for px_y in rect.y..(rect.y + rect.height) {
+            if px_x - rect.x < quads_x * 2 && px_y - rect.y < quads_y * 2 {
+                continue;
+            }
+            let color = pixel_radiance(scene, camera, render_options, px_x, px_y);
+            tile.put_pixel(px_x, px_y, P::from_radiance(color, render_options, px_x, px_y));
+        }
+    }
+}
+
+// Renders the 2x2 quad of pixels from (`px_x`, `px_y`) as `pixel_radiance`
+// would with one ray per pixel, finding where the four rays first hit as a
+// packet
+fn render_quad<P: FramePixel>(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    px_x: u32,
+    px_y: u32,
+    tile: &mut TileBuffer<P>,
+) {
+    let pixels = [(px_x, px_y), (px_x + 1, px_y), (px_x, px_y + 1), (px_x + 1, px_y + 1)];
+    let ray = |(px_x, px_y)| pixel_ray(camera, render_options, px_x, px_y, 0);
+    let rays = [ray(pixels[0]), ray(pixels[1]), ray(pixels[2]), ray(pixels[3])];
+    let hits = closest_intersections(scene, &rays, render_options);
+    for ((&(px_x, px_y), ray), found) in pixels.iter().zip(&rays).zip(hits) {
+        let color = checked(scene, ray, render_options, px_x, px_y, || {
+            shade(scene, ray, found, render_options, 0)
+        });
+        tile.put_pixel(px_x, px_y, P::from_radiance(color, render_options, px_x, px_y));
+    }
+}
+
+// Pixels of one tile, rendered apart from the frame so that tiles can be
+// traced on several threads at once and copied in afterwards.
+pub struct TileBuffer<P: FramePixel> {
+    rect: Rect,
+    pixels: Frame<P>,
+}
+
+impl<P: FramePixel> TileBuffer<P> {
+    pub fn new(rect: Rect) -> TileBuffer<P> {
+        TileBuffer {
+            rect,
+            pixels: Frame::new(rect.width, rect.height),
+        }
+    }
+
+    // `px_x` and `px_y` are frame coordinates inside the tile
+    pub fn put_pixel(&mut self, px_x: u32, px_y: u32, pixel: P) {
+        self.pixels.put_pixel(px_x - self.rect.x, px_y - self.rect.y, pixel);
+    }
+}
+
+// Renders the tile with `render`, keeping a panic from taking the whole
+// render down with it. A tile that panics is retried a pixel at a time, and
+// each pixel that still panics is logged and filled with the panic colour.
+fn render_isolated<P, F>(tile: &mut TileBuffer<P>, render: F)
+where
+    P: FramePixel,
+    F: Fn(&Rect, &mut TileBuffer<P>),
+{
+    let rect = tile.rect;
+    if panic::catch_unwind(AssertUnwindSafe(|| render(&rect, tile))).is_ok() {
+        return;
+    }
+    for px_x in rect.x..(rect.x + rect.width) {
+        for px_y in rect.y..(rect.y + rect.height) {
+            let pixel = Rect {
+                x: px_x,
+                y: px_y,
+                width: 1,
+                height: 1,
+            };
+            if panic::catch_unwind(AssertUnwindSafe(|| render(&pixel, tile))).is_err() {
+                eprintln!("panic rendering pixel ({}, {})", px_x, px_y);
+                tile.put_pixel(px_x, px_y, P::panic_color());
+            }
+        }
+    }
+}
+
+// Renders `rects` in parallel on the render thread pool, each in isolation,
+// and copies the finished tiles into `img`. Returns the time each took.
+pub fn render_parallel<P, F>(rects: &[Rect], img: &mut Frame<P>, render: F) -> Vec<Duration>
+where
+    P: FramePixel + Send,
+    P::Subpixel: Send,
+    F: Fn(&Rect, &mut TileBuffer<P>) + Sync,
+{
+    let rendered: Vec<(TileBuffer<P>, Duration)> = rects
+        .par_iter()
+        .map(|&rect| {
+            let start = Instant::now();
+            let mut tile = TileBuffer::new(rect);
+            render_isolated(&mut tile, &render);
+            report::flush_rays();
+            (tile, start.elapsed())
+        })
+        .collect();
+    rendered
+        .into_iter()
+        .map(|(tile, took)| {
+            img.copy_from(&tile.pixels, tile.rect.x, tile.rect.y);
+            took
+        })
+        .collect()
+}
+
+// Renders the tiles of `img` not yet marked `done`, in `tiles::tiles`
+// order, stopping early on Ctrl+C. Returns whether every tile is done.
+pub fn render_tiles<P>(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    img: &mut Frame<P>,
+    done: &mut [bool],
+) -> bool
+where
+    P: FramePixel + Send,
+    P::Subpixel: Send,
+{
+    let tiles = tiles::tiles(render_options.width, render_options.height, render_options.tile_size);
+    let remaining: Vec<usize> = (0..tiles.len()).filter(|&i| !done[i]).collect();
+    let mut progress = progress::Progress::new("tiles", remaining.len());
+    // A few tiles per thread between interrupt checks keeps every thread
+    // busy without making Ctrl+C wait for the whole frame
+    let batch_size = rayon::current_num_threads() * 4;
+    for batch in remaining.chunks(batch_size) {
+        if interrupt::interrupted() {
+            return false;
+        }
+        let rects: Vec<Rect> = batch.iter().map(|&i| tiles[i]).collect();
+        render_parallel(&rects, img, |rect, tile| {
+            render_rect(scene, camera, render_options, rect, tile)
+        });
+        for &i in batch {
+            done[i] = true;
+            progress.tick();
+        }
+    }
+    true
+}
+
+/// Renders the whole frame `camera` sees of `scene` as 8-bit RGBA, exposed,
+/// encoded in the output space and dithered as an image output would be.
+/// Tiles are traced in parallel on the render thread pool.
+pub fn render_frame(scene: &Scene, camera: &Camera, render_options: &RenderOptions) -> RgbaImage {
+    let (width, height) = (render_options.width, render_options.height);
+    let rects = tiles::tiles(width, height, render_options.tile_size);
+    let mut frame = RgbaImage::new(width, height);
+    render_parallel(&rects, &mut frame, |rect, tile| {
+        render_rect(scene, camera, render_options, rect, tile)
+    });
+    frame
+}
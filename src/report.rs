@@ -0,0 +1,151 @@
+//! Machine-readable reports and exit codes for offline renders, so scripts
+//! and CI can tell what a run produced and whether it succeeded.
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+pub const EXIT_OK: i32 = 0;
+// Bad command line
+pub const EXIT_USAGE: i32 = 2;
+// A scene or batch file couldn't be loaded
+pub const EXIT_SCENE: i32 = 3;
+// Rendering finished but an output couldn't be written
+pub const EXIT_OUTPUT: i32 = 4;
+// One or more batch jobs failed
+pub const EXIT_PARTIAL: i32 = 5;
+// A self-check, such as the furnace test, found a fault
+pub const EXIT_CHECK: i32 = 6;
+// Stopped by Ctrl+C, as shells report for SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+// Rays traced by this process, of every kind, but for those still counted
+// only by the threads that traced them
+static RAYS: AtomicUsize = AtomicUsize::new(0);
+// How many rays a thread counts before adding them to `RAYS` itself, for
+// rays traced other than in tiles
+const RAY_BATCH: usize = 4096;
+
+thread_local! {
+    // Rays this thread has traced and not yet added to `RAYS`
+    static UNCOUNTED: Cell<usize> = const { Cell::new(0) };
+}
+
+// Counts a ray on this thread, so that tracing one costs no contention
+pub fn count_ray() {
+    let uncounted = UNCOUNTED.with(|n| {
+        n.set(n.get() + 1);
+        n.get()
+    });
+    if uncounted == RAY_BATCH {
+        flush_rays();
+    }
+}
+
+// Adds the rays this thread has counted to `RAYS`, as each tile finishes
+pub fn flush_rays() {
+    let uncounted = UNCOUNTED.with(|n| n.replace(0));
+    if uncounted > 0 {
+        RAYS.fetch_add(uncounted, Ordering::Relaxed);
+    }
+}
+
+pub fn rays_traced() -> usize {
+    RAYS.load(Ordering::Relaxed) + UNCOUNTED.with(|n| n.get())
+}
+
+pub struct Report {
+    command: String,
+    started: Instant,
+    rays_at_start: usize,
+    samples_per_pixel: u32,
+    outputs: Vec<PathBuf>,
+    warnings: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl Report {
+    pub fn new(command: &str) -> Report {
+        Report {
+            command: command.to_string(),
+            started: Instant::now(),
+            rays_at_start: rays_traced(),
+            samples_per_pixel: 1,
+            outputs: Vec::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn set_samples_per_pixel(&mut self, samples: u32) {
+        self.samples_per_pixel = samples;
+    }
+
+    pub fn output(&mut self, path: &Path) {
+        self.outputs.push(path.to_path_buf());
+    }
+
+    pub fn warning(&mut self, message: String) {
+        println!("warning: {}", message);
+        self.warnings.push(message);
+    }
+
+    pub fn error(&mut self, message: String) {
+        println!("{}", message);
+        self.errors.push(message);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn to_json(&self, exit_code: i32) -> String {
+        let seconds = self.started.elapsed().as_secs_f64();
+        let rays = rays_traced() - self.rays_at_start;
+        let rays_per_second = if seconds > 0.0 { rays as f64 / seconds } else { 0.0 };
+        let outputs: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|p| json_string(&p.display().to_string()))
+            .collect();
+        let strings = |v: &[String]| v.iter().map(|s| json_string(s)).collect::<Vec<_>>();
+        format!(
+            "{{\n  \"command\": {},\n  \"exit_code\": {},\n  \"elapsed_seconds\": {:.3},\n  \
+             \"samples_per_pixel\": {},\n  \"rays\": {},\n  \"rays_per_second\": {:.0},\n  \
+             \"outputs\": [{}],\n  \"warnings\": [{}],\n  \"errors\": [{}]\n}}\n",
+            json_string(&self.command),
+            exit_code,
+            seconds,
+            self.samples_per_pixel,
+            rays,
+            rays_per_second,
+            outputs.join(", "),
+            strings(&self.warnings).join(", "),
+            strings(&self.errors).join(", ")
+        )
+    }
+
+    pub fn write(&self, path: &Path, exit_code: i32) -> io::Result<()> {
+        File::create(path)?.write_all(self.to_json(exit_code).as_bytes())
+    }
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
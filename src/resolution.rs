@@ -0,0 +1,138 @@
+//! Dynamic resolution for the interactive window. When whole frames take
+//! longer to trace than a target frame rate allows, frames are traced at a
+//! fraction of the window's size and scaled up to fill it, and once frames
+//! come in comfortably under the target the resolution steps back up to
+//! full.
+//!
+//! The rate held to is that of whole frames traced, not of frames shown: a
+//! frame budget keeps the window responsive on its own, but a slow scene
+//! still takes many shown frames to fill in, and that is what lowering the
+//! resolution cuts.
+
+use im::{Rgba, RgbaImage};
+use std::time::Instant;
+
+use render::RenderOptions;
+
+// Fractions of the window's width and height frames are traced at, from
+// full size down
+const SCALES: [f32; 4] = [1.0, 0.75, 0.5, 0.25];
+// Frames over the target in a row that lower the resolution a step, and
+// frames that would fit at the next size up that raise it. Raising takes
+// longer, so a frame rate near the target doesn't flicker between sizes.
+const SLOW_FRAMES: u32 = 2;
+const FAST_FRAMES: u32 = 8;
+// Share of the target a frame at the next size up must be expected to
+// take before the resolution is raised
+const HEADROOM: f64 = 0.8;
+
+/// Picks the resolution frames are traced at to hold a target frame rate.
+pub struct DynamicResolution {
+    // Longest a whole frame should take, in seconds
+    target: f64,
+    // Index into `SCALES` of the current size
+    level: usize,
+    // When the last whole frame was finished
+    last: Instant,
+    slow: u32,
+    fast: u32,
+}
+
+impl DynamicResolution {
+    /// Holds whole frames to at least `fps` a second, starting at full
+    /// resolution.
+    pub fn new(fps: f64) -> DynamicResolution {
+        DynamicResolution {
+            target: 1.0 / fps,
+            level: 0,
+            last: Instant::now(),
+            slow: 0,
+            fast: 0,
+        }
+    }
+
+    /// Fraction of the window's width and height to trace frames at.
+    pub fn scale(&self) -> f32 {
+        SCALES[self.level]
+    }
+
+    /// Records that a whole frame has just been traced, and returns true if
+    /// the next is to be traced at a different size.
+    pub fn frame_finished(&mut self) -> bool {
+        let now = Instant::now();
+        let took = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+
+        // Time goes mostly on tracing, so with the area of the frame
+        let larger = match self.level {
+            0 => None,
+            level => Some(took * f64::from(SCALES[level - 1] / SCALES[level]).powi(2)),
+        };
+        if took > self.target {
+            self.slow += 1;
+            self.fast = 0;
+        } else if larger.is_some_and(|t| t <= self.target * HEADROOM) {
+            self.fast += 1;
+            self.slow = 0;
+        } else {
+            self.slow = 0;
+            self.fast = 0;
+        }
+
+        let level = if self.slow >= SLOW_FRAMES && self.level + 1 < SCALES.len() {
+            self.level + 1
+        } else if self.fast >= FAST_FRAMES {
+            self.level - 1
+        } else {
+            return false;
+        };
+        self.level = level;
+        self.slow = 0;
+        self.fast = 0;
+        true
+    }
+}
+
+/// `render_options` for a frame `scale` times the size of theirs, at least
+/// a pixel each way.
+pub fn scaled(render_options: &RenderOptions, scale: f32) -> RenderOptions {
+    let size = |pixels: u32| ((pixels as f32 * scale).round() as u32).max(1);
+    RenderOptions {
+        width: size(render_options.width),
+        height: size(render_options.height),
+        ..render_options.clone()
+    }
+}
+
+/// Resizes `from` to fill `to`, blending the four nearest pixels of `from`
+/// for each pixel of `to`.
+pub fn resample(from: &RgbaImage, to: &mut RgbaImage) {
+    let (width, height) = from.dimensions();
+    let scale_x = width as f32 / to.width() as f32;
+    let scale_y = height as f32 / to.height() as f32;
+    // Position in `from` of the centre of pixel `px` of `to`, and the two
+    // pixels either side of it with the weight of the second
+    let span = |px: u32, scale: f32, size: u32| {
+        let x = ((px as f32 + 0.5) * scale - 0.5).clamp(0.0, (size - 1) as f32);
+        let x0 = x.floor() as u32;
+        (x0, (x0 + 1).min(size - 1), x - x0 as f32)
+    };
+    for px_y in 0..to.height() {
+        let (y0, y1, fy) = span(px_y, scale_y, height);
+        for px_x in 0..to.width() {
+            let (x0, x1, fx) = span(px_x, scale_x, width);
+            let corners = [
+                (from.get_pixel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+                (from.get_pixel(x1, y0), fx * (1.0 - fy)),
+                (from.get_pixel(x0, y1), (1.0 - fx) * fy),
+                (from.get_pixel(x1, y1), fx * fy),
+            ];
+            let mut pixel = Rgba([0; 4]);
+            for c in 0..4 {
+                let value: f32 = corners.iter().map(|&(p, w)| p.data[c] as f32 * w).sum();
+                pixel.data[c] = value.round() as u8;
+            }
+            to.put_pixel(px_x, px_y, pixel);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Resolution of asset paths named in scene files.
+//!
+//! `${NAME}` is replaced with the environment variable `NAME`. A relative
+//! path is then looked up next to the file that names it, falling back to
+//! each search path in order. Search paths come from `--search-path` and
+//! the `RS_TRACER_PATH` environment variable.
+//!
+//! Meshes, IES profiles, environment maps and fonts are loaded through the
+//! resolver too, which keeps each one it reads, so every scene loaded
+//! through the same resolver shares them. A file is read again once it has
+//! been modified.
+//!
+//! Unless the scene cache is turned off, the resolver also keeps every
+//! asset it resolves, as named and as found, for the cache to record which
+//! files a scene was loaded from and to check they'd still be found there.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use rusttype::Font;
+use std::time::SystemTime;
+
+use environment::{self, EnvironmentMap};
+use ies::{self, Profile};
+use label;
+use mesh::Mesh;
+use obj;
+
+const SEARCH_PATH_VAR: &str = "RS_TRACER_PATH";
+
+// Assets by path, along with when each file was modified as it was read
+type Cache<T> = RefCell<HashMap<PathBuf, (Option<SystemTime>, T)>>;
+
+pub struct AssetResolver {
+    search_paths: Vec<PathBuf>,
+    meshes: Cache<Mesh>,
+    profiles: Cache<Arc<Profile>>,
+    environments: Cache<Arc<EnvironmentMap>>,
+    fonts: Cache<Font<'static>>,
+    scene_cache: bool,
+    // Assets resolved since the cache last took them
+    resolved: RefCell<Vec<Resolution>>,
+}
+
+/// An asset as a scene file named it, and the file it was resolved to.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Resolution {
+    pub asset: String,
+    // Directory of the file that named the asset
+    pub dir: PathBuf,
+    pub path: PathBuf,
+}
+
+impl AssetResolver {
+    pub fn new(mut search_paths: Vec<PathBuf>) -> AssetResolver {
+        if let Some(paths) = env::var_os(SEARCH_PATH_VAR) {
+            search_paths.extend(env::split_paths(&paths));
+        }
+        AssetResolver {
+            search_paths,
+            meshes: RefCell::new(HashMap::new()),
+            profiles: RefCell::new(HashMap::new()),
+            environments: RefCell::new(HashMap::new()),
+            fonts: RefCell::new(HashMap::new()),
+            scene_cache: true,
+            resolved: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Loads scenes without the binary cache, neither reading nor writing
+    /// it.
+    pub fn disable_scene_cache(&mut self) {
+        self.scene_cache = false;
+    }
+
+    pub fn scene_cache(&self) -> bool {
+        self.scene_cache
+    }
+
+    /// Assets resolved since the last call.
+    pub fn take_resolved(&self) -> Vec<Resolution> {
+        self.resolved.replace(Vec::new())
+    }
+
+    /// Resolves `asset` as named by a file in `dir`.
+    pub fn resolve(&self, asset: &str, dir: &Path) -> Result<PathBuf, String> {
+        let expanded = PathBuf::from(expand_vars(asset)?);
+        if expanded.is_absolute() {
+            self.note_resolved(asset, dir, &expanded);
+            return Ok(expanded);
+        }
+        let candidates = Some(dir)
+            .into_iter()
+            .chain(self.search_paths.iter().map(PathBuf::as_path))
+            .map(|base| base.join(&expanded));
+        let mut tried = Vec::new();
+        for candidate in candidates {
+            if candidate.exists() {
+                self.note_resolved(asset, dir, &candidate);
+                return Ok(candidate);
+            }
+            tried.push(candidate.display().to_string());
+        }
+        Err(format!("{}: not found (tried {})", asset, tried.join(", ")))
+    }
+
+    fn note_resolved(&self, asset: &str, dir: &Path, path: &Path) {
+        if self.scene_cache {
+            self.resolved.borrow_mut().push(Resolution {
+                asset: asset.to_string(),
+                dir: dir.to_path_buf(),
+                path: path.to_path_buf(),
+            });
+        }
+    }
+
+    /// The OBJ model at resolved `path`.
+    pub fn mesh(&self, path: &Path) -> Result<Mesh, String> {
+        cached(&self.meshes, path, obj::load)
+    }
+
+    /// The IES profile at resolved `path`.
+    pub fn profile(&self, path: &Path) -> Result<Arc<Profile>, String> {
+        cached(&self.profiles, path, |path| ies::load(path).map(Arc::new))
+    }
+
+    /// The environment map at resolved `path`.
+    pub fn environment(&self, path: &Path) -> Result<Arc<EnvironmentMap>, String> {
+        cached(&self.environments, path, |path| environment::load(path).map(Arc::new))
+    }
+
+    /// The TrueType font at resolved `path`.
+    pub fn font(&self, path: &Path) -> Result<Font<'static>, String> {
+        cached(&self.fonts, path, label::load_font)
+    }
+}
+
+// The asset at `path` from `cache`, loading it with `load` if it hasn't been
+// yet or the file has changed since
+fn cached<T: Clone, F>(cache: &Cache<T>, path: &Path, load: F) -> Result<T, String>
+where
+    F: FnOnce(&Path) -> Result<T, String>,
+{
+    let modified = modified(path);
+    if let Some(&(when, ref asset)) = cache.borrow().get(path) {
+        if when.is_some() && when == modified {
+            return Ok(asset.clone());
+        }
+    }
+    let asset = load(path)?;
+    cache.borrow_mut().insert(path.to_path_buf(), (modified, asset.clone()));
+    Ok(asset)
+}
+
+/// When the file at `path` was last modified, if that can be told.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn expand_vars(s: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(format!("{}: unterminated ${{", s)),
+        };
+        let name = &rest[start + 2..end];
+        let value = env::var(name).map_err(|_| format!("{}: ${{{}}} is not set", s, name))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// An empty directory of its own for the test `name` to write files into.
+#[cfg(test)]
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rs-tracer-test-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
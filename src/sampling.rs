@@ -0,0 +1,130 @@
+//! Sample patterns for estimating light that arrives over an area or a
+//! range of directions rather than from a single point.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use std::f32::consts::PI;
+
+// Fractional part of the bits of `i` mirrored about the binary point
+fn radical_inverse(i: u32) -> f32 {
+    i.reverse_bits() as f32 * (1.0 / 4_294_967_296.0)
+}
+
+/// Point `i` of an `n` point Hammersley set in the unit square, shifted by
+/// `rotation` and wrapped. The set covers the square evenly for any `n`.
+pub fn hammersley(i: u32, n: u32, rotation: (f32, f32)) -> (f32, f32) {
+    let x = (i as f32 + 0.5) / n as f32 + rotation.0;
+    let y = radical_inverse(i) + rotation.1;
+    (x.fract(), y.fract())
+}
+
+/// Index `i` of `n` moved to another place among them, no two to the same
+/// one. Pairing points `i` and `shuffle(i, n)` of two `hammersley` sets
+/// samples two squares at once without the second following the first.
+pub fn shuffle(i: u32, n: u32) -> u32 {
+    // Stepping round by a stride with no factor in common with `n` reaches
+    // every index once; near the golden ratio of `n`, neighbours land far
+    // apart
+    let mut stride = (n as f64 * 0.618_034) as u32 | 1;
+    while gcd(stride, n) != 1 {
+        stride += 2;
+    }
+    (i as u64 * stride as u64 % n as u64) as u32
+}
+
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+/// Point of the unit disk, as `sample` covers the unit square, keeping
+/// points evenly spread ("A Low Distortion Map Between Disk and Square",
+/// Shirley and Chiu).
+pub fn concentric_disk(sample: (f32, f32)) -> (f32, f32) {
+    let (a, b) = (2.0 * sample.0 - 1.0, 2.0 * sample.1 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * phi.cos(), r * phi.sin())
+}
+
+/// Offset in the unit square that varies from point to point. Rotating each
+/// shading point's sample pattern by a different one turns the error of a
+/// few samples into fine noise rather than banding.
+pub fn rotation(point: Point3<f32>) -> (f32, f32) {
+    scramble(point.x.to_bits(), point.y.to_bits(), point.z.to_bits())
+}
+
+/// Offset in the unit square that varies from pixel to pixel, as `rotation`
+/// does from point to point, and from one progressive `pass` to the next.
+pub fn pixel_rotation(px_x: u32, px_y: u32, pass: u32) -> (f32, f32) {
+    scramble(px_x, px_y, pass)
+}
+
+// Two well mixed unit values from three words
+fn scramble(a: u32, b: u32, c: u32) -> (f32, f32) {
+    let mut h = a;
+    h = (h ^ b).wrapping_mul(0x9e37_79b1);
+    h = (h ^ c).wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 13;
+    let unit = |bits: u32| (bits >> 8) as f32 * (1.0 / 16_777_216.0);
+    (unit(h), unit(h.rotate_left(16)))
+}
+
+/// Direction spread evenly over the hemisphere about unit `normal` as
+/// `sample` covers the unit square. Each has a probability density of
+/// 1 / 2 pi per steradian.
+pub fn uniform_hemisphere(normal: Vector3<f32>, sample: (f32, f32)) -> Vector3<f32> {
+    uniform_cone(normal, 0.0, sample)
+}
+
+/// Direction spread evenly over the cone about unit `axis` whose half-angle
+/// has a cosine of `cos_max`. Each has a probability density of
+/// 1 / (2 pi (1 - cos_max)) per steradian.
+pub fn uniform_cone(axis: Vector3<f32>, cos_max: f32, sample: (f32, f32)) -> Vector3<f32> {
+    let cos_theta = 1.0 - sample.0 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * sample.1;
+    let (tangent, bitangent) = tangents(axis);
+    tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta
+}
+
+/// Direction over the hemisphere about unit `normal`, denser towards the
+/// normal as `sample` covers the unit square: each has a probability
+/// density of its cosine with the normal over pi per steradian, so
+/// diffuse bounces need no cosine weighting. Points spread evenly over the
+/// unit disk and lifted onto the hemisphere, as in Malley's method.
+pub fn cosine_hemisphere(normal: Vector3<f32>, sample: (f32, f32)) -> Vector3<f32> {
+    let r = sample.0.sqrt();
+    let phi = 2.0 * PI * sample.1;
+    let (tangent, bitangent) = tangents(normal);
+    let up = (1.0 - sample.0).max(0.0).sqrt();
+    tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * up
+}
+
+// Two unit vectors perpendicular to unit `axis` and to each other
+fn tangents(axis: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    // Any tangent will do; pick the axis least parallel to the given one
+    let other = if axis.x.abs() < 0.9 {
+        Vector3::new(1.0, 0.0, 0.0)
+    } else {
+        Vector3::new(0.0, 1.0, 0.0)
+    };
+    let tangent = axis.cross(other).normalize();
+    (tangent, axis.cross(tangent))
+}
+
+/// Solid angle of a cone whose half-angle has a cosine of `cos_max`.
+pub fn cone_solid_angle(cos_max: f32) -> f32 {
+    2.0 * PI * (1.0 - cos_max)
+}
@@ -0,0 +1,449 @@
+//! What is being rendered: objects, lights and the data kept about them,
+//! and the queries that trace rays through them.
+
+use cgmath::{Deg, Matrix4, Point3, SquareMatrix, Vector3};
+use std::cmp::Ordering;
+use std::path::Path;
+
+use cache;
+use camera::Camera;
+use components::{self, Column};
+use geometry::{Ray, Sphere};
+use gltf;
+use handle::{Handle, Pool};
+use json;
+use light;
+use lod;
+use material::Material;
+use mesh::Mesh;
+use packet::{RayPacket, LANES};
+use particles;
+use pbrt;
+use primitive::{Hit, Intersectable, Primitive};
+use render::RenderOptions;
+use report;
+use resolve;
+use usd;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+/// Linear unit and up axis that a scene, or an asset imported into one, is
+/// authored in.
+#[derive(Clone, Copy, Debug)]
+pub struct Units {
+    pub meters_per_unit: f32,
+    pub up_axis: UpAxis,
+}
+
+impl Units {
+    pub fn up(&self) -> Vector3<f32> {
+        match self.up_axis {
+            UpAxis::Y => Vector3::new(0.0, 1.0, 0.0),
+            UpAxis::Z => Vector3::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    /// Transform taking coordinates authored in these units into `target`.
+    pub fn conversion_to(&self, target: &Units) -> Matrix4<f32> {
+        let scale = Matrix4::from_scale(self.meters_per_unit / target.meters_per_unit);
+        let axis = match (self.up_axis, target.up_axis) {
+            (UpAxis::Z, UpAxis::Y) => Matrix4::from_angle_x(Deg(-90.0)),
+            (UpAxis::Y, UpAxis::Z) => Matrix4::from_angle_x(Deg(90.0)),
+            _ => Matrix4::identity(),
+        };
+        axis * scale
+    }
+}
+
+impl Default for Units {
+    fn default() -> Units {
+        Units {
+            meters_per_unit: 1.0,
+            up_axis: UpAxis::Y,
+        }
+    }
+}
+
+/// Everything a frame is rendered from, and the clock it is animated by.
+pub struct Scene {
+    pub(crate) primitives: Pool<Primitive>,
+    pub(crate) clusters: Vec<lod::SphereCluster>,
+    pub(crate) emitters: Vec<particles::Emitter>,
+    pub(crate) lights: Pool<light::Light>,
+    // Materials the scene names, for edits to apply to its objects
+    pub(crate) materials: Pool<Material>,
+    // Which of those each object was given, for edits to a named material
+    // to reach every object using it
+    pub(crate) material_bindings: Column<Handle<Material>>,
+    pub(crate) animations: Column<components::Animation>,
+    pub(crate) keyframes: Column<components::Keyframes>,
+    // Where each mesh read from a model file came from, to reload it
+    pub(crate) mesh_sources: Column<components::MeshSource>,
+    pub(crate) units: Units,
+    // Scene clock, in seconds
+    pub time: f32,
+}
+
+impl Scene {
+    /// A scene with nothing in it, in metres with +Y up.
+    pub fn empty() -> Scene {
+        Scene {
+            primitives: Pool::new(),
+            clusters: Vec::new(),
+            emitters: Vec::new(),
+            lights: Pool::new(),
+            materials: Pool::new(),
+            material_bindings: Column::new(),
+            animations: Column::new(),
+            keyframes: Column::new(),
+            mesh_sources: Column::new(),
+            units: Units::default(),
+            time: 0.0,
+        }
+    }
+
+    /// The default built-in scene: two spheres drifting away from a camera
+    /// at the origin, lit by the camera's headlight.
+    pub fn demo() -> Scene {
+        let mut scene = Scene::empty();
+        let spheres = vec![
+            Sphere {
+                center: Point3::new(-2.0, 0.0, -4.0),
+                radius: 1.0,
+                material: Material::diffuse(Vector3::new(0.9, 0.3, 0.2)),
+            },
+            Sphere {
+                center: Point3::new(4.0, 2.0, -10.0),
+                radius: 0.9,
+                material: Material::diffuse(Vector3::new(0.3, 0.5, 0.9)),
+            },
+        ];
+        // Both spheres drift away from the camera, in units per second
+        for (sphere, speed) in spheres.into_iter().zip(vec![0.24, 0.36]) {
+            let object = scene.primitives.push(Primitive::Sphere(sphere));
+            let velocity = Vector3::new(0.0, 0.0, -speed);
+            scene.animations.insert(object, components::Animation { velocity });
+        }
+        scene
+    }
+
+    /// A scene of `spheres`, in metres with +Y up, such as a field of them
+    /// scattered with the `procedural` helpers. Without lights it's lit by
+    /// the camera's headlight.
+    pub fn from_spheres<I: IntoIterator<Item = Sphere>>(spheres: I) -> Scene {
+        let mut scene = Scene::empty();
+        for sphere in spheres {
+            scene.add_sphere(sphere);
+        }
+        scene
+    }
+
+    /// Adds `sphere` to the scene, returning the handle edits name it by.
+    pub fn add_sphere(&mut self, sphere: Sphere) -> Handle<Primitive> {
+        self.primitives.push(Primitive::Sphere(sphere))
+    }
+
+    /// Adds `mesh` to the scene, returning the handle edits name it by.
+    pub fn add_mesh(&mut self, mesh: Mesh) -> Handle<Primitive> {
+        self.primitives.push(Primitive::Mesh(mesh))
+    }
+
+    /// Adds `light` to the scene, returning the handle edits name it by.
+    pub fn add_light(&mut self, light: light::Light) -> Handle<light::Light> {
+        self.lights.push(light)
+    }
+
+    /// Loads the scene file at `path`, along with the first camera in it.
+    /// Assets the file names are looked for beside it, then on the
+    /// `RS_TRACER_PATH` search path. The loaded scene is cached beside the
+    /// file as `<path>.cache`, to be read back while nothing has changed.
+    pub fn load(path: &Path) -> Result<(Scene, Option<Camera>), String> {
+        let resolver = resolve::AssetResolver::new(Vec::new());
+        let stage = load_stage(path, &Units::default(), &resolver)?;
+        let camera = stage.camera(None).cloned();
+        Ok((stage.scene, camera))
+    }
+
+    /// Moves the scene clock on by `dt` seconds, stepping anything animated
+    /// by it.
+    pub fn advance(&mut self, dt: f32) {
+        let from = self.time;
+        self.time += dt;
+        for emitter in &mut self.emitters {
+            emitter.step(dt);
+        }
+        self.displace(from, dt);
+    }
+
+    /// Moves the moving objects from where they stand at time `from` on to
+    /// where they stand `dt` seconds later, or earlier for a negative `dt`,
+    /// leaving the clock and particles as they are.
+    pub(crate) fn displace(&mut self, from: f32, dt: f32) {
+        for (object, animation) in self.animations.iter() {
+            if let Some(primitive) = self.primitives.get_mut(object) {
+                primitive.translate(animation.velocity * dt);
+            }
+        }
+        for (object, keyframes) in self.keyframes.iter() {
+            if let Some(primitive) = self.primitives.get_mut(object) {
+                primitive.translate(keyframes.offset(from + dt) - keyframes.offset(from));
+            }
+        }
+    }
+
+    /// Whether any objects move as the clock runs.
+    pub fn is_moving(&self) -> bool {
+        !self.animations.is_empty() || !self.keyframes.is_empty()
+    }
+
+    /// Whether advancing the clock changes the scene.
+    pub fn is_animated(&self) -> bool {
+        self.is_moving() || !self.emitters.is_empty()
+    }
+
+    /// Takes `object` out of the scene along with all its data.
+    pub(crate) fn remove_object(&mut self, object: Handle<Primitive>) -> Option<Primitive> {
+        self.material_bindings.remove(object);
+        self.animations.remove(object);
+        self.keyframes.remove(object);
+        self.mesh_sources.remove(object);
+        self.primitives.remove(object)
+    }
+
+    /// The scene's spheres, in order, leaving out those in LOD clusters.
+    pub(crate) fn spheres<'a>(&'a self) -> impl Iterator<Item = &'a Sphere> + 'a {
+        self.primitives.iter().filter_map(Primitive::as_sphere)
+    }
+
+    pub(crate) fn spheres_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut Sphere> + 'a {
+        self.primitives.iter_mut().filter_map(Primitive::as_sphere_mut)
+    }
+}
+
+// Every shape `ray` hits, in no particular order, with the nearest hit on
+// each
+fn hits<'a>(
+    scene: &'a Scene,
+    ray: &'a Ray,
+    render_options: &'a RenderOptions,
+) -> impl Iterator<Item = (&'a dyn Intersectable, Hit)> + 'a {
+    report::count_ray();
+    shapes(scene, ray).filter_map(move |shape| {
+        shape
+            .intersect(ray, render_options)
+            .map(|hit| (shape, hit))
+    })
+}
+
+// Every shape that `ray` might hit: the scene's primitives, the spheres of
+// any LOD cluster it passes through and live particles
+fn shapes<'a>(scene: &'a Scene, ray: &'a Ray) -> impl Iterator<Item = &'a dyn Intersectable> + 'a {
+    let clustered = scene
+        .clusters
+        .iter()
+        .flat_map(move |cluster| cluster.candidates(ray))
+        .map(|sphere| sphere as &dyn Intersectable);
+    let particles = scene
+        .emitters
+        .iter()
+        .flat_map(|emitter| emitter.spheres())
+        .map(|sphere| sphere as &dyn Intersectable);
+    scene
+        .primitives
+        .iter()
+        .map(Primitive::shape)
+        .chain(clustered)
+        .chain(particles)
+}
+
+// Nearest hit along `ray`, and the shape it is on
+pub fn closest_intersection<'a>(
+    scene: &'a Scene,
+    ray: &'a Ray,
+    render_options: &'a RenderOptions,
+) -> Option<(&'a dyn Intersectable, Hit)> {
+    // Hits only have finite distances, so total_cmp orders them exactly;
+    // ties keep the first shape
+    hits(scene, ray, render_options).min_by(|a, b| a.1.distance.total_cmp(&b.1.distance))
+}
+
+/// `closest_intersection` for each of a packet of rays, testing spheres
+/// against all of them at once. Only for rays traced without clipping or
+/// robust intersections, which spheres test ray by ray.
+pub fn closest_intersections<'a>(
+    scene: &'a Scene,
+    rays: &[Ray; LANES],
+    render_options: &RenderOptions,
+) -> [Option<(&'a dyn Intersectable, Hit)>; LANES] {
+    let packet = RayPacket::new(rays);
+    let mut nearest: [Option<(&'a dyn Intersectable, Hit)>; LANES] = Default::default();
+    // Shapes are offered in the order `shapes` gives them and only a nearer
+    // hit replaces a lane's, so ties keep the first shape
+    let mut offer = |lane: usize, shape: &'a dyn Intersectable, hit: Option<Hit>| {
+        let hit = match hit {
+            Some(hit) => hit,
+            None => return,
+        };
+        let nearer = match nearest[lane] {
+            Some((_, ref best)) => hit.distance.total_cmp(&best.distance) == Ordering::Less,
+            None => true,
+        };
+        if nearer {
+            nearest[lane] = Some((shape, hit));
+        }
+    };
+    let sphere_hit = |distance: f32| {
+        if distance.is_finite() {
+            Some(Hit {
+                distance,
+                part: 0,
+                cap: None,
+            })
+        } else {
+            None
+        }
+    };
+
+    for primitive in &scene.primitives {
+        match primitive.as_sphere() {
+            Some(sphere) => {
+                for (lane, &t) in sphere.intersects_packet(&packet).iter().enumerate() {
+                    offer(lane, sphere, sphere_hit(t));
+                }
+            }
+            None => {
+                let shape = primitive.shape();
+                for (lane, ray) in rays.iter().enumerate() {
+                    offer(lane, shape, shape.intersect(ray, render_options));
+                }
+            }
+        }
+    }
+    for cluster in &scene.clusters {
+        for (lane, ray) in rays.iter().enumerate() {
+            for sphere in cluster.candidates(ray) {
+                offer(lane, sphere, sphere.intersect(ray, render_options));
+            }
+        }
+    }
+    for sphere in scene.emitters.iter().flat_map(|emitter| emitter.spheres()) {
+        for (lane, &t) in sphere.intersects_packet(&packet).iter().enumerate() {
+            offer(lane, sphere, sphere_hit(t));
+        }
+    }
+    for _ in rays {
+        report::count_ray();
+    }
+    nearest
+}
+
+// Whether anything blocks `ray` closer than `max_distance`. Any hit will do,
+// so the search stops at the first one rather than finding the nearest.
+// Nothing blocks anything while shadows are off.
+pub fn occluded(
+    scene: &Scene,
+    ray: &Ray,
+    max_distance: f32,
+    render_options: &RenderOptions,
+) -> bool {
+    if !render_options.shadows {
+        return false;
+    }
+    report::count_ray();
+    shapes(scene, ray).any(|shape| shape.occludes(ray, max_distance, render_options))
+}
+
+// Loads the scene file at `path`, through its binary cache unless
+// `resolver` has the cache turned off
+pub fn load_stage(
+    path: &Path,
+    units: &Units,
+    resolver: &resolve::AssetResolver,
+) -> Result<usd::Stage, String> {
+    if resolver.scene_cache() {
+        cache::load(path, units, resolver)
+    } else {
+        parse_stage(path, units, resolver)
+    }
+}
+
+// Parses the scene file at `path`, as JSON, pbrt or glTF if it is named
+// `.json`, `.pbrt`, `.gltf` or `.glb` and as USD otherwise
+pub fn parse_stage(
+    path: &Path,
+    units: &Units,
+    resolver: &resolve::AssetResolver,
+) -> Result<usd::Stage, String> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("json") => json::load(path, units, resolver),
+        Some("pbrt") => pbrt::load(path, units, resolver),
+        Some("gltf") | Some("glb") => gltf::load(path, units, resolver),
+        _ => usd::load(path, units, resolver),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere_at(z: f32, radius: f32, albedo: f32) -> Primitive {
+        Primitive::Sphere(Sphere {
+            center: Point3::new(0.0, 0.0, z),
+            radius,
+            material: Material::diffuse(Vector3::new(albedo, albedo, albedo)),
+        })
+    }
+
+    // Albedo of the material closest along -Z from the origin, and where
+    fn closest(scene: &Scene) -> Option<(f32, f32)> {
+        let ray = Ray {
+            origin: Point3::new(0.0, 0.0, 0.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        let options = RenderOptions::default();
+        closest_intersection(scene, &ray, &options)
+            .map(|(shape, hit)| (shape.material().albedo.x, hit.distance))
+    }
+
+    #[test]
+    fn nearest_shape_wins() {
+        let mut scene = Scene::empty();
+        scene.primitives.push(sphere_at(-10.0, 1.0, 0.1));
+        scene.primitives.push(sphere_at(-5.0, 1.0, 0.2));
+        assert_eq!(closest(&scene), Some((0.2, 4.0)));
+    }
+
+    #[test]
+    fn first_of_equally_near_shapes_wins() {
+        let mut scene = Scene::empty();
+        scene.primitives.push(sphere_at(-5.0, 1.0, 0.1));
+        scene.primitives.push(sphere_at(-5.0, 1.0, 0.2));
+        assert_eq!(closest(&scene), Some((0.1, 4.0)));
+    }
+
+    #[test]
+    fn degenerate_shapes_are_passed_over() {
+        let mut scene = Scene::empty();
+        scene.primitives.push(Primitive::Sphere(Sphere {
+            center: Point3::new(f32::NAN, 0.0, -5.0),
+            radius: 1.0,
+            material: Material::default(),
+        }));
+        scene.primitives.push(sphere_at(-2.0, 0.0, 0.3));
+        scene.primitives.push(sphere_at(-5.0, f32::INFINITY, 0.4));
+        scene.primitives.push(sphere_at(-5.0, 1.0, 0.2));
+        assert_eq!(closest(&scene), Some((0.2, 4.0)));
+    }
+
+    #[test]
+    fn nothing_to_hit() {
+        let mut scene = Scene::empty();
+        scene.primitives.push(sphere_at(5.0, 1.0, 0.2));
+        assert_eq!(closest(&scene), None);
+    }
+}
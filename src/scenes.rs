@@ -0,0 +1,144 @@
+//! Scenes kept loaded side by side in an interactive session, so it can
+//! switch between them without restarting.
+//!
+//! One scene is active, out with the session to be rendered and edited.
+//! The rest wait with their clocks stopped, keeping any edits made to them,
+//! and carry on where they left off when activated again. Every scene is
+//! loaded through the session's one resolver, so meshes and IES profiles
+//! used by several of them are only read once, and a changed file is read
+//! again for each as it is checked for changed assets.
+
+use std::mem;
+use std::path::Path;
+
+use camera::Camera;
+use edit::Damage;
+use lod;
+use reload;
+use render::RenderOptions;
+use resolve::AssetResolver;
+use scene::{load_stage, Scene, Units};
+
+struct Entry {
+    name: String,
+    // The scene and its camera, unless they are out with the session
+    parked: Option<(Scene, Camera)>,
+}
+
+pub struct SceneSet {
+    resolver: AssetResolver,
+    units: Units,
+    entries: Vec<Entry>,
+    active: usize,
+}
+
+impl SceneSet {
+    /// A set of just `scene`, seen through `camera`, which is active.
+    pub fn new(
+        resolver: AssetResolver,
+        units: Units,
+        name: String,
+        scene: Scene,
+        camera: Camera,
+    ) -> SceneSet {
+        SceneSet {
+            resolver,
+            units,
+            entries: vec![Entry {
+                name,
+                parked: Some((scene, camera)),
+            }],
+            active: 0,
+        }
+    }
+
+    /// Hands the active scene and its camera out to the session.
+    pub fn take_active(&mut self) -> (Scene, Camera) {
+        self.entries[self.active]
+            .parked
+            .take()
+            .expect("active scene is already out")
+    }
+
+    /// Loads the scene file at `path` as an inactive scene, seen through
+    /// its first camera or else one at the origin, and returns what it
+    /// couldn't load.
+    pub fn load(
+        &mut self,
+        path: &Path,
+        render_options: &RenderOptions,
+    ) -> Result<Vec<String>, String> {
+        let mut stage = load_stage(path, &self.units, &self.resolver)?;
+        let camera = match stage.camera(None) {
+            Some(camera) => camera.clone(),
+            None => Camera {
+                up: self.units.up(),
+                ..Camera::default()
+            },
+        }
+        .with_overrides(render_options);
+        if render_options.lod_pixels > 0.0 {
+            lod::build_clusters(&mut stage.scene);
+        }
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        self.entries.push(Entry {
+            name: name.to_string_lossy().into_owned(),
+            parked: Some((stage.scene, camera)),
+        });
+        Ok(stage.warnings)
+    }
+
+    /// Makes the scene at `index` the session's, parking `scene` and
+    /// `camera` as the one it replaces.
+    pub fn activate(
+        &mut self,
+        index: usize,
+        scene: &mut Scene,
+        camera: &mut Camera,
+    ) -> Result<(), String> {
+        if index >= self.entries.len() {
+            return Err(format!("no scene {}", index));
+        }
+        if index == self.active {
+            return Ok(());
+        }
+        let (next_scene, next_camera) = self.entries[index]
+            .parked
+            .take()
+            .expect("inactive scene is out");
+        let previous = (mem::replace(scene, next_scene), mem::replace(camera, next_camera));
+        self.entries[self.active].parked = Some(previous);
+        self.active = index;
+        Ok(())
+    }
+
+    /// Drops the inactive scene at `index`. Later scenes move down one.
+    pub fn unload(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.entries.len() {
+            return Err(format!("no scene {}", index));
+        }
+        if index == self.active {
+            return Err(format!("scene {} is active", index));
+        }
+        self.entries.remove(index);
+        if index < self.active {
+            self.active -= 1;
+        }
+        Ok(())
+    }
+
+    /// Reloads the assets of `scene`, the active scene, whose files have
+    /// changed, returning the damage done and what failed to load.
+    pub fn reload(&self, scene: &mut Scene) -> (Damage, Vec<String>) {
+        reload::reload(scene, &self.resolver)
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Names of the scenes, in order.
+    pub fn names<'a>(&'a self) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+}
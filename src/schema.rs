@@ -0,0 +1,323 @@
+//! A JSON Schema of the tracer's JSON scene files, for editors to complete
+//! and check scenes against as they're written.
+//!
+//! The schema describes the fields the loader in `json` understands, with
+//! their types and the few limits it enforces, and is written out by the
+//! `schema` command. Where the loader skips a field it doesn't understand
+//! with a warning, the schema rejects it, catching misspellings while the
+//! scene is being edited. Material libraries are objects of named materials,
+//! as a scene's `materials` are, and have no schema of their own.
+
+use std::fs;
+use std::path::Path;
+
+use json::{self, Value};
+
+// Objects are laid out a member to a line down to the fields' own schemas,
+// and within a line below them
+const LAYOUT_DEPTH: usize = 4;
+
+fn object_of(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(members.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn string_of(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn of_type(kind: &str) -> Value {
+    object_of(vec![("type", string_of(kind))])
+}
+
+fn number() -> Value {
+    of_type("number")
+}
+
+fn string() -> Value {
+    of_type("string")
+}
+
+fn positive() -> Value {
+    object_of(vec![
+        ("type", string_of("number")),
+        ("exclusiveMinimum", Value::Number(0.0)),
+    ])
+}
+
+fn array(items: Value) -> Value {
+    object_of(vec![("type", string_of("array")), ("items", items)])
+}
+
+// A list of exactly `count` of `items`
+fn tuple(items: Value, count: usize) -> Value {
+    object_of(vec![
+        ("type", string_of("array")),
+        ("items", items),
+        ("minItems", Value::Number(count as f64)),
+        ("maxItems", Value::Number(count as f64)),
+    ])
+}
+
+fn vec3() -> Value {
+    tuple(number(), 3)
+}
+
+fn reference(name: &str) -> Value {
+    object_of(vec![("$ref", Value::String(format!("#/definitions/{}", name)))])
+}
+
+fn constant(value: &str) -> Value {
+    object_of(vec![("const", string_of(value))])
+}
+
+// The schema of an object of the `fields` the loader knows, each described
+// by `describe`, `required` among them, and nothing else
+fn members(
+    fields: &[&str],
+    required: &[&str],
+    describe: impl Fn(&str) -> Value,
+) -> Vec<(String, Value)> {
+    let properties = fields.iter().map(|&f| (f.to_string(), describe(f))).collect();
+    let mut members = vec![
+        ("type".to_string(), string_of("object")),
+        ("properties".to_string(), Value::Object(properties)),
+        ("additionalProperties".to_string(), Value::Bool(false)),
+    ];
+    if !required.is_empty() {
+        let required = required.iter().map(|r| string_of(r)).collect();
+        members.push(("required".to_string(), Value::Array(required)));
+    }
+    members
+}
+
+fn fields(fields: &[&str], required: &[&str], describe: impl Fn(&str) -> Value) -> Value {
+    Value::Object(members(fields, required, describe))
+}
+
+// A material by name or written in place
+fn material_use() -> Value {
+    object_of(vec![("anyOf", Value::Array(vec![string(), reference("material")]))])
+}
+
+fn material() -> Value {
+    fields(json::MATERIAL_FIELDS, &[], |field| match field {
+        "base" => string(),
+        "albedo" | "emissive" => vec3(),
+        "texture" => fields(json::TEXTURE_FIELDS, &["type"], |field| match field {
+            "type" => {
+                let types = ["checker", "noise", "marble"].iter().map(|t| string_of(t)).collect();
+                object_of(vec![("enum", Value::Array(types))])
+            }
+            "colors" => tuple(vec3(), 2),
+            "scale" => positive(),
+            _ => number(),
+        }),
+        _ => number(),
+    })
+}
+
+// The `keys` moving a sphere, box, model or label
+fn keys() -> Value {
+    array(fields(json::KEY_FIELDS, &["time"], |field| match field {
+        "time" => number(),
+        _ => vec3(),
+    }))
+}
+
+fn light() -> Value {
+    let point = fields(json::POINT_LIGHT_FIELDS, &["type"], |field| match field {
+        "type" => constant("point"),
+        "intensity" => number(),
+        _ => vec3(),
+    });
+    let directional = fields(json::DIRECTIONAL_LIGHT_FIELDS, &["type"], |field| match field {
+        "type" => constant("directional"),
+        "intensity" => number(),
+        _ => vec3(),
+    });
+    let dome = fields(json::DOME_LIGHT_FIELDS, &["type"], |field| match field {
+        "type" => constant("dome"),
+        "portals" => array(fields(json::PORTAL_FIELDS, &[], |_| vec3())),
+        "texture" => string(),
+        "intensity" => number(),
+        _ => vec3(),
+    });
+    object_of(vec![("oneOf", Value::Array(vec![point, directional, dome]))])
+}
+
+/// The schema of scenes of the version of the format `json` reads.
+pub fn schema() -> Value {
+    let mut schema = vec![
+        ("$schema".to_string(), string_of("http://json-schema.org/draft-07/schema#")),
+        ("title".to_string(), string_of("rs-tracer scene")),
+        ("definitions".to_string(), object_of(vec![("material", material())])),
+    ];
+    schema.extend(members(json::SCENE_FIELDS, &[], |field| match field {
+        "version" => object_of(vec![
+            ("type", string_of("integer")),
+            ("minimum", Value::Number(1.0)),
+            ("maximum", Value::Number(f64::from(json::VERSION))),
+        ]),
+        "libraries" => array(string()),
+        "materials" => object_of(vec![
+            ("type", string_of("object")),
+            ("additionalProperties", reference("material")),
+        ]),
+        "cameras" => array(fields(json::CAMERA_FIELDS, &[], |field| match field {
+            "name" => string(),
+            "keys" => array(fields(json::CAMERA_KEY_FIELDS, &["time"], |field| match field {
+                "time" => number(),
+                _ => vec3(),
+            })),
+            "fov" | "aperture" | "focus" => number(),
+            _ => vec3(),
+        })),
+        "spheres" => array(fields(json::SPHERE_FIELDS, &[], |field| match field {
+            "radius" => positive(),
+            "material" => material_use(),
+            "keys" => keys(),
+            _ => vec3(),
+        })),
+        "boxes" => array(fields(json::BOX_FIELDS, &[], |field| match field {
+            "material" => material_use(),
+            "keys" => keys(),
+            _ => vec3(),
+        })),
+        "models" => array(fields(json::MODEL_FIELDS, &["file"], |field| match field {
+            "file" => string(),
+            "material" => material_use(),
+            _ => keys(),
+        })),
+        "labels" => array(fields(json::LABEL_FIELDS, &["text"], |field| match field {
+            "text" | "font" => string(),
+            "height" => number(),
+            "keys" => keys(),
+            _ => vec3(),
+        })),
+        _ => array(light()),
+    }));
+    Value::Object(schema)
+}
+
+/// Writes the schema to `path`.
+pub fn save(path: &Path) -> Result<(), String> {
+    let text = schema().to_json(LAYOUT_DEPTH) + "\n";
+    fs::write(path, text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Whether `value` meets `schema`, in as much of JSON Schema as `schema`
+    // uses, with `root` holding its definitions
+    fn valid(value: &Value, schema: &Value, root: &Value) -> bool {
+        if let Some(target) = schema.get("$ref").and_then(Value::as_str) {
+            let name = target.trim_start_matches("#/definitions/");
+            return valid(value, root.get("definitions").unwrap().get(name).unwrap(), root);
+        }
+        let number = |key| schema.get(key).and_then(Value::as_f64);
+        let met = match schema.get("type").and_then(Value::as_str) {
+            None => true,
+            Some("number") => value.as_f64().is_some(),
+            Some("integer") => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+            Some("string") => value.as_str().is_some(),
+            Some("array") => value.as_array().is_some(),
+            Some("object") => value.is_object(),
+            Some(other) => panic!("type {}", other),
+        };
+        let n = value.as_f64();
+        let items = value.as_array().unwrap_or(&[]);
+        let members = match *value {
+            Value::Object(ref members) => &members[..],
+            _ => &[],
+        };
+        let list = |key| schema.get(key).and_then(Value::as_array).unwrap_or(&[]);
+        met && schema.get("const").map_or(true, |c| c == value)
+            && (schema.get("enum").is_none() || list("enum").contains(value))
+            && number("minimum").map_or(true, |m| n.is_some_and(|n| n >= m))
+            && number("maximum").map_or(true, |m| n.is_some_and(|n| n <= m))
+            && number("exclusiveMinimum").map_or(true, |m| n.is_some_and(|n| n > m))
+            && number("minItems").map_or(true, |m| items.len() as f64 >= m)
+            && number("maxItems").map_or(true, |m| items.len() as f64 <= m)
+            && schema.get("items").map_or(true, |s| items.iter().all(|i| valid(i, s, root)))
+            && list("required").iter().all(|r| value.get(r.as_str().unwrap()).is_some())
+            && members.iter().all(|(key, member)| {
+                match schema.get("properties").and_then(|p| p.get(key)) {
+                    Some(s) => valid(member, s, root),
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(allowed)) => *allowed,
+                        Some(s) => valid(member, s, root),
+                        None => true,
+                    },
+                }
+            })
+            && (schema.get("anyOf").is_none()
+                || list("anyOf").iter().any(|s| valid(value, s, root)))
+            && (schema.get("oneOf").is_none()
+                || list("oneOf").iter().filter(|s| valid(value, s, root)).count() == 1)
+    }
+
+    fn check(text: &str) -> bool {
+        let schema = schema();
+        valid(&json::parse(text).unwrap(), &schema, &schema)
+    }
+
+    // The example scene in the documentation of the loader
+    fn documented_scene() -> String {
+        let source = include_str!("json.rs");
+        let start = source.find("//! ```json\n").unwrap() + "//! ```json\n".len();
+        let end = start + source[start..].find("//! ```\n").unwrap();
+        source[start..end].lines().map(|l| l.trim_start_matches("//!")).collect()
+    }
+
+    #[test]
+    fn documented_scene_is_valid() {
+        assert!(check(&documented_scene()));
+    }
+
+    #[test]
+    fn scene_of_every_kind_of_thing_is_valid() {
+        assert!(check(
+            r#"{
+              "version": 1,
+              "materials": {
+                "steel": { "albedo": [0.6, 0.6, 0.6], "specular": 0.9, "roughness": 0.2 },
+                "scuffed": { "base": "steel", "roughness": 0.6 }
+              },
+              "cameras": [ { "position": [0, 1, 5], "fov": 60, "aperture": 0.1, "focus": 5,
+                             "keys": [ { "time": 0 }, { "time": 2, "position": [1, 1, 5] } ] } ],
+              "spheres": [ { "radius": 0.5, "velocity": [1, 0, 0],
+                             "material": { "texture": { "type": "marble", "turbulence": 3,
+                                                        "colors": [[1, 1, 1], [0, 0, 0]] } } } ],
+              "boxes": [ { "min": [0, 0, 0], "max": [1, 1, 1], "material": "scuffed",
+                           "keys": [ { "time": 1, "translate": [0, 1, 0] } ] } ],
+              "labels": [ { "text": "Hi", "color": [1, 0, 0], "font": "sans.ttf" } ],
+              "lights": [
+                { "type": "directional", "direction": [0, -1, 0], "intensity": 2 },
+                { "type": "dome", "zenith": [0.2, 0.4, 1], "horizon": [1, 1, 1],
+                  "portals": [ { "corner": [0, 0, 0], "u": [1, 0, 0], "v": [0, 1, 0] } ] }
+              ]
+            }"#
+        ));
+    }
+
+    #[test]
+    fn scenes_the_loader_would_refuse_or_warn_of_are_invalid() {
+        assert!(!check(r#"{ "version": 2 }"#));
+        assert!(!check(r#"{ "sphere": [] }"#));
+        assert!(!check(r#"{ "spheres": [ { "radius": 0 } ] }"#));
+        assert!(!check(r#"{ "spheres": [ { "center": [0, 0] } ] }"#));
+        assert!(!check(r#"{ "models": [ { "material": "steel" } ] }"#));
+        assert!(!check(r#"{ "lights": [ { "type": "spot" } ] }"#));
+        assert!(!check(r#"{ "lights": [ { "type": "point", "direction": [0, -1, 0] } ] }"#));
+        assert!(!check(r#"{ "materials": { "red": { "colour": [1, 0, 0] } } }"#));
+        assert!(!check(r#"{ "boxes": [ { "material": { "texture": { "type": "wood" } } } ] }"#));
+    }
+
+    #[test]
+    fn schema_is_valid_json() {
+        let text = schema().to_json(2);
+        assert_eq!(json::parse(&text), Ok(schema()));
+    }
+}
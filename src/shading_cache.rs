@@ -0,0 +1,140 @@
+//! A cache of direct lighting for the interactive window, so the parts of a
+//! scene that stay the same from frame to frame aren't lit afresh every
+//! frame.
+//!
+//! Shading points are keyed by a hash of the cell of a grid they fall in,
+//! their normal, quantized, the parts of their material highlights depend
+//! on and the set of lights, along with the direction they're seen from,
+//! also quantized, when the material makes highlights at all. A point with
+//! the key of one lit before takes its light and shadows, so points sharing
+//! a cell share their lighting: larger cells are reused more as the camera
+//! moves, and show coarser shadows. The window drops every entry when the
+//! scene changes in a way the key can't tell, such as a shape moving and
+//! its shadow with it.
+
+use cgmath::{Point3, Vector3};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use handle::Pool;
+use light::Light;
+use material::Material;
+use render::{direct_light, Color, RenderOptions};
+use scene::Scene;
+
+// Entries are spread over this many separately locked maps, so render
+// threads seldom wait on each other
+const SHARDS: usize = 64;
+// Entries a map holds before it's emptied to make room
+const SHARD_CAPACITY: usize = 1 << 13;
+// Steps each component of a unit normal and of a view direction is
+// rounded to, over -1 to 1
+const NORMAL_STEPS: f32 = 16.0;
+const VIEW_STEPS: f32 = 8.0;
+
+pub struct ShadingCache {
+    // Edge of the grid's cells, in scene units
+    cell: f32,
+    // Hash of the lights the scene was last known to have
+    lights: AtomicU64,
+    shards: Vec<Mutex<HashMap<u64, (Color, Color)>>>,
+    // Lookups since the hit rate was last taken
+    hits: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+fn quantize(v: Vector3<f32>, steps: f32) -> [i32; 3] {
+    [
+        (v.x * steps).round() as i32,
+        (v.y * steps).round() as i32,
+        (v.z * steps).round() as i32,
+    ]
+}
+
+impl ShadingCache {
+    /// An empty cache over a grid of cells `cell` scene units across.
+    pub fn new(cell: f32) -> ShadingCache {
+        ShadingCache {
+            cell,
+            lights: AtomicU64::new(0),
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            hits: AtomicUsize::new(0),
+            lookups: AtomicUsize::new(0),
+        }
+    }
+
+    /// Drops every entry, for when the scene has changed.
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            shard.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+
+    /// Keys entries from now on by `lights`, so lighting cached under other
+    /// lights isn't reused.
+    pub fn set_lights(&self, lights: &Pool<Light>) {
+        let mut hasher = DefaultHasher::new();
+        for light in lights.iter() {
+            format!("{:?}", light).hash(&mut hasher);
+        }
+        self.lights.store(hasher.finish(), Ordering::Relaxed);
+    }
+
+    /// Share of lookups since the last call that found their lighting
+    /// cached, or None if there were none.
+    pub fn take_hit_rate(&self) -> Option<f32> {
+        let hits = self.hits.swap(0, Ordering::Relaxed);
+        let lookups = self.lookups.swap(0, Ordering::Relaxed);
+        if lookups == 0 {
+            None
+        } else {
+            Some(hits as f32 / lookups as f32)
+        }
+    }
+
+    /// Light and highlights at `point`, as `direct_light` finds them, from
+    /// the cache if the point's key is in it and otherwise found and cached.
+    pub fn direct_light(
+        &self,
+        scene: &Scene,
+        point: Point3<f32>,
+        normal: Vector3<f32>,
+        to_viewer: Vector3<f32>,
+        material: &Material,
+        render_options: &RenderOptions,
+    ) -> (Color, Color) {
+        let mut hasher = DefaultHasher::new();
+        let cell = point / self.cell;
+        [cell.x.floor() as i32, cell.y.floor() as i32, cell.z.floor() as i32].hash(&mut hasher);
+        quantize(normal, NORMAL_STEPS).hash(&mut hasher);
+        self.lights.load(Ordering::Relaxed).hash(&mut hasher);
+        // Sampling, clipping and shadows change which light gets through
+        render_options.light_samples.hash(&mut hasher);
+        render_options.clipping.hash(&mut hasher);
+        render_options.shadows.hash(&mut hasher);
+        if material.specular > 0.0 {
+            material.specular.to_bits().hash(&mut hasher);
+            material.shininess.to_bits().hash(&mut hasher);
+            quantize(to_viewer, VIEW_STEPS).hash(&mut hasher);
+        }
+        let key = hasher.finish();
+
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        let shard = &self.shards[key as usize % SHARDS];
+        if let Some(&lighting) = shard.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return lighting;
+        }
+        // Lit without the lock held, so other threads can use the shard
+        let lighting = direct_light(scene, point, normal, to_viewer, material, render_options);
+        let mut entries = shard.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= SHARD_CAPACITY {
+            entries.clear();
+        }
+        entries.insert(key, lighting);
+        lighting
+    }
+}
@@ -0,0 +1,42 @@
+//! Handheld camera shake: smooth noise added to the camera position as the
+//! scene clock runs, so animated views don't look locked off.
+
+use cgmath::{Point3, Vector3};
+
+use camera::Camera;
+use procedural;
+
+const OCTAVES: u32 = 3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Shake {
+    // Rough largest offset from the camera's own position, in scene units
+    pub amplitude: f32,
+    // Rough number of direction changes per second
+    pub frequency: f32,
+}
+
+impl Shake {
+    /// Offset of the shaken camera at scene time `time`. It is zero at time
+    /// zero, so stills are unaffected.
+    pub fn offset(&self, time: f32) -> Vector3<f32> {
+        // Noise is sampled along a line at an angle to the lattice, so it
+        // seldom passes lattice points (where gradient noise is zero) after
+        // the start
+        let t = time * self.frequency;
+        let p = Point3::new(t, t * 0.618, t * 0.382);
+        Vector3::new(
+            procedural::fbm(p, 0, OCTAVES),
+            procedural::fbm(p, 100, OCTAVES),
+            procedural::fbm(p, 200, OCTAVES),
+        ) * self.amplitude
+    }
+
+    /// `camera` as seen through a handheld camera at scene time `time`.
+    pub fn apply(&self, camera: &Camera, time: f32) -> Camera {
+        Camera {
+            position: camera.position + self.offset(time),
+            ..camera.clone()
+        }
+    }
+}
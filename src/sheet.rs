@@ -0,0 +1,129 @@
+//! Contact sheets: top, front and side orthographic views of the scene next
+//! to the camera's perspective view, for documenting and checking layouts.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use im::{ImageResult, RgbaImage};
+use std::path::Path;
+
+use camera::Camera;
+use geometry::Ray;
+use primitive::Intersectable;
+use progress::Progress;
+use render::{camera_pixel_color, get_pixel_color, Rect, RenderOptions};
+use scene::{Scene, UpAxis};
+
+// Space left around the scene's bounds in the orthographic views
+const MARGIN: f32 = 1.1;
+
+// Axis-aligned bounding box of everything in the scene
+fn bounds(scene: &Scene) -> Option<(Point3<f32>, Point3<f32>)> {
+    let clusters = scene.clusters.iter().map(|c| c.bounds().bounds());
+    let particles = scene.emitters.iter().flat_map(|e| e.spheres()).map(|s| s.bounds());
+    scene
+        .primitives
+        .iter()
+        .map(|p| p.shape().bounds())
+        .chain(clusters)
+        .chain(particles)
+        .fold(None, |acc, (lo, hi)| match acc {
+            None => Some((lo, hi)),
+            Some((min, max)) => Some((
+                Point3::new(min.x.min(lo.x), min.y.min(lo.y), min.z.min(lo.z)),
+                Point3::new(max.x.max(hi.x), max.y.max(hi.y), max.z.max(hi.z)),
+            )),
+        })
+}
+
+// View direction and screen up vector of the top, front and side views
+fn ortho_views(up_axis: UpAxis) -> [(Vector3<f32>, Vector3<f32>); 3] {
+    let up = match up_axis {
+        UpAxis::Y => Vector3::new(0.0, 1.0, 0.0),
+        UpAxis::Z => Vector3::new(0.0, 0.0, 1.0),
+    };
+    let front = match up_axis {
+        UpAxis::Y => Vector3::new(0.0, 0.0, -1.0),
+        UpAxis::Z => Vector3::new(0.0, 1.0, 0.0),
+    };
+    [(-up, front), (front, up), (Vector3::new(-1.0, 0.0, 0.0), up)]
+}
+
+fn render_ortho(
+    scene: &Scene,
+    render_options: &RenderOptions,
+    (direction, screen_up): (Vector3<f32>, Vector3<f32>),
+    (min, max): (Point3<f32>, Point3<f32>),
+    panel: &Rect,
+    img: &mut RgbaImage,
+) {
+    let center = min + (max - min) / 2.0;
+    // Every view shares one scale, so sizes compare across panels
+    let half_extent = (max - min).magnitude() / 2.0 * MARGIN;
+    let right = direction.cross(screen_up);
+    let eye = center + direction * -half_extent;
+    for px_x in 0..panel.width {
+        for px_y in 0..panel.height {
+            let s = 2.0 * ((px_x as f32) + 0.5) / (panel.width as f32) - 1.0;
+            let t = 1.0 - 2.0 * ((px_y as f32) + 0.5) / (panel.height as f32);
+            let ray = Ray {
+                origin: eye + (right * s + screen_up * t) * half_extent,
+                direction,
+            };
+            let color = get_pixel_color(scene, &ray, render_options, px_x, px_y);
+            img.put_pixel(panel.x + px_x, panel.y + px_y, color);
+        }
+    }
+}
+
+/// Renders a 2x2 sheet of `size` pixel square panels: top view, front view,
+/// side view (from +X) and the camera's own view, in reading order.
+pub fn render_contact_sheet(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    size: u32,
+) -> RgbaImage {
+    let mut img = RgbaImage::new(size * 2, size * 2);
+    let panels: Vec<Rect> = (0..4)
+        .map(|i| Rect {
+            x: (i % 2) * size,
+            y: (i / 2) * size,
+            width: size,
+            height: size,
+        })
+        .collect();
+
+    let panel_options = RenderOptions {
+        width: size,
+        height: size,
+        ..render_options.clone()
+    };
+    let mut progress = Progress::new("views", panels.len());
+    let bounds = bounds(scene);
+    for (view, panel) in ortho_views(scene.units.up_axis).iter().zip(&panels) {
+        if let Some(bounds) = bounds {
+            render_ortho(scene, &panel_options, *view, bounds, panel, &mut img);
+        }
+        progress.tick();
+    }
+    let perspective = &panels[3];
+    for px_x in 0..size {
+        for px_y in 0..size {
+            let color = camera_pixel_color(scene, camera, &panel_options, px_x, px_y);
+            img.put_pixel(perspective.x + px_x, perspective.y + px_y, color);
+        }
+    }
+    progress.tick();
+    img
+}
+
+/// Writes a contact sheet to `path`, in the format its extension names.
+pub fn export_contact_sheet(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    size: u32,
+    path: &Path,
+) -> ImageResult<()> {
+    render_contact_sheet(scene, camera, render_options, size).save(path)?;
+    Ok(())
+}
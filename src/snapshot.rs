@@ -0,0 +1,202 @@
+//! Snapshots of everything in a scene that can change between frames, and
+//! the differences between two of them, for tracking down animation or
+//! simulation steps that misbehave.
+
+use cgmath::{Point3, Vector3};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use camera::Camera;
+use light::Light;
+use primitive::Primitive;
+use scene::Scene;
+
+// Frames are stepped at the rate burn-in timecodes count at
+pub const FRAME_RATE: f32 = 24.0;
+
+/// Scene state flattened into named values, such as
+/// `primitives[0].center = (0, 1, -4)`, in a fixed order.
+pub struct Snapshot {
+    entries: Vec<(String, String)>,
+}
+
+pub enum Change<'a> {
+    Added(&'a str, &'a str),
+    Removed(&'a str, &'a str),
+    Changed(&'a str, &'a str, &'a str),
+}
+
+fn point(p: Point3<f32>) -> String {
+    format!("({}, {}, {})", p.x, p.y, p.z)
+}
+
+fn vector(v: Vector3<f32>) -> String {
+    format!("({}, {}, {})", v.x, v.y, v.z)
+}
+
+impl Snapshot {
+    pub fn capture(scene: &Scene, camera: &Camera) -> Snapshot {
+        let mut entries = Vec::new();
+        {
+            let mut add = |key: String, value: String| entries.push((key, value));
+            add("time".to_string(), scene.time.to_string());
+            add("camera.position".to_string(), point(camera.position));
+            add("camera.up".to_string(), vector(camera.up));
+            add("camera.fov".to_string(), camera.fov.to_string());
+            add("camera.aperture".to_string(), camera.aperture.to_string());
+            add("camera.focal_distance".to_string(), camera.focal_distance.to_string());
+
+            for (i, primitive) in scene.primitives.entries() {
+                let key = |field: &str| format!("primitives[{}].{}", i, field);
+                add(key("kind"), primitive.kind().to_string());
+                match *primitive {
+                    Primitive::Sphere(ref sphere) => {
+                        add(key("center"), point(sphere.center));
+                        add(key("radius"), sphere.radius.to_string());
+                    }
+                    Primitive::Mesh(ref mesh) => {
+                        add(key("triangles"), mesh.len().to_string());
+                        let (min, max) = primitive.shape().bounds();
+                        add(key("bounds"), format!("{} {}", point(min), point(max)));
+                    }
+                    Primitive::Cuboid(ref cuboid) => {
+                        add(key("min"), point(cuboid.min));
+                        add(key("max"), point(cuboid.max));
+                    }
+                    Primitive::Label(ref label) => {
+                        add(key("text"), label.text.clone());
+                        add(key("center"), point(label.center));
+                        add(key("height"), label.height.to_string());
+                    }
+                }
+                if let Some(animation) = scene.animations.get(i) {
+                    add(key("velocity"), vector(animation.velocity));
+                }
+                if let Some(material) = scene.material_bindings.get(i) {
+                    add(key("material.named"), material.to_string());
+                }
+                let material = primitive.shape().material();
+                add(key("material.albedo"), vector(material.albedo));
+                add(key("material.specular"), material.specular.to_string());
+                add(key("material.shininess"), material.shininess.to_string());
+                add(key("material.reflectivity"), material.reflectivity.to_string());
+                add(key("material.roughness"), material.roughness.to_string());
+                add(key("material.emissive"), vector(material.emissive));
+                add(key("material.transparency"), material.transparency.to_string());
+                add(key("material.ior"), material.ior.to_string());
+            }
+
+            for (i, cluster) in scene.clusters.iter().enumerate() {
+                let proxy = cluster.bounds();
+                add(format!("clusters[{}].center", i), point(proxy.center));
+                add(format!("clusters[{}].radius", i), proxy.radius.to_string());
+            }
+
+            for (i, emitter) in scene.emitters.iter().enumerate() {
+                for (j, (sphere, velocity, age)) in emitter.particles().enumerate() {
+                    let key = |field: &str| format!("emitters[{}].particles[{}].{}", i, j, field);
+                    add(key("center"), point(sphere.center));
+                    add(key("radius"), sphere.radius.to_string());
+                    add(key("velocity"), vector(velocity));
+                    add(key("age"), age.to_string());
+                }
+            }
+
+            for (i, light) in scene.lights.entries() {
+                let key = |field: &str| format!("lights[{}].{}", i, field);
+                match *light {
+                    Light::Point {
+                        position,
+                        color,
+                        intensity,
+                        ref shaping,
+                    } => {
+                        add(key("position"), point(position));
+                        add(key("color"), vector(color));
+                        add(key("intensity"), intensity.to_string());
+                        if let Some(ref shaping) = *shaping {
+                            add(key("shaping.down"), vector(shaping.down));
+                            add(key("shaping.across"), vector(shaping.across));
+                        }
+                    }
+                    Light::Directional {
+                        direction,
+                        color,
+                        intensity,
+                    } => {
+                        add(key("direction"), vector(direction));
+                        add(key("color"), vector(color));
+                        add(key("intensity"), intensity.to_string());
+                    }
+                    Light::Dome {
+                        color,
+                        intensity,
+                        ref portals,
+                        ..
+                    } => {
+                        add(key("color"), vector(color));
+                        add(key("intensity"), intensity.to_string());
+                        for (j, portal) in portals.iter().enumerate() {
+                            let edges = format!("{} {}", vector(portal.u), vector(portal.v));
+                            add(key(&format!("portals[{}].corner", j)), point(portal.corner));
+                            add(key(&format!("portals[{}].edges", j)), edges);
+                        }
+                    }
+                }
+            }
+        }
+        Snapshot { entries }
+    }
+
+    /// Writes every value, one `key = value` per line.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        for (key, value) in &self.entries {
+            writeln!(file, "{} = {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// What changed from `before` to `after`: values whose key is in both but
+/// differ, in `before`'s order, then values only one of them has.
+pub fn diff<'a>(before: &'a Snapshot, after: &'a Snapshot) -> Vec<Change<'a>> {
+    let lookup = |s: &'a Snapshot| -> HashMap<&'a str, &'a str> {
+        s.entries
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect()
+    };
+    let (old, new) = (lookup(before), lookup(after));
+    let mut changes = Vec::new();
+    for (key, value) in &before.entries {
+        match new.get(key.as_str()) {
+            Some(&v) if v != value => changes.push(Change::Changed(key, value, v)),
+            _ => {}
+        }
+    }
+    for (key, value) in &before.entries {
+        if !new.contains_key(key.as_str()) {
+            changes.push(Change::Removed(key, value));
+        }
+    }
+    for (key, value) in &after.entries {
+        if !old.contains_key(key.as_str()) {
+            changes.push(Change::Added(key, value));
+        }
+    }
+    changes
+}
+
+impl<'a> fmt::Display for Change<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Added(key, value) => write!(f, "+ {} = {}", key, value),
+            Change::Removed(key, value) => write!(f, "- {} = {}", key, value),
+            Change::Changed(key, before, after) => write!(f, "~ {}: {} -> {}", key, before, after),
+        }
+    }
+}
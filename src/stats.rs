@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Number of recent frames the frame time statistics are taken over
+const WINDOW: usize = 60;
+
+/// Rolling frame time statistics for the interactive window.
+pub struct FrameStats {
+    frame_times: VecDeque<Duration>,
+    last: Instant,
+}
+
+impl FrameStats {
+    pub fn new() -> FrameStats {
+        FrameStats {
+            frame_times: VecDeque::with_capacity(WINDOW),
+            last: Instant::now(),
+        }
+    }
+
+    /// Records the time since the previous tick as one frame.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if self.frame_times.len() == WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(now.duration_since(self.last));
+        self.last = now;
+    }
+
+    pub fn fps(&self) -> f64 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let total: Duration = self.frame_times.iter().sum();
+        self.frame_times.len() as f64 / total.as_secs_f64()
+    }
+
+    pub fn min(&self) -> Duration {
+        self.frame_times.iter().min().cloned().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Duration {
+        self.frame_times.iter().max().cloned().unwrap_or_default()
+    }
+
+    /// Frame time below which `p` percent of the window's frames fall.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let mut sorted: Vec<Duration> = self.frame_times.iter().cloned().collect();
+        if sorted.is_empty() {
+            return Duration::default();
+        }
+        sorted.sort();
+        let rank = (p / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+        sorted[rank.min(sorted.len() - 1)]
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "\r {:.2} fps (min {:.1} ms, max {:.1} ms, p95 {:.1} ms)",
+            self.fps(),
+            millis(self.min()),
+            millis(self.max()),
+            millis(self.percentile(95.0))
+        )
+    }
+}
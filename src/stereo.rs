@@ -0,0 +1,75 @@
+//! Stereo rendering: a scene seen from two eyes either side of a camera,
+//! and red/cyan anaglyphs made of the two views for seeing it in depth
+//! through paper glasses.
+//!
+//! The eyes stand half the eye separation to the camera's left and right
+//! and turn in to look at the point the camera is focused on, so whatever
+//! lies at the focal distance sits at the depth of the screen, nearer
+//! things stand out of it and farther things sink behind. An anaglyph
+//! keeps the red of the left eye's view and the green and blue of the
+//! right's, for the red filter over the left eye and the cyan over the
+//! right to part again.
+
+use cgmath::InnerSpace;
+use im::Rgb;
+
+use camera::Camera;
+use motion;
+use render::{render_tiles, Frame, RenderOptions};
+use scene::Scene;
+use tiles;
+
+/// The left and right eyes of `camera`, `separation` apart in scene units,
+/// both looking at the point `camera` is focused on.
+pub fn eyes(camera: &Camera, separation: f32) -> (Camera, Camera) {
+    let (right, _, forward) = camera.basis();
+    let focus = camera.position + forward * camera.focal_distance;
+    let eye = |side: f32| {
+        let position = camera.position + right * (side * separation / 2.0);
+        Camera {
+            position,
+            at: (focus - position).normalize(),
+            keys: Vec::new(),
+            ..camera.clone()
+        }
+    };
+    (eye(-1.0), eye(1.0))
+}
+
+/// Radiance of the anaglyph of `scene` seen through the eyes of `camera`
+/// `separation` apart, or None if the render was interrupted. Each eye's
+/// view is blurred as `motion::render` would blur it, leaving the scene as
+/// it was found.
+pub fn render_anaglyph(
+    scene: &mut Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    separation: f32,
+) -> Option<Frame<Rgb<f32>>> {
+    let (left, right) = eyes(camera, separation);
+    let left = render_eye(scene, &left, render_options)?;
+    let right = render_eye(scene, &right, render_options)?;
+    Some(Frame::from_fn(left.width(), left.height(), |px_x, px_y| {
+        let (l, r) = (left.get_pixel(px_x, px_y).data, right.get_pixel(px_x, px_y).data);
+        Rgb([l[0], r[1], r[2]])
+    }))
+}
+
+// Radiance of the frame `eye` sees of `scene`, or None if interrupted
+fn render_eye(
+    scene: &mut Scene,
+    eye: &Camera,
+    render_options: &RenderOptions,
+) -> Option<Frame<Rgb<f32>>> {
+    if motion::is_blurred(scene, render_options) {
+        return motion::render(scene, eye, render_options);
+    }
+    let (width, height) = (render_options.width, render_options.height);
+    let mut radiance = Frame::new(width, height);
+    let mut done = vec![false; tiles::tiles(width, height, render_options.tile_size).len()];
+    if render_tiles(scene, eye, render_options, &mut radiance, &mut done) {
+        Some(radiance)
+    } else {
+        None
+    }
+}
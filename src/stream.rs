@@ -0,0 +1,68 @@
+//! Streaming output for renders too large to hold in memory: the frame is
+//! rendered a strip of tiles at a time and each strip is compressed into
+//! the PNG and dropped before the next one starts.
+
+use im::RgbaImage;
+use png;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use camera::Camera;
+use embed;
+use hud::Hud;
+use interrupt;
+use output::Metadata;
+use progress::Progress;
+use render::RenderOptions;
+use scene::Scene;
+
+/// Frames with more pixels than this are streamed rather than rendered
+/// into an image in memory.
+pub const STREAM_PIXELS: u64 = 16 * 1024 * 1024;
+
+/// Renders the frame straight into a PNG at `path`. On Ctrl+C the rows not
+/// yet rendered are written black, so the file is still a valid image, and
+/// `Ok(false)` is returned. Any `hud` is burnt in strip by strip.
+pub fn render_png(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    path: &Path,
+    metadata: &Metadata,
+    hud: Option<&Hud>,
+) -> Result<bool, String> {
+    let (width, height) = (render_options.width, render_options.height);
+    let file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    let mut stream = writer.stream_writer().map_err(|e| e.to_string())?;
+
+    let strip_size = render_options.tile_size;
+    let strips: Vec<u32> = (0..height).step_by(strip_size as usize).collect();
+    let mut progress = Progress::new("strips", strips.len());
+    let mut complete = true;
+    for strip_y in strips {
+        let strip_height = strip_size.min(height - strip_y);
+        let mut strip = RgbaImage::new(width, strip_height);
+        if complete && interrupt::interrupted() {
+            complete = false;
+        }
+        if complete {
+            let rows = strip_y..strip_y + strip_height;
+            let stride = width as usize * 4;
+            embed::render_rgba8(scene, camera, render_options, rows, &mut strip, stride)?;
+            progress.tick();
+        }
+        if let Some(hud) = hud {
+            hud.draw(&mut strip, strip_y);
+        }
+        stream.write_all(&strip).map_err(|e| e.to_string())?;
+    }
+    stream.finish().map_err(|e| e.to_string())?;
+    metadata.write_png(&mut writer)?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(complete)
+}
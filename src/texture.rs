@@ -0,0 +1,109 @@
+//! Procedural textures, which vary a material's albedo over a surface
+//! without any image to read.
+//!
+//! Textures are solid: each gives a colour for every point in space, and a
+//! surface takes the colours where it lies, in scene coordinates. Nothing
+//! needs unwrapping, but a textured object that moves slides through its
+//! texture rather than carrying it along.
+
+use cgmath::Point3;
+use std::f32::consts::PI;
+
+use procedural;
+use render::Color;
+
+/// A colour for every point in space.
+pub trait Texture {
+    fn color(&self, point: Point3<f32>) -> Color;
+}
+
+/// Cubes of side `scale` alternating between two colours, which on a
+/// plane along the axes is a checkerboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checker {
+    pub even: Color,
+    pub odd: Color,
+    pub scale: f32,
+}
+
+impl Texture for Checker {
+    fn color(&self, point: Point3<f32>) -> Color {
+        let cell = |x: f32| (x / self.scale).floor() as i64;
+        // Nudged off cube faces, so a plane through them isn't speckled
+        let nudge = self.scale * 1e-4;
+        let sum = cell(point.x + nudge) + cell(point.y + nudge) + cell(point.z + nudge);
+        if sum.rem_euclid(2) == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Perlin noise blending between two colours, in blobs about `scale`
+/// across.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub low: Color,
+    pub high: Color,
+    pub scale: f32,
+}
+
+impl Texture for Noise {
+    fn color(&self, point: Point3<f32>) -> Color {
+        let t = (procedural::noise(point / self.scale, 0) + 1.0) / 2.0;
+        self.low + (self.high - self.low) * t
+    }
+}
+
+/// Veins of one colour through another, bands along X about `scale` apart
+/// bent by turbulence, as in marble.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Marble {
+    pub base: Color,
+    pub vein: Color,
+    pub scale: f32,
+    // How far the veins wander, in bands
+    pub turbulence: f32,
+}
+
+impl Texture for Marble {
+    fn color(&self, point: Point3<f32>) -> Color {
+        let p = point / self.scale;
+        let phase = p.x + self.turbulence * turbulence(p);
+        // Sharpened, so veins are thin and the base shows between them
+        let t = ((phase * PI).sin() + 1.0) / 2.0;
+        let t = t.powi(4);
+        self.base + (self.vein - self.base) * t
+    }
+}
+
+/// One of the procedural textures, as materials hold them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    Checker(Checker),
+    Noise(Noise),
+    Marble(Marble),
+}
+
+impl Texture for Pattern {
+    fn color(&self, point: Point3<f32>) -> Color {
+        match *self {
+            Pattern::Checker(ref checker) => checker.color(point),
+            Pattern::Noise(ref noise) => noise.color(point),
+            Pattern::Marble(ref marble) => marble.color(point),
+        }
+    }
+}
+
+// Noise summed over octaves of halving size and strength, folded to be
+// positive, which gives marble its streaks
+fn turbulence(p: Point3<f32>) -> f32 {
+    let mut sum = 0.0;
+    let mut scale = 1.0;
+    for _ in 0..5 {
+        sum += procedural::noise(p * scale, 0).abs() / scale;
+        scale *= 2.0;
+    }
+    sum
+}
@@ -0,0 +1,195 @@
+use std::mem;
+use std::time::{Duration, Instant};
+
+use render::Rect;
+
+pub const DEFAULT_TILE_SIZE: u32 = 32;
+// Tiles that took this many times the mean of a pass are split for the
+// next, into pieces nearer the mean
+const SPLIT_COST: f64 = 2.0;
+// Narrowest a split tile's pieces get
+const MIN_PIECE_SIZE: u32 = 8;
+
+/// Splits a `width` x `height` frame into tiles of at most `size` pixels
+/// square, in scanline order.
+pub fn tiles(width: u32, height: u32, size: u32) -> Vec<Rect> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(size as usize) {
+        for x in (0..width).step_by(size as usize) {
+            tiles.push(Rect {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+}
+
+// Splits `rect` into quarters, and those into quarters, `levels` deep,
+// leaving whole any piece too small to split
+fn split(rect: Rect, levels: u32, pieces: &mut Vec<Rect>) {
+    if levels == 0 || rect.width < 2 * MIN_PIECE_SIZE || rect.height < 2 * MIN_PIECE_SIZE {
+        pieces.push(rect);
+        return;
+    }
+    let (left, top) = (rect.width / 2, rect.height / 2);
+    let (right, bottom) = (rect.width - left, rect.height - top);
+    for &(x, y, width, height) in &[
+        (0, 0, left, top),
+        (left, 0, right, top),
+        (0, top, left, bottom),
+        (left, top, right, bottom),
+    ] {
+        let piece = Rect {
+            x: rect.x + x,
+            y: rect.y + y,
+            width,
+            height,
+        };
+        split(piece, levels - 1, pieces);
+    }
+}
+
+// A piece of work for the scheduler: a tile, or a piece of one split for
+// costing too much
+#[derive(Clone, Copy)]
+struct Unit {
+    rect: Rect,
+    // Index of the tile it's part of
+    tile: usize,
+}
+
+/// Renders a frame a few tiles at a time for the interactive window. Each
+/// call stops once its time budget is spent and the next call resumes from
+/// the following tile, so a slow scene can't stall event handling.
+///
+/// Every pass starts with the tiles nearest the focus point (the cursor, or
+/// the image centre without one) so the area being looked at updates first,
+/// after any tiles an edit to the scene has damaged since the last pass.
+///
+/// The time each tile takes is kept, and tiles that took far longer than
+/// most in one pass are split into smaller pieces for the next, so the
+/// expensive parts of a scene, like one glass object in a corner, are
+/// shared between threads rather than holding up a batch on one. Tiles are
+/// laid out afresh each pass, so a part that gets cheaper is whole again.
+pub struct TileScheduler {
+    tiles: Vec<Rect>,
+    // The pass's tiles and pieces of tiles, in the order they're rendered
+    units: Vec<Unit>,
+    next: usize,
+    // Seconds each tile has taken so far this pass, and took in all last
+    // pass
+    costs: Vec<f64>,
+    last_costs: Vec<f64>,
+    // Tiles handed out per call of the render closure, so they can be
+    // rendered in parallel
+    batch_size: usize,
+    budget: Option<Duration>,
+    center: [f64; 2],
+    focus: Option<[f64; 2]>,
+    damage: Option<Rect>,
+}
+
+impl TileScheduler {
+    pub fn new(
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        batch_size: usize,
+        budget: Option<Duration>,
+    ) -> TileScheduler {
+        let tiles = tiles(width, height, tile_size);
+        TileScheduler {
+            units: Vec::new(),
+            next: 0,
+            costs: vec![0.0; tiles.len()],
+            last_costs: vec![0.0; tiles.len()],
+            tiles,
+            batch_size: batch_size.max(1),
+            budget,
+            center: [width as f64 / 2.0, height as f64 / 2.0],
+            focus: None,
+            damage: None,
+        }
+    }
+
+    pub fn set_focus(&mut self, focus: Option<[f64; 2]>) {
+        self.focus = focus;
+    }
+
+    /// Renders the tiles overlapping `damage` first in the next pass.
+    pub fn set_damage(&mut self, damage: Option<Rect>) {
+        self.damage = damage;
+    }
+
+    // Lays out the pass's units, splitting each tile that cost too much
+    // last pass into about as many pieces as it took mean tiles' time
+    fn subdivide(&mut self) {
+        let mean = self.last_costs.iter().sum::<f64>() / self.tiles.len() as f64;
+        self.units.clear();
+        let mut pieces = Vec::new();
+        for (tile, &rect) in self.tiles.iter().enumerate() {
+            let ratio = self.last_costs[tile] / mean;
+            let levels = if ratio > SPLIT_COST { ratio.log(4.0).ceil() as u32 } else { 0 };
+            pieces.clear();
+            split(rect, levels, &mut pieces);
+            self.units.extend(pieces.iter().map(|&rect| Unit { rect, tile }));
+        }
+    }
+
+    fn prioritize(&mut self) {
+        let focus = self.focus.unwrap_or(self.center);
+        let distance = |tile: &Rect| {
+            let dx = tile.x as f64 + tile.width as f64 / 2.0 - focus[0];
+            let dy = tile.y as f64 + tile.height as f64 / 2.0 - focus[1];
+            dx * dx + dy * dy
+        };
+        let damage = self.damage.take();
+        let undamaged = |tile: &Rect| !damage.is_some_and(|d| overlaps(tile, &d));
+        self.units.sort_by(|a, b| {
+            let (a, b) = (&a.rect, &b.rect);
+            undamaged(a)
+                .cmp(&undamaged(b))
+                .then(distance(a).total_cmp(&distance(b)))
+        });
+    }
+
+    /// Renders batches of tiles until the budget runs out, always making
+    /// progress by at least one batch. `render_tiles` returns the time each
+    /// tile of a batch took. Returns true if this call finished the frame.
+    pub fn render<F>(&mut self, mut render_tiles: F) -> bool
+    where
+        F: FnMut(&[Rect]) -> Vec<Duration>,
+    {
+        let start = Instant::now();
+        if self.next == 0 {
+            self.subdivide();
+            self.prioritize();
+        }
+        loop {
+            let end = (self.next + self.batch_size).min(self.units.len());
+            let batch = &self.units[self.next..end];
+            let rects: Vec<Rect> = batch.iter().map(|unit| unit.rect).collect();
+            for (unit, took) in batch.iter().zip(render_tiles(&rects)) {
+                self.costs[unit.tile] += took.as_secs_f64();
+            }
+            self.next = end;
+            if self.next == self.units.len() {
+                self.next = 0;
+                self.last_costs = mem::replace(&mut self.costs, vec![0.0; self.tiles.len()]);
+                return true;
+            }
+            if let Some(budget) = self.budget {
+                if start.elapsed() >= budget {
+                    return false;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Keyframed animation, for shots laid out in a scene file rather than
+//! driven live. Objects are keyed by where they're moved to and cameras by
+//! where they stand and look, each key at a time on the scene clock; the
+//! clock passing between two keys moves things along the straight line
+//! between them, and before the first key or after the last they hold
+//! still.
+//!
+//! Shots are rendered as sequences of frames at `snapshot::FRAME_RATE`,
+//! each written to a file numbered after it, for assembling into video.
+
+use cgmath::Vector3;
+use std::path::PathBuf;
+
+/// Value at `time` of `keys`, at least one and in time order, given each
+/// key's time and value by `key`.
+pub fn sample<K, F>(keys: &[K], time: f32, key: F) -> Vector3<f32>
+where
+    F: Fn(&K) -> (f32, Vector3<f32>),
+{
+    // The first key later than `time`
+    let next = keys.iter().position(|k| key(k).0 > time);
+    match next {
+        None => key(&keys[keys.len() - 1]).1,
+        Some(0) => key(&keys[0]).1,
+        Some(i) => {
+            let ((t0, v0), (t1, v1)) = (key(&keys[i - 1]), key(&keys[i]));
+            v0 + (v1 - v0) * ((time - t0) / (t1 - t0))
+        }
+    }
+}
+
+/// Where frame `frame` of a sequence named by `pattern` is written: the
+/// pattern with its run of `#`s replaced by the frame number, padded with
+/// zeros to the run's length. None if the pattern has no such run, or more
+/// than one.
+pub fn frame_path(pattern: &str, frame: u32) -> Option<PathBuf> {
+    let start = pattern.find('#')?;
+    let width = pattern[start..].find(|c| c != '#').unwrap_or(pattern.len() - start);
+    let rest = &pattern[start + width..];
+    if rest.contains('#') {
+        return None;
+    }
+    let number = format!("{:0width$}", frame, width = width);
+    Some(PathBuf::from(format!("{}{}{}", &pattern[..start], number, rest)))
+}
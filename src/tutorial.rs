@@ -0,0 +1,116 @@
+//! Tutorial mode for the interactive window, for showing what each stage of
+//! a ray tracer adds to a picture. The stages are turned on and off live by
+//! the function keys, each labelled on screen with its key and whether it's
+//! on, so switching one off and on again shows its part in the image:
+//!
+//! - shadows, from shadow rays finding something between a surface and a
+//!   light,
+//! - reflections, from rays followed off mirrors and through glass,
+//! - antialiasing, from several rays spread over each pixel rather than one
+//!   through its centre,
+//! - global illumination, from paths that carry on off diffuse surfaces,
+//!   lighting them with each other's light,
+//! - tone mapping, from bright light compressed into what a screen shows
+//!   rather than clipped.
+//!
+//! A session starts with each stage as the render options have it, and
+//! antialiasing and global illumination come back on with the samples and
+//! integrator it started with, or defaults if it started without them.
+
+use path::Integrator;
+use render::RenderOptions;
+
+// Rays per pixel antialiasing comes back on with when the session started
+// with one
+const DEFAULT_SAMPLES: u32 = 4;
+
+/// The stages of the renderer the tutorial turns on and off, in the order
+/// of their keys, F1 onwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    Shadows,
+    Reflections,
+    Antialiasing,
+    GlobalIllumination,
+    ToneMapping,
+}
+
+pub const FEATURES: [Feature; 5] = [
+    Feature::Shadows,
+    Feature::Reflections,
+    Feature::Antialiasing,
+    Feature::GlobalIllumination,
+    Feature::ToneMapping,
+];
+
+impl Feature {
+    fn name(self) -> &'static str {
+        match self {
+            Feature::Shadows => "shadows",
+            Feature::Reflections => "reflections",
+            Feature::Antialiasing => "antialiasing",
+            Feature::GlobalIllumination => "global illumination",
+            Feature::ToneMapping => "tone mapping",
+        }
+    }
+
+    fn is_on(self, render_options: &RenderOptions) -> bool {
+        match self {
+            Feature::Shadows => render_options.shadows,
+            Feature::Reflections => render_options.reflections,
+            Feature::Antialiasing => render_options.samples_per_pixel > 1,
+            Feature::GlobalIllumination => render_options.integrator != Integrator::Direct,
+            Feature::ToneMapping => render_options.tone_mapping,
+        }
+    }
+}
+
+pub struct Tutorial {
+    // What antialiasing and global illumination are turned back on with
+    samples_per_pixel: u32,
+    integrator: Integrator,
+}
+
+impl Tutorial {
+    pub fn new(render_options: &RenderOptions) -> Tutorial {
+        Tutorial {
+            samples_per_pixel: match render_options.samples_per_pixel {
+                1 => DEFAULT_SAMPLES,
+                samples => samples,
+            },
+            integrator: match render_options.integrator {
+                Integrator::Direct => Integrator::Path,
+                integrator => integrator,
+            },
+        }
+    }
+
+    /// Turns `feature` off in `render_options` if it's on, and on if not.
+    pub fn toggle(&self, feature: Feature, render_options: &mut RenderOptions) {
+        let on = !feature.is_on(render_options);
+        match feature {
+            Feature::Shadows => render_options.shadows = on,
+            Feature::Reflections => render_options.reflections = on,
+            Feature::Antialiasing => {
+                render_options.samples_per_pixel = if on { self.samples_per_pixel } else { 1 }
+            }
+            Feature::GlobalIllumination => {
+                render_options.integrator = if on { self.integrator } else { Integrator::Direct }
+            }
+            Feature::ToneMapping => render_options.tone_mapping = on,
+        }
+    }
+
+    /// A line for each feature naming its key and whether `render_options`
+    /// have it on.
+    pub fn labels(&self, render_options: &RenderOptions) -> Vec<String> {
+        FEATURES
+            .iter()
+            .enumerate()
+            .map(|(i, &feature)| {
+                let state = if feature.is_on(render_options) { "on" } else { "off" };
+                format!("F{} {} {}", i + 1, feature.name(), state)
+            })
+            .collect()
+    }
+}
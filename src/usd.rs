@@ -0,0 +1,1571 @@
+//! Loader for a pragmatic subset of USD ascii (`.usda`) files.
+//!
+//! Prims are parsed generically into a tree and then walked to build the
+//! scene. Transformable prims honour `xformOpOrder` with translate, scale,
+//! rotate and transform ops. `Sphere` prims become spheres, `Cube` prims
+//! boxes, or triangle meshes when turned off the axes, and `Mesh` prims
+//! triangle meshes, all made of the `UsdPreviewSurface` material bound to
+//! them or else coloured by `primvars:displayColor`. A mesh's scalar
+//! primvars with a value at each vertex become attributes for `--heatmap`.
+//! `Camera` prims are collected by prim path, the first being the scene
+//! camera, with depth of field from their `fStop` and `focusDistance`.
+//!
+//! `SphereLight` and `DistantLight` prims become point and directional
+//! lights, with a `units` token (`candela`, `lumens`, `watts`, `lux` or
+//! `W/m2`) giving their intensity in physical units and an IES file in
+//! `inputs:shaping:ies:file` shaping a sphere light's emission. `DomeLight`
+//! prims become dome lights, in `nits` if given units, uniform unless
+//! `inputs:texture:file` names a Radiance HDR environment map for them, and
+//! `PortalLight` prims portals guiding the sampling of every dome light on
+//! the stage at points behind them, on their -Z side. `RectLight` prims
+//! become rectangles glowing at their luminance, in `nits` if given units,
+//! as bright as a dome light of the same intensity; they light the scene
+//! by way of the path integrators, which find emissive surfaces, and glow
+//! on both faces rather than only towards -Z. Other UsdLux lights are
+//! skipped with a warning.
+//!
+//! The tracer's own `ParticleEmitter` prim type adds a particle emitter and
+//! its `Scatter` prim type scatters spheres over a plane or a sphere; any
+//! other typed prim is skipped with a warning on the stage.
+//!
+//! Geometry is converted from the layer's `metersPerUnit` and `upAxis` into
+//! the units of the scene being loaded into.
+//!
+//! Other files can be included with `subLayers` in the layer metadata or
+//! with `references`/`payload` on a prim. An included prim (the target prim
+//! path, else the layer's `defaultPrim`, else its first root prim) is
+//! composed as a child of the referencing prim, so the referencing prim's
+//! transform places the included subtree. A reference to a Wavefront `.obj`
+//! file adds its mesh, placed by the referencing prim's transform. Asset
+//! paths are resolved with an `AssetResolver`, relative to the layer that
+//! names them.
+
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3,
+    Vector4,
+};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use camera::Camera;
+use components::MeshSource;
+use cuboid::Cuboid;
+use environment::{self, Environment};
+use geometry::Sphere;
+use handle::Handle;
+use light::{self, Light, LightUnit, Portal, Shaping};
+use material::{self, Material};
+use mesh::Mesh;
+use particles::{Emitter, EmitterSettings};
+use primitive::Primitive;
+use procedural::{self, Patch};
+use render::Color;
+use resolve::{self, AssetResolver};
+use scene::{Scene, Units, UpAxis};
+
+// USD camera defaults, in millimetres
+const DEFAULT_FOCAL_LENGTH: f64 = 50.0;
+const DEFAULT_VERTICAL_APERTURE: f64 = 15.2908;
+
+// Fallbacks USD itself uses for layers that don't declare their units
+const DEFAULT_METERS_PER_UNIT: f64 = 0.01;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Asset(String),
+    Path(String),
+    Punct(char),
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Asset(String, Option<String>),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Samples(Vec<(f64, Value)>),
+    // Relationship target, such as a material binding's
+    Path(String),
+    Opaque,
+}
+
+impl Value {
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            Value::Samples(ref samples) => samples.first().and_then(|s| s.1.as_f64()),
+            _ => None,
+        }
+    }
+
+    fn as_vec3(&self) -> Option<Vector3<f64>> {
+        match *self {
+            Value::Tuple(ref v) if v.len() == 3 => {
+                match (v[0].as_f64(), v[1].as_f64(), v[2].as_f64()) {
+                    (Some(x), Some(y), Some(z)) => Some(Vector3::new(x, y, z)),
+                    _ => None,
+                }
+            }
+            Value::Samples(ref samples) => samples.first().and_then(|s| s.1.as_vec3()),
+            _ => None,
+        }
+    }
+
+    fn as_matrix(&self) -> Option<Matrix4<f64>> {
+        let rows = match *self {
+            Value::Tuple(ref rows) if rows.len() == 4 => rows,
+            Value::Samples(ref samples) => return samples.first().and_then(|s| s.1.as_matrix()),
+            _ => return None,
+        };
+        let mut m = [0.0; 16];
+        for (i, row) in rows.iter().enumerate() {
+            match *row {
+                Value::Tuple(ref cols) if cols.len() == 4 => {
+                    for (j, col) in cols.iter().enumerate() {
+                        m[i * 4 + j] = col.as_f64()?;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        // USD matrices use row vectors, so each row is a cgmath column
+        Some(Matrix4::new(
+            m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12],
+            m[13], m[14], m[15],
+        ))
+    }
+
+    fn as_list(&self) -> &[Value] {
+        match *self {
+            Value::List(ref items) => items,
+            Value::Samples(ref samples) => samples.first().map_or(&[], |s| s.1.as_list()),
+            _ => &[],
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref s) | Value::Ident(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_strings(&self) -> Vec<String> {
+        match *self {
+            Value::List(ref items) => items
+                .iter()
+                .filter_map(|item| match *item {
+                    Value::Str(ref s) | Value::Ident(ref s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn as_path(&self) -> Option<&str> {
+        match *self {
+            Value::Path(ref path) => Some(path),
+            Value::List(ref items) => items.first().and_then(Value::as_path),
+            _ => None,
+        }
+    }
+
+    fn as_assets(&self) -> Vec<(String, Option<String>)> {
+        match *self {
+            Value::Asset(ref asset, ref prim) => vec![(asset.clone(), prim.clone())],
+            Value::List(ref items) => items.iter().flat_map(Value::as_assets).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn metadata_assets(metadata: &[(String, Value)], key: &str) -> Vec<(String, Option<String>)> {
+    metadata
+        .iter()
+        .filter(|entry| entry.0 == key)
+        .flat_map(|entry| entry.1.as_assets())
+        .collect()
+}
+
+struct Layer {
+    // From the `#usda <major>.<minor>` header
+    version: (u32, u32),
+    metadata: Vec<(String, Value)>,
+    prims: Vec<Prim>,
+}
+
+impl Layer {
+    fn units(&self) -> Units {
+        let entry = |key: &str| self.metadata.iter().find(|e| e.0 == key).map(|e| &e.1);
+        let meters_per_unit = entry("metersPerUnit")
+            .and_then(Value::as_f64)
+            .unwrap_or(DEFAULT_METERS_PER_UNIT);
+        let up_axis = match entry("upAxis").and_then(Value::as_str) {
+            Some("Z") => UpAxis::Z,
+            _ => UpAxis::Y,
+        };
+        Units {
+            meters_per_unit: meters_per_unit as f32,
+            up_axis,
+        }
+    }
+
+    fn assets(&self, key: &str) -> Vec<(String, Option<String>)> {
+        metadata_assets(&self.metadata, key)
+    }
+
+    // Finds a prim by absolute path, or the default prim when the path is
+    // empty.
+    fn find(&self, path: &str) -> Option<&Prim> {
+        let mut names = path.split('/').filter(|name| !name.is_empty());
+        let mut prim = match names.next() {
+            Some(name) => self.prims.iter().find(|p| p.name == name)?,
+            None => return self.default_prim(),
+        };
+        for name in names {
+            prim = prim.children.iter().find(|p| p.name == name)?;
+        }
+        Some(prim)
+    }
+
+    fn default_prim(&self) -> Option<&Prim> {
+        let default = self
+            .metadata
+            .iter()
+            .find(|e| e.0 == "defaultPrim")
+            .and_then(|e| e.1.as_str());
+        match default {
+            Some(name) => self.prims.iter().find(|p| p.name == name),
+            None => self.prims.first(),
+        }
+    }
+}
+
+struct Prim {
+    type_name: String,
+    name: String,
+    metadata: Vec<(String, Value)>,
+    attributes: Vec<(String, Value)>,
+    children: Vec<Prim>,
+}
+
+impl Prim {
+    fn assets(&self, key: &str) -> Vec<(String, Option<String>)> {
+        metadata_assets(&self.metadata, key)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.0 == name)
+            .map(|attribute| &attribute.1)
+    }
+}
+
+/// A loaded USD stage: the scene and its cameras and materials, by prim
+/// path, in the order they were found.
+pub struct Stage {
+    pub scene: Scene,
+    pub cameras: Vec<(String, Camera)>,
+    // Handles of the named materials in the scene's palette
+    pub materials: Vec<(String, Handle<Material>)>,
+    // Parts of the files that were understood but not loaded
+    pub warnings: Vec<String>,
+}
+
+impl Stage {
+    /// The camera at `path`, or the first camera without one.
+    pub fn camera(&self, path: Option<&str>) -> Option<&Camera> {
+        match path {
+            Some(path) => self.cameras.iter().find(|c| c.0 == path),
+            None => self.cameras.first(),
+        }
+        .map(|c| &c.1)
+    }
+
+    /// Records that `object` was given the material named `name`, if the
+    /// palette has one by that name.
+    pub fn bind_material(&mut self, object: Handle<Primitive>, name: &str) {
+        if let Some(&(_, material)) = self.materials.iter().find(|m| m.0 == name) {
+            self.scene.material_bindings.insert(object, material);
+        }
+    }
+
+    /// Adds `material` to the scene's palette under `name`.
+    pub fn add_material(&mut self, name: String, material: Material) -> Handle<Material> {
+        let handle = self.scene.materials.push(material);
+        self.materials.push((name, handle));
+        handle
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '"' || c == '\'' {
+            let triple = i + 2 < chars.len() && chars[i + 1] == c && chars[i + 2] == c;
+            let start_line = line;
+            i += if triple { 3 } else { 1 };
+            let mut s = String::new();
+            loop {
+                if i >= chars.len() {
+                    return Err(format!("line {}: unterminated string", start_line));
+                }
+                if triple {
+                    if i + 2 < chars.len() && chars[i] == c && chars[i + 1] == c && chars[i + 2] == c {
+                        i += 3;
+                        break;
+                    }
+                } else if chars[i] == c {
+                    i += 1;
+                    break;
+                }
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    i += 1;
+                    s.push(match chars[i] {
+                        'n' => '\n',
+                        't' => '\t',
+                        other => other,
+                    });
+                } else {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    s.push(chars[i]);
+                }
+                i += 1;
+            }
+            tokens.push((Token::Str(s), start_line));
+        } else if c == '@' || c == '<' {
+            let close = if c == '@' { '@' } else { '>' };
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != close {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err(format!("line {}: unterminated {}", line, c));
+            }
+            let s: String = chars[start..i].iter().collect();
+            i += 1;
+            tokens.push((if c == '@' { Token::Asset(s) } else { Token::Path(s) }, line));
+        } else if "()[]{}=,:;".contains(c) {
+            tokens.push((Token::Punct(c), line));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "+-.".contains(chars[i])) {
+                i += 1;
+            }
+            let s: String = chars[start..i].iter().collect();
+            match s.parse() {
+                Ok(n) => tokens.push((Token::Number(n), line)),
+                Err(_) => return Err(format!("line {}: invalid number '{}'", line, s)),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || "_:.".contains(chars[i])) {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else {
+            return Err(format!("line {}: unexpected character '{}'", line, c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(0, |t| t.1)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        match self.peek().cloned() {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t)
+            }
+            None => Err("unexpected end of file".to_string()),
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line(), message))
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), String> {
+        if self.is_punct(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", c))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Ident(s) => Ok(s),
+            _ => {
+                self.pos -= 1;
+                self.error("expected identifier")
+            }
+        }
+    }
+
+    fn sequence(&mut self, close: char) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        while !self.is_punct(close) {
+            items.push(self.value()?);
+            if self.is_punct(',') {
+                self.pos += 1;
+            }
+        }
+        self.pos += 1;
+        Ok(items)
+    }
+
+    // Time samples (`{ 0: value, ... }`) are kept; any other dictionary is
+    // skipped since nothing in the tracer consumes one.
+    fn dictionary(&mut self) -> Result<Value, String> {
+        let mut samples = Vec::new();
+        let mut opaque = false;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek() {
+                Some(&Token::Number(time)) if depth == 1 && !opaque => {
+                    self.pos += 1;
+                    self.expect_punct(':')?;
+                    let value = self.value()?;
+                    samples.push((time, value));
+                    if self.is_punct(',') {
+                        self.pos += 1;
+                    }
+                    continue;
+                }
+                Some(&Token::Punct('{')) => depth += 1,
+                Some(&Token::Punct('}')) => depth -= 1,
+                Some(_) => opaque = true,
+                None => return self.error("unterminated dictionary"),
+            }
+            self.pos += 1;
+        }
+        Ok(if opaque { Value::Opaque } else { Value::Samples(samples) })
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.next()? {
+            Token::Number(n) => Ok(Value::Number(n)),
+            Token::Str(s) => Ok(Value::Str(s)),
+            Token::Asset(asset) => {
+                // A reference may name a prim within the asset: @a.usda@</Prim>
+                let prim_path = match self.peek() {
+                    Some(Token::Path(p)) => Some(p.clone()),
+                    _ => None,
+                };
+                if prim_path.is_some() {
+                    self.pos += 1;
+                }
+                Ok(Value::Asset(asset, prim_path))
+            }
+            Token::Path(path) => Ok(Value::Path(path)),
+            Token::Ident(s) => Ok(match s.as_str() {
+                "inf" => Value::Number(f64::INFINITY),
+                "nan" => Value::Number(f64::NAN),
+                _ => Value::Ident(s),
+            }),
+            Token::Punct('(') => Ok(Value::Tuple(self.sequence(')')?)),
+            Token::Punct('[') => Ok(Value::List(self.sequence(']')?)),
+            Token::Punct('{') => self.dictionary(),
+            Token::Punct(c) => {
+                self.pos -= 1;
+                self.error(&format!("unexpected '{}'", c))
+            }
+        }
+    }
+
+    // Parses a `( key = value ... )` metadata block, the opening paren
+    // already consumed. List-op keywords (prepend, append, ...) are dropped
+    // and bare doc strings are ignored.
+    fn metadata(&mut self) -> Result<Vec<(String, Value)>, String> {
+        let mut entries = Vec::new();
+        while !self.is_punct(')') {
+            match self.next()? {
+                Token::Str(_) => continue,
+                Token::Punct(';') => continue,
+                Token::Ident(mut key) => {
+                    match key.as_str() {
+                        "prepend" | "append" | "add" | "delete" | "reorder" => key = self.ident()?,
+                        _ => {}
+                    }
+                    self.expect_punct('=')?;
+                    let value = self.value()?;
+                    entries.push((key, value));
+                }
+                _ => {
+                    self.pos -= 1;
+                    return self.error("expected metadata entry");
+                }
+            }
+        }
+        self.pos += 1;
+        Ok(entries)
+    }
+
+    fn layer(&mut self) -> Result<Layer, String> {
+        let mut metadata = Vec::new();
+        if self.is_punct('(') {
+            self.pos += 1;
+            metadata = self.metadata()?;
+        }
+        let mut prims = Vec::new();
+        while self.peek().is_some() {
+            prims.push(self.prim()?);
+        }
+        Ok(Layer {
+            version: USDA_VERSION,
+            metadata,
+            prims,
+        })
+    }
+
+    fn prim(&mut self) -> Result<Prim, String> {
+        match self.ident()?.as_str() {
+            "def" | "over" | "class" => {}
+            _ => {
+                self.pos -= 1;
+                return self.error("expected prim specifier");
+            }
+        }
+        let type_name = match self.peek() {
+            Some(&Token::Ident(_)) => self.ident()?,
+            _ => String::new(),
+        };
+        let name = match self.next()? {
+            Token::Str(s) => s,
+            _ => {
+                self.pos -= 1;
+                return self.error("expected prim name");
+            }
+        };
+        let mut prim = Prim {
+            type_name,
+            name,
+            metadata: Vec::new(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+        };
+        if self.is_punct('(') {
+            self.pos += 1;
+            prim.metadata = self.metadata()?;
+        }
+        self.expect_punct('{')?;
+        while !self.is_punct('}') {
+            let keyword = match self.peek() {
+                Some(Token::Ident(s)) => s.clone(),
+                Some(&Token::Punct(';')) => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => return self.error("expected prim or property"),
+            };
+            match keyword.as_str() {
+                "def" | "over" | "class" => prim.children.push(self.prim()?),
+                "variantSet" => {
+                    // Variants are not supported; skip the whole block.
+                    self.pos += 1;
+                    self.next()?;
+                    self.expect_punct('=')?;
+                    self.expect_punct('{')?;
+                    self.dictionary()?;
+                }
+                _ => {
+                    let (name, value) = self.property()?;
+                    prim.attributes.push((name, value));
+                }
+            }
+        }
+        self.pos += 1;
+        Ok(prim)
+    }
+
+    // Parses `[custom] [uniform] type[[]] name [= value] [( metadata )]`
+    // or `rel name [= targets]`.
+    fn property(&mut self) -> Result<(String, Value), String> {
+        let mut type_name = self.ident()?;
+        while type_name == "custom" || type_name == "uniform" || type_name == "varying" {
+            type_name = self.ident()?;
+        }
+        if self.is_punct('[') {
+            self.pos += 1;
+            self.expect_punct(']')?;
+        }
+        let name = self.ident()?;
+        let mut value = Value::Opaque;
+        if self.is_punct('=') {
+            self.pos += 1;
+            value = self.value()?;
+        }
+        if self.is_punct('(') {
+            self.pos += 1;
+            self.metadata()?;
+        }
+        // Fold `name.timeSamples` onto the attribute itself.
+        let name = name.trim_end_matches(".timeSamples").to_string();
+        Ok((name, value))
+    }
+}
+
+// Colour and intensity of a UsdLux light, from its `inputs:` attributes or
+// the unprefixed names older files use. The tracer's own `units` token
+// gives the intensity in physical units for a scene of `meters_per_unit`.
+fn light_emission(prim: &Prim, meters_per_unit: f32) -> Result<(Color, f32), String> {
+    let input = |name: &str| {
+        prim.attribute(&format!("inputs:{}", name))
+            .or_else(|| prim.attribute(name))
+    };
+    let color = input("color")
+        .and_then(Value::as_vec3)
+        .map_or(Vector3::new(1.0, 1.0, 1.0), to_vector);
+    let intensity = input("intensity").and_then(Value::as_f64).unwrap_or(1.0);
+    let exposure = input("exposure").and_then(Value::as_f64).unwrap_or(0.0);
+    let intensity = (intensity * exposure.exp2()) as f32;
+
+    let unit = match prim.attribute("units").and_then(Value::as_str) {
+        Some(name) => LightUnit::from_name(name).ok_or(format!("unknown units {}", name))?,
+        None => return Ok((color, intensity)),
+    };
+    let intensity = match prim.type_name.as_str() {
+        "DistantLight" => unit.directional_intensity(intensity)?,
+        "DomeLight" | "RectLight" => unit.dome_intensity(intensity)?,
+        _ => unit.point_intensity(intensity, meters_per_unit)?,
+    };
+    Ok((light::unit_luminance(color), intensity))
+}
+
+// The rectangle of a `RectLight` prim in world space: `inputs:width` by
+// `inputs:height` in the XY plane, centred on the origin and facing -Z
+fn rect(prim: &Prim, world: &Matrix4<f64>) -> Result<Mesh, String> {
+    let input = |name: &str| {
+        prim.attribute(&format!("inputs:{}", name))
+            .or_else(|| prim.attribute(name))
+            .and_then(Value::as_f64)
+            .unwrap_or(1.0)
+    };
+    let (x, y) = (input("width") / 2.0, input("height") / 2.0);
+    let positions = [(-x, -y), (x, -y), (x, y), (-x, y)]
+        .iter()
+        .map(|&(x, y)| to_point(world.transform_point(Point3::new(x, y, 0.0))))
+        .collect();
+    Mesh::new(positions, vec![[0, 2, 1], [0, 3, 2]])
+}
+
+// Triangles of a `Mesh` prim in world space. Polygons of more than three
+// sides are split into fans around their first vertex.
+fn mesh(prim: &Prim, world: &Matrix4<f64>) -> Result<Mesh, String> {
+    let list = |name: &str| prim.attribute(name).map_or(&[][..], Value::as_list);
+    let positions = list("points")
+        .iter()
+        .map(|p| {
+            let p = p.as_vec3().ok_or("bad points")?;
+            Ok(to_point(world.transform_point(Point3::from_vec(p))))
+        })
+        .collect::<Result<Vec<_>, &str>>()?;
+    let indices = list("faceVertexIndices")
+        .iter()
+        .map(|i| i.as_f64().map(|i| i as u32).ok_or("bad faceVertexIndices"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut triangles = Vec::new();
+    let mut face = &indices[..];
+    for count in list("faceVertexCounts") {
+        let count = count.as_f64().ok_or("bad faceVertexCounts")? as usize;
+        if count > face.len() {
+            return Err("faceVertexCounts needs more indices than given".to_string());
+        }
+        for i in 2..count {
+            triangles.push([face[0], face[i - 1], face[i]]);
+        }
+        face = &face[count..];
+    }
+    Mesh::new(positions, triangles)
+}
+
+// Gives `mesh` the scalar primvars of `prim` as attributes, warning of any
+// without a value for every vertex
+fn add_primvars(prim: &Prim, mesh: &mut Mesh, warnings: &mut Vec<String>) {
+    for (name, value) in &prim.attributes {
+        let name = match name.strip_prefix("primvars:") {
+            Some(name) => name,
+            None => continue,
+        };
+        // Not colours, texture coordinates and the like
+        let values: Option<Vec<f32>> =
+            value.as_list().iter().map(|v| v.as_f64().map(|v| v as f32)).collect();
+        if let Some(values) = values.filter(|values| !values.is_empty()) {
+            if let Err(e) = mesh.set_attribute(name, values.into()) {
+                warnings.push(format!("skipping primvar of {}: {}", prim.name, e));
+            }
+        }
+    }
+}
+
+// The IES profile a light's `inputs:shaping:ies:file` names, if any, with
+// its nadir down the light's -Z axis and horizontal angle 0 along its +X
+fn shaping(
+    prim: &Prim,
+    world: &Matrix4<f64>,
+    dir: &Path,
+    resolver: &AssetResolver,
+) -> Result<Option<Shaping>, String> {
+    let asset = match prim.attribute("inputs:shaping:ies:file").map(Value::as_assets) {
+        Some(ref assets) if !assets.is_empty() => assets[0].0.clone(),
+        _ => return Ok(None),
+    };
+    let path = resolver.resolve(&asset, dir)?;
+    let modified = resolve::modified(&path);
+    let profile = resolver.profile(&path)?;
+    let down = to_vector((world * Vector4::new(0.0, 0.0, -1.0, 0.0)).truncate()).normalize();
+    let x = to_vector((world * Vector4::new(1.0, 0.0, 0.0, 0.0)).truncate());
+    let across = (x - down * x.dot(down)).normalize();
+    Ok(Some(Shaping {
+        profile,
+        path,
+        modified,
+        down,
+        across,
+    }))
+}
+
+// Environment map of a dome light, from the Radiance HDR file its
+// `inputs:texture:file` names. Maps wrap around the light's own axes, with
+// the pole along the layer's up axis.
+fn dome_environment(
+    prim: &Prim,
+    world: &Matrix4<f64>,
+    units: &Units,
+    dir: &Path,
+    resolver: &AssetResolver,
+) -> Result<Option<Environment>, String> {
+    let asset = match prim.attribute("inputs:texture:file").map(Value::as_assets) {
+        Some(ref assets) if !assets.is_empty() => assets[0].0.clone(),
+        _ => return Ok(None),
+    };
+    let path = resolver.resolve(&asset, dir)?;
+    let axis = |column: Vector4<f64>| to_vector(column.truncate());
+    let rotation = Matrix3::from_cols(axis(world.x), axis(world.y), axis(world.z));
+    let to_light = rotation.invert().ok_or("transform is singular")?;
+    let to_map = environment::up_to_map(units.up()) * to_light;
+    Environment::map(path, to_map, resolver).map(Some)
+}
+
+// Material of a gprim: the `UsdPreviewSurface` shader of the `Material`
+// its `material:binding` targets in `layer`, else a matte material of its
+// `primvars:displayColor`, else none.
+fn material(prim: &Prim, layer: &Layer) -> Result<Option<Material>, String> {
+    let target = match prim.attribute("material:binding").and_then(Value::as_path) {
+        Some(target) => target,
+        None => {
+            let color = prim
+                .attribute("primvars:displayColor")
+                .and_then(|v| v.as_list().first().or(Some(v)))
+                .and_then(Value::as_vec3);
+            return Ok(color.map(|c| Material::diffuse(to_vector(c))));
+        }
+    };
+    let bound = layer.find(target).ok_or(format!("no material at {}", target))?;
+    preview_surface(bound, target).map(Some)
+}
+
+// The `UsdPreviewSurface` shader of `material`, the `Material` prim at
+// `path`. Inputs it leaves unset take USD's defaults. The metallic
+// workflow's `metallic` becomes reflectivity, the specular workflow's
+// `specularColor` the strength of highlights, `roughness` how far they
+// spread, and anything short of full `opacity` transparency.
+fn preview_surface(material: &Prim, path: &str) -> Result<Material, String> {
+    let is_preview_surface = |shader: &&Prim| {
+        shader.type_name == "Shader"
+            && shader.attribute("info:id").and_then(Value::as_str) == Some("UsdPreviewSurface")
+    };
+    let shader = material
+        .children
+        .iter()
+        .find(is_preview_surface)
+        .ok_or(format!("no UsdPreviewSurface in {}", path))?;
+
+    let color = |name: &str, default: Color| {
+        shader
+            .attribute(&format!("inputs:{}", name))
+            .and_then(Value::as_vec3)
+            .map_or(default, to_vector)
+    };
+    let float = |name: &str, default: f64| {
+        shader
+            .attribute(&format!("inputs:{}", name))
+            .and_then(Value::as_f64)
+            .unwrap_or(default) as f32
+    };
+    let black = Vector3::new(0.0, 0.0, 0.0);
+    let specular = if float("useSpecularWorkflow", 0.0) != 0.0 {
+        let c = color("specularColor", black);
+        c.x.max(c.y).max(c.z)
+    } else {
+        0.0
+    };
+    let roughness = float("roughness", 0.5);
+    Ok(Material {
+        albedo: color("diffuseColor", Vector3::new(0.18, 0.18, 0.18)),
+        specular,
+        shininess: material::shininess(roughness),
+        reflectivity: float("metallic", 0.0),
+        roughness,
+        emissive: color("emissiveColor", black),
+        transparency: 1.0 - float("opacity", 1.0),
+        ior: float("ior", 1.5),
+        texture: None,
+    })
+}
+
+// Attributes a `ParticleEmitter` prim understands, besides xformOps
+const EMITTER_ATTRIBUTES: [&str; 9] = [
+    "rate",
+    "velocity",
+    "spread",
+    "acceleration",
+    "lifetime",
+    "startRadius",
+    "endRadius",
+    "seed",
+    "visibility",
+];
+
+// Attributes a `Scatter` prim understands, besides xformOps
+const SCATTER_ATTRIBUTES: [&str; 12] = [
+    "surface",
+    "width",
+    "depth",
+    "surfaceRadius",
+    "spacing",
+    "sphereRadius",
+    "radiusVariation",
+    "noiseScale",
+    "seed",
+    "visibility",
+    "material:binding",
+    "primvars:displayColor",
+];
+
+// Warns about attributes of `prim`, one of the tracer's own prim types, that
+// aren't in `known`, so misspelt attributes are caught rather than silently
+// defaulted
+fn check_attributes(prim: &Prim, known: &[&str], warnings: &mut Vec<String>) {
+    for attribute in &prim.attributes {
+        let name = attribute.0.as_str();
+        if !known.contains(&name) && !name.starts_with("xformOp") {
+            warnings.push(format!(
+                "unknown attribute {} on {} {}",
+                name, prim.type_name, prim.name
+            ));
+        }
+    }
+}
+
+// Newest usda version this loader was written against. Layers from a later
+// minor version load with a warning; other major versions are refused.
+const USDA_VERSION: (u32, u32) = (1, 0);
+
+fn usda_version(src: &str) -> Result<(u32, u32), String> {
+    let header = src.lines().next().unwrap_or("");
+    let version = match header.strip_prefix("#usda") {
+        Some(version) => version.trim(),
+        None => return Err("missing #usda header".to_string()),
+    };
+    if version.is_empty() {
+        return Ok(USDA_VERSION);
+    }
+    let mut parts = version.splitn(2, '.').map(str::parse::<u32>);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) if major == USDA_VERSION.0 => Ok((major, minor)),
+        (Some(Ok(_)), Some(Ok(_))) => Err(format!("unsupported usda version {}", version)),
+        _ => Err(format!("bad #usda version {:?}", version)),
+    }
+}
+
+fn parse_layer(src: &str) -> Result<Layer, String> {
+    let version = usda_version(src)?;
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+    };
+    let mut layer = parser.layer()?;
+    layer.version = version;
+    Ok(layer)
+}
+
+fn op_matrix(prim: &Prim, op: &str, warnings: &mut Vec<String>) -> Option<Matrix4<f64>> {
+    let (invert, op) = match op.strip_prefix("!invert!") {
+        Some(op) => (true, op),
+        None => (false, op),
+    };
+    let value = prim.attribute(op)?;
+    let kind = op.split(':').nth(1).unwrap_or("");
+    let matrix = match kind {
+        "translate" => Matrix4::from_translation(value.as_vec3()?),
+        "scale" => {
+            let s = value.as_vec3()?;
+            Matrix4::from_nonuniform_scale(s.x, s.y, s.z)
+        }
+        "rotateX" => Matrix4::from_angle_x(Deg(value.as_f64()?)),
+        "rotateY" => Matrix4::from_angle_y(Deg(value.as_f64()?)),
+        "rotateZ" => Matrix4::from_angle_z(Deg(value.as_f64()?)),
+        "rotateXYZ" => {
+            let r = value.as_vec3()?;
+            Matrix4::from_angle_z(Deg(r.z)) * Matrix4::from_angle_y(Deg(r.y))
+                * Matrix4::from_angle_x(Deg(r.x))
+        }
+        "transform" => value.as_matrix()?,
+        _ => {
+            warnings.push(format!("unsupported transform op {} on {}", op, prim.name));
+            return None;
+        }
+    };
+    if invert {
+        matrix.invert()
+    } else {
+        Some(matrix)
+    }
+}
+
+fn local_transform(prim: &Prim, warnings: &mut Vec<String>) -> (Matrix4<f64>, bool) {
+    let order = match prim.attribute("xformOpOrder") {
+        Some(value) => value.as_strings(),
+        None => return (Matrix4::identity(), false),
+    };
+    let mut matrix = Matrix4::identity();
+    let mut reset = false;
+    for op in order {
+        if op == "!resetXformStack!" {
+            reset = true;
+            matrix = Matrix4::identity();
+        } else if let Some(m) = op_matrix(prim, &op, warnings) {
+            matrix = matrix * m;
+        }
+    }
+    (matrix, reset)
+}
+
+fn to_point(p: Point3<f64>) -> Point3<f32> {
+    Point3::new(p.x as f32, p.y as f32, p.z as f32)
+}
+
+fn to_vector(v: Vector3<f64>) -> Vector3<f32> {
+    Vector3::new(v.x as f32, v.y as f32, v.z as f32)
+}
+
+fn max_scale(m: &Matrix4<f64>) -> f64 {
+    m.x.truncate()
+        .magnitude()
+        .max(m.y.truncate().magnitude())
+        .max(m.z.truncate().magnitude())
+}
+
+// A cube of edge `size` centred on the origin of `world`: a box while
+// `world` keeps its faces square to the axes, and a mesh once it doesn't
+fn cube(size: f64, world: &Matrix4<f64>, material: Material) -> Primitive {
+    let half = (size / 2.0) as f32;
+    let local = Cuboid {
+        min: Point3::new(-half, -half, -half),
+        max: Point3::new(half, half, half),
+        material,
+    };
+    let corners: Vec<Point3<f32>> = local
+        .corners()
+        .iter()
+        .map(|c| to_point(world.transform_point(Point3::new(c.x as f64, c.y as f64, c.z as f64))))
+        .collect();
+    let (min, max) = corners.iter().fold((corners[0], corners[0]), |(min, max), p| {
+        (
+            Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+            Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+        )
+    });
+    // Square to the axes, every corner lies on a corner of the bounds
+    let square = corners.iter().all(|c| {
+        (0..3).all(|a| {
+            let tolerance = 1e-5 * (max[a] - min[a]);
+            (c[a] - min[a]).abs() <= tolerance || (c[a] - max[a]).abs() <= tolerance
+        })
+    });
+    if square {
+        Primitive::Cuboid(Cuboid { min, max, material })
+    } else {
+        let mesh = Mesh::new(corners, Cuboid::triangles()).expect("triangles index the corners");
+        Primitive::Mesh(mesh.with_material(material))
+    }
+}
+
+// State threaded through a load: parsed layers are cached so an asset
+// referenced many times is only read once, and the chain of layers being
+// composed is kept to report include cycles.
+struct Loader<'a> {
+    resolver: &'a AssetResolver,
+    stage: Stage,
+    layers: HashMap<PathBuf, Rc<Layer>>,
+    // Every portal on the stage, each guiding every dome light
+    portals: Vec<Portal>,
+    composing: Vec<PathBuf>,
+    // Objects and the paths of the materials bound to them, recorded once
+    // every material has been read
+    bindings: Vec<(Handle<Primitive>, String)>,
+}
+
+impl<'a> Loader<'a> {
+    fn layer(&mut self, path: &Path) -> Result<Rc<Layer>, String> {
+        if let Some(layer) = self.layers.get(path) {
+            return Ok(layer.clone());
+        }
+        let mut src = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut src))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let layer = Rc::new(parse_layer(&src).map_err(|e| format!("{}: {}", path.display(), e))?);
+        if layer.version > USDA_VERSION {
+            self.stage.warnings.push(format!(
+                "{}: usda {}.{} is newer than {}.{}, unknown syntax may fail to parse",
+                path.display(),
+                layer.version.0,
+                layer.version.1,
+                USDA_VERSION.0,
+                USDA_VERSION.1
+            ));
+        }
+        self.layers.insert(path.to_path_buf(), layer.clone());
+        Ok(layer)
+    }
+
+    // Composes the layer at `path` beneath `parent`, which maps coordinates
+    // in `parent_units` into the scene and sits at `parent_path` on the
+    // stage. With a `prim_path` only that prim's subtree is included,
+    // otherwise the whole layer.
+    fn include(
+        &mut self,
+        path: &Path,
+        prim_path: Option<&str>,
+        parent: &Matrix4<f64>,
+        parent_path: &str,
+        parent_units: &Units,
+    ) -> Result<(), String> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if self.composing.contains(&path) {
+            return Err(format!("{}: include cycle", path.display()));
+        }
+        let layer = self.layer(&path)?;
+        let units = layer.units();
+        let root = parent * units.conversion_to(parent_units).cast();
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        self.composing.push(path.clone());
+        for sublayer in layer.assets("subLayers") {
+            let sublayer = self.resolver.resolve(&sublayer.0, &dir)?;
+            self.include(&sublayer, None, &root, parent_path, &units)?;
+        }
+        match prim_path {
+            Some(prim_path) => match layer.find(prim_path) {
+                Some(prim) => self.walk(prim, &layer, &root, parent_path, &dir, &units)?,
+                None => return Err(format!("{}: no prim at {}", path.display(), prim_path)),
+            },
+            None => {
+                for prim in &layer.prims {
+                    self.walk(prim, &layer, &root, parent_path, &dir, &units)?;
+                }
+            }
+        }
+        self.composing.pop();
+        Ok(())
+    }
+
+    fn walk(
+        &mut self,
+        prim: &Prim,
+        layer: &Layer,
+        parent: &Matrix4<f64>,
+        parent_path: &str,
+        dir: &Path,
+        units: &Units,
+    ) -> Result<(), String> {
+        let path = format!("{}/{}", parent_path, prim.name);
+        let stage = &mut self.stage;
+        let (local, reset) = local_transform(prim, &mut stage.warnings);
+        let world = if reset { local } else { parent * local };
+
+        let material = material(prim, layer)
+            .unwrap_or_else(|e| {
+                let warning = format!("default material for {}: {}", prim.name, e);
+                stage.warnings.push(warning);
+                None
+            })
+            .unwrap_or_default();
+        let binding = prim.attribute("material:binding").and_then(Value::as_path);
+        let mut objects = Vec::new();
+
+        match prim.type_name.as_str() {
+            // Materials reach objects through the bindings of the prims using
+            // them, and those that can be read join the palette for edits
+            "Material" => {
+                if let Ok(material) = preview_surface(prim, &path) {
+                    stage.add_material(path.clone(), material);
+                }
+            }
+            "" | "Xform" | "Scope" | "Shader" => {}
+            "Sphere" => {
+                let radius = prim.attribute("radius").and_then(Value::as_f64).unwrap_or(1.0);
+                objects.push(stage.scene.primitives.push(Primitive::Sphere(Sphere {
+                    center: to_point(world.transform_point(Point3::new(0.0, 0.0, 0.0))),
+                    radius: (radius * max_scale(&world)) as f32,
+                    material,
+                })));
+            }
+            "Cube" => {
+                let size = prim.attribute("size").and_then(Value::as_f64).unwrap_or(2.0);
+                objects.push(stage.scene.primitives.push(cube(size, &world, material)));
+            }
+            "Mesh" => match mesh(prim, &world) {
+                Ok(mut mesh) => {
+                    add_primvars(prim, &mut mesh, &mut stage.warnings);
+                    let mesh = Primitive::Mesh(mesh.with_material(material));
+                    objects.push(stage.scene.primitives.push(mesh));
+                }
+                Err(e) => stage
+                    .warnings
+                    .push(format!("skipping Mesh {}: {}", prim.name, e)),
+            },
+            "Camera" => {
+                let focal_length = prim
+                    .attribute("focalLength")
+                    .and_then(Value::as_f64)
+                    .unwrap_or(DEFAULT_FOCAL_LENGTH);
+                let aperture = prim
+                    .attribute("verticalAperture")
+                    .and_then(Value::as_f64)
+                    .unwrap_or(DEFAULT_VERTICAL_APERTURE);
+                let fov = 2.0 * (aperture / (2.0 * focal_length)).atan();
+                // Lenses are measured in tenths of a unit, and stopped down
+                // to no depth of field at all by default
+                let f_stop = prim.attribute("fStop").and_then(Value::as_f64).unwrap_or(0.0);
+                let focus = prim.attribute("focusDistance").and_then(Value::as_f64);
+                let lens = if f_stop > 0.0 { focal_length / f_stop / 10.0 } else { 0.0 };
+                let up = world * Vector4::new(0.0, 1.0, 0.0, 0.0);
+                let at = world * Vector4::new(0.0, 0.0, -1.0, 0.0);
+                let camera = Camera {
+                    position: to_point(world.transform_point(Point3::new(0.0, 0.0, 0.0))),
+                    up: to_vector(up.truncate().normalize()),
+                    at: to_vector(at.truncate().normalize()),
+                    fov: fov.to_degrees() as f32,
+                    aperture: (lens * max_scale(&world)) as f32,
+                    focal_distance: (focus.unwrap_or(1.0) * max_scale(&world)) as f32,
+                    keys: Vec::new(),
+                };
+                stage.cameras.push((path.clone(), camera));
+            }
+            "SphereLight" | "DistantLight" | "DomeLight" => {
+                match light_emission(prim, stage.scene.units.meters_per_unit) {
+                    Ok((color, intensity)) => {
+                        let light = if prim.type_name == "DomeLight" {
+                            let environment = dome_environment(
+                                prim,
+                                &world,
+                                units,
+                                dir,
+                                self.resolver,
+                            )
+                            .unwrap_or_else(|e| {
+                                let warning = format!("uniform {}: {}", prim.name, e);
+                                stage.warnings.push(warning);
+                                None
+                            });
+                            // Portals are added once the whole stage is loaded
+                            Light::Dome {
+                                color,
+                                intensity,
+                                portals: Vec::new(),
+                                environment,
+                            }
+                        } else if prim.type_name == "SphereLight" {
+                            let position = world.transform_point(Point3::new(0.0, 0.0, 0.0));
+                            let shaping = shaping(prim, &world, dir, self.resolver)
+                                .unwrap_or_else(|e| {
+                                    let warning = format!("unshaped {}: {}", prim.name, e);
+                                    stage.warnings.push(warning);
+                                    None
+                                });
+                            Light::Point {
+                                position: to_point(position),
+                                color,
+                                intensity,
+                                shaping,
+                            }
+                        } else {
+                            // Distant lights shine down their -Z axis
+                            let direction = world * Vector4::new(0.0, 0.0, -1.0, 0.0);
+                            Light::Directional {
+                                direction: to_vector(direction.truncate().normalize()),
+                                color,
+                                intensity,
+                            }
+                        };
+                        stage.scene.lights.push(light);
+                    }
+                    Err(e) => stage.warnings.push(format!(
+                        "skipping {} {}: {}",
+                        prim.type_name, prim.name, e
+                    )),
+                }
+            }
+            "RectLight" => match light_emission(prim, stage.scene.units.meters_per_unit) {
+                Ok((color, intensity)) => {
+                    let material = Material {
+                        albedo: Vector3::new(0.0, 0.0, 0.0),
+                        specular: 0.0,
+                        // Shown as dome lights show their luminance, pi
+                        // times over
+                        emissive: color * (intensity * PI),
+                        ..Material::default()
+                    };
+                    match rect(prim, &world) {
+                        Ok(mesh) => {
+                            let mesh = Primitive::Mesh(mesh.with_material(material));
+                            objects.push(stage.scene.primitives.push(mesh));
+                        }
+                        Err(e) => stage
+                            .warnings
+                            .push(format!("skipping RectLight {}: {}", prim.name, e)),
+                    }
+                }
+                Err(e) => stage
+                    .warnings
+                    .push(format!("skipping RectLight {}: {}", prim.name, e)),
+            },
+            "DiskLight" | "CylinderLight" | "GeometryLight" | "PluginLight" => {
+                stage.warnings.push(format!(
+                    "skipping unsupported light {} {}: only sphere, distant, dome and rect \
+                     lights are loaded",
+                    prim.type_name, prim.name
+                ))
+            }
+            "PortalLight" => {
+                // A rectangle in the XY plane, centred on the origin
+                let input = |name: &str| {
+                    prim.attribute(&format!("inputs:{}", name))
+                        .and_then(Value::as_f64)
+                        .unwrap_or(1.0)
+                };
+                let (width, height) = (input("width"), input("height"));
+                let corner = Point3::new(-width / 2.0, -height / 2.0, 0.0);
+                let edge = |x: f64, y: f64| {
+                    to_vector((world * Vector4::new(x, y, 0.0, 0.0)).truncate())
+                };
+                self.portals.push(Portal {
+                    corner: to_point(world.transform_point(corner)),
+                    u: edge(width, 0.0),
+                    v: edge(0.0, height),
+                });
+            }
+            "ParticleEmitter" => {
+                check_attributes(prim, &EMITTER_ATTRIBUTES, &mut stage.warnings);
+                let float = |name: &str, default: f64| {
+                    prim.attribute(name).and_then(Value::as_f64).unwrap_or(default)
+                };
+                let vector = |name: &str| {
+                    let v = prim
+                        .attribute(name)
+                        .and_then(Value::as_vec3)
+                        .unwrap_or_else(|| Vector3::new(0.0, 0.0, 0.0));
+                    to_vector((world * v.extend(0.0)).truncate())
+                };
+                let scale = max_scale(&world);
+                let start_radius = float("startRadius", 0.05);
+                stage.scene.emitters.push(Emitter::new(EmitterSettings {
+                    position: to_point(world.transform_point(Point3::new(0.0, 0.0, 0.0))),
+                    rate: float("rate", 10.0) as f32,
+                    velocity: vector("velocity"),
+                    spread: (float("spread", 0.0) * scale) as f32,
+                    acceleration: vector("acceleration"),
+                    lifetime: float("lifetime", 1.0) as f32,
+                    start_radius: (start_radius * scale) as f32,
+                    end_radius: (float("endRadius", start_radius) * scale) as f32,
+                    seed: float("seed", 0.0) as u32,
+                }));
+            }
+            "Scatter" => {
+                check_attributes(prim, &SCATTER_ATTRIBUTES, &mut stage.warnings);
+                let float = |name: &str, default: f64| {
+                    prim.attribute(name).and_then(Value::as_f64).unwrap_or(default)
+                };
+                let spacing = float("spacing", 0.5) as f32;
+                let radius = float("sphereRadius", 0.1) as f32;
+                let variation = float("radiusVariation", 0.0) as f32;
+                let noise_scale = float("noiseScale", 1.0) as f32;
+                let seed = float("seed", 0.0) as u32;
+                // Points are scattered in the prim's own space, on the XZ
+                // plane or on a sphere about its origin
+                let mut rng = procedural::rng(seed);
+                let points = match prim.attribute("surface").and_then(Value::as_str) {
+                    None | Some("plane") => {
+                        let width = float("width", 10.0) as f32;
+                        let depth = float("depth", 10.0) as f32;
+                        let patch = Patch {
+                            origin: Point3::new(-width / 2.0, 0.0, -depth / 2.0),
+                            u: Vector3::new(width, 0.0, 0.0),
+                            v: Vector3::new(0.0, 0.0, depth),
+                        };
+                        patch.poisson_disk(&mut rng, spacing)
+                    }
+                    Some("sphere") => {
+                        let surface = Sphere {
+                            center: Point3::new(0.0, 0.0, 0.0),
+                            radius: float("surfaceRadius", 1.0) as f32,
+                            material: Material::default(),
+                        };
+                        procedural::poisson_on_sphere(&mut rng, &surface, spacing)
+                    }
+                    Some(other) => {
+                        stage.warnings.push(format!(
+                            "unknown surface {} on Scatter {}, nothing scattered",
+                            other, prim.name
+                        ));
+                        Vec::new()
+                    }
+                };
+                let scale = max_scale(&world);
+                for point in points {
+                    // Noise rather than independent random radii, so sizes
+                    // vary in patches the way natural scatterings do
+                    let noise = procedural::fbm(point * noise_scale, seed, 4);
+                    let local = Point3::new(point.x as f64, point.y as f64, point.z as f64);
+                    objects.push(stage.scene.primitives.push(Primitive::Sphere(Sphere {
+                        center: to_point(world.transform_point(local)),
+                        radius: (radius * (1.0 + variation * noise)).max(0.0) * scale as f32,
+                        material,
+                    })));
+                }
+            }
+            other => stage
+                .warnings
+                .push(format!("skipping unsupported {} prim {}", other, prim.name)),
+        }
+
+        for child in &prim.children {
+            self.walk(child, layer, &world, &path, dir, units)?;
+        }
+        for key in &["references", "payload"] {
+            for (asset, prim_path) in prim.assets(key) {
+                let asset = self.resolver.resolve(&asset, dir)?;
+                // OBJ models have no units of their own, so take the
+                // referencing prim's
+                if asset.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj")) {
+                    let source = MeshSource {
+                        modified: resolve::modified(&asset),
+                        path: asset,
+                        transform: world.cast(),
+                    };
+                    let mesh = self.resolver.mesh(&source.path)?;
+                    let mesh = mesh.transformed(&source.transform).with_material(material);
+                    let object = self.stage.scene.primitives.push(Primitive::Mesh(mesh));
+                    self.stage.scene.mesh_sources.insert(object, source);
+                    objects.push(object);
+                    continue;
+                }
+                self.include(&asset, prim_path.as_deref(), &world, &path, units)?;
+            }
+        }
+        if let Some(target) = binding {
+            let bound = objects.iter().map(|&object| (object, target.to_string()));
+            self.bindings.extend(bound);
+        }
+        Ok(())
+    }
+}
+
+/// Loads the spheres and cameras of a `.usda` file, and of any layers it
+/// includes, into a scene using `units`.
+pub fn load(path: &Path, units: &Units, resolver: &AssetResolver) -> Result<Stage, String> {
+    let mut loader = Loader {
+        resolver,
+        stage: Stage {
+            scene: Scene {
+                units: *units,
+                ..Scene::empty()
+            },
+            cameras: Vec::new(),
+            materials: Vec::new(),
+            warnings: Vec::new(),
+        },
+        layers: HashMap::new(),
+        portals: Vec::new(),
+        composing: Vec::new(),
+        bindings: Vec::new(),
+    };
+    loader.include(path, None, &Matrix4::identity(), "", units)?;
+    for light in loader.stage.scene.lights.iter_mut() {
+        if let Light::Dome { ref mut portals, .. } = *light {
+            portals.clone_from(&loader.portals);
+        }
+    }
+    for (object, target) in loader.bindings {
+        loader.stage.bind_material(object, &target);
+    }
+    Ok(loader.stage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use primitive::Intersectable;
+    use resolve::scratch_dir;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+
+    fn load_text(name: &str, text: &str) -> Result<Stage, String> {
+        let dir = scratch_dir(name);
+        fs::write(dir.join("triangle.obj"), TRIANGLE).unwrap();
+        let path = dir.join("scene.usda");
+        fs::write(&path, text).unwrap();
+        load(&path, &Units::default(), &AssetResolver::new(Vec::new()))
+    }
+
+    #[test]
+    fn stage_loads() {
+        let stage = load_text(
+            "usd-stage",
+            r#"#usda 1.0
+(
+    metersPerUnit = 1
+)
+def Sphere "Ball"
+{
+    double radius = 2
+    color3f[] primvars:displayColor = [(0.9, 0.1, 0.1)]
+    double3 xformOp:translate = (0, 1, 0)
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+}
+def Xform "Model" (
+    references = @triangle.obj@
+)
+{
+    double3 xformOp:translate = (0, 0, -3)
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+}
+def Camera "Main"
+{
+    double3 xformOp:translate = (0, 1, 5)
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+}
+def SphereLight "Key"
+{
+    float inputs:intensity = 5
+}
+"#,
+        )
+        .unwrap();
+        assert!(stage.warnings.is_empty(), "{:?}", stage.warnings);
+        let spheres: Vec<&Sphere> = stage.scene.spheres().collect();
+        assert_eq!(spheres.len(), 1);
+        assert_eq!(spheres[0].radius, 2.0);
+        assert_eq!(spheres[0].center, Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(spheres[0].material.albedo, Vector3::new(0.9, 0.1, 0.1));
+        assert_eq!(stage.scene.primitives.len(), 2);
+        assert_eq!(stage.scene.lights.len(), 1);
+        assert_eq!(stage.cameras[0].0, "/Main");
+        assert_eq!(stage.cameras[0].1.position, Point3::new(0.0, 1.0, 5.0));
+    }
+
+    #[test]
+    fn mesh_primvars_become_attributes() {
+        let stage = load_text(
+            "usd-primvars",
+            r#"#usda 1.0
+(
+    metersPerUnit = 1
+)
+def Mesh "Plate"
+{
+    point3f[] points = [(0, 0, 0), (1, 0, 0), (0, 1, 0)]
+    int[] faceVertexCounts = [3]
+    int[] faceVertexIndices = [0, 1, 2]
+    float[] primvars:temperature = [10, 20, 40]
+    float[] primvars:pressure = [1, 2]
+}
+"#,
+        )
+        .unwrap();
+        assert_eq!(stage.warnings.len(), 1, "{:?}", stage.warnings);
+        let primitives: Vec<&Primitive> = stage.scene.primitives.iter().collect();
+        let mesh = match primitives[..] {
+            [Primitive::Mesh(ref mesh)] => mesh,
+            _ => panic!("not one mesh"),
+        };
+        let names: Vec<&str> = mesh.attributes().map(|(name, _)| name).collect();
+        assert_eq!(names, ["temperature"]);
+        let point = Point3::new(0.25, 0.5, 0.0);
+        assert_eq!(mesh.attribute("temperature", point, 0), Some(27.5));
+        assert_eq!(mesh.attribute("pressure", point, 0), None);
+    }
+
+    #[test]
+    fn malformed_stages_are_refused() {
+        let stages = [
+            "#usda 1.0\ndef Sphere \"Ball\"\n{\n    double radius = 1\n",
+            "#usda 1.0\ndef Sphere \"Ball\"\n{\n    double radius = (1\n}\n",
+            "#usda 2.0\ndef Sphere \"Ball\"\n{\n}\n",
+            "#usda 1.0\ndef Xform \"Model\" (\n    references = @missing.obj@\n)\n{\n}\n",
+            "def Sphere \"Ball\"\n{\n}\n",
+        ];
+        for (i, text) in stages.iter().enumerate() {
+            assert!(load_text(&format!("usd-malformed-{}", i), text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Watch folder mode: renders each `.usda`, `.json` or `.pbrt` scene that
+//! appears in, or changes in, a directory into an output directory, until
+//! the process is stopped.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use batch::{self, Job};
+use camera::Camera;
+use interrupt;
+use render::RenderOptions;
+use report::Report;
+use resolve::{modified, AssetResolver};
+use scene::Units;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SCENE_EXTENSIONS: [&str; 3] = ["usda", "json", "pbrt"];
+
+/// Polls `in_dir` until interrupted, rendering each `<name>.usda`, `.json` or
+/// `.pbrt` to `<out_dir>/<name>.png` whenever the scene is newer than its
+/// image. A scene is only picked up once its modification time has held still
+/// for a poll, so files still being written aren't read half finished.
+pub fn watch(
+    in_dir: &Path,
+    out_dir: &Path,
+    units: &Units,
+    resolver: &AssetResolver,
+    render_options: &RenderOptions,
+    default_camera: &Camera,
+) {
+    // Modification time each pending scene had at the previous poll
+    let mut pending: HashMap<PathBuf, SystemTime> = HashMap::new();
+    // Scenes that failed, by the modification time that failed
+    let mut failed: HashMap<PathBuf, SystemTime> = HashMap::new();
+    println!("watching {} for scenes", in_dir.display());
+    while !interrupt::interrupted() {
+        let entries = match fs::read_dir(in_dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("{}: {}", in_dir.display(), e);
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let scene = entry.path();
+            if scene.extension().is_none_or(|e| !SCENE_EXTENSIONS.iter().any(|&s| e == s)) {
+                continue;
+            }
+            let stamp = match modified(&scene) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            let output = out_dir.join(scene.with_extension("png").file_name().unwrap());
+            let up_to_date = modified(&output).is_some_and(|rendered| rendered >= stamp);
+            if up_to_date || failed.get(&scene) == Some(&stamp) {
+                continue;
+            }
+            if pending.insert(scene.clone(), stamp) != Some(stamp) {
+                continue;
+            }
+
+            pending.remove(&scene);
+            println!("rendering {}", scene.display());
+            let job = Job::new(scene.clone(), output);
+            let mut report = Report::new(&scene.display().to_string());
+            if batch::run(&[job], units, resolver, render_options, default_camera, &mut report) > 0 {
+                failed.insert(scene, stamp);
+            }
+            if interrupt::interrupted() {
+                return;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,169 @@
+//! Wavefront path tracing: the paths of a whole tile traced together, a
+//! bounce at a time, rather than each from start to finish before the next.
+//!
+//! Every camera ray of the tile is generated up front into a queue. Then,
+//! until the queue is empty, each of its rays is intersected with the scene,
+//! each path is shaded where its ray hit and picks the ray it carries on
+//! along, and paths that have ended are taken out of the queue, which is
+//! compacted so that the rays left are contiguous for the next round.
+//! Tracing one kind of work over many rays at a time keeps the scene's data
+//! in cache while the paths scatter, and is how a GPU would have to run
+//! them.
+//!
+//! Shading doesn't trace shadow rays as it goes. The shadow rays of every
+//! surface shaded in a round are gathered into a batch of their own and
+//! traced together, sorted so that rays heading the same way from nearby
+//! points are traced one after another, before their light is added to the
+//! paths.
+//!
+//! Paths are the path integrator's, seeded the same way, so the image is
+//! the same as its.
+
+use cgmath::Vector3;
+use std::cmp::Ordering;
+
+use camera::Camera;
+use geometry::Ray;
+use path::Path;
+use render::{
+    self, is_finite, pixel_radiance, Color, FramePixel, LightSum, Rect, RenderOptions, ShadowRay,
+    TileBuffer,
+};
+use scene::{closest_intersection, occluded, Scene};
+
+/// Renders `rect` as `render::render_rect` would, tracing the paths of all
+/// its pixels' camera rays together.
+pub fn render_rect<P: FramePixel>(
+    scene: &Scene,
+    camera: &Camera,
+    render_options: &RenderOptions,
+    rect: &Rect,
+    tile: &mut TileBuffer<P>,
+) {
+    // False colour has no paths to trace
+    if render_options.heatmap.is_some() {
+        for px_x in rect.x..(rect.x + rect.width) {
+            for px_y in rect.y..(rect.y + rect.height) {
+                let color = pixel_radiance(scene, camera, render_options, px_x, px_y);
+                tile.put_pixel(px_x, px_y, P::from_radiance(color, render_options, px_x, px_y));
+            }
+        }
+        return;
+    }
+
+    // Generate: a path per camera ray, each with a slot for the light it
+    // gathers, slots running through each pixel's rays in turn
+    let count = render::pixel_ray_count(render_options);
+    let pixel = |slot: usize| {
+        let index = slot as u32 / count;
+        (rect.x + index % rect.width, rect.y + index / rect.width)
+    };
+    let slots = (rect.width * rect.height * count) as usize;
+    let black = Vector3::new(0.0, 0.0, 0.0);
+    let mut colors = vec![black; slots];
+    let mut rays = Vec::with_capacity(slots);
+    let mut paths = Vec::with_capacity(slots);
+    for slot in 0..slots {
+        let (px_x, px_y) = pixel(slot);
+        let ray = render::pixel_ray(camera, render_options, px_x, px_y, slot as u32 % count);
+        // A ray that isn't finite gathers nothing, as in `checked_radiance`
+        if is_finite(ray.direction) {
+            paths.push((slot, Path::new(&ray, render_options.pass)));
+            rays.push(ray);
+        }
+    }
+
+    let mut next: Vec<Option<Ray>> = Vec::with_capacity(slots);
+    // Shadow rays of the round, after the position in the queue of the
+    // path they light
+    let mut shadows: Vec<(usize, ShadowRay)> = Vec::new();
+    let mut order = Vec::new();
+    let mut blocked = Vec::new();
+    while !rays.is_empty() {
+        // Intersect every ray in the queue, then shade each path where its
+        // ray hit
+        let hits: Vec<_> = rays
+            .iter()
+            .map(|ray| closest_intersection(scene, ray, render_options))
+            .collect();
+        let shading = paths.iter_mut().zip(rays.iter().zip(hits)).enumerate();
+        for (i, (entry, (ray, found))) in shading {
+            let shadow = |shadow| shadows.push((i, shadow));
+            next.push(entry.1.shade(scene, ray, found, render_options, shadow));
+        }
+
+        // Trace the round's shadow rays in coherent order, then add the
+        // light each path's let through in the order they were made
+        order.clear();
+        order.extend(0..shadows.len());
+        order.sort_unstable_by(|&a, &b| coherent_order(&shadows[a].1, &shadows[b].1));
+        blocked.clear();
+        blocked.resize(shadows.len(), false);
+        for &k in &order {
+            let shadow = &shadows[k].1;
+            blocked[k] = occluded(scene, &shadow.ray, shadow.distance, render_options);
+        }
+        let mut k = 0;
+        for (i, entry) in paths.iter_mut().enumerate() {
+            let mut light = LightSum::new();
+            while k < shadows.len() && shadows[k].0 == i {
+                light.add(&shadows[k].1, blocked[k]);
+                k += 1;
+            }
+            entry.1.gather(light.total());
+        }
+        shadows.clear();
+
+        // Compact: paths that carry on move down over those that ended,
+        // keeping their order
+        rays.clear();
+        let mut kept = 0;
+        for (i, ray) in next.drain(..).enumerate() {
+            match ray {
+                Some(ray) => {
+                    rays.push(ray);
+                    paths.swap(kept, i);
+                    kept += 1;
+                }
+                None => {
+                    let (slot, ref path) = paths[i];
+                    colors[slot] = finished(path.color(), pixel(slot));
+                }
+            }
+        }
+        paths.truncate(kept);
+    }
+
+    // Each pixel's rays averaged in order, as `pixel_radiance` sums them
+    for (index, samples) in colors.chunks(count as usize).enumerate() {
+        let (px_x, px_y) = pixel(index * count as usize);
+        let color = samples.iter().fold(black, |sum, &c| sum + c) / count as f32;
+        tile.put_pixel(px_x, px_y, P::from_radiance(color, render_options, px_x, px_y));
+    }
+}
+
+// Orders shadow rays by the octant of their direction, then by origin
+fn coherent_order(a: &ShadowRay, b: &ShadowRay) -> Ordering {
+    let octant = |ray: &Ray| {
+        let d = ray.direction;
+        (d.x < 0.0, d.y < 0.0, d.z < 0.0)
+    };
+    let (a, b) = (&a.ray, &b.ray);
+    octant(a)
+        .cmp(&octant(b))
+        .then(a.origin.x.total_cmp(&b.origin.x))
+        .then(a.origin.y.total_cmp(&b.origin.y))
+        .then(a.origin.z.total_cmp(&b.origin.z))
+}
+
+// The light a finished path gathered, or black if it isn't finite, as in
+// `checked_radiance`
+fn finished(color: Color, (px_x, px_y): (u32, u32)) -> Color {
+    if is_finite(color) {
+        return color;
+    }
+    if cfg!(debug_assertions) {
+        eprintln!("non-finite radiance {:?} at pixel ({}, {})", color, px_x, px_y);
+    }
+    Vector3::new(0.0, 0.0, 0.0)
+}